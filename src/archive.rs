@@ -0,0 +1,1349 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Reading tar, gzip and zip archives and writing tar archives, so that
+//! packages can be unpacked where no archive tools are installed.
+
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use color_eyre::Report;
+
+use crate::argparse::{Opt, Spec};
+use crate::output_device::OutputDevice;
+use crate::shell_base::{Shell, EXIT_FAILURE, EXIT_SUCCESS, STDERR};
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Reads bits, least significant first, from a byte stream
+struct BitReader<'a> {
+    input: &'a mut dyn BufRead,
+    /// Bits of the last byte read that were not used yet
+    byte: u8,
+    left: u8,
+}
+
+fn read_error(error: io::Error) -> String {
+    if error.kind() == io::ErrorKind::UnexpectedEof {
+        String::from("unexpected end of compressed data")
+    } else {
+        error.to_string()
+    }
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u32, String> {
+        if self.left == 0 {
+            let mut byte = [0u8];
+            self.input.read_exact(&mut byte).map_err(read_error)?;
+            self.byte = byte[0];
+            self.left = 8;
+        }
+        let bit = self.byte & 1;
+        self.byte >>= 1;
+        self.left -= 1;
+        Ok(bit as u32)
+    }
+
+    /// `count` bits, least significant first
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary
+    fn align(&mut self) {
+        self.left = 0;
+    }
+}
+
+/// Canonical Huffman code given by code lengths of its symbols
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|symbol| lengths[*symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in self.counts.iter().skip(1) {
+            code |= reader.bit()? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(String::from("invalid Huffman code"))
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        };
+    }
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literals = reader.bits(5)? as usize + 257;
+    let distances = reader.bits(5)? as usize + 1;
+    let code_lengths = reader.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+    for position in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*position] = reader.bits(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&lengths);
+
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (value, repeat) = match code_length_code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths
+                    .last()
+                    .ok_or("repeated length without a previous one")?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.resize(lengths.len() + repeat as usize, value);
+    }
+    if lengths.len() > literals + distances {
+        return Err(String::from("too many code lengths"));
+    }
+    Ok((
+        Huffman::new(&lengths[..literals]),
+        Huffman::new(&lengths[literals..]),
+    ))
+}
+
+/// Distance back-references can reach in DEFLATE data
+const WINDOW_SIZE: usize = 32 * 1024;
+
+/// Output of `inflate`, passed on in pieces while keeping the part that
+/// back-references can still refer to
+struct Window<'a> {
+    data: Vec<u8>,
+    /// Bytes produced so far
+    total: usize,
+    limit: usize,
+    sink: &'a mut dyn FnMut(&[u8]) -> Result<(), String>,
+}
+
+impl<'a> Window<'a> {
+    fn push(&mut self, byte: u8) -> Result<(), String> {
+        if self.total == self.limit {
+            return Err(String::from("decompressed size limit exceeded"));
+        }
+        self.data.push(byte);
+        self.total += 1;
+        if self.data.len() == 4 * WINDOW_SIZE {
+            self.pass_on(WINDOW_SIZE)?;
+        }
+        Ok(())
+    }
+
+    /// Passes on everything but the last `keep` bytes
+    fn pass_on(&mut self, keep: usize) -> Result<(), String> {
+        let end = self.data.len().saturating_sub(keep);
+        (self.sink)(&self.data[..end])?;
+        self.data.drain(..end);
+        Ok(())
+    }
+}
+
+/// Decompresses raw DEFLATE data, as found in zip entries and gzip members,
+/// passing it on to `sink` in pieces. Fails once more than `limit` bytes are
+/// produced, returns the number of bytes produced otherwise.
+pub fn inflate(
+    input: &mut dyn BufRead,
+    limit: usize,
+    sink: &mut dyn FnMut(&[u8]) -> Result<(), String>,
+) -> Result<usize, String> {
+    let mut reader = BitReader {
+        input,
+        byte: 0,
+        left: 0,
+    };
+    let mut output = Window {
+        data: Vec::new(),
+        total: 0,
+        limit,
+        sink,
+    };
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => {
+                reader.align();
+                let mut header = [0u8; 4];
+                reader.input.read_exact(&mut header).map_err(read_error)?;
+                let length = u16::from_le_bytes([header[0], header[1]]);
+                if length != !u16::from_le_bytes([header[2], header[3]]) {
+                    return Err(String::from("corrupted stored block length"));
+                }
+                let mut block = vec![0u8; length as usize];
+                reader.input.read_exact(&mut block).map_err(read_error)?;
+                for byte in block {
+                    output.push(byte)?;
+                }
+            }
+            kind @ (1 | 2) => {
+                let (literals, distances) = if kind == 1 {
+                    fixed_codes()
+                } else {
+                    dynamic_codes(&mut reader)?
+                };
+                loop {
+                    let symbol = literals.decode(&mut reader)? as usize;
+                    if symbol < 256 {
+                        output.push(symbol as u8)?;
+                        continue;
+                    }
+                    if symbol == 256 {
+                        break;
+                    }
+                    let index = symbol - 257;
+                    if index >= LENGTH_BASE.len() {
+                        return Err(String::from("invalid length symbol"));
+                    }
+                    let length =
+                        LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+                    let index = distances.decode(&mut reader)? as usize;
+                    if index >= DISTANCE_BASE.len() {
+                        return Err(String::from("invalid distance symbol"));
+                    }
+                    let distance = DISTANCE_BASE[index] as usize
+                        + reader.bits(DISTANCE_EXTRA[index])? as usize;
+                    if distance > output.data.len() {
+                        return Err(String::from("distance too far back"));
+                    }
+                    // copies may overlap the bytes they produce, and the
+                    // window may move while copying
+                    for _ in 0..length {
+                        output.push(output.data[output.data.len() - distance])?;
+                    }
+                }
+            }
+            _ => return Err(String::from("invalid block type")),
+        }
+        if last {
+            output.pass_on(0)?;
+            return Ok(output.total);
+        }
+    }
+}
+
+/// CRC-32 of data following the data `crc` was computed for, 0 to start with
+pub fn crc32_update(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+/// Decompresses all members of a gzip stream, passing the data on to `sink`
+/// in pieces. Fails once more than `limit` bytes are produced.
+pub fn gunzip(
+    input: &mut dyn BufRead,
+    limit: usize,
+    sink: &mut dyn FnMut(&[u8]) -> Result<(), String>,
+) -> Result<(), String> {
+    const FHCRC: u8 = 2;
+    const FEXTRA: u8 = 4;
+    const FNAME: u8 = 8;
+    const FCOMMENT: u8 = 16;
+
+    let truncated = |error: io::Error| match error.kind() {
+        io::ErrorKind::UnexpectedEof => String::from("truncated gzip header"),
+        _ => error.to_string(),
+    };
+    let mut total = 0;
+    loop {
+        // anything following the last member is ignored
+        let mut header = Vec::new();
+        (&mut *input)
+            .take(10)
+            .read_to_end(&mut header)
+            .map_err(|error| error.to_string())?;
+        if !is_gzip(&header) {
+            return Ok(());
+        }
+        if header.len() < 10 {
+            return Err(String::from("truncated gzip header"));
+        }
+        if header[2] != 8 {
+            return Err(String::from("unsupported gzip compression method"));
+        }
+        let flags = header[3];
+        if flags & FEXTRA != 0 {
+            let mut length = [0u8; 2];
+            input.read_exact(&mut length).map_err(truncated)?;
+            let length = u16::from_le_bytes(length) as u64;
+            let skipped =
+                io::copy(&mut (&mut *input).take(length), &mut io::sink()).map_err(truncated)?;
+            if skipped < length {
+                return Err(String::from("truncated gzip header"));
+            }
+        }
+        for flag in [FNAME, FCOMMENT].iter() {
+            if flags & flag != 0 {
+                let mut field = Vec::new();
+                input.read_until(0, &mut field).map_err(truncated)?;
+                if field.last() != Some(&0) {
+                    return Err(String::from("truncated gzip header"));
+                }
+            }
+        }
+        if flags & FHCRC != 0 {
+            input.read_exact(&mut [0u8; 2]).map_err(truncated)?;
+        }
+
+        let mut crc = 0;
+        let mut pass_on = |data: &[u8]| {
+            crc = crc32_update(crc, data);
+            sink(data)
+        };
+        total += inflate(input, limit - total, &mut pass_on)?;
+        let mut trailer = [0u8; 8];
+        input
+            .read_exact(&mut trailer)
+            .map_err(|_| String::from("truncated gzip trailer"))?;
+        if crc != u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) {
+            return Err(String::from("gzip checksum mismatch"));
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Directory,
+    Symlink(String),
+}
+
+/// File, directory or link stored in an archive
+#[derive(Debug)]
+pub struct Entry {
+    pub path: String,
+    pub kind: EntryKind,
+    /// Unix permission bits, if the archive records them
+    pub mode: Option<u32>,
+}
+
+fn field(header: &[u8], start: usize, len: usize) -> String {
+    let field = &header[start..start + len];
+    let end = field.iter().position(|byte| *byte == 0).unwrap_or(len);
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Numeric field, octal or base-256 for values that do not fit
+fn number(header: &[u8], start: usize, len: usize) -> Result<u64, String> {
+    let field = &header[start..start + len];
+    if field[0] & 0x80 != 0 {
+        return Ok(field[1..]
+            .iter()
+            .fold(0u64, |value, byte| (value << 8) | *byte as u64));
+    }
+    let text = field
+        .iter()
+        .filter(|byte| **byte != 0 && **byte != b' ')
+        .map(|byte| *byte as char)
+        .collect::<String>();
+    if text.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(&text, 8).map_err(|_| format!("invalid number in tar header: {text}"))
+}
+
+/// Records of a PAX extended header, `<length> <key>=<value>\n` each
+fn pax_records(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+    while let Some(space) = rest.iter().position(|byte| *byte == b' ') {
+        let length: usize = match String::from_utf8_lossy(&rest[..space]).parse() {
+            Ok(length) if length > space && length <= rest.len() => length,
+            _ => break,
+        };
+        let record = String::from_utf8_lossy(&rest[space + 1..length]);
+        if let Some((key, value)) = record.trim_end_matches('\n').split_once('=') {
+            records.push((key.to_string(), value.to_string()));
+        }
+        rest = &rest[length..];
+    }
+    records
+}
+
+/// Largest GNU long name or PAX header entry that is read
+const MAX_HEADER_ENTRY: u64 = 1024 * 1024;
+
+#[derive(Debug, Default)]
+enum TarState {
+    #[default]
+    Header,
+    /// Content of a GNU long name or PAX header entry of type `kind`
+    HeaderEntry {
+        kind: u8,
+        size: usize,
+    },
+    /// Data of an entry, passed on to the unpacker when `pass`, and the
+    /// padding after it
+    Data {
+        left: u64,
+        padding: usize,
+        pass: bool,
+    },
+    End,
+}
+
+/// Reads a tar archive fed to it in pieces of any size, including GNU long
+/// names and PAX paths, passing entries on as soon as their headers are read
+#[derive(Debug, Default)]
+struct TarReader {
+    state: TarState,
+    /// Header block or header entry being collected
+    pending: Vec<u8>,
+    /// Offset of the next byte in the archive
+    offset: u64,
+    long_path: Option<String>,
+    long_link: Option<String>,
+}
+
+impl TarReader {
+    fn feed(&mut self, mut data: &[u8], unpacker: &mut Unpacker) -> Result<(), String> {
+        while !data.is_empty() {
+            let wanted = match &mut self.state {
+                TarState::End => return Ok(()),
+                TarState::Data {
+                    left,
+                    padding,
+                    pass,
+                } => {
+                    if *left > 0 {
+                        let count = (*left).min(data.len() as u64) as usize;
+                        if *pass {
+                            unpacker.write(&data[..count]);
+                        }
+                        *left -= count as u64;
+                        if *left == 0 && *pass {
+                            unpacker.finish_entry();
+                        }
+                        data = &data[count..];
+                        self.offset += count as u64;
+                    } else {
+                        let count = (*padding).min(data.len());
+                        *padding -= count;
+                        data = &data[count..];
+                        self.offset += count as u64;
+                    }
+                    if *left == 0 && *padding == 0 {
+                        self.state = TarState::Header;
+                    }
+                    continue;
+                }
+                TarState::Header => 512,
+                TarState::HeaderEntry { size, .. } => size.div_ceil(512) * 512,
+            };
+            let count = (wanted - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..count]);
+            data = &data[count..];
+            self.offset += count as u64;
+            if self.pending.len() == wanted {
+                let block = std::mem::take(&mut self.pending);
+                match self.state {
+                    TarState::HeaderEntry { kind, size } => {
+                        self.header_entry(kind, &block[..size]);
+                        self.state = TarState::Header;
+                    }
+                    _ => self.header(&block, unpacker)?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that the archive did not end in the middle of an entry
+    fn finish(&self) -> Result<(), String> {
+        match self.state {
+            TarState::Header | TarState::End => Ok(()),
+            _ => Err(String::from("unexpected end of tar archive")),
+        }
+    }
+
+    fn header_entry(&mut self, kind: u8, content: &[u8]) {
+        let text = || {
+            String::from_utf8_lossy(content)
+                .trim_end_matches('\0')
+                .to_string()
+        };
+        match kind {
+            b'L' => self.long_path = Some(text()),
+            b'K' => self.long_link = Some(text()),
+            _ => {
+                for (key, value) in pax_records(content) {
+                    match key.as_str() {
+                        "path" => self.long_path = Some(value),
+                        "linkpath" => self.long_link = Some(value),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+
+    fn header(&mut self, header: &[u8], unpacker: &mut Unpacker) -> Result<(), String> {
+        if header.iter().all(|byte| *byte == 0) {
+            self.state = TarState::End;
+            return Ok(());
+        }
+        let checksum = number(header, 148, 8)?;
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| if (148..156).contains(&i) { b' ' } else { *byte } as u64)
+            .sum();
+        if checksum != sum {
+            return Err(format!(
+                "corrupted tar header at offset {}",
+                self.offset - 512
+            ));
+        }
+
+        let size = number(header, 124, 12)?;
+        let padding = ((512 - size % 512) % 512) as usize;
+        if let kind @ (b'L' | b'K' | b'x') = header[156] {
+            if size > MAX_HEADER_ENTRY {
+                return Err(format!(
+                    "oversized tar header entry at offset {}",
+                    self.offset - 512
+                ));
+            }
+            let size = size as usize;
+            if size == 0 {
+                self.header_entry(kind, &[]);
+            } else {
+                self.state = TarState::HeaderEntry { kind, size };
+            }
+            return Ok(());
+        }
+
+        let mut path = field(header, 0, 100);
+        if &header[257..262] == b"ustar" {
+            let prefix = field(header, 345, 155);
+            if !prefix.is_empty() {
+                path = format!("{prefix}/{path}");
+            }
+        }
+        let mut link = field(header, 157, 100);
+        if let Some(long_path) = self.long_path.take() {
+            path = long_path;
+        }
+        if let Some(long_link) = self.long_link.take() {
+            link = long_link;
+        }
+        let kind = match header[156] {
+            b'0' | 0 | b'7' => Some(EntryKind::File),
+            b'5' => Some(EntryKind::Directory),
+            b'2' => Some(EntryKind::Symlink(link)),
+            // other types, e.g. devices and global PAX headers, are skipped
+            _ => None,
+        };
+        let pass = kind.is_some();
+        if let Some(kind) = kind {
+            unpacker.entry(Entry {
+                path,
+                kind,
+                mode: Some(number(header, 100, 8)? as u32 & 0o7777),
+            });
+            if size == 0 {
+                unpacker.finish_entry();
+            }
+        }
+        if size > 0 {
+            self.state = TarState::Data {
+                left: size,
+                padding,
+                pass,
+            };
+        }
+        Ok(())
+    }
+}
+
+/// Lists or extracts a tar archive read from `input`, decompressing it on
+/// the fly if it is gzipped. At most `limit` bytes are decompressed.
+fn read_tar(input: &mut dyn BufRead, limit: usize, unpacker: &mut Unpacker) -> Result<(), String> {
+    let mut reader = TarReader::default();
+    let gzipped = is_gzip(input.fill_buf().map_err(|error| error.to_string())?);
+    if gzipped {
+        gunzip(input, limit, &mut |data| reader.feed(data, unpacker))?;
+    } else {
+        loop {
+            let data = input.fill_buf().map_err(|error| error.to_string())?;
+            if data.is_empty() {
+                break;
+            }
+            let count = data.len();
+            reader.feed(data, unpacker)?;
+            input.consume(count);
+        }
+    }
+    reader.finish()
+}
+
+fn set_field(header: &mut [u8], start: usize, value: &[u8]) {
+    header[start..start + value.len()].copy_from_slice(value);
+}
+
+fn set_number(header: &mut [u8], start: usize, len: usize, value: u64) {
+    let text = format!("{:0width$o}", value, width = len - 1);
+    set_field(header, start, text.as_bytes());
+}
+
+/// Appends a GNU `L` or `K` entry holding a path too long for a ustar header
+fn write_long_name(archive: &mut Vec<u8>, kind: u8, value: &str) {
+    let mut long = value.as_bytes().to_vec();
+    long.push(0);
+    write_tar_header(archive, "././@LongLink", kind, 0, long.len() as u64, 0, "");
+    archive.extend_from_slice(&long);
+    archive.resize(archive.len().div_ceil(512) * 512, 0);
+}
+
+/// Appends a ustar header for `path`, preceded by GNU long name entries
+/// when the path or link target do not fit
+fn write_tar_header(
+    archive: &mut Vec<u8>,
+    path: &str,
+    kind: u8,
+    mode: u32,
+    size: u64,
+    mtime: u64,
+    link: &str,
+) {
+    let mut header = [0u8; 512];
+    let (prefix, name) = match path
+        .char_indices()
+        .rev()
+        .find(|(i, c)| *c == '/' && path.len() - i - 1 <= 100 && *i <= 155 && *i > 0)
+    {
+        _ if path.len() <= 100 => ("", path),
+        Some((i, _)) => (&path[..i], &path[i + 1..]),
+        None => {
+            write_long_name(archive, b'L', path);
+            ("", path)
+        }
+    };
+    if link.len() > 100 {
+        write_long_name(archive, b'K', link);
+    }
+    set_field(&mut header, 0, &name.as_bytes()[..name.len().min(100)]);
+    set_number(&mut header, 100, 8, mode as u64);
+    set_number(&mut header, 108, 8, 0);
+    set_number(&mut header, 116, 8, 0);
+    set_number(&mut header, 124, 12, size);
+    set_number(&mut header, 136, 12, mtime);
+    header[156] = kind;
+    set_field(&mut header, 157, &link.as_bytes()[..link.len().min(100)]);
+    set_field(&mut header, 257, b"ustar\x0000");
+    set_field(&mut header, 345, prefix.as_bytes());
+    header[148..156].copy_from_slice(b"        ");
+    let checksum: u64 = header.iter().map(|byte| *byte as u64).sum();
+    set_field(&mut header, 148, format!("{checksum:06o}\0 ").as_bytes());
+    archive.extend_from_slice(&header);
+}
+
+#[cfg(not(target_os = "wasi"))]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o7777
+}
+
+#[cfg(target_os = "wasi")]
+fn file_mode(metadata: &fs::Metadata) -> u32 {
+    if metadata.is_dir() {
+        0o755
+    } else {
+        0o644
+    }
+}
+
+/// Appends `path` and, for directories, everything below it to a tar archive
+/// under the name `name`
+pub fn append_tar(archive: &mut Vec<u8>, path: &Path, name: &str) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+        .map(|since| since.as_secs())
+        .unwrap_or(0);
+    let mode = file_mode(&metadata);
+    if metadata.file_type().is_symlink() {
+        let target = fs::read_link(path)?.display().to_string();
+        write_tar_header(archive, name, b'2', mode, 0, mtime, &target);
+    } else if metadata.is_dir() {
+        write_tar_header(archive, &format!("{name}/"), b'5', mode, 0, mtime, "");
+        let mut children: Vec<_> = fs::read_dir(path)?
+            .filter_map(Result::ok)
+            .map(|entry| entry.file_name())
+            .collect();
+        children.sort();
+        for child in children {
+            let child_name = format!("{}/{}", name, child.to_string_lossy());
+            append_tar(archive, &path.join(&child), &child_name)?;
+        }
+    } else {
+        let content = fs::read(path)?;
+        write_tar_header(archive, name, b'0', mode, content.len() as u64, mtime, "");
+        archive.extend_from_slice(&content);
+        archive.resize(archive.len().div_ceil(512) * 512, 0);
+    }
+    Ok(())
+}
+
+/// Marks the end of a tar archive with two empty blocks
+pub fn finish_tar(archive: &mut Vec<u8>) {
+    archive.resize(archive.len() + 1024, 0);
+}
+
+fn le16(data: &[u8], pos: usize) -> usize {
+    u16::from_le_bytes([data[pos], data[pos + 1]]) as usize
+}
+
+fn le32(data: &[u8], pos: usize) -> usize {
+    u32::from_le_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]]) as usize
+}
+
+/// Lists or extracts the entries of a zip archive, read through its central
+/// directory. At most `limit` bytes are decompressed.
+fn read_zip(data: &[u8], limit: usize, unpacker: &mut Unpacker) -> Result<(), String> {
+    const END_SIGNATURE: &[u8] = b"PK\x05\x06";
+    const CENTRAL_SIGNATURE: &[u8] = b"PK\x01\x02";
+    const LOCAL_SIGNATURE: &[u8] = b"PK\x03\x04";
+    const UNIX_HOST: u8 = 3;
+    /// Longest symbolic link target that is read
+    const MAX_TARGET: usize = 4096;
+    let truncated = || String::from("truncated zip archive");
+
+    // the end record is followed by a comment of up to 64 KiB
+    let end = (0..data.len().saturating_sub(21))
+        .rev()
+        .take(65536)
+        .find(|pos| data[*pos..].starts_with(END_SIGNATURE))
+        .ok_or("not a zip archive")?;
+    let record = data.get(end..end + 22).ok_or_else(truncated)?;
+    let count = le16(record, 10);
+    let mut pos = le32(record, 16);
+    if count == 0xffff || pos == 0xffffffff {
+        return Err(String::from("zip64 archives are not supported"));
+    }
+
+    let mut total = 0;
+    for _ in 0..count {
+        let header = data.get(pos..pos + 46).ok_or_else(truncated)?;
+        if !header.starts_with(CENTRAL_SIGNATURE) {
+            return Err(String::from("corrupted zip central directory"));
+        }
+        let flags = le16(header, 8);
+        let method = le16(header, 10);
+        let crc = le32(header, 16) as u32;
+        let compressed = le32(header, 20);
+        let name_len = le16(header, 28);
+        let next = pos + 46 + name_len + le16(header, 30) + le16(header, 32);
+        let path = String::from_utf8_lossy(
+            data.get(pos + 46..pos + 46 + name_len)
+                .ok_or_else(truncated)?,
+        )
+        .into_owned();
+        let mode = (header[5] == UNIX_HOST).then(|| (le32(header, 38) >> 16) as u32);
+        let local = le32(header, 42);
+        pos = next;
+
+        if flags & 1 != 0 {
+            return Err(format!("{path}: encrypted entries are not supported"));
+        }
+        let local_header = data.get(local..local + 30).ok_or_else(truncated)?;
+        if !local_header.starts_with(LOCAL_SIGNATURE) {
+            return Err(format!("{path}: corrupted local header"));
+        }
+        let start = local + 30 + le16(local_header, 26) + le16(local_header, 28);
+        let mut stored = data.get(start..start + compressed).ok_or_else(truncated)?;
+        if method != 0 && method != 8 {
+            return Err(format!("{path}: unsupported compression method {method}"));
+        }
+
+        let is_symlink = mode.is_some_and(|mode| mode & 0o170000 == 0o120000);
+        let mode = mode.map(|mode| mode & 0o7777).filter(|mode| *mode != 0);
+        if is_symlink && !path.ends_with('/') {
+            // the link target is the content of the entry
+            let mut target = Vec::new();
+            let mut collect = |data: &[u8]| {
+                if target.len() + data.len() > MAX_TARGET {
+                    return Err(String::from("link target too long"));
+                }
+                target.extend_from_slice(data);
+                Ok(())
+            };
+            if method == 0 {
+                collect(stored)?;
+            } else {
+                total += inflate(&mut stored, limit - total, &mut collect)
+                    .map_err(|error| format!("{path}: {error}"))?;
+            }
+            if crc32_update(0, &target) != crc {
+                return Err(format!("{path}: checksum mismatch"));
+            }
+            unpacker.entry(Entry {
+                path,
+                kind: EntryKind::Symlink(String::from_utf8_lossy(&target).into_owned()),
+                mode,
+            });
+            unpacker.finish_entry();
+            continue;
+        }
+
+        let kind = if path.ends_with('/') {
+            EntryKind::Directory
+        } else {
+            EntryKind::File
+        };
+        unpacker.entry(Entry {
+            path: path.clone(),
+            kind,
+            mode,
+        });
+        let mut content_crc = 0;
+        let mut pass_on = |data: &[u8]| {
+            content_crc = crc32_update(content_crc, data);
+            unpacker.write(data);
+            Ok(())
+        };
+        if method == 0 {
+            pass_on(stored)?;
+        } else {
+            total += inflate(&mut stored, limit - total, &mut pass_on)
+                .map_err(|error| format!("{path}: {error}"))?;
+        }
+        if content_crc != crc {
+            return Err(format!("{path}: checksum mismatch"));
+        }
+        unpacker.finish_entry();
+    }
+    Ok(())
+}
+
+/// Destination of an entry below `directory`, `None` for absolute paths and
+/// paths leaving the directory through `..`
+fn entry_destination(directory: &Path, path: &str) -> Option<PathBuf> {
+    let mut destination = directory.to_path_buf();
+    let mut depth = 0;
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => {
+                destination.push(part);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (depth > 0).then_some(destination)
+}
+
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(not(target_os = "wasi"))]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))
+    }
+    #[cfg(target_os = "wasi")]
+    {
+        let _ = (path, mode);
+        Ok(())
+    }
+}
+
+/// Extracts `entry` below `directory`, returning the file its data goes to.
+/// Paths below symlinks in `symlinks`, the ones extracted before, are
+/// refused as they could lead outside of it.
+fn extract_entry(
+    directory: &Path,
+    entry: &Entry,
+    symlinks: &mut Vec<PathBuf>,
+) -> io::Result<Option<(fs::File, PathBuf)>> {
+    let outside = || io::Error::other("refusing to extract outside of the target directory");
+    let destination = entry_destination(directory, &entry.path).ok_or_else(outside)?;
+    if symlinks
+        .iter()
+        .any(|link| destination != *link && destination.starts_with(link))
+    {
+        return Err(outside());
+    }
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match &entry.kind {
+        EntryKind::Directory => fs::create_dir_all(&destination)?,
+        EntryKind::File => {
+            // replace rather than write through an existing symlink
+            if fs::symlink_metadata(&destination)
+                .is_ok_and(|metadata| metadata.file_type().is_symlink())
+            {
+                fs::remove_file(&destination)?;
+            }
+            return Ok(Some((fs::File::create(&destination)?, destination)));
+        }
+        EntryKind::Symlink(target) => {
+            let target_path = Path::new(target);
+            if target_path.is_absolute()
+                || target_path
+                    .components()
+                    .any(|component| component == Component::ParentDir)
+            {
+                return Err(io::Error::other(format!(
+                    "refusing to create a link to {target}"
+                )));
+            }
+            #[cfg(not(target_os = "wasi"))]
+            {
+                if fs::symlink_metadata(&destination).is_ok() {
+                    fs::remove_file(&destination)?;
+                }
+                std::os::unix::fs::symlink(target, &destination)?;
+                symlinks.push(destination);
+            }
+            #[cfg(target_os = "wasi")]
+            {
+                let _ = target;
+                return Err(io::Error::other("symbolic links are not supported"));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn report(name: &str, path: &str, error: impl std::fmt::Display, output_device: &mut OutputDevice) {
+    let path = output_device.file_link(STDERR, Path::new(path));
+    output_device.eprintln(&format!("{name}: {path}: {error}"));
+}
+
+/// File being extracted, the data of the current entry goes to it
+struct Extracted {
+    file: fs::File,
+    destination: PathBuf,
+    path: String,
+    mode: Option<u32>,
+}
+
+/// Lists or extracts entries while they are read, printing entry paths when
+/// `verbose`
+struct Unpacker<'a, 'b> {
+    name: &'static str,
+    directory: &'a Path,
+    list: bool,
+    verbose: bool,
+    output_device: &'a mut OutputDevice<'b>,
+    symlinks: Vec<PathBuf>,
+    /// Extracted directories with the mode to give them at the end
+    directories: Vec<(PathBuf, String, u32)>,
+    file: Option<Extracted>,
+    exit_status: i32,
+}
+
+impl<'a, 'b> Unpacker<'a, 'b> {
+    fn new(
+        name: &'static str,
+        directory: &'a Path,
+        list: bool,
+        verbose: bool,
+        output_device: &'a mut OutputDevice<'b>,
+    ) -> Self {
+        Unpacker {
+            name,
+            directory,
+            list,
+            verbose,
+            output_device,
+            symlinks: Vec::new(),
+            directories: Vec::new(),
+            file: None,
+            exit_status: EXIT_SUCCESS,
+        }
+    }
+
+    fn fail(&mut self, path: &str, error: impl std::fmt::Display) {
+        report(self.name, path, error, self.output_device);
+        self.exit_status = EXIT_FAILURE;
+    }
+
+    fn entry(&mut self, entry: Entry) {
+        if self.list {
+            match &entry.kind {
+                EntryKind::Symlink(target) if self.verbose => self
+                    .output_device
+                    .println(&format!("{} -> {}", entry.path, target)),
+                _ => self.output_device.println(&entry.path),
+            }
+            return;
+        }
+        if self.verbose {
+            self.output_device.println(&entry.path);
+        }
+        match extract_entry(self.directory, &entry, &mut self.symlinks) {
+            Ok(Some((file, destination))) => {
+                self.file = Some(Extracted {
+                    file,
+                    destination,
+                    path: entry.path,
+                    mode: entry.mode,
+                })
+            }
+            Ok(None) => {
+                if let (EntryKind::Directory, Some(mode)) = (&entry.kind, entry.mode) {
+                    if let Some(destination) = entry_destination(self.directory, &entry.path) {
+                        self.directories.push((destination, entry.path, mode));
+                    }
+                }
+            }
+            Err(error) => self.fail(&entry.path, error),
+        }
+    }
+
+    /// Writes data of the current entry
+    fn write(&mut self, data: &[u8]) {
+        if let Some(extracted) = &mut self.file {
+            if let Err(error) = extracted.file.write_all(data) {
+                let path = extracted.path.clone();
+                self.file = None;
+                self.fail(&path, error);
+            }
+        }
+    }
+
+    fn finish_entry(&mut self) {
+        if let Some(extracted) = self.file.take() {
+            drop(extracted.file);
+            if let Some(mode) = extracted.mode {
+                if let Err(error) = set_mode(&extracted.destination, mode) {
+                    self.fail(&extracted.path, error);
+                }
+            }
+        }
+    }
+
+    /// Gives directories their modes, returns the exit status
+    fn finish(mut self) -> i32 {
+        // read-only directories can only be restricted once their files exist
+        for (destination, path, mode) in std::mem::take(&mut self.directories).into_iter().rev() {
+            if let Err(error) = set_mode(&destination, mode) {
+                self.fail(&path, error);
+            }
+        }
+        self.exit_status
+    }
+}
+
+/// Reads a zip archive, which is read in whole to get to its central
+/// directory, refusing ones larger than `limit`
+fn read_archive(
+    name: &str,
+    operand: &str,
+    limit: usize,
+    output_device: &mut OutputDevice,
+) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let result = output_device.open_input(operand).and_then(|reader| {
+        reader
+            .take((limit as u64).saturating_add(1))
+            .read_to_end(&mut data)
+    });
+    match result {
+        Ok(_) if data.len() > limit => {
+            report(name, operand, "archive size limit exceeded", output_device);
+            None
+        }
+        Ok(_) => Some(data),
+        Err(error) => {
+            report(name, operand, error, output_device);
+            None
+        }
+    }
+}
+
+/// Lists or extracts the tar archive `operand`, gzipped or not
+fn unpack_tar(operand: &str, limit: usize, mut unpacker: Unpacker) -> i32 {
+    let result = unpacker
+        .output_device
+        .open_input(operand)
+        .map_err(|error| error.to_string())
+        .and_then(|mut input| read_tar(&mut *input, limit, &mut unpacker));
+    if let Err(error) = result {
+        unpacker.fail(operand, error);
+    }
+    unpacker.finish()
+}
+
+pub fn untar(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "untar",
+        about: "Extract a tar archive, gzipped or not, standard input when ARCHIVE is -",
+        operands: "<ARCHIVE>",
+        options: &[
+            Opt::flag('t', "list", "List entries instead of extracting them"),
+            Opt::flag('v', "verbose", "Print entries while extracting"),
+            Opt::value(
+                'C',
+                "directory",
+                "DIR",
+                "Extract into DIR, the working directory by default",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let operand = match matches.operands.as_slice() {
+        [operand] => operand,
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let directory = Path::new(matches.value("directory").unwrap_or("."));
+    let unpacker = Unpacker::new(
+        "untar",
+        directory,
+        matches.flag("list"),
+        matches.flag("verbose"),
+        output_device,
+    );
+    Ok(unpack_tar(
+        operand,
+        shell.guard.limits.max_unpacked,
+        unpacker,
+    ))
+}
+
+pub fn unzip(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "unzip",
+        about: "Extract a zip archive, replacing existing files",
+        operands: "<ARCHIVE>",
+        options: &[
+            Opt::flag('l', "list", "List entries instead of extracting them"),
+            Opt::flag('v', "verbose", "Print entries while extracting"),
+            Opt::value(
+                'd',
+                "directory",
+                "DIR",
+                "Extract into DIR, the working directory by default",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let operand = match matches.operands.as_slice() {
+        [operand] => operand,
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let limit = shell.guard.limits.max_unpacked;
+    let data = match read_archive("unzip", operand, limit, output_device) {
+        Some(data) => data,
+        None => return Ok(EXIT_FAILURE),
+    };
+    let directory = Path::new(matches.value("directory").unwrap_or("."));
+    let mut unpacker = Unpacker::new(
+        "unzip",
+        directory,
+        matches.flag("list"),
+        matches.flag("verbose"),
+        output_device,
+    );
+    if let Err(error) = read_zip(&data, limit, &mut unpacker) {
+        unpacker.fail(operand, error);
+    }
+    Ok(unpacker.finish())
+}
+
+/// Path under which `operand` is stored, without leading `/` and `..`
+fn archive_name(operand: &str) -> String {
+    let parts: Vec<String> = Path::new(operand)
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    parts.join("/")
+}
+
+pub fn tar(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "tar",
+        about: "Create, list or extract tar archives, reading gzipped ones as well",
+        operands: "[PATH...]",
+        options: &[
+            Opt::flag('c', "create", "Create ARCHIVE from PATHs"),
+            Opt::flag('x', "extract", "Extract ARCHIVE"),
+            Opt::flag('t', "list", "List entries of ARCHIVE"),
+            Opt::value(
+                'f',
+                "file",
+                "ARCHIVE",
+                "Archive to use, - for standard input or output",
+            ),
+            Opt::flag('v', "verbose", "Print entries while processing them"),
+            Opt::value(
+                'C',
+                "directory",
+                "DIR",
+                "Extract into, or add PATHs relative to, DIR",
+            ),
+            // compression is detected when reading, accepted for compatibility
+            Opt::flag('z', "gzip", ""),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let modes = ["create", "extract", "list"];
+    let archive = match matches.value("file") {
+        Some(archive) if modes.iter().filter(|mode| matches.flag(mode)).count() == 1 => archive,
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let directory = Path::new(matches.value("directory").unwrap_or("."));
+    let verbose = matches.flag("verbose");
+
+    if !matches.flag("create") {
+        let unpacker = Unpacker::new(
+            "tar",
+            directory,
+            matches.flag("list"),
+            verbose,
+            output_device,
+        );
+        return Ok(unpack_tar(
+            archive,
+            shell.guard.limits.max_unpacked,
+            unpacker,
+        ));
+    }
+
+    if matches.flag("gzip") {
+        output_device.eprintln("tar: creating compressed archives is not supported");
+        return Ok(EXIT_FAILURE);
+    }
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut data = Vec::new();
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in &matches.operands {
+        let name = archive_name(operand);
+        if name.is_empty() {
+            output_device.eprintln(&format!("tar: {operand}: cannot be stored in an archive"));
+            exit_status = EXIT_FAILURE;
+            continue;
+        }
+        if verbose {
+            output_device.eprintln(&name);
+        }
+        if let Err(error) = append_tar(&mut data, &directory.join(operand), &name) {
+            report("tar", operand, error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    finish_tar(&mut data);
+    if archive == "-" {
+        output_device.write_bytes(&data);
+    } else if let Err(error) = fs::write(archive, &data) {
+        report("tar", archive, error, output_device);
+        exit_status = EXIT_FAILURE;
+    }
+    Ok(exit_status)
+}
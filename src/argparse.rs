@@ -0,0 +1,338 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use crate::locale::tr;
+use crate::output_device::OutputDevice;
+use crate::shell_base::{EXIT_FAILURE, EXIT_SUCCESS};
+
+/// Option accepted by an internal
+#[derive(Debug, Clone, Copy)]
+pub struct Opt {
+    pub short: Option<char>,
+    /// Long name, also used to look the option up in `Matches`
+    pub long: &'static str,
+    /// Name of the option's argument, `None` for flags
+    pub value: Option<&'static str>,
+    /// Whether the option can be turned off with `+c`, as in `set +o`
+    pub plus: bool,
+    /// Description in `--help`, options without one are not listed
+    pub help: &'static str,
+}
+
+impl Opt {
+    pub const fn flag(short: char, long: &'static str, help: &'static str) -> Opt {
+        Opt {
+            short: Some(short),
+            long,
+            value: None,
+            plus: false,
+            help,
+        }
+    }
+
+    pub const fn value(
+        short: char,
+        long: &'static str,
+        value: &'static str,
+        help: &'static str,
+    ) -> Opt {
+        Opt {
+            short: Some(short),
+            long,
+            value: Some(value),
+            plus: false,
+            help,
+        }
+    }
+
+    pub const fn plus(self) -> Opt {
+        Opt { plus: true, ..self }
+    }
+
+    fn synopsis(&self) -> String {
+        let name = match self.short {
+            Some(short) if self.plus => format!("-{short} | +{short}"),
+            Some(short) => format!("-{short}"),
+            None => format!("--{}", self.long),
+        };
+        match self.value {
+            Some(value) => format!("[{name} {value}]"),
+            None => format!("[{name}]"),
+        }
+    }
+}
+
+/// Declarative description of an internal's command line, used to parse
+/// arguments and to generate usage and `--help` output
+#[derive(Debug)]
+pub struct Spec {
+    pub name: &'static str,
+    pub about: &'static str,
+    /// Synopsis of operands following the options
+    pub operands: &'static str,
+    pub options: &'static [Opt],
+}
+
+#[derive(Debug)]
+struct Occurrence {
+    long: &'static str,
+    enabled: bool,
+    value: Option<String>,
+}
+
+/// Options and operands parsed according to a `Spec`
+#[derive(Debug, Default)]
+pub struct Matches {
+    occurrences: Vec<Occurrence>,
+    pub operands: Vec<String>,
+}
+
+impl Matches {
+    /// Whether `-c` or `--long` was given
+    pub fn flag(&self, long: &str) -> bool {
+        self.occurrences
+            .iter()
+            .any(|occurrence| occurrence.long == long && occurrence.enabled)
+    }
+
+    /// Whether `+c` was given
+    pub fn plus(&self, long: &str) -> bool {
+        self.occurrences
+            .iter()
+            .any(|occurrence| occurrence.long == long && !occurrence.enabled)
+    }
+
+    /// Argument of the last occurrence of the option
+    pub fn value(&self, long: &str) -> Option<&str> {
+        self.occurrences
+            .iter()
+            .rev()
+            .find(|occurrence| occurrence.long == long)
+            .and_then(|occurrence| occurrence.value.as_deref())
+    }
+
+    /// Arguments of all occurrences of the option in order, paired with
+    /// `false` for the `+c` form
+    pub fn values<'a>(&'a self, long: &'a str) -> impl Iterator<Item = (bool, &'a str)> {
+        self.occurrences
+            .iter()
+            .filter(move |occurrence| occurrence.long == long)
+            .filter_map(|occurrence| Some((occurrence.enabled, occurrence.value.as_deref()?)))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseError {
+    Help,
+    Invalid(String),
+}
+
+impl Spec {
+    /// One line synopsis, e.g. `ls [-al] [PATH...]`
+    pub fn usage(&self) -> String {
+        let mut parts = vec![self.name.to_string()];
+        let combined: String = self
+            .options
+            .iter()
+            .filter(|opt| !opt.help.is_empty() && opt.value.is_none() && !opt.plus)
+            .filter_map(|opt| opt.short)
+            .collect();
+        if !combined.is_empty() {
+            parts.push(format!("[-{combined}]"));
+        }
+        parts.extend(
+            self.options
+                .iter()
+                .filter(|opt| !opt.help.is_empty())
+                .filter(|opt| opt.value.is_some() || opt.plus || opt.short.is_none())
+                .map(Opt::synopsis),
+        );
+        if !self.operands.is_empty() {
+            parts.push(self.operands.to_string());
+        }
+        parts.join(" ")
+    }
+
+    pub fn help(&self) -> String {
+        let mut help = format!("{} {}\n", tr("Usage:"), self.usage());
+        if !self.about.is_empty() {
+            help.push_str(&format!("\n{}\n", tr(self.about)));
+        }
+        let mut rows: Vec<(String, String, String)> = self
+            .options
+            .iter()
+            .filter(|opt| !opt.help.is_empty())
+            .map(|opt| {
+                let short = match opt.short {
+                    Some(short) if opt.plus => format!("-{short}, +{short}, "),
+                    Some(short) => format!("-{short}, "),
+                    None => String::new(),
+                };
+                let value = opt
+                    .value
+                    .map(|value| format!(" {value}"))
+                    .unwrap_or_default();
+                (short, format!("--{}{value}", opt.long), tr(opt.help))
+            })
+            .collect();
+        rows.push((String::new(), String::from("--help"), tr("Print this help")));
+        let short_width = rows
+            .iter()
+            .map(|(short, _, _)| short.len())
+            .max()
+            .unwrap_or(0);
+        let long_width = rows
+            .iter()
+            .map(|(_, long, _)| long.len())
+            .max()
+            .unwrap_or(0);
+        help.push_str(&format!("\n{}\n", tr("Options:")));
+        for (short, long, description) in rows {
+            help.push_str(&format!(
+                "  {short:>short_width$}{long:<long_width$}  {description}\n"
+            ));
+        }
+        help
+    }
+
+    fn find(&self, short: char) -> Option<&Opt> {
+        self.options.iter().find(|opt| opt.short == Some(short))
+    }
+
+    /// Parses options up to the first operand or `--`, later arguments are
+    /// operands even if they start with `-`
+    pub fn try_parse(&self, args: &[String]) -> Result<Matches, ParseError> {
+        let mut matches = Matches::default();
+        let mut i = 0;
+        while let Some(arg) = args.get(i) {
+            if arg == "--" {
+                i += 1;
+                break;
+            }
+            if arg == "--help" {
+                return Err(ParseError::Help);
+            }
+
+            if let Some(long) = arg.strip_prefix("--") {
+                let (name, inline) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                let opt = self
+                    .options
+                    .iter()
+                    .find(|opt| opt.long == name)
+                    .ok_or_else(|| {
+                        ParseError::Invalid(format!("--{name}: {}", tr("invalid option")))
+                    })?;
+                let value = match (opt.value, inline) {
+                    (None, None) => None,
+                    (None, Some(_)) => {
+                        return Err(ParseError::Invalid(format!(
+                            "--{name}: {}",
+                            tr("option does not take an argument")
+                        )))
+                    }
+                    (Some(_), Some(value)) => Some(value.to_string()),
+                    (Some(_), None) => {
+                        i += 1;
+                        Some(args.get(i).cloned().ok_or_else(|| {
+                            ParseError::Invalid(format!(
+                                "--{name}: {}",
+                                tr("option requires an argument")
+                            ))
+                        })?)
+                    }
+                };
+                matches.occurrences.push(Occurrence {
+                    long: opt.long,
+                    enabled: true,
+                    value,
+                });
+                i += 1;
+                continue;
+            }
+
+            let (sign, shorts) = match arg.chars().next() {
+                Some(sign @ ('-' | '+')) => (sign, &arg[1..]),
+                _ => break,
+            };
+            let first = match shorts.chars().next() {
+                Some(first) => first,
+                // `-` alone usually means standard input or the previous directory
+                None => break,
+            };
+            // `+` is only special for options that can be turned off, so that
+            // `at +5m` works, and negative numbers are operands
+            if (sign == '+' && !matches!(self.find(first), Some(opt) if opt.plus))
+                || (first.is_ascii_digit() && self.find(first).is_none())
+            {
+                break;
+            }
+            for (pos, short) in shorts.char_indices() {
+                let opt = match self.find(short) {
+                    Some(opt) if sign == '-' || opt.plus => opt,
+                    _ => {
+                        return Err(ParseError::Invalid(format!(
+                            "{sign}{short}: {}",
+                            tr("invalid option")
+                        )))
+                    }
+                };
+                let value = if opt.value.is_some() {
+                    let rest = &shorts[pos + short.len_utf8()..];
+                    if rest.is_empty() {
+                        i += 1;
+                        Some(args.get(i).cloned().ok_or_else(|| {
+                            ParseError::Invalid(format!(
+                                "{sign}{short}: {}",
+                                tr("option requires an argument")
+                            ))
+                        })?)
+                    } else {
+                        Some(rest.to_string())
+                    }
+                } else {
+                    None
+                };
+                let takes_rest = value.is_some();
+                matches.occurrences.push(Occurrence {
+                    long: opt.long,
+                    enabled: sign == '-',
+                    value,
+                });
+                if takes_rest {
+                    break;
+                }
+            }
+            i += 1;
+        }
+        matches.operands = args[i..].to_vec();
+        Ok(matches)
+    }
+
+    /// Parses `args`, on `--help` prints help and on invalid options prints
+    /// usage, returning the exit status the internal should finish with
+    pub fn parse(&self, args: &[String], output_device: &mut OutputDevice) -> Result<Matches, i32> {
+        match self.try_parse(args) {
+            Ok(matches) => Ok(matches),
+            Err(ParseError::Help) => {
+                output_device.print(&self.help());
+                Err(EXIT_SUCCESS)
+            }
+            Err(ParseError::Invalid(error)) => {
+                output_device.eprintln(&format!("{}: {}", self.name, error));
+                self.print_usage(output_device);
+                Err(EXIT_FAILURE)
+            }
+        }
+    }
+
+    /// Prints usage in the form internals use to report invalid operands
+    pub fn print_usage(&self, output_device: &mut OutputDevice) {
+        output_device.eprintln(&format!("{}: help: {}", self.name, self.usage()));
+    }
+}
@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Evaluation of arithmetic expansions, e.g. `$((1 + 2 * x))`. Values are
+//! 64-bit integers wrapping on overflow, variables are read from and
+//! assigned to shell variables.
+
+use std::env;
+
+use conch_parser::ast::{
+    AndOrList, Arithmetic, Command, ComplexWord, ListableCommand, ParameterSubstitution,
+    PipeableCommand, RedirectOrCmdWord, SimpleWord, TopLevelCommand, TopLevelWord, Word,
+};
+
+use crate::shell_base::Shell;
+use crate::syntax;
+
+type Expr = Arithmetic<String>;
+
+/// Value of a variable, unset and empty variables are 0
+fn var(shell: &Shell, name: &str) -> Result<i64, String> {
+    let value = match shell.dynamic_var(name) {
+        Some(value) => value,
+        None => match shell.vars.get(name) {
+            Some(value) => value.clone(),
+            None => env::var(name).unwrap_or_default(),
+        },
+    };
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(0);
+    }
+    parse_number(value).ok_or_else(|| format!("{value}: invalid arithmetic operand"))
+}
+
+/// Decimal, `0x` hexadecimal or `0` octal integer, optionally signed
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text.strip_prefix('+').unwrap_or(text)),
+    };
+    let value = if let Some(hex) = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()? as i64
+    } else if digits.len() > 1 && digits.starts_with('0') {
+        u64::from_str_radix(&digits[1..], 8).ok()? as i64
+    } else {
+        digits.parse::<u64>().ok()? as i64
+    };
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn assign(shell: &mut Shell, name: &str, value: i64) -> Result<i64, String> {
+    shell.set_var(name, value.to_string())?;
+    Ok(value)
+}
+
+fn truth(value: bool) -> i64 {
+    value as i64
+}
+
+/// Evaluates the expression in `text`, e.g. an array subscript
+pub fn eval_text(shell: &mut Shell, text: &str) -> Result<i64, String> {
+    let invalid = || format!("{text}: invalid arithmetic expression");
+    let commands = syntax::parse(&format!("$(({text}))")).map_err(|_| invalid())?;
+    let expr = match commands.as_slice() {
+        [TopLevelCommand(Command::List(AndOrList {
+            first: ListableCommand::Single(PipeableCommand::Simple(simple)),
+            rest,
+        }))] if rest.is_empty() => match simple.redirects_or_cmd_words.as_slice() {
+            [RedirectOrCmdWord::CmdWord(TopLevelWord(ComplexWord::Single(Word::Simple(
+                SimpleWord::Subst(subst),
+            ))))] => match subst.as_ref() {
+                ParameterSubstitution::Arith(expr) => expr.clone(),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    match expr {
+        Some(expr) => eval(shell, &expr),
+        None => Ok(0),
+    }
+}
+
+/// Evaluates `expr`, assignments like `i++` or `x = 2` update shell
+/// variables
+pub fn eval(shell: &mut Shell, expr: &Expr) -> Result<i64, String> {
+    let mut binary = |lhs: &Expr, rhs: &Expr, op: fn(i64, i64) -> Result<i64, String>| {
+        let lhs = eval(shell, lhs)?;
+        let rhs = eval(shell, rhs)?;
+        op(lhs, rhs)
+    };
+    Ok(match expr {
+        Arithmetic::Literal(value) => *value as i64,
+        Arithmetic::Var(name) => var(shell, name)?,
+        Arithmetic::PostIncr(name) => {
+            let value = var(shell, name)?;
+            assign(shell, name, value.wrapping_add(1))?;
+            value
+        }
+        Arithmetic::PostDecr(name) => {
+            let value = var(shell, name)?;
+            assign(shell, name, value.wrapping_sub(1))?;
+            value
+        }
+        Arithmetic::PreIncr(name) => {
+            let value = var(shell, name)?.wrapping_add(1);
+            assign(shell, name, value)?
+        }
+        Arithmetic::PreDecr(name) => {
+            let value = var(shell, name)?.wrapping_sub(1);
+            assign(shell, name, value)?
+        }
+        Arithmetic::UnaryPlus(operand) => eval(shell, operand)?,
+        Arithmetic::UnaryMinus(operand) => eval(shell, operand)?.wrapping_neg(),
+        Arithmetic::LogicalNot(operand) => truth(eval(shell, operand)? == 0),
+        Arithmetic::BitwiseNot(operand) => !eval(shell, operand)?,
+        Arithmetic::Pow(lhs, rhs) => binary(lhs, rhs, |base, exp| {
+            if exp < 0 {
+                return Err(String::from("exponent less than 0"));
+            }
+            Ok(base.wrapping_pow(exp.min(u32::MAX as i64) as u32))
+        })?,
+        Arithmetic::Mult(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a.wrapping_mul(b)))?,
+        Arithmetic::Div(lhs, rhs) => binary(lhs, rhs, |a, b| match b {
+            0 => Err(String::from("division by 0")),
+            _ => Ok(a.wrapping_div(b)),
+        })?,
+        Arithmetic::Modulo(lhs, rhs) => binary(lhs, rhs, |a, b| match b {
+            0 => Err(String::from("division by 0")),
+            _ => Ok(a.wrapping_rem(b)),
+        })?,
+        Arithmetic::Add(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a.wrapping_add(b)))?,
+        Arithmetic::Sub(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a.wrapping_sub(b)))?,
+        Arithmetic::ShiftLeft(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a.wrapping_shl(b as u32)))?,
+        Arithmetic::ShiftRight(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a.wrapping_shr(b as u32)))?,
+        Arithmetic::Less(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a < b)))?,
+        Arithmetic::LessEq(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a <= b)))?,
+        Arithmetic::Great(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a > b)))?,
+        Arithmetic::GreatEq(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a >= b)))?,
+        Arithmetic::Eq(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a == b)))?,
+        Arithmetic::NotEq(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(truth(a != b)))?,
+        Arithmetic::BitwiseAnd(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a & b))?,
+        Arithmetic::BitwiseXor(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a ^ b))?,
+        Arithmetic::BitwiseOr(lhs, rhs) => binary(lhs, rhs, |a, b| Ok(a | b))?,
+        // `&&`, `||` and `?:` only evaluate the operands they need
+        Arithmetic::LogicalAnd(lhs, rhs) => truth(eval(shell, lhs)? != 0 && eval(shell, rhs)? != 0),
+        Arithmetic::LogicalOr(lhs, rhs) => truth(eval(shell, lhs)? != 0 || eval(shell, rhs)? != 0),
+        Arithmetic::Ternary(condition, then, otherwise) => {
+            if eval(shell, condition)? != 0 {
+                eval(shell, then)?
+            } else {
+                eval(shell, otherwise)?
+            }
+        }
+        Arithmetic::Assign(name, value) => {
+            let value = eval(shell, value)?;
+            assign(shell, name, value)?
+        }
+        Arithmetic::Sequence(exprs) => {
+            let mut value = 0;
+            for expr in exprs {
+                value = eval(shell, expr)?;
+            }
+            value
+        }
+    })
+}
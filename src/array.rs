@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+
+/// Array variable, e.g. `arr=(a b c)`
+#[derive(Debug, Clone)]
+pub enum Array {
+    /// Elements may be sparse, `arr[10]=x` does not create the elements
+    /// before it
+    Indexed(BTreeMap<usize, String>),
+    /// String keyed map declared with `declare -A`
+    Associative(BTreeMap<String, String>),
+}
+
+/// Element of an array, subscripts of associative arrays are not evaluated
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subscript {
+    Index(i64),
+    Key(String),
+}
+
+impl Default for Array {
+    fn default() -> Self {
+        Array::Indexed(BTreeMap::new())
+    }
+}
+
+impl Array {
+    pub fn from_values(values: Vec<String>) -> Self {
+        Array::Indexed(values.into_iter().enumerate().collect())
+    }
+
+    pub fn associative() -> Self {
+        Array::Associative(BTreeMap::new())
+    }
+
+    pub fn is_associative(&self) -> bool {
+        matches!(self, Array::Associative(_))
+    }
+
+    /// Position of `index` in an indexed array, negative indices count back
+    /// from the element after the last one
+    fn position(elements: &BTreeMap<usize, String>, index: i64) -> Option<usize> {
+        match usize::try_from(index) {
+            Ok(position) => Some(position),
+            Err(_) => Self::end(elements)?.checked_sub(usize::try_from(index.unsigned_abs()).ok()?),
+        }
+    }
+
+    /// Index after the last element of an indexed array, `None` when the
+    /// last index is the largest one
+    fn end(elements: &BTreeMap<usize, String>) -> Option<usize> {
+        match elements.keys().next_back() {
+            Some(last) => last.checked_add(1),
+            None => Some(0),
+        }
+    }
+
+    pub fn get(&self, subscript: &Subscript) -> Option<&String> {
+        match (self, subscript) {
+            (Array::Indexed(elements), Subscript::Index(index)) => {
+                elements.get(&Self::position(elements, *index)?)
+            }
+            (Array::Indexed(_), Subscript::Key(_)) => None,
+            (Array::Associative(elements), Subscript::Index(index)) => {
+                elements.get(&index.to_string())
+            }
+            (Array::Associative(elements), Subscript::Key(key)) => elements.get(key),
+        }
+    }
+
+    /// Sets an element, fails for negative indices before the first element
+    pub fn set(&mut self, subscript: Subscript, value: String) -> Result<(), String> {
+        match (self, subscript) {
+            (Array::Indexed(elements), Subscript::Index(index)) => {
+                let position = Self::position(elements, index)
+                    .ok_or_else(|| format!("[{index}]: bad array subscript"))?;
+                elements.insert(position, value);
+            }
+            (Array::Indexed(_), Subscript::Key(key)) => {
+                return Err(format!("[{key}]: bad array subscript"))
+            }
+            (Array::Associative(elements), Subscript::Index(index)) => {
+                elements.insert(index.to_string(), value);
+            }
+            (Array::Associative(elements), Subscript::Key(key)) => {
+                elements.insert(key, value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds `values` after the last element, associative arrays have no
+    /// order to add them in
+    pub fn append(&mut self, values: Vec<String>) -> Result<(), String> {
+        match self {
+            Array::Indexed(_) if values.is_empty() => Ok(()),
+            Array::Indexed(elements) => {
+                let end = Self::end(elements)
+                    .filter(|end| end.checked_add(values.len()).is_some())
+                    .ok_or_else(|| String::from("array index out of range"))?;
+                elements.extend(
+                    values
+                        .into_iter()
+                        .enumerate()
+                        .map(|(i, value)| (end + i, value)),
+                );
+                Ok(())
+            }
+            Array::Associative(_) if values.is_empty() => Ok(()),
+            Array::Associative(_) => Err(format!(
+                "{}: must use subscript when assigning associative array",
+                values[0]
+            )),
+        }
+    }
+
+    pub fn values(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.values().cloned().collect(),
+            Array::Associative(elements) => elements.values().cloned().collect(),
+        }
+    }
+
+    /// Indices or keys of the set elements, `${!name[@]}`
+    pub fn keys(&self) -> Vec<String> {
+        match self {
+            Array::Indexed(elements) => elements.keys().map(usize::to_string).collect(),
+            Array::Associative(elements) => elements.keys().cloned().collect(),
+        }
+    }
+
+    /// Number of set elements
+    pub fn len(&self) -> usize {
+        match self {
+            Array::Indexed(elements) => elements.len(),
+            Array::Associative(elements) => elements.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -4,7 +4,7 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::env;
 use std::io;
 use std::io::Read;
@@ -13,7 +13,10 @@ use std::process;
 
 use clap::{Arg, ArgAction, Command};
 
-use wash::shell_base::{is_fd_tty, Fd};
+use wash::internals::INTERNALS_MAP;
+use wash::logging;
+use wash::profiling::StartupProfile;
+use wash::shell_base::{bootstrap_env, is_fd_tty, Fd};
 use wash::Shell;
 
 const STDIN: Fd = 0;
@@ -28,6 +31,52 @@ fn main() {
         path.set_extension("");
         path.file_name().unwrap().to_str().unwrap().to_string()
     };
+
+    let pwd;
+    let should_echo = true;
+
+    #[cfg(target_os = "wasi")]
+    {
+        let _ = wasi_ext_lib::chdir(match wasi_ext_lib::getcwd() {
+            Ok(p) => {
+                pwd = p;
+                &pwd
+            }
+            Err(e) => {
+                eprintln!("Could not obtain current working dir path (error {e})");
+                pwd = String::from("/");
+                &pwd
+            }
+        });
+    }
+    #[cfg(not(target_os = "wasi"))]
+    {
+        if let Ok(cwd) = env::current_dir() {
+            pwd = cwd.display().to_string();
+        } else {
+            pwd = String::from("/");
+        }
+    }
+
+    bootstrap_env(&pwd);
+    logging::init();
+
+    // multi-call binary: invoked through a link named after an internal, e.g.
+    // `ls` -> wash, runs just that internal
+    if name != env!("CARGO_PKG_NAME") && INTERNALS_MAP.contains_key(name.as_str()) {
+        let mut shell = Shell::new(should_echo, &pwd, VecDeque::new());
+        shell.apply_config();
+        let mut args: Vec<String> = env::args().skip(1).collect();
+        let exit_code = match shell.execute_command(&name, &mut args, &HashMap::new(), false, &[]) {
+            Ok(exit_code) => exit_code,
+            Err(e) => {
+                eprintln!("{}: error occurred: {}", name, e);
+                2
+            }
+        };
+        process::exit(exit_code);
+    }
+
     let version_short = format!(
         "{}-{} ({})\nCopyright (c) 2021-{} Antmicro <www.antmicro.com>",
         env!("CARGO_PKG_VERSION"),
@@ -66,6 +115,26 @@ fn main() {
                 .long("command")
                 .value_name("COMMAND")
                 .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("dap-fd")
+                .help("Debug FILE with Debug Adapter Protocol client connected on FD")
+                .long("dap-fd")
+                .value_name("FD")
+                .value_parser(clap::value_parser!(Fd))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("profile")
+                .help("Print per-command timings and invocation counts at exit")
+                .long("profile")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("profile-startup")
+                .help("Print timing breakdown of startup")
+                .long("profile-startup")
+                .action(ArgAction::SetTrue),
         );
 
     // Run CLI parser to find script argument only
@@ -86,39 +155,6 @@ fn main() {
 
     let matches = cli.get_matches_from(wash_args);
 
-    let pwd;
-    let should_echo = true;
-
-    #[cfg(target_os = "wasi")]
-    {
-        let _ = wasi_ext_lib::chdir(match wasi_ext_lib::getcwd() {
-            Ok(p) => {
-                pwd = p;
-                &pwd
-            }
-            Err(e) => {
-                eprintln!("Could not obtain current working dir path (error {e})");
-                pwd = String::from("/");
-                &pwd
-            }
-        });
-    }
-    #[cfg(not(target_os = "wasi"))]
-    {
-        if let Ok(cwd) = env::current_dir() {
-            pwd = cwd.display().to_string();
-        } else {
-            pwd = String::from("/");
-        }
-    }
-
-    if env::var("PWD").is_err() {
-        env::set_var("PWD", &pwd);
-    }
-    if env::var("HOME").is_err() {
-        env::set_var("HOME", "/");
-    }
-
     let script: String;
     let len: usize;
     let mut shell = Shell::new(
@@ -136,10 +172,21 @@ fn main() {
         },
     );
 
+    if matches.get_flag("profile-startup") {
+        shell.startup_profile = Some(StartupProfile::new());
+    }
+    shell.profile.enabled = matches.get_flag("profile");
+
     let result = if let Some(command) = matches.get_one::<String>("command") {
+        shell.report_startup_profile("first command");
         shell.run_command(command)
     } else if len != 0 {
-        shell.run_script(PathBuf::from(script))
+        shell.report_startup_profile("first command");
+        if let Some(fd) = matches.get_one::<Fd>("dap-fd") {
+            shell.debug_script(PathBuf::from(script), *fd)
+        } else {
+            shell.run_script(PathBuf::from(script))
+        }
     } else {
         match is_fd_tty(STDIN) {
             Err(_) => {
@@ -170,6 +217,7 @@ fn main() {
                 let mut input = String::new();
                 let stdin = io::stdin();
                 stdin.lock().read_to_string(&mut input).unwrap();
+                shell.report_startup_profile("first command");
                 shell.run_command(&input)
             }
         }
@@ -183,5 +231,5 @@ fn main() {
         }
     };
 
-    process::exit(exit_code);
+    shell.exit(exit_code);
 }
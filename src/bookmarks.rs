@@ -0,0 +1,80 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::xdg;
+
+/// Named directories used as `cd @NAME`, stored as `NAME<TAB>PATH` lines
+#[derive(Debug)]
+pub struct Bookmarks {
+    path: PathBuf,
+    entries: BTreeMap<String, PathBuf>,
+}
+
+/// Bookmark names cannot contain path separators or whitespace so `@NAME/sub`
+/// stays unambiguous
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(|c: char| c == '/' || c.is_whitespace())
+}
+
+impl Bookmarks {
+    pub fn load() -> Bookmarks {
+        let path = xdg::state_dir().join("bookmarks");
+        let entries = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .map(|(name, dir)| (name.to_string(), PathBuf::from(dir)))
+            .collect();
+        Bookmarks { path, entries }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content: String = self
+            .entries
+            .iter()
+            .map(|(name, dir)| format!("{}\t{}\n", name, dir.display()))
+            .collect();
+        xdg::write_atomic(&self.path, content.as_bytes())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Path> {
+        self.entries.get(name).map(PathBuf::as_path)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Path)> {
+        self.entries
+            .iter()
+            .map(|(name, dir)| (name.as_str(), dir.as_path()))
+    }
+
+    pub fn add(&mut self, name: &str, dir: &Path) -> io::Result<()> {
+        self.entries.insert(name.to_string(), dir.to_path_buf());
+        self.save()
+    }
+
+    /// Returns whether the bookmark existed
+    pub fn remove(&mut self, name: &str) -> io::Result<bool> {
+        if self.entries.remove(name).is_none() {
+            return Ok(false);
+        }
+        self.save().map(|_| true)
+    }
+
+    /// Expands `@NAME` or `@NAME/rest` to the bookmarked directory
+    pub fn expand(&self, target: &str) -> Option<PathBuf> {
+        let target = target.strip_prefix('@')?;
+        let (name, rest) = target.split_once('/').unwrap_or((target, ""));
+        self.get(name).map(|dir| dir.join(rest))
+    }
+}
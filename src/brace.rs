@@ -0,0 +1,249 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Brace expansion of words, e.g. `file.{c,h}` to `file.c file.h` and
+//! `dir{1..3}` to `dir1 dir2 dir3`. It runs before pathname expansion and
+//! only considers braces and commas that are not quoted.
+
+use std::convert::TryFrom;
+
+use crate::limits::ResourceGuard;
+
+/// Part of a word, text from quotes and parameter expansions is not `active`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Piece {
+    /// Text of the part as it ends up in the word
+    pub value: String,
+    /// Text of the part as a glob pattern
+    pub pattern: String,
+    pub active: bool,
+    /// Unquoted result of an expansion, subject to field splitting
+    pub split: bool,
+    /// Quoted or escaped in the word, matched literally as a regex
+    pub quoted: bool,
+}
+
+impl Piece {
+    fn literal(value: String) -> Piece {
+        Piece {
+            pattern: glob::Pattern::escape(&value),
+            value,
+            active: false,
+            split: false,
+            quoted: false,
+        }
+    }
+
+    /// Unquoted text that is matched as a glob pattern
+    pub fn unquoted(value: String) -> Piece {
+        Piece {
+            pattern: value.clone(),
+            value,
+            active: false,
+            split: false,
+            quoted: false,
+        }
+    }
+
+    fn syntax(&self) -> Option<char> {
+        match (self.active, self.value.as_str()) {
+            (true, "{") => Some('{'),
+            (true, ",") => Some(','),
+            (true, "}") => Some('}'),
+            _ => None,
+        }
+    }
+}
+
+/// Words resulting from brace expansion of `pieces`, just `pieces` when
+/// there is nothing to expand. Fails when the words would exceed the
+/// expansion size limit of `guard`.
+pub fn expand(pieces: Vec<Piece>, guard: &ResourceGuard) -> Result<Vec<Vec<Piece>>, String> {
+    let mut chars = Vec::new();
+    for piece in pieces {
+        if piece.active {
+            chars.extend(piece.value.chars().map(|c| Piece {
+                value: c.to_string(),
+                pattern: c.to_string(),
+                active: true,
+                split: false,
+                quoted: false,
+            }));
+        } else {
+            chars.push(piece);
+        }
+    }
+    let mut words = Vec::new();
+    expand_chars(chars, guard, &mut words, &mut 0)?;
+    Ok(words)
+}
+
+/// Appends words resulting from `pieces` to `words`, `size` counts their
+/// bytes including a separator each
+fn expand_chars(
+    pieces: Vec<Piece>,
+    guard: &ResourceGuard,
+    words: &mut Vec<Vec<Piece>>,
+    size: &mut usize,
+) -> Result<(), String> {
+    for start in 0..pieces.len() {
+        if pieces[start].syntax() != Some('{') {
+            continue;
+        }
+        let (end, commas) = match closing_brace(&pieces, start) {
+            Some(found) => found,
+            None => continue,
+        };
+        let alternatives = if commas.is_empty() {
+            match sequence(&pieces[start + 1..end]) {
+                Some(sequence) => {
+                    // every item takes at least a byte
+                    let count = usize::try_from(sequence.len()).unwrap_or(usize::MAX);
+                    guard.check_expansion(size.saturating_add(count))?;
+                    sequence
+                        .items()
+                        .map(|item| vec![Piece::literal(item)])
+                        .collect()
+                }
+                None => continue,
+            }
+        } else {
+            let mut bounds = vec![start];
+            bounds.extend(commas);
+            bounds.push(end);
+            bounds
+                .windows(2)
+                .map(|bound| pieces[bound[0] + 1..bound[1]].to_vec())
+                .collect::<Vec<_>>()
+        };
+        // the suffix and nested braces are expanded by recursion
+        for alternative in alternatives {
+            let mut word = pieces[..start].to_vec();
+            word.extend(alternative);
+            word.extend_from_slice(&pieces[end + 1..]);
+            expand_chars(word, guard, words, size)?;
+        }
+        return Ok(());
+    }
+    *size += pieces.iter().map(|piece| piece.value.len()).sum::<usize>() + 1;
+    guard.check_expansion(*size)?;
+    words.push(pieces);
+    Ok(())
+}
+
+/// Index of the `}` closing the `{` at `start` and indices of commas
+/// separating its top-level alternatives
+fn closing_brace(pieces: &[Piece], start: usize) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = Vec::new();
+    for (i, piece) in pieces.iter().enumerate().skip(start + 1) {
+        match piece.syntax() {
+            Some('{') => depth += 1,
+            Some('}') if depth == 0 => return Some((i, commas)),
+            Some('}') => depth -= 1,
+            Some(',') if depth == 0 => commas.push(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Sequence expression like `1..5`, `a..e` or `0..10..2`
+struct Sequence {
+    from: i64,
+    to: i64,
+    step: u64,
+    /// Width numbers are zero padded to, 0 when they are not
+    width: usize,
+    letters: bool,
+}
+
+impl Sequence {
+    /// Number of items
+    fn len(&self) -> u128 {
+        (self.to as i128 - self.from as i128).unsigned_abs() / self.step as u128 + 1
+    }
+
+    /// `from` to `to` inclusive, counting down when `to` is smaller
+    fn items(&self) -> impl Iterator<Item = String> + '_ {
+        let mut next = Some(self.from);
+        std::iter::from_fn(move || {
+            let value = next?;
+            next = if self.from <= self.to {
+                value
+                    .checked_add_unsigned(self.step)
+                    .filter(|next| *next <= self.to)
+            } else {
+                value
+                    .checked_sub_unsigned(self.step)
+                    .filter(|next| *next >= self.to)
+            };
+            Some(value)
+        })
+        .map(move |value| {
+            if self.letters {
+                (value as u8 as char).to_string()
+            } else if value < 0 {
+                format!(
+                    "-{:0width$}",
+                    value.unsigned_abs(),
+                    width = self.width.saturating_sub(1)
+                )
+            } else {
+                format!("{:0width$}", value, width = self.width)
+            }
+        })
+    }
+}
+
+/// Sequence given by `pieces`, zero padded like `01..10` when either end is
+fn sequence(pieces: &[Piece]) -> Option<Sequence> {
+    if !pieces.iter().all(|piece| piece.active) {
+        return None;
+    }
+    let text: String = pieces.iter().map(|piece| piece.value.as_str()).collect();
+    let parts: Vec<&str> = text.split("..").collect();
+    let (first, last, step) = match parts.as_slice() {
+        [first, last] => (*first, *last, 1),
+        [first, last, step] => (*first, *last, step.parse::<i64>().ok()?.unsigned_abs()),
+        _ => return None,
+    };
+    let step = step.max(1);
+
+    if let (Ok(from), Ok(to)) = (first.parse::<i64>(), last.parse::<i64>()) {
+        let padded = |end: &str| {
+            end.trim_start_matches('-').len() > 1 && end.trim_start_matches('-').starts_with('0')
+        };
+        let width = if padded(first) || padded(last) {
+            first.len().max(last.len())
+        } else {
+            0
+        };
+        return Some(Sequence {
+            from,
+            to,
+            step,
+            width,
+            letters: false,
+        });
+    }
+
+    let mut ends = (first.chars(), last.chars());
+    match (ends.0.next(), ends.0.next(), ends.1.next(), ends.1.next()) {
+        (Some(from), None, Some(to), None)
+            if from.is_ascii_alphabetic() && to.is_ascii_alphabetic() =>
+        {
+            Some(Sequence {
+                from: from as i64,
+                to: to as i64,
+                step,
+                width: 0,
+                letters: true,
+            })
+        }
+        _ => None,
+    }
+}
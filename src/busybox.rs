@@ -0,0 +1,1141 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Minimal file and text utilities available as internals so that an image
+//! containing only wash is usable before any other tools are installed.
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::Report;
+use regex::bytes::Regex;
+
+use crate::argparse::{Opt, Spec};
+use crate::locale;
+use crate::output_device::OutputDevice;
+use crate::shell_base::{Shell, EXIT_FAILURE, EXIT_INTERRUPTED, EXIT_SUCCESS, STDERR};
+
+fn report(name: &str, path: &Path, error: io::Error, output_device: &mut OutputDevice) {
+    let path = output_device.file_link(STDERR, path);
+    output_device.eprintln(&format!("{name}: {path}: {}", locale::io_error(&error)));
+}
+
+fn describe_entry(path: &Path, name: &str, long: bool) -> String {
+    if !long {
+        return name.to_string();
+    }
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => {
+            let target = fs::read_link(path).unwrap_or_default();
+            format!("l {:>10} {} -> {}", metadata.len(), name, target.display())
+        }
+        Ok(metadata) => {
+            let kind = if metadata.is_dir() { 'd' } else { '-' };
+            format!("{} {:>10} {}", kind, metadata.len(), name)
+        }
+        Err(_) => format!("? {:>10} {}", "?", name),
+    }
+}
+
+pub fn ls(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "ls",
+        about: "List directory contents, one entry per line",
+        operands: "[PATH...]",
+        options: &[
+            Opt::flag('a', "all", "Include entries starting with ."),
+            Opt::flag('l', "long", "Show entry types and sizes"),
+            // accepted for compatibility, entries are always listed one per line
+            Opt::flag('1', "one-per-line", ""),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let all = matches.flag("all");
+    let long = matches.flag("long");
+    let operands = if matches.operands.is_empty() {
+        vec![".".to_string()]
+    } else {
+        matches.operands
+    };
+
+    let mut exit_status = EXIT_SUCCESS;
+    for (i, operand) in operands.iter().enumerate() {
+        let path = Path::new(operand);
+        if !path.is_dir() {
+            if fs::symlink_metadata(path).is_err() {
+                report("ls", path, io::ErrorKind::NotFound.into(), output_device);
+                exit_status = EXIT_FAILURE;
+            } else {
+                output_device.println(&describe_entry(path, operand, long));
+            }
+            continue;
+        }
+        let mut entries: Vec<(String, PathBuf)> = match fs::read_dir(path) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| {
+                    (
+                        entry.file_name().to_string_lossy().into_owned(),
+                        entry.path(),
+                    )
+                })
+                .filter(|(name, _)| all || !name.starts_with('.'))
+                .collect(),
+            Err(error) => {
+                report("ls", path, error, output_device);
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+        };
+        entries.sort();
+        if operands.len() > 1 {
+            if i > 0 {
+                output_device.println("");
+            }
+            output_device.println(&format!("{operand}:"));
+        }
+        for (name, path) in entries {
+            output_device.println(&describe_entry(&path, &name, long));
+        }
+    }
+    Ok(exit_status)
+}
+
+pub fn cat(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "cat",
+        about: "Print contents of files, standard input without them or for -",
+        operands: "[FILE]...",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let operands = if matches.operands.is_empty() {
+        vec![String::from("-")]
+    } else {
+        matches.operands
+    };
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in &operands {
+        let result = output_device
+            .open_input(operand)
+            .and_then(|mut reader| copy_to_output(&mut reader, output_device));
+        if let Err(error) = result {
+            report("cat", Path::new(operand), error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+/// Size of chunks in which internals stream their input
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Calls `consume` with consecutive chunks of `reader` until it is exhausted
+fn read_chunks(
+    reader: &mut dyn Read,
+    mut consume: impl FnMut(&[u8]) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut buffer = vec![0; CHUNK_SIZE];
+    loop {
+        match reader.read(&mut buffer) {
+            Ok(0) => return Ok(()),
+            Ok(read) => consume(&buffer[..read])?,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// Copies `reader` to standard output unchanged, writing it out chunk by
+/// chunk rather than keeping all of it in memory
+fn copy_to_output(reader: &mut dyn Read, output_device: &mut OutputDevice) -> io::Result<()> {
+    read_chunks(reader, |chunk| {
+        output_device.write_bytes(chunk);
+        output_device
+            .flush()
+            .map_err(|error| io::Error::other(error.to_string()))
+    })
+}
+
+fn copy_recursive(source: &Path, target: &Path) -> io::Result<()> {
+    if source.is_dir() {
+        fs::create_dir_all(target)?;
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &target.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(source, target).map(|_| ())
+    }
+}
+
+/// Absolute `path` with symlinks resolved, also when only its parent exists
+fn resolve(path: &Path) -> io::Result<PathBuf> {
+    match fs::canonicalize(path) {
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            let name = match path.file_name() {
+                Some(name) => name,
+                None => return Err(error),
+            };
+            let parent = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent,
+                _ => Path::new("."),
+            };
+            Ok(fs::canonicalize(parent)?.join(name))
+        }
+        result => result,
+    }
+}
+
+/// Fails for copies that would destroy their source, onto itself or of a
+/// directory into itself
+fn check_transfer(source: &Path, destination: &Path) -> io::Result<()> {
+    let source = fs::canonicalize(source)?;
+    let destination = resolve(destination)?;
+    if source == destination {
+        Err(io::Error::other("source and destination are the same file"))
+    } else if source.is_dir() && destination.starts_with(&source) {
+        Err(io::Error::other("cannot copy a directory into itself"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Pairs of source and destination paths for `cp` and `mv`, copying into the
+/// last operand when it is a directory
+fn transfer_targets(operands: &[String]) -> Option<Vec<(PathBuf, PathBuf)>> {
+    let (target, sources) = operands.split_last()?;
+    let target = Path::new(target);
+    if sources.is_empty() || (sources.len() > 1 && !target.is_dir()) {
+        return None;
+    }
+    Some(
+        sources
+            .iter()
+            .map(|source| {
+                let source = PathBuf::from(source);
+                let destination = match source.file_name() {
+                    Some(name) if target.is_dir() => target.join(name),
+                    _ => target.to_path_buf(),
+                };
+                (source, destination)
+            })
+            .collect(),
+    )
+}
+
+pub fn cp(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "cp",
+        about: "Copy files, into DEST if it is a directory",
+        operands: "<SOURCE>... <DEST>",
+        options: &[
+            Opt::flag('r', "recursive", "Copy directories recursively"),
+            Opt::flag('R', "recursive", ""),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let targets = match transfer_targets(&matches.operands) {
+        Some(targets) => targets,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let recursive = matches.flag("recursive");
+    let mut exit_status = EXIT_SUCCESS;
+    for (source, destination) in targets {
+        let result = if source.is_dir() && !recursive {
+            Err(io::Error::other("Is a directory (use -r to copy it)"))
+        } else {
+            check_transfer(&source, &destination)
+                .and_then(|()| copy_recursive(&source, &destination))
+        };
+        if let Err(error) = result {
+            report("cp", &source, error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+pub fn mv(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "mv",
+        about: "Move or rename files, into DEST if it is a directory",
+        operands: "<SOURCE>... <DEST>",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let targets = match transfer_targets(&matches.operands) {
+        Some(targets) => targets,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let mut exit_status = EXIT_SUCCESS;
+    for (source, destination) in targets {
+        let result = match fs::rename(&source, &destination) {
+            // rename cannot move between file systems
+            Err(error) if error.kind() == io::ErrorKind::CrossesDevices => {
+                check_transfer(&source, &destination)
+                    .and_then(|()| copy_recursive(&source, &destination))
+                    .and_then(|()| {
+                        if source.is_dir() {
+                            fs::remove_dir_all(&source)
+                        } else {
+                            fs::remove_file(&source)
+                        }
+                    })
+            }
+            result => result,
+        };
+        if let Err(error) = result {
+            report("mv", &source, error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+pub fn rm(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "rm",
+        about: "Remove files and directories",
+        operands: "<PATH>...",
+        options: &[
+            Opt::flag('r', "recursive", "Remove directories and their contents"),
+            Opt::flag('R', "recursive", ""),
+            Opt::flag('f', "force", "Ignore missing files"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let recursive = matches.flag("recursive");
+    let force = matches.flag("force");
+    if matches.operands.is_empty() && !force {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in &matches.operands {
+        let path = Path::new(operand);
+        let result = match fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() && recursive => fs::remove_dir_all(path),
+            Ok(metadata) if metadata.is_dir() => {
+                Err(io::Error::other("Is a directory (use -r to remove it)"))
+            }
+            Ok(_) => fs::remove_file(path),
+            Err(error) => Err(error),
+        };
+        match result {
+            Err(error) if !(force && error.kind() == io::ErrorKind::NotFound) => {
+                report("rm", path, error, output_device);
+                exit_status = EXIT_FAILURE;
+            }
+            _ => {}
+        }
+    }
+    Ok(exit_status)
+}
+
+pub fn mkdir(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "mkdir",
+        about: "Create directories",
+        operands: "<DIR>...",
+        options: &[Opt::flag(
+            'p',
+            "parents",
+            "Create missing parents, existing directories are not an error",
+        )],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in &matches.operands {
+        let result = if matches.flag("parents") {
+            fs::create_dir_all(operand)
+        } else {
+            fs::create_dir(operand)
+        };
+        if let Err(error) = result {
+            report("mkdir", Path::new(operand), error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+pub fn touch(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "touch",
+        about: "Create files or update their modification time",
+        operands: "<FILE>...",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in &matches.operands {
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(operand)
+            .and_then(|file| file.set_modified(SystemTime::now()));
+        if let Err(error) = result {
+            report("touch", Path::new(operand), error, output_device);
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+/// FILE operands, standard input when there are none
+fn input_operands(operands: &[String]) -> Vec<String> {
+    if operands.is_empty() {
+        vec![String::from("-")]
+    } else {
+        operands.to_vec()
+    }
+}
+
+fn display_name(operand: &str) -> &str {
+    if operand == "-" {
+        "standard input"
+    } else {
+        operand
+    }
+}
+
+/// Lines of `reader` as bytes ending with their `\n`, so that binary data and
+/// `\r\n` line endings are printed unchanged
+fn lines(mut reader: Box<dyn BufRead>) -> impl Iterator<Item = io::Result<Vec<u8>>> {
+    std::iter::from_fn(move || {
+        let mut line = Vec::new();
+        match reader.read_until(b'\n', &mut line) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(line)),
+            Err(error) => Some(Err(error)),
+        }
+    })
+}
+
+/// `==> FILE <==` separating output of several files in `head` and `tail`
+fn print_header(operand: &str, first: bool, output_device: &mut OutputDevice) {
+    if !first {
+        output_device.println("");
+    }
+    output_device.println(&format!("==> {} <==", display_name(operand)));
+}
+
+pub fn head(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "head",
+        about: "Print the first lines of files or standard input",
+        operands: "[FILE...]",
+        options: &[Opt::value(
+            'n',
+            "lines",
+            "NUM",
+            "Number of lines to print, 10 by default",
+        )],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let count = match matches.value("lines").map(str::parse::<usize>) {
+        None => 10,
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+
+    let operands = input_operands(&matches.operands);
+    let mut exit_status = EXIT_SUCCESS;
+    for (i, operand) in operands.iter().enumerate() {
+        let reader = match output_device.open_input(operand) {
+            Ok(reader) => reader,
+            Err(error) => {
+                report("head", Path::new(operand), error, output_device);
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+        };
+        if operands.len() > 1 {
+            print_header(operand, i == 0, output_device);
+        }
+        // stop reading as soon as enough lines were printed
+        for line in lines(reader).take(count) {
+            match line {
+                Ok(line) => output_device.write_bytes(&line),
+                Err(error) => {
+                    report("head", Path::new(operand), error, output_device);
+                    exit_status = EXIT_FAILURE;
+                    break;
+                }
+            }
+        }
+    }
+    Ok(exit_status)
+}
+
+/// Prints data appended to `files` until the shell is interrupted
+fn follow(
+    shell: &mut Shell,
+    mut files: Vec<(String, File)>,
+    interval: Duration,
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    let mut last_printed = files.last().map(|(operand, _)| operand.clone());
+    loop {
+        output_device.flush()?;
+        thread::sleep(interval);
+        if shell.interrupted() {
+            return Ok(EXIT_INTERRUPTED);
+        }
+        for (operand, file) in files.iter_mut() {
+            let position = file.stream_position()?;
+            // start over when the file was truncated, e.g. by log rotation
+            if file.metadata().map(|metadata| metadata.len() < position)? {
+                file.seek(SeekFrom::Start(0))?;
+            }
+            let mut appended = Vec::new();
+            file.read_to_end(&mut appended)?;
+            if appended.is_empty() {
+                continue;
+            }
+            if last_printed.as_ref() != Some(operand) {
+                print_header(operand, false, output_device);
+                last_printed = Some(operand.clone());
+            }
+            output_device.write_bytes(&appended);
+        }
+    }
+}
+
+pub fn tail(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "tail",
+        about: "Print the last lines of files or standard input",
+        operands: "[FILE...]",
+        options: &[
+            Opt::value(
+                'n',
+                "lines",
+                "NUM",
+                "Number of lines to print, 10 by default, +NUM starts at line NUM",
+            ),
+            Opt::flag('f', "follow", "Keep printing data appended to files"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let lines_value = matches.value("lines").unwrap_or("10");
+    let (from_start, count) = match lines_value.strip_prefix('+') {
+        Some(start) => (true, start.parse::<usize>()),
+        None => (false, lines_value.parse::<usize>()),
+    };
+    let count = match count {
+        Ok(count) => count,
+        Err(_) => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+
+    let operands = input_operands(&matches.operands);
+    let mut followed = Vec::new();
+    let mut exit_status = EXIT_SUCCESS;
+    for (i, operand) in operands.iter().enumerate() {
+        // files are kept open so that `-f` continues where reading stopped
+        let (reader, file): (Box<dyn BufRead>, _) = if operand == "-" {
+            match output_device.stdin() {
+                Ok(reader) => (reader, None),
+                Err(error) => {
+                    report("tail", Path::new(operand), error, output_device);
+                    exit_status = EXIT_FAILURE;
+                    continue;
+                }
+            }
+        } else {
+            match File::open(operand).and_then(|file| Ok((file.try_clone()?, file))) {
+                Ok((reader, file)) => (Box::new(BufReader::new(reader)), Some(file)),
+                Err(error) => {
+                    report("tail", Path::new(operand), error, output_device);
+                    exit_status = EXIT_FAILURE;
+                    continue;
+                }
+            }
+        };
+        if operands.len() > 1 {
+            print_header(operand, i == 0, output_device);
+        }
+
+        let mut last = VecDeque::new();
+        for (number, line) in lines(reader).enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    report("tail", Path::new(operand), error, output_device);
+                    exit_status = EXIT_FAILURE;
+                    break;
+                }
+            };
+            if from_start {
+                if number + 1 >= count {
+                    output_device.write_bytes(&line);
+                }
+            } else {
+                last.push_back(line);
+                if last.len() > count {
+                    last.pop_front();
+                }
+            }
+        }
+        for line in last {
+            output_device.write_bytes(&line);
+        }
+        if let Some(file) = file {
+            followed.push((operand.clone(), file));
+        }
+    }
+
+    if matches.flag("follow") && !followed.is_empty() {
+        return follow(shell, followed, Duration::from_millis(500), output_device);
+    }
+    Ok(exit_status)
+}
+
+pub fn wc(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "wc",
+        about: "Count lines, words and bytes of files or standard input",
+        operands: "[FILE...]",
+        options: &[
+            Opt::flag('l', "lines", "Print the number of lines"),
+            Opt::flag('w', "words", "Print the number of words"),
+            Opt::flag('c', "bytes", "Print the number of bytes"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let mut columns = [
+        matches.flag("lines"),
+        matches.flag("words"),
+        matches.flag("bytes"),
+    ];
+    if !columns.contains(&true) {
+        columns = [true; 3];
+    }
+    let format = |counts: &[usize; 3], name: &str| {
+        let mut line: String = counts
+            .iter()
+            .zip(columns.iter())
+            .filter(|(_, shown)| **shown)
+            .map(|(count, _)| format!("{count:>7} "))
+            .collect();
+        line.push_str(name);
+        line.trim_end().to_string()
+    };
+
+    let mut total = [0; 3];
+    let mut exit_status = EXIT_SUCCESS;
+    for operand in input_operands(&matches.operands) {
+        let mut counts = [0; 3];
+        // words can continue in the next chunk
+        let mut in_word = false;
+        let result = output_device.open_input(&operand).and_then(|mut reader| {
+            read_chunks(&mut reader, |chunk| {
+                for byte in chunk {
+                    if *byte == b'\n' {
+                        counts[0] += 1;
+                    }
+                    if byte.is_ascii_whitespace() {
+                        in_word = false;
+                    } else if !in_word {
+                        in_word = true;
+                        counts[1] += 1;
+                    }
+                }
+                counts[2] += chunk.len();
+                Ok(())
+            })
+        });
+        if let Err(error) = result {
+            report("wc", Path::new(&operand), error, output_device);
+            exit_status = EXIT_FAILURE;
+            continue;
+        }
+        for (total, count) in total.iter_mut().zip(counts.iter()) {
+            *total += count;
+        }
+        let name = if operand == "-" { "" } else { &operand };
+        output_device.println(&format(&counts, name));
+    }
+    if matches.operands.len() > 1 {
+        output_device.println(&format(&total, "total"));
+    }
+    Ok(exit_status)
+}
+
+/// Translates a POSIX basic regular expression, where `\(`, `\{`, `\|`, `\+`
+/// and `\?` are operators and their unescaped forms are literals
+fn basic_to_extended(pattern: &str) -> String {
+    let mut extended = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(operator @ ('(' | ')' | '{' | '}' | '|' | '+' | '?')) => {
+                    extended.push(operator)
+                }
+                Some(escaped) => {
+                    extended.push('\\');
+                    extended.push(escaped);
+                }
+                None => extended.push_str("\\\\"),
+            },
+            '(' | ')' | '{' | '}' | '|' | '+' | '?' => {
+                extended.push('\\');
+                extended.push(c);
+            }
+            _ => extended.push(c),
+        }
+    }
+    extended
+}
+
+pub fn grep(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "grep",
+        about: "Print lines of files or standard input matching a pattern",
+        operands: "<PATTERN> [FILE...]",
+        options: &[
+            Opt::flag('E', "extended-regexp", "Use extended regular expressions"),
+            Opt::flag('i', "ignore-case", "Ignore case distinctions"),
+            Opt::flag('n', "line-number", "Prefix lines with their line numbers"),
+            Opt::flag('v', "invert-match", "Print lines that do not match"),
+        ],
+    };
+    // like other greps, exit status 2 means an error rather than no match
+    const EXIT_TROUBLE: i32 = 2;
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(EXIT_FAILURE) => return Ok(EXIT_TROUBLE),
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let (pattern, files) = match matches.operands.split_first() {
+        Some(split) => split,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_TROUBLE);
+        }
+    };
+    let mut pattern = if matches.flag("extended-regexp") {
+        pattern.clone()
+    } else {
+        basic_to_extended(pattern)
+    };
+    if matches.flag("ignore-case") {
+        pattern.insert_str(0, "(?i)");
+    }
+    let regex = match Regex::new(&pattern) {
+        Ok(regex) => regex,
+        Err(error) => {
+            output_device.eprintln(&format!("grep: {error}"));
+            return Ok(EXIT_TROUBLE);
+        }
+    };
+    let invert = matches.flag("invert-match");
+    let line_numbers = matches.flag("line-number");
+
+    let mut found = false;
+    let mut trouble = false;
+    for operand in input_operands(files) {
+        let reader = match output_device.open_input(&operand) {
+            Ok(reader) => reader,
+            Err(error) => {
+                report("grep", Path::new(&operand), error, output_device);
+                trouble = true;
+                continue;
+            }
+        };
+        for (number, line) in lines(reader).enumerate() {
+            let mut line = match line {
+                Ok(line) => line,
+                Err(error) => {
+                    report("grep", Path::new(&operand), error, output_device);
+                    trouble = true;
+                    break;
+                }
+            };
+            if line.last() == Some(&b'\n') {
+                line.pop();
+            }
+            if regex.is_match(&line) == invert {
+                continue;
+            }
+            found = true;
+            let mut prefix = String::new();
+            if files.len() > 1 {
+                prefix.push_str(&format!("{}:", display_name(&operand)));
+            }
+            if line_numbers {
+                prefix.push_str(&format!("{}:", number + 1));
+            }
+            output_device.print(&prefix);
+            output_device.write_bytes(&line);
+            output_device.println("");
+        }
+    }
+    Ok(if trouble {
+        EXIT_TROUBLE
+    } else if found {
+        EXIT_SUCCESS
+    } else {
+        EXIT_FAILURE
+    })
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut encoded = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                encoded.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decodes base64 ignoring line breaks and other whitespace
+fn base64_decode(encoded: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(encoded.len() / 4 * 3);
+    let mut group = 0u32;
+    let mut bits = 0;
+    let mut padding = 0;
+    for byte in encoded.iter().filter(|byte| !byte.is_ascii_whitespace()) {
+        if *byte == b'=' {
+            padding += 1;
+            continue;
+        }
+        // data after padding means concatenated or corrupted input
+        if padding > 0 {
+            return None;
+        }
+        let value = BASE64_ALPHABET.iter().position(|c| c == byte)?;
+        group = (group << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((group >> bits) as u8);
+        }
+    }
+    if padding > 2 || bits >= 6 {
+        return None;
+    }
+    Some(decoded)
+}
+
+/// Reads a whole FILE operand, standard input when there is none
+fn read_input(
+    name: &str,
+    operands: &[String],
+    output_device: &mut OutputDevice,
+) -> Option<Vec<u8>> {
+    let operand = operands.first().map(String::as_str).unwrap_or("-");
+    let mut content = Vec::new();
+    let result = output_device
+        .open_input(operand)
+        .and_then(|mut reader| reader.read_to_end(&mut content));
+    match result {
+        Ok(_) => Some(content),
+        Err(error) => {
+            report(name, Path::new(operand), error, output_device);
+            None
+        }
+    }
+}
+
+pub fn base64(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "base64",
+        about: "Encode or decode base64 data of a file or standard input",
+        operands: "[FILE]",
+        options: &[
+            Opt::flag('d', "decode", "Decode data"),
+            Opt::value(
+                'w',
+                "wrap",
+                "COLS",
+                "Wrap encoded lines after COLS characters, 76 by default, 0 disables wrapping",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let wrap = match matches.value("wrap").map(str::parse::<usize>) {
+        None => 76,
+        Some(Ok(wrap)) => wrap,
+        Some(Err(_)) => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if matches.operands.len() > 1 {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let content = match read_input("base64", &matches.operands, output_device) {
+        Some(content) => content,
+        None => return Ok(EXIT_FAILURE),
+    };
+
+    if matches.flag("decode") {
+        return match base64_decode(&content) {
+            Some(decoded) => {
+                output_device.write_bytes(&decoded);
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                output_device.eprintln("base64: invalid input");
+                Ok(EXIT_FAILURE)
+            }
+        };
+    }
+    let encoded = base64_encode(&content);
+    if wrap == 0 {
+        output_device.println(&encoded);
+    } else {
+        // the alphabet is ASCII, so byte chunks are valid strings
+        for line in encoded.as_bytes().chunks(wrap) {
+            output_device.println(&String::from_utf8_lossy(line));
+        }
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+/// Width of the hex column of an `xxd` dump line, 8 groups of 2 bytes
+const XXD_HEX_WIDTH: usize = 39;
+
+fn xxd_dump(content: &[u8], output_device: &mut OutputDevice) {
+    for (i, line) in content.chunks(16).enumerate() {
+        let hex: Vec<String> = line
+            .chunks(2)
+            .map(|group| group.iter().map(|byte| format!("{byte:02x}")).collect())
+            .collect();
+        let text: String = line
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        output_device.println(&format!(
+            "{:08x}: {:<width$}  {}",
+            i * 16,
+            hex.join(" "),
+            text,
+            width = XXD_HEX_WIDTH
+        ));
+    }
+}
+
+/// Bytes of hex digits in `text`, other characters are skipped
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16).map(|digit| digit as u8))
+        .collect::<Option<_>>()?;
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    Some(
+        digits
+            .chunks(2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect(),
+    )
+}
+
+/// Bytes of a dump in the `xxd` format, offsets are assumed to be contiguous
+fn xxd_revert(dump: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    for line in dump.lines().filter(|line| !line.trim().is_empty()) {
+        let (_, rest) = line.split_once(": ")?;
+        let hex: String = rest.chars().take(XXD_HEX_WIDTH).collect();
+        bytes.extend(parse_hex(&hex)?);
+    }
+    Some(bytes)
+}
+
+pub fn xxd(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "xxd",
+        about: "Print a hex dump of a file or standard input, or revert one with -r",
+        operands: "[FILE]",
+        options: &[
+            Opt::flag('p', "plain", "Print only hex digits, 60 per line"),
+            Opt::flag('r', "revert", "Convert a dump back to binary data"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.len() > 1 {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let content = match read_input("xxd", &matches.operands, output_device) {
+        Some(content) => content,
+        None => return Ok(EXIT_FAILURE),
+    };
+
+    let plain = matches.flag("plain");
+    if matches.flag("revert") {
+        let dump = String::from_utf8_lossy(&content);
+        let bytes = if plain {
+            parse_hex(&dump)
+        } else {
+            xxd_revert(&dump)
+        };
+        return match bytes {
+            Some(bytes) => {
+                output_device.write_bytes(&bytes);
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                output_device.eprintln("xxd: invalid hex dump");
+                Ok(EXIT_FAILURE)
+            }
+        };
+    }
+    if plain {
+        for line in content.chunks(30) {
+            output_device.println(
+                &line
+                    .iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>(),
+            );
+        }
+    } else {
+        xxd_dump(&content, output_device);
+    }
+    Ok(EXIT_SUCCESS)
+}
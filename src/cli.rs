@@ -4,21 +4,143 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+use std::collections::HashMap;
 use std::io;
 use std::io::Write;
+use std::mem;
 
 use vte::{Params, Perform};
 
+/// Number of kills remembered for Ctrl+Y and Alt+Y
+const KILL_RING_SIZE: usize = 60;
+
+/// Editing command run by a control key, named as in readline for
+/// `keybindings.<key>` in the configuration
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    AcceptLine,
+    Complete,
+    ReverseSearchHistory,
+    BeginningOfLine,
+    EndOfLine,
+    UnixLineDiscard,
+    KillLine,
+    UnixWordRubout,
+    Yank,
+    Undo,
+}
+
+const ACTIONS: [(&str, Action); 10] = [
+    ("accept-line", Action::AcceptLine),
+    ("complete", Action::Complete),
+    ("reverse-search-history", Action::ReverseSearchHistory),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("kill-line", Action::KillLine),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("yank", Action::Yank),
+    ("undo", Action::Undo),
+];
+
+/// Control keys and the actions they run unless bound to other ones
+const DEFAULT_BINDINGS: [(u8, Action); 11] = [
+    // Ctrl+J and Ctrl+M, sent by Enter
+    (0xa, Action::AcceptLine),
+    (0xd, Action::AcceptLine),
+    // Ctrl+I, sent by Tab
+    (0x9, Action::Complete),
+    (0x12, Action::ReverseSearchHistory),
+    (0x1, Action::BeginningOfLine),
+    (0x5, Action::EndOfLine),
+    (0x15, Action::UnixLineDiscard),
+    (0xb, Action::KillLine),
+    (0x17, Action::UnixWordRubout),
+    (0x19, Action::Yank),
+    (0x1f, Action::Undo),
+];
+
+impl Action {
+    pub fn parse(name: &str) -> Option<Action> {
+        ACTIONS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, action)| *action)
+    }
+}
+
+/// Byte sent by a control key written like `ctrl-a` or `ctrl-_`
+pub fn parse_key(key: &str) -> Option<u8> {
+    match key.to_ascii_lowercase().strip_prefix("ctrl-")?.as_bytes() {
+        [letter @ b'a'..=b'z'] => Some(letter - b'a' + 1),
+        [b'_'] => Some(0x1f),
+        _ => None,
+    }
+}
+
+/// Key handled before the current one, consecutive kills are joined and
+/// Alt+Y replaces the text of the previous yank
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum LastAction {
+    #[default]
+    Other,
+    /// Typed characters, a run of them is undone at once
+    Insert,
+    Kill,
+    /// Yanked text spans `start..end` of the input, `index` in the kill ring
+    Yank {
+        start: usize,
+        end: usize,
+        index: usize,
+    },
+}
+
+/// State of a Ctrl+R reverse incremental history search
+struct Search {
+    query: Vec<char>,
+    /// Terminal cells taken by the drawn search line
+    width: usize,
+    /// Index of the matching history entry
+    found: Option<usize>,
+    /// Whether the last key found no older match
+    failed: bool,
+    /// Input from before the search, restored when it is cancelled
+    stash: Vec<char>,
+}
+
 pub struct Cli {
     pub history: Vec<Vec<char>>,
     pub should_echo: bool,
     pub cursor_position: usize,
+    /// Edited command, lines after the first one follow a `\n`
     pub input: Vec<char>,
+    /// Expanded `PS2`, shown before every line after the first one
+    pub continuation_prompt: String,
+    /// Terminal cells taken by the prompt on the first line
+    pub prompt_width: usize,
 
     history_entry_to_display: i32,
     input_ready: bool,
+    /// Set by Tab until the shell completes the word before the cursor
+    completion_requested: bool,
     input_stash: Vec<char>,
     insert_mode: bool,
+    search: Option<Search>,
+    /// Input and cursor position before each edit, restored by Ctrl+_ or
+    /// Ctrl+X Ctrl+U
+    undo_stack: Vec<(Vec<char>, usize)>,
+    /// States left by undo, restored by Ctrl+X Ctrl+R until the next edit
+    redo_stack: Vec<(Vec<char>, usize)>,
+    /// Set while edits are undone together with the one before them
+    undo_group: bool,
+    /// Set by Ctrl+X until the next key
+    ctrl_x: bool,
+    /// Killed text, most recent last, kept between lines
+    kill_ring: Vec<Vec<char>>,
+    last_action: LastAction,
+    previous_action: LastAction,
+    /// Actions of control keys, Ctrl+X is a prefix unless bound
+    bindings: HashMap<u8, Action>,
 }
 
 impl Cli {
@@ -28,13 +150,51 @@ impl Cli {
             history: Vec::new(),
             history_entry_to_display: -1,
             input: Vec::new(),
+            continuation_prompt: String::new(),
+            prompt_width: 0,
             input_ready: false,
+            completion_requested: false,
             input_stash: Vec::new(),
             insert_mode: true,
+            search: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            undo_group: false,
+            ctrl_x: false,
+            kill_ring: Vec::new(),
+            last_action: LastAction::Other,
+            previous_action: LastAction::Other,
+            bindings: DEFAULT_BINDINGS.iter().copied().collect(),
             should_echo,
         }
     }
 
+    /// Whether a Ctrl+R search is shown instead of the input
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    /// Draws the input again as `painted`, the same text with escape
+    /// sequences added
+    pub fn repaint(&mut self, painted: &str) {
+        let cursor_position = self.cursor_position;
+        self.erase_input();
+        self.echo(&painted.replace('\n', &format!("\r\n{}", self.continuation_prompt)));
+        self.cursor_position = self.input.len();
+        self.move_cursor(cursor_position);
+        io::stdout().flush().unwrap();
+    }
+
+    /// Makes control key `key` run `action`
+    pub fn bind(&mut self, key: u8, action: Action) {
+        self.bindings.insert(key, action);
+    }
+
+    /// Restores the default actions of all keys
+    pub fn reset_bindings(&mut self) {
+        self.bindings = DEFAULT_BINDINGS.iter().copied().collect();
+    }
+
     pub fn is_input_ready(&self) -> bool {
         self.input_ready
     }
@@ -44,13 +204,74 @@ impl Cli {
         self.history_entry_to_display = -1;
         self.input.clear();
         self.input_ready = false;
+        self.completion_requested = false;
         self.input_stash.clear();
+        self.search = None;
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.undo_group = false;
+        self.ctrl_x = false;
+        self.last_action = LastAction::Other;
 
         if !self.insert_mode {
             self.insert_mode = true;
         }
     }
 
+    /// Whether Tab was pressed since the last call
+    pub fn take_completion_request(&mut self) -> bool {
+        std::mem::take(&mut self.completion_requested)
+    }
+
+    /// Inserts `text` at the cursor as if it was typed
+    pub fn insert(&mut self, text: &str) {
+        self.save_undo();
+        for c in text.chars() {
+            self.insert_char(c);
+        }
+    }
+
+    /// Removes `count` characters before the cursor as if with backspace
+    pub fn delete_before_cursor(&mut self, count: usize) {
+        let start = self.cursor_position.saturating_sub(count);
+        self.delete_range(start, self.cursor_position);
+    }
+
+    /// Shows `text` after the prompt with the cursor at its end
+    pub fn display(&mut self, text: &str) {
+        let text: Vec<char> = text.chars().collect();
+        self.echo_input(&text);
+        self.cursor_position = text.len();
+    }
+
+    /// Starts another line of the command, after Enter was pressed while it
+    /// is incomplete
+    pub fn continue_line(&mut self) {
+        self.save_undo();
+        self.input_ready = false;
+        self.move_cursor(self.input.len());
+        self.input.push('\n');
+        self.echo_input(&['\n']);
+        self.cursor_position = self.input.len();
+    }
+
+    /// Moves to the line below the command once it is accepted
+    pub fn finish_line(&mut self) {
+        self.move_cursor(self.input.len());
+        self.echo("\n");
+        self.cursor_position = 0;
+    }
+
+    /// Makes the following edits undo at once, until `end_undo_group`
+    pub fn begin_undo_group(&mut self) {
+        self.save_undo();
+        self.undo_group = true;
+    }
+
+    pub fn end_undo_group(&mut self) {
+        self.undo_group = false;
+    }
+
     fn echo(&self, output: &str) {
         if self.should_echo {
             // TODO: should this maybe use OutputDevice too?
@@ -60,59 +281,452 @@ impl Cli {
         }
     }
 
-    fn get_cursor_to_beginning(&mut self) {
-        if self.cursor_position > 0 {
-            // bring cursor to the beggining with `ESC[nD` escape sequence
-            self.echo(&format!("\x1b[{}D", self.cursor_position));
+    /// Moves the terminal cursor `cells` to the left with `ESC[nD`
+    fn cursor_left(&self, cells: usize) {
+        // 0 would move by one cell
+        if cells > 0 {
+            self.echo(&format!("\x1b[{cells}D"));
+        }
+    }
+
+    /// Moves the terminal cursor `cells` to the right with `ESC[nC`
+    fn cursor_right(&self, cells: usize) {
+        if cells > 0 {
+            self.echo(&format!("\x1b[{cells}C"));
+        }
+    }
+
+    /// Inserts `cells` blank cells at the cursor with `ESC[n@`
+    fn insert_cells(&self, cells: usize) {
+        if cells > 0 {
+            self.echo(&format!("\x1b[{cells}@"));
+        }
+    }
+
+    /// Deletes `cells` cells under the cursor with `ESC[nP`
+    fn delete_cells(&self, cells: usize) {
+        if cells > 0 {
+            self.echo(&format!("\x1b[{cells}P"));
         }
-        self.cursor_position = 0;
+    }
+
+    /// Echoes `chars`, with the continuation prompt after every newline
+    fn echo_input(&self, chars: &[char]) {
+        let text: String = chars.iter().collect();
+        self.echo(&text.replace('\n', &format!("\r\n{}", self.continuation_prompt)));
+    }
+
+    fn get_cursor_to_beginning(&mut self) {
+        self.move_cursor(self.line_start(self.cursor_position));
     }
 
     fn get_cursor_to_end(&mut self) {
-        let to_end = self.input.len() - self.cursor_position;
-        if self.input.len() - self.cursor_position > 0 {
-            // bring cursor to the end with `ESC[nC` escape sequence
-            self.echo(&format!("\x1b[{}C", to_end));
+        self.move_cursor(self.line_end(self.cursor_position));
+    }
+
+    /// Position of the first character of the line `position` is in
+    fn line_start(&self, position: usize) -> usize {
+        self.input[..position]
+            .iter()
+            .rposition(|c| *c == '\n')
+            .map_or(0, |newline| newline + 1)
+    }
+
+    /// Position of the newline ending the line `position` is in
+    fn line_end(&self, position: usize) -> usize {
+        self.input[position..]
+            .iter()
+            .position(|c| *c == '\n')
+            .map_or(self.input.len(), |newline| position + newline)
+    }
+
+    /// Line and terminal column of the character at `position`
+    fn screen_position(&self, position: usize) -> (usize, usize) {
+        let line = self.input[..position]
+            .iter()
+            .filter(|c| **c == '\n')
+            .count();
+        let prompt_width = if line == 0 {
+            self.prompt_width
+        } else {
+            prompt_width(&self.continuation_prompt)
+        };
+        let start = self.line_start(position);
+        (line, prompt_width + width(&self.input[start..position]))
+    }
+
+    /// Moves the cursor to the character at `position` in the input
+    pub fn move_cursor(&mut self, position: usize) {
+        let (from_line, from_column) = self.screen_position(self.cursor_position);
+        let (to_line, to_column) = self.screen_position(position);
+        // move between lines with `ESC[nA` and `ESC[nB`
+        if to_line < from_line {
+            self.echo(&format!("\x1b[{}A", from_line - to_line));
+        } else if to_line > from_line {
+            self.echo(&format!("\x1b[{}B", to_line - from_line));
+        }
+        if to_column < from_column {
+            self.cursor_left(from_column - to_column);
+        } else {
+            self.cursor_right(to_column - from_column);
+        }
+        self.cursor_position = position;
+    }
+
+    /// Moves the cursor to the same column of the line above or below,
+    /// false when there is no such line
+    fn move_vertically(&mut self, up: bool) -> bool {
+        let start = self.line_start(self.cursor_position);
+        let target_start = if up {
+            match start.checked_sub(1) {
+                Some(newline) => self.line_start(newline),
+                None => return false,
+            }
+        } else {
+            match self.line_end(self.cursor_position) {
+                end if end == self.input.len() => return false,
+                end => end + 1,
+            }
+        };
+        let cells = width(&self.input[start..self.cursor_position]);
+        let target_end = self.line_end(target_start);
+        let mut target = target_start;
+        let mut target_cells = 0;
+        while target < target_end && target_cells + char_width(self.input[target]) <= cells {
+            target_cells += char_width(self.input[target]);
+            target += 1;
+        }
+        self.move_cursor(target);
+        true
+    }
+
+    /// Removes characters from `start` to `end` and returns them
+    fn delete_range(&mut self, start: usize, end: usize) -> Vec<char> {
+        if start >= end {
+            return Vec::new();
+        }
+        self.save_undo();
+        if self.input[start..end].contains(&'\n') {
+            // lines below have to be drawn again
+            let mut input = self.input.clone();
+            let deleted = input.drain(start..end).collect();
+            self.redraw(input, start);
+            return deleted;
+        }
+        self.move_cursor(start);
+        self.delete_cells(width(&self.input[start..end]));
+        self.input.drain(start..end).collect()
+    }
+
+    /// Removes characters from `start` to `end` onto the kill ring, right
+    /// after another kill they are joined with the text it killed
+    fn kill(&mut self, start: usize, end: usize) {
+        let backward = end == self.cursor_position;
+        let killed = self.delete_range(start, end);
+        if killed.is_empty() {
+            return;
         }
+        match self.kill_ring.last_mut() {
+            Some(last) if self.previous_action == LastAction::Kill => {
+                // text killed backwards goes before the previous kill
+                if backward {
+                    last.splice(0..0, killed);
+                } else {
+                    last.extend(killed);
+                }
+            }
+            _ => {
+                self.kill_ring.push(killed);
+                if self.kill_ring.len() > KILL_RING_SIZE {
+                    self.kill_ring.remove(0);
+                }
+            }
+        }
+        self.last_action = LastAction::Kill;
+    }
+
+    /// Inserts the kill at `index` of the kill ring at the cursor
+    fn yank(&mut self, index: usize) {
+        let text = match self.kill_ring.get(index) {
+            Some(text) => text.clone(),
+            None => {
+                // bell
+                self.echo("\x07");
+                return;
+            }
+        };
+        self.save_undo();
+        let start = self.cursor_position;
+        if text.contains(&'\n') {
+            let mut input = self.input.clone();
+            input.splice(start..start, text.iter().copied());
+            self.redraw(input, start + text.len());
+        } else {
+            self.insert_cells(width(&text));
+            self.echo(&text.iter().collect::<String>());
+            self.input.splice(start..start, text.iter().copied());
+            self.cursor_position += text.len();
+        }
+        self.last_action = LastAction::Yank {
+            start,
+            end: self.cursor_position,
+            index,
+        };
+    }
+
+    /// Replaces the text of the previous yank with the next older kill
+    fn yank_pop(&mut self) {
+        match self.previous_action {
+            LastAction::Yank { start, end, index } => {
+                self.begin_undo_group();
+                self.delete_range(start, end);
+                let older = index.checked_sub(1).unwrap_or(self.kill_ring.len() - 1);
+                self.yank(older);
+                self.end_undo_group();
+            }
+            // bell
+            _ => self.echo("\x07"),
+        }
+    }
+
+    /// Start of the word before the cursor, words are made of characters
+    /// for which `in_word` holds
+    fn word_before_cursor(&self, in_word: fn(&char) -> bool) -> usize {
+        let before = &self.input[..self.cursor_position];
+        let word_end = before.len() - before.iter().rev().take_while(|c| !in_word(c)).count();
+        word_end
+            - before[..word_end]
+                .iter()
+                .rev()
+                .take_while(|c| in_word(c))
+                .count()
+    }
+
+    /// End of the word after the cursor
+    fn word_after_cursor(&self, in_word: fn(&char) -> bool) -> usize {
+        let after = &self.input[self.cursor_position..];
+        let word_start = after.iter().take_while(|c| !in_word(c)).count();
+        self.cursor_position
+            + word_start
+            + after[word_start..]
+                .iter()
+                .take_while(|c| in_word(c))
+                .count()
+    }
+
+    fn insert_char(&mut self, c: char) {
+        if self.cursor_position == self.input.len() {
+            self.input.push(c);
+            self.echo(&c.to_string());
+        } else if self.insert_mode || self.input[self.cursor_position] == '\n' {
+            // in insert mode, when cursor is in the middle, new character expand CLI
+            // instead of replacing character under cursor
+
+            self.input.insert(self.cursor_position, c);
+            self.insert_cells(char_width(c));
+            self.echo(&c.to_string());
+        } else {
+            // the replaced character may take a different number of cells
+            self.delete_cells(char_width(self.input[self.cursor_position]));
+            self.insert_cells(char_width(c));
+            self.input[self.cursor_position] = c;
+            self.echo(&c.to_string());
+        }
+
+        self.cursor_position += 1;
+    }
+
+    /// Remembers the input before it is changed
+    fn save_undo(&mut self) {
+        if self.undo_group {
+            return;
+        }
+        self.redo_stack.clear();
+        if self.undo_stack.last().map(|(input, _)| input) != Some(&self.input) {
+            self.undo_stack
+                .push((self.input.clone(), self.cursor_position));
+        }
+    }
+
+    fn undo(&mut self) {
+        if let Some((input, cursor_position)) = self.undo_stack.pop() {
+            self.redo_stack
+                .push((self.input.clone(), self.cursor_position));
+            self.redraw(input, cursor_position);
+        } else {
+            // bell
+            self.echo("\x07");
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some((input, cursor_position)) = self.redo_stack.pop() {
+            self.undo_stack
+                .push((self.input.clone(), self.cursor_position));
+            self.redraw(input, cursor_position);
+        } else {
+            // bell
+            self.echo("\x07");
+        }
+    }
+
+    /// Replaces the whole input and puts the cursor at `cursor_position`
+    fn redraw(&mut self, input: Vec<char>, cursor_position: usize) {
+        self.erase_input();
+        self.input = input;
         self.cursor_position = self.input.len();
+        self.echo_input(&self.input);
+        self.move_cursor(cursor_position.min(self.input.len()));
     }
 
     fn erase_input(&mut self) {
-        // bring cursor to the beginning and clear line to the right with `ESC[0K`
-        self.get_cursor_to_beginning();
+        // bring cursor to the beginning and clear the screen below with `ESC[0J`
+        self.move_cursor(0);
+        self.echo("\x1b[0J");
+    }
+
+    fn start_search(&mut self) {
+        self.erase_input();
+        self.search = Some(Search {
+            query: Vec::new(),
+            width: 0,
+            found: None,
+            failed: false,
+            stash: self.input.clone(),
+        });
+        self.draw_search();
+    }
+
+    /// Looks for the query in history entries from `from` back
+    fn find_in_history(&mut self, from: Option<usize>) {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+        let history = &self.history;
+        let query: String = search.query.iter().collect();
+        let found = from.and_then(|from| {
+            (0..=from.min(history.len().checked_sub(1)?))
+                .rev()
+                .find(|&index| history[index].iter().collect::<String>().contains(&query))
+        });
+        search.failed = found.is_none();
+        search.found = found.or(search.found);
+    }
+
+    /// Handles a character typed while searching, backspace shortens the query
+    fn search_input(&mut self, c: char) {
+        let search = match &mut self.search {
+            Some(search) => search,
+            None => return,
+        };
+        let from = if c == '\x7f' {
+            search.query.pop();
+            // a shorter query may match a more recent entry again
+            self.history.len().checked_sub(1)
+        } else {
+            search.query.push(c);
+            search.found.or_else(|| self.history.len().checked_sub(1))
+        };
+        self.find_in_history(from);
+        self.draw_search();
+    }
+
+    /// Moves to the next older match of the query
+    fn search_older(&mut self) {
+        let from = match &self.search {
+            Some(Search {
+                found: Some(found), ..
+            }) => found.checked_sub(1),
+            Some(_) => self.history.len().checked_sub(1),
+            None => return,
+        };
+        self.find_in_history(from);
+        self.draw_search();
+    }
+
+    fn draw_search(&mut self) {
+        let search = match &self.search {
+            Some(search) => search,
+            None => return,
+        };
+        // the search line replaces the input until the search finishes
+        self.cursor_left(search.width);
         self.echo("\x1b[0K");
+        // lines of the match are shown joined
+        let found: String = search
+            .found
+            .map(|index| {
+                self.history[index]
+                    .iter()
+                    .map(|c| if *c == '\n' { ' ' } else { *c })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let status = format!(
+            "({}reverse-i-search)`{}': {}",
+            if search.failed { "failed " } else { "" },
+            search.query.iter().collect::<String>(),
+            found
+        );
+        self.echo(&status);
+        if let Some(search) = &mut self.search {
+            search.width = width(&status.chars().collect::<Vec<char>>());
+        }
+    }
+
+    /// Leaves search mode with the match in the input, or the previous input
+    /// when the search was cancelled
+    fn finish_search(&mut self, accept: bool) {
+        let search = match self.search.take() {
+            Some(search) => search,
+            None => return,
+        };
+        self.cursor_left(search.width);
+        self.echo("\x1b[0K");
+        match search.found {
+            Some(found) if accept => {
+                self.undo_stack
+                    .push((search.stash.clone(), search.stash.len()));
+                self.redo_stack.clear();
+                // history navigation continues from the match
+                if self.history_entry_to_display == -1 {
+                    self.input_stash = search.stash;
+                }
+                self.history_entry_to_display = found as i32;
+                self.input = self.history[found].clone();
+            }
+            _ => self.input = search.stash,
+        }
+        self.cursor_position = self.input.len();
+        self.echo_input(&self.input);
     }
 }
 
 impl Perform for Cli {
     fn print(&mut self, c: char) {
+        self.previous_action = mem::take(&mut self.last_action);
+        self.ctrl_x = false;
+        if self.search.is_some() {
+            self.search_input(c);
+            io::stdout().flush().unwrap();
+            return;
+        }
         let byte = c as u16;
         match byte {
             // backspace
             0x7f => {
-                if !self.input.is_empty() && self.cursor_position > 0 {
-                    self.echo("\x1b[D\x1b[P");
-                    self.input.remove(self.cursor_position - 1);
-                    self.cursor_position -= 1;
+                if self.cursor_position > 0 {
+                    self.delete_range(self.cursor_position - 1, self.cursor_position);
                 }
             }
             // regular characters
             _ => {
-                if self.cursor_position == self.input.len() {
-                    self.input.push(c);
-                    self.echo(&c.to_string());
-                } else if self.insert_mode {
-                    // in insert mode, when cursor is in the middle, new character expand CLI
-                    // instead of replacing character under cursor
-
-                    self.input.insert(self.cursor_position, c);
-                    self.echo(&format!("\x1b[@{}", c));
-                } else {
-                    self.input[self.cursor_position] = c;
-                    self.echo(&c.to_string());
+                if self.previous_action != LastAction::Insert {
+                    self.save_undo();
                 }
-
-                self.cursor_position += 1;
+                self.insert_char(c);
+                self.last_action = LastAction::Insert;
             }
         }
 
@@ -121,14 +735,57 @@ impl Perform for Cli {
 
     fn execute(&mut self, byte: u8) {
         // C0 and C1 control functions
-        match byte {
-            // enter
-            0xa | 0xd => {
-                self.echo("\n");
-                self.cursor_position = 0;
-                self.input_ready = true;
+        self.previous_action = mem::take(&mut self.last_action);
+        // Ctrl+X changes the meaning of the key right after it
+        if mem::take(&mut self.ctrl_x) && self.search.is_none() {
+            match byte {
+                // Ctrl+X Ctrl+U
+                0x15 => self.undo(),
+                // Ctrl+X Ctrl+R
+                0x12 => self.redo(),
+                // bell
+                _ => self.echo("\x07"),
+            }
+            io::stdout().flush().unwrap();
+            return;
+        }
+        if self.search.is_some() {
+            match byte {
+                // Ctrl+R
+                0x12 => self.search_older(),
+                // Ctrl+G
+                0x7 => self.finish_search(false),
+                // other keys act on the match
+                _ => self.finish_search(true),
+            }
+            if matches!(byte, 0x12 | 0x7) {
+                io::stdout().flush().unwrap();
+                return;
             }
-            _ => { /* ignore for now */ }
+        }
+        match self.bindings.get(&byte) {
+            // the shell finishes the line, or continues it when incomplete
+            Some(Action::AcceptLine) => self.input_ready = true,
+            Some(Action::Complete) => self.completion_requested = true,
+            Some(Action::ReverseSearchHistory) => self.start_search(),
+            Some(Action::BeginningOfLine) => self.get_cursor_to_beginning(),
+            Some(Action::EndOfLine) => self.get_cursor_to_end(),
+            Some(Action::UnixLineDiscard) => {
+                self.kill(self.line_start(self.cursor_position), self.cursor_position)
+            }
+            Some(Action::KillLine) => {
+                self.kill(self.cursor_position, self.line_end(self.cursor_position))
+            }
+            // words are delimited by whitespace
+            Some(Action::UnixWordRubout) => {
+                let start = self.word_before_cursor(|c| !c.is_whitespace());
+                self.kill(start, self.cursor_position);
+            }
+            Some(Action::Yank) => self.yank(self.kill_ring.len().wrapping_sub(1)),
+            Some(Action::Undo) => self.undo(),
+            // Ctrl+X
+            None if byte == 0x18 => self.ctrl_x = true,
+            None => { /* ignore for now */ }
         }
         io::stdout().flush().unwrap();
     }
@@ -150,12 +807,19 @@ impl Perform for Cli {
     }
 
     fn csi_dispatch(&mut self, params: &Params, _intermediates: &[u8], _ignore: bool, c: char) {
+        self.previous_action = mem::take(&mut self.last_action);
+        self.ctrl_x = false;
+        self.finish_search(true);
         if params.len() == 1 {
             let param = params.iter().next().unwrap();
             match (param[0], c) {
                 // UpArrow
                 (_, 'A') => {
-                    if !self.history.is_empty() && self.history_entry_to_display != 0 {
+                    // in a command of several lines, arrows move between them
+                    if !self.move_vertically(true)
+                        && !self.history.is_empty()
+                        && self.history_entry_to_display != 0
+                    {
                         if self.history_entry_to_display == -1 {
                             self.history_entry_to_display = (self.history.len() - 1) as i32;
                             self.input_stash = self.input.clone();
@@ -163,15 +827,18 @@ impl Perform for Cli {
                             self.history_entry_to_display -= 1;
                         }
 
+                        // history recalls can be undone
+                        self.save_undo();
                         self.erase_input();
                         self.input = self.history[self.history_entry_to_display as usize].clone();
                         self.cursor_position = self.input.len();
-                        self.echo(&self.input.iter().collect::<String>());
+                        self.echo_input(&self.input);
                     }
                 }
                 // DownArrow
                 (_, 'B') => {
-                    if self.history_entry_to_display != -1 {
+                    if !self.move_vertically(false) && self.history_entry_to_display != -1 {
+                        self.save_undo();
                         self.erase_input();
                         if self.history.len() - 1 > (self.history_entry_to_display as usize) {
                             self.history_entry_to_display += 1;
@@ -182,23 +849,19 @@ impl Perform for Cli {
                             self.history_entry_to_display = -1;
                         }
                         self.cursor_position = self.input.len();
-                        self.echo(&self.input.iter().collect::<String>());
+                        self.echo_input(&self.input);
                     }
                 }
                 // RightArrow
                 (_, 'C') => {
                     if self.cursor_position < self.input.len() {
-                        // move cursor right with `ESC[C`
-                        self.echo("\x1b[C");
-                        self.cursor_position += 1;
+                        self.move_cursor(self.cursor_position + 1);
                     }
                 }
                 // LeftArrow
                 (_, 'D') => {
                     if self.cursor_position > 0 {
-                        // move cursor left with `ESC[D`
-                        self.echo("\x1b[D");
-                        self.cursor_position -= 1;
+                        self.move_cursor(self.cursor_position - 1);
                     }
                 }
                 // End
@@ -215,10 +878,8 @@ impl Perform for Cli {
                 }
                 // Del
                 (3, '~') => {
-                    if self.input.len() - self.cursor_position > 0 {
-                        self.echo("\x1b[P");
-                        self.input.remove(self.cursor_position);
-                    }
+                    let end = (self.cursor_position + 1).min(self.input.len());
+                    self.delete_range(self.cursor_position, end);
                 }
                 // PageUp
                 (5, '~') => {
@@ -227,31 +888,128 @@ impl Perform for Cli {
                             self.input_stash = self.input.clone();
                         }
                         self.history_entry_to_display = 0;
+                        self.save_undo();
                         self.erase_input();
                         self.input = self.history[0].clone();
                         self.cursor_position = self.input.len();
-                        self.echo(&self.input.iter().collect::<String>());
+                        self.echo_input(&self.input);
                     }
                 }
                 // PageDown
                 (6, '~') => {
                     if self.history_entry_to_display != -1 {
+                        self.save_undo();
                         self.erase_input();
                         self.input = self.input_stash.clone();
                         self.history_entry_to_display = -1;
                         self.cursor_position = self.input.len();
-                        self.echo(&self.input.iter().collect::<String>());
+                        self.echo_input(&self.input);
                     }
                 }
                 (_, _) => { /* ignore for now */ }
             }
         } else {
-            /* ignore for now */
+            // modified keys are sent as `ESC[1;{modifier}{key}`, 5 is Ctrl and
+            // 3 is Alt, words are delimited by whitespace
+            let modifier = params.iter().nth(1).map(|param| param[0]);
+            match (modifier, c) {
+                // Ctrl+RightArrow
+                (Some(5 | 3), 'C') => {
+                    self.move_cursor(self.word_after_cursor(|c| !c.is_whitespace()))
+                }
+                // Ctrl+LeftArrow
+                (Some(5 | 3), 'D') => {
+                    self.move_cursor(self.word_before_cursor(|c| !c.is_whitespace()))
+                }
+                (_, _) => { /* ignore for now */ }
+            }
         }
         io::stdout().flush().unwrap();
     }
 
-    fn esc_dispatch(&mut self, _intermediates: &[u8], _ignore: bool, _byte: u8) {
-        /* ignore for now */
+    fn esc_dispatch(&mut self, intermediates: &[u8], _ignore: bool, byte: u8) {
+        self.previous_action = mem::take(&mut self.last_action);
+        self.ctrl_x = false;
+        self.finish_search(true);
+        // Alt+key arrives as ESC followed by the key
+        if intermediates.is_empty() {
+            match byte {
+                // Alt+b
+                b'b' => self.move_cursor(self.word_before_cursor(|c| c.is_alphanumeric())),
+                // Alt+f
+                b'f' => self.move_cursor(self.word_after_cursor(|c| c.is_alphanumeric())),
+                // Alt+y
+                b'y' => self.yank_pop(),
+                _ => { /* ignore for now */ }
+            }
+        }
+        io::stdout().flush().unwrap();
+    }
+}
+
+/// Number of terminal cells taken by `c`, 2 for wide East Asian characters
+/// and emoji, 0 for combining marks and zero width characters
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x0300..=0x036F
+        | 0x1AB0..=0x1AFF
+        | 0x1DC0..=0x1DFF
+        | 0x200B..=0x200F
+        | 0x20D0..=0x20FF
+        | 0xFE00..=0xFE0F
+        | 0xFE20..=0xFE2F => 0,
+        0x1100..=0x115F
+        | 0x2E80..=0x303E
+        | 0x3041..=0x33FF
+        | 0x3400..=0x4DBF
+        | 0x4E00..=0x9FFF
+        | 0xA000..=0xA4CF
+        | 0xAC00..=0xD7A3
+        | 0xF900..=0xFAFF
+        | 0xFE30..=0xFE4F
+        | 0xFF00..=0xFF60
+        | 0xFFE0..=0xFFE6
+        | 0x1F300..=0x1F64F
+        | 0x1F900..=0x1F9FF
+        | 0x20000..=0x2FFFD
+        | 0x30000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+/// Number of terminal cells taken by `chars`
+fn width(chars: &[char]) -> usize {
+    chars.iter().copied().map(char_width).sum()
+}
+
+/// Terminal cells taken by the last line of `prompt`, without escape
+/// sequences and the `\x01`, `\x02` markers of non-printing text
+pub fn prompt_width(prompt: &str) -> usize {
+    let last_line = prompt.rsplit('\n').next().unwrap_or_default();
+    let mut visible = Vec::new();
+    let (mut chars, mut hidden) = (last_line.chars(), false);
+    while let Some(c) = chars.next() {
+        match c {
+            '\x01' => hidden = true,
+            '\x02' => hidden = false,
+            // CSI sequences end with a letter, OSC sequences with BEL or ST
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    chars.find(|c| ('@'..='~').contains(c));
+                }
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || (c == '\x1b' && chars.next() == Some('\\')) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => visible.clear(),
+            c if !hidden && !c.is_control() => visible.push(c),
+            _ => {}
+        }
     }
+    width(&visible)
 }
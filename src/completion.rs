@@ -0,0 +1,335 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Tab completion of the word before the cursor in the line editor. The
+//! first word of a command completes to aliases, internals and commands in
+//! `PATH`. Other words use the spec registered with `complete` for the
+//! command, otherwise they complete to paths relative to the working
+//! directory.
+
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use crate::array::Array;
+use crate::internals::INTERNALS_MAP;
+use crate::shell_base::{shell_quote, Shell};
+
+/// Characters that end a word unless escaped
+const SEPARATORS: &str = " \t\n;|&()<>";
+/// Characters escaped with a backslash in completed names
+const SPECIAL: &str = " \t\n;|&()<>'\"\\$`*?[]{}#~!";
+/// Words after which a new command starts
+const COMMAND_WORDS: [&str; 10] = [
+    "!", "{", "do", "elif", "else", "if", "then", "time", "until", "while",
+];
+
+/// How arguments of a command are completed, registered with `complete`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompletionSpec {
+    pub aliases: bool,
+    pub builtins: bool,
+    pub commands: bool,
+    pub directories: bool,
+    pub files: bool,
+    /// Candidates separated by blanks, `-W`
+    pub words: Option<String>,
+    /// Command run in the shell that sets `COMPREPLY`, `-F`
+    pub function: Option<String>,
+}
+
+impl CompletionSpec {
+    /// `complete` command registering the spec for `name`
+    pub fn to_command(&self, name: &str) -> String {
+        let mut command = String::from("complete");
+        for (set, flag) in [
+            (self.aliases, " -a"),
+            (self.builtins, " -b"),
+            (self.commands, " -c"),
+            (self.directories, " -d"),
+            (self.files, " -f"),
+        ] {
+            if set {
+                command.push_str(flag);
+            }
+        }
+        if let Some(words) = &self.words {
+            command.push_str(&format!(" -W {}", shell_quote(words)));
+        }
+        if let Some(function) = &self.function {
+            command.push_str(&format!(" -F {}", shell_quote(function)));
+        }
+        format!("{command} {}", shell_quote(name))
+    }
+}
+
+/// Candidates for the word ending at the cursor
+#[derive(Debug, Default)]
+pub struct Completion {
+    /// Number of characters of the word before the cursor
+    pub word_len: usize,
+    /// Completed words, escaped for the command line. Directories end with
+    /// a slash.
+    pub candidates: Vec<String>,
+}
+
+/// Completes the word ending at `cursor` in `line`
+pub fn complete(shell: &mut Shell, line: &[char], cursor: usize) -> Completion {
+    let start = word_start(line, cursor);
+    let word: String = line[start..cursor].iter().collect();
+    let before: String = line[..start].iter().collect();
+    let candidates = if starts_command(&before) && !word.contains('/') {
+        commands(shell, &unescape(&word))
+    } else {
+        let words = command_words(&before);
+        let spec = words
+            .first()
+            .and_then(|name| shell.completions.get(name))
+            .cloned();
+        match spec {
+            Some(spec) => {
+                let line: String = line.iter().collect();
+                programmed(shell, &spec, &words, &word, &line, cursor)
+            }
+            None => paths(shell, &word),
+        }
+    };
+    Completion {
+        word_len: cursor - start,
+        candidates,
+    }
+}
+
+/// Longest common prefix of `candidates`
+pub fn common_prefix(candidates: &[String]) -> String {
+    let mut prefix: Vec<char> = match candidates.first() {
+        Some(first) => first.chars().collect(),
+        None => return String::new(),
+    };
+    for candidate in &candidates[1..] {
+        let common = prefix
+            .iter()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        prefix.truncate(common);
+    }
+    prefix.into_iter().collect()
+}
+
+/// Index of the first character of the word ending at `cursor`
+fn word_start(line: &[char], cursor: usize) -> usize {
+    let mut start = cursor;
+    while start > 0 {
+        let escaped = start > 1 && line[start - 2] == '\\';
+        if SEPARATORS.contains(line[start - 1]) && !escaped {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+/// Whether a word following `before` is the name of a command
+fn starts_command(before: &str) -> bool {
+    let before = before.trim_end();
+    match before.chars().last() {
+        None => true,
+        Some(last) if ";|&(".contains(last) => true,
+        Some(_) => {
+            let previous = before
+                .rsplit(|c: char| SEPARATORS.contains(c))
+                .next()
+                .unwrap_or_default();
+            COMMAND_WORDS.contains(&previous)
+        }
+    }
+}
+
+/// Unescaped words of the command `before` ends in, without reserved words
+/// preceding its name
+fn command_words(before: &str) -> Vec<String> {
+    let command = before
+        .rsplit(|c: char| ";|&(\n".contains(c))
+        .next()
+        .unwrap_or_default();
+    command
+        .split_whitespace()
+        .skip_while(|word| COMMAND_WORDS.contains(word))
+        .map(unescape)
+        .collect()
+}
+
+/// Candidates of `spec` for `word`, the argument following `words`
+fn programmed(
+    shell: &mut Shell,
+    spec: &CompletionSpec,
+    words: &[String],
+    word: &str,
+    line: &str,
+    cursor: usize,
+) -> Vec<String> {
+    let prefix = unescape(word);
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    if spec.aliases {
+        names.extend(shell.aliases.keys().cloned());
+    }
+    if spec.builtins {
+        names.extend(INTERNALS_MAP.keys().map(|name| name.to_string()));
+    }
+    if let Some(list) = &spec.words {
+        names.extend(list.split_whitespace().map(String::from));
+    }
+    let mut candidates: Vec<String> = names
+        .into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .map(|name| escape(&name))
+        .collect();
+    if spec.commands {
+        candidates.extend(commands(shell, &prefix));
+    }
+    if spec.files || spec.directories {
+        candidates.extend(
+            paths(shell, word)
+                .into_iter()
+                .filter(|path| spec.files || path.ends_with('/')),
+        );
+    }
+    // the command filters `COMPREPLY` itself
+    if let Some(function) = &spec.function {
+        let reply = comp_reply(shell, function, words, &prefix, line, cursor);
+        candidates.extend(reply.iter().map(|candidate| escape(candidate)));
+    }
+    candidates.sort();
+    candidates.dedup();
+    candidates
+}
+
+/// Runs `command` with `COMP_WORDS`, `COMP_CWORD`, `COMP_LINE` and
+/// `COMP_POINT` describing the line, returns the `COMPREPLY` it set
+fn comp_reply(
+    shell: &mut Shell,
+    command: &str,
+    words: &[String],
+    word: &str,
+    line: &str,
+    cursor: usize,
+) -> Vec<String> {
+    let mut comp_words = words.to_vec();
+    comp_words.push(word.to_string());
+    shell
+        .arrays
+        .insert(String::from("COMP_WORDS"), Array::from_values(comp_words));
+    shell
+        .vars
+        .insert(String::from("COMP_CWORD"), words.len().to_string());
+    shell
+        .vars
+        .insert(String::from("COMP_LINE"), line.to_string());
+    shell
+        .vars
+        .insert(String::from("COMP_POINT"), cursor.to_string());
+    shell.arrays.remove("COMPREPLY");
+
+    let exit_status = shell.last_exit_status;
+    if let Err(error) = shell.run_command(command) {
+        eprintln!("\n{}: complete: {}", env!("CARGO_PKG_NAME"), error);
+    }
+    shell.last_exit_status = exit_status;
+
+    shell.arrays.remove("COMP_WORDS");
+    for name in ["COMP_CWORD", "COMP_LINE", "COMP_POINT"] {
+        shell.vars.remove(name);
+    }
+    match shell.arrays.remove("COMPREPLY") {
+        Some(reply) => reply.values(),
+        None => shell.vars.remove("COMPREPLY").into_iter().collect(),
+    }
+}
+
+/// `word` without quotes and escaping backslashes
+fn unescape(word: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            '\'' | '"' => {}
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+fn escape(name: &str) -> String {
+    let mut escaped = String::new();
+    for c in name.chars() {
+        if SPECIAL.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Aliases, internals and files in `PATH` starting with `prefix`
+fn commands(shell: &Shell, prefix: &str) -> Vec<String> {
+    let mut names: BTreeSet<String> = BTreeSet::new();
+    names.extend(shell.aliases.keys().cloned());
+    names.extend(INTERNALS_MAP.keys().map(|name| name.to_string()));
+    for bin_dir in env::var("PATH").unwrap_or_default().split(':') {
+        if let Ok(entries) = fs::read_dir(bin_dir) {
+            names.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|entry| !entry.path().is_dir())
+                    .filter_map(|entry| entry.file_name().into_string().ok()),
+            );
+        }
+    }
+    names
+        .into_iter()
+        .filter(|name| name.starts_with(prefix))
+        .map(|name| escape(&name))
+        .collect()
+}
+
+/// Paths starting with `word`, relative ones to the working directory
+fn paths(shell: &Shell, word: &str) -> Vec<String> {
+    // the typed directory part is kept as it is
+    let (dir, file) = match word.rfind('/') {
+        Some(slash) => word.split_at(slash + 1),
+        None => ("", word),
+    };
+    let file = unescape(file);
+    let unescaped_dir = unescape(dir);
+    let search_dir = match unescaped_dir.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => {
+            PathBuf::from(env::var("HOME").unwrap_or_else(|_| String::from("/")))
+                .join(rest.trim_start_matches('/'))
+        }
+        _ => shell.pwd.join(&unescaped_dir),
+    };
+    let entries = match fs::read_dir(search_dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut candidates: Vec<String> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // hidden files are completed only when asked for
+            if !name.starts_with(&file) || (name.starts_with('.') && !file.starts_with('.')) {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{dir}{}{slash}", escape(&name)))
+        })
+        .collect();
+    candidates.sort();
+    candidates
+}
@@ -0,0 +1,292 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Evaluation of `[[ ... ]]` extended conditionals. Words are expanded by
+//! the interpreter without field splitting and pathname expansion, `==` and
+//! `!=` match the right side as a glob pattern and `=~` as a regex.
+
+use std::env;
+use std::fs;
+
+use glob::Pattern;
+use regex::Regex;
+
+use crate::arith;
+use crate::array::Array;
+use crate::shell_base::Shell;
+
+/// Word between `[[` and `]]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CondWord {
+    /// Unquoted `&&`, `||`, `!`, `(` or `)`
+    Operator(String),
+    /// Word expanded only when its value is needed. `literal` is its text
+    /// when it has neither quotes nor expansions, only then it can be an
+    /// operator like `-n` or `==`.
+    Operand {
+        literal: Option<String>,
+        index: usize,
+    },
+}
+
+/// Expanded operand
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Operand {
+    pub value: String,
+    /// The word as a glob pattern, quoted parts match literally
+    pub pattern: String,
+    /// The word as a regex, quoted parts match literally
+    pub regex: String,
+}
+
+const UNARY: [&str; 10] = ["-n", "-z", "-e", "-a", "-f", "-d", "-s", "-L", "-h", "-v"];
+const BINARY: [&str; 13] = [
+    "==", "=", "!=", "=~", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-ef",
+];
+
+struct Cond<'a> {
+    shell: &'a mut Shell,
+    words: &'a [CondWord],
+    expand: &'a mut dyn FnMut(&mut Shell, usize) -> Operand,
+    pos: usize,
+    /// Set on the side of `&&` or `||` that cannot change the result, its
+    /// operands are parsed but neither expanded nor tested
+    skip: bool,
+}
+
+/// Evaluates the words of `[[ ... ]]`, without the brackets. `expand` gives
+/// the value of the operand with an index.
+pub fn eval(
+    shell: &mut Shell,
+    words: &[CondWord],
+    expand: &mut dyn FnMut(&mut Shell, usize) -> Operand,
+) -> Result<bool, String> {
+    let mut cond = Cond {
+        shell,
+        words,
+        expand,
+        pos: 0,
+        skip: false,
+    };
+    let result = cond.or()?;
+    match words.get(cond.pos) {
+        None => Ok(result),
+        Some(word) => Err(format!("syntax error near `{}'", cond.text(word))),
+    }
+}
+
+impl<'a> Cond<'a> {
+    /// Skips operator `name` if it is next
+    fn operator(&mut self, name: &str) -> bool {
+        let found = match self.words.get(self.pos) {
+            Some(CondWord::Operator(operator)) => operator == name,
+            _ => false,
+        };
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn operand_at(&self, pos: usize) -> Option<&'a CondWord> {
+        match self.words.get(pos) {
+            Some(word @ CondWord::Operand { .. }) => Some(word),
+            _ => None,
+        }
+    }
+
+    /// Text of the operand at `pos` if it can be an operator
+    fn literal_at(&self, pos: usize) -> Option<&'a str> {
+        match self.words.get(pos) {
+            Some(CondWord::Operand {
+                literal: Some(literal),
+                ..
+            }) => Some(literal),
+            _ => None,
+        }
+    }
+
+    fn value(&mut self, word: &CondWord) -> Operand {
+        match word {
+            CondWord::Operand { index, .. } => (self.expand)(self.shell, *index),
+            CondWord::Operator(operator) => Operand {
+                value: operator.clone(),
+                ..Operand::default()
+            },
+        }
+    }
+
+    fn text(&mut self, word: &CondWord) -> String {
+        match word {
+            CondWord::Operand {
+                literal: Some(literal),
+                ..
+            } => literal.clone(),
+            word => self.value(word).value,
+        }
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.operator("||") {
+            let skip = self.skip;
+            self.skip = skip || result;
+            let rhs = self.and();
+            self.skip = skip;
+            result = rhs? || result;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.operator("&&") {
+            let skip = self.skip;
+            self.skip = skip || !result;
+            let rhs = self.not();
+            self.skip = skip;
+            result = rhs? && result;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        if self.operator("!") {
+            Ok(!self.not()?)
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        if self.operator("(") {
+            let result = self.or()?;
+            if !self.operator(")") {
+                return Err(String::from("expected `)'"));
+            }
+            return Ok(result);
+        }
+        let first = match self.operand_at(self.pos) {
+            Some(first) => first,
+            None => {
+                return Err(match self.words.get(self.pos) {
+                    Some(word) => format!("syntax error near `{}'", self.text(word)),
+                    None => String::from("unexpected end of expression"),
+                })
+            }
+        };
+        let operator = self.literal_at(self.pos + 1);
+        if let (Some(operator), Some(rhs)) = (operator, self.operand_at(self.pos + 2)) {
+            if BINARY.contains(&operator) {
+                self.pos += 3;
+                if self.skip {
+                    return Ok(false);
+                }
+                let lhs = self.value(first).value;
+                let rhs = self.value(rhs);
+                return self.binary(&lhs, operator, &rhs);
+            }
+        }
+        if let (Some(operator), Some(operand)) =
+            (self.literal_at(self.pos), self.operand_at(self.pos + 1))
+        {
+            if UNARY.contains(&operator) {
+                self.pos += 2;
+                if self.skip {
+                    return Ok(false);
+                }
+                let operand = self.value(operand).value;
+                return Ok(self.unary(operator, &operand));
+            }
+        }
+        self.pos += 1;
+        if self.skip {
+            return Ok(false);
+        }
+        Ok(!self.value(first).value.is_empty())
+    }
+
+    fn unary(&self, operator: &str, operand: &str) -> bool {
+        let path = self.shell.pwd.join(operand);
+        match operator {
+            "-n" => !operand.is_empty(),
+            "-z" => operand.is_empty(),
+            "-e" | "-a" => path.exists(),
+            "-f" => path.is_file(),
+            "-d" => path.is_dir(),
+            "-s" => fs::metadata(&path).is_ok_and(|metadata| metadata.len() > 0),
+            "-L" | "-h" => path.is_symlink(),
+            "-v" => {
+                self.shell.vars.contains_key(operand)
+                    || env::var(operand).is_ok()
+                    || self.shell.dynamic_var(operand).is_some()
+                    || self
+                        .shell
+                        .arrays
+                        .get(operand)
+                        .is_some_and(|array| !array.is_empty())
+            }
+            _ => unreachable!("unknown unary operator {}", operator),
+        }
+    }
+
+    fn binary(&mut self, lhs: &str, operator: &str, rhs: &Operand) -> Result<bool, String> {
+        let Operand {
+            value,
+            pattern,
+            regex,
+        } = rhs;
+        Ok(match operator {
+            "==" | "=" | "!=" => {
+                let matched = match Pattern::new(pattern) {
+                    Ok(pattern) => pattern.matches(lhs),
+                    Err(_) => value == lhs,
+                };
+                matched == (operator != "!=")
+            }
+            "=~" => {
+                let regex = Regex::new(regex).map_err(|err| format!("{value}: {err}"))?;
+                match regex.captures(lhs) {
+                    Some(captures) => {
+                        let groups = captures
+                            .iter()
+                            .map(|group| group.map_or("", |group| group.as_str()).to_string())
+                            .collect();
+                        self.shell
+                            .arrays
+                            .insert(String::from("BASH_REMATCH"), Array::from_values(groups));
+                        true
+                    }
+                    None => {
+                        self.shell.arrays.remove("BASH_REMATCH");
+                        false
+                    }
+                }
+            }
+            "<" => lhs < value.as_str(),
+            ">" => lhs > value.as_str(),
+            "-ef" => {
+                let lhs = fs::canonicalize(self.shell.pwd.join(lhs));
+                let rhs = fs::canonicalize(self.shell.pwd.join(value));
+                matches!((lhs, rhs), (Ok(lhs), Ok(rhs)) if lhs == rhs)
+            }
+            _ => {
+                // operands are arithmetic expressions, like in `((...))`
+                let lhs = arith::eval_text(self.shell, lhs)?;
+                let rhs = arith::eval_text(self.shell, value)?;
+                match operator {
+                    "-eq" => lhs == rhs,
+                    "-ne" => lhs != rhs,
+                    "-lt" => lhs < rhs,
+                    "-le" => lhs <= rhs,
+                    "-gt" => lhs > rhs,
+                    "-ge" => lhs >= rhs,
+                    _ => unreachable!("unknown binary operator {}", operator),
+                }
+            }
+        })
+    }
+}
@@ -0,0 +1,303 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use color_eyre::Report;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Bool(bool),
+    Array(Vec<ConfigValue>),
+}
+
+impl fmt::Display for ConfigValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigValue::String(s) => write!(f, "{s:?}"),
+            ConfigValue::Integer(i) => write!(f, "{i}"),
+            ConfigValue::Float(n) => write!(f, "{n}"),
+            ConfigValue::Bool(b) => write!(f, "{b}"),
+            ConfigValue::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl ConfigValue {
+    /// Parses TOML value, falls back to plain string for unquoted text
+    pub fn parse_lenient(input: &str) -> ConfigValue {
+        parse_value(input.trim()).unwrap_or_else(|_| ConfigValue::String(input.to_string()))
+    }
+}
+
+/// Keys the shell reads, the ones ending with a dot stand for whole tables
+const KNOWN_KEYS: [&str; 21] = [
+    "aliases.",
+    "completion.style",
+    "history.size",
+    "hooks.",
+    "keybindings.",
+    "limits.depth",
+    "limits.expansion_size",
+    "limits.loop_iterations",
+    "limits.time",
+    "limits.unpacked_size",
+    "notify.after",
+    "notify.style",
+    "prompt.ps1",
+    "prompt.ps2",
+    "rc.project",
+    "terminal.colors",
+    "terminal.hyperlinks",
+    "terminal.report_cwd",
+    "terminal.title",
+    "theme.colors.",
+    "theme.name",
+];
+
+/// Whether the shell uses `key`, others are most likely misspelled
+pub fn is_known_key(key: &str) -> bool {
+    KNOWN_KEYS
+        .iter()
+        .any(|known| key == *known || (known.ends_with('.') && key.starts_with(known)))
+}
+
+/// Shell options read from `config.toml`, stored under dotted keys
+/// (e.g. `history.size`, `prompt.ps1`, `aliases.ll`)
+#[derive(Debug, Default)]
+pub struct Config {
+    values: BTreeMap<String, ConfigValue>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Report> {
+        let content = fs::read_to_string(path)?;
+        Config::parse(&content).map_err(|e| Report::msg(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Parses the TOML subset used by the configuration: tables, dotted keys,
+    /// strings, integers, floats, booleans and single-line arrays.
+    pub fn parse(input: &str) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut table = String::new();
+
+        for (n, line) in input.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |msg: &str| format!("line {}: {}", n + 1, msg);
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header
+                    .strip_suffix(']')
+                    .ok_or_else(|| error("unterminated table header"))?;
+                table = parse_key(header.trim()).map_err(|e| error(&e))?;
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected key = value"))?;
+            let key = parse_key(key.trim()).map_err(|e| error(&e))?;
+            let value = parse_value(value.trim()).map_err(|e| error(&e))?;
+            let full_key = if table.is_empty() {
+                key
+            } else {
+                format!("{table}.{key}")
+            };
+            config.values.insert(full_key, value);
+        }
+        Ok(config)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.values.get(key)
+    }
+
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        match self.values.get(key) {
+            Some(ConfigValue::String(s)) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        match self.values.get(key) {
+            Some(ConfigValue::Integer(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    /// Number of seconds given as an integer or a float
+    pub fn get_seconds(&self, key: &str) -> Option<f64> {
+        match self.values.get(key) {
+            Some(ConfigValue::Integer(i)) => Some(*i as f64),
+            Some(ConfigValue::Float(f)) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.values.get(key) {
+            Some(ConfigValue::Bool(b)) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn set(&mut self, key: &str, value: ConfigValue) {
+        self.values.insert(key.to_string(), value);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &ConfigValue)> {
+        self.values.iter()
+    }
+
+    /// Keys the shell does not use
+    pub fn unknown_keys(&self) -> impl Iterator<Item = &str> {
+        self.values
+            .keys()
+            .map(String::as_str)
+            .filter(|key| !is_known_key(key))
+    }
+
+    /// Returns entries of a table with the table prefix stripped from keys
+    pub fn table<'a>(&'a self, name: &str) -> impl Iterator<Item = (&'a str, &'a ConfigValue)> {
+        let prefix = format!("{name}.");
+        self.values
+            .iter()
+            .filter_map(move |(k, v)| k.strip_prefix(&prefix).map(|k| (k, v)))
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '#') => return &line[..i],
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_key(key: &str) -> Result<String, String> {
+    let parts = key
+        .split('.')
+        .map(|part| {
+            let part = part.trim();
+            let unquoted = part
+                .strip_prefix('"')
+                .and_then(|p| p.strip_suffix('"'))
+                .or_else(|| part.strip_prefix('\'').and_then(|p| p.strip_suffix('\'')));
+            match unquoted {
+                Some(p) => Ok(p.to_string()),
+                None if !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+                {
+                    Ok(part.to_string())
+                }
+                None => Err(format!("invalid key '{key}'")),
+            }
+        })
+        .collect::<Result<Vec<String>, String>>()?;
+    Ok(parts.join("."))
+}
+
+fn parse_value(value: &str) -> Result<ConfigValue, String> {
+    if let Some(inner) = value.strip_prefix('"') {
+        let inner = inner
+            .strip_suffix('"')
+            .ok_or_else(|| "unterminated string".to_string())?;
+        let mut result = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                result.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => result.push('\n'),
+                Some('t') => result.push('\t'),
+                Some('r') => result.push('\r'),
+                Some('e') => result.push('\x1b'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let code = u32::from_str_radix(&hex, 16)
+                        .map_err(|_| format!("invalid unicode escape '\\u{hex}'"))?;
+                    result.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                }
+                Some(c) => result.push(c),
+                None => return Err("unterminated escape sequence".to_string()),
+            }
+        }
+        Ok(ConfigValue::String(result))
+    } else if let Some(inner) = value.strip_prefix('\'') {
+        inner
+            .strip_suffix('\'')
+            .map(|s| ConfigValue::String(s.to_string()))
+            .ok_or_else(|| "unterminated string".to_string())
+    } else if let Some(inner) = value.strip_prefix('[') {
+        let inner = inner
+            .strip_suffix(']')
+            .ok_or_else(|| "unterminated array".to_string())?;
+        split_array(inner)
+            .into_iter()
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| parse_value(item.trim()))
+            .collect::<Result<Vec<_>, _>>()
+            .map(ConfigValue::Array)
+    } else if value == "true" || value == "false" {
+        Ok(ConfigValue::Bool(value == "true"))
+    } else if let Ok(i) = value.replace('_', "").parse::<i64>() {
+        Ok(ConfigValue::Integer(i))
+    } else if let Ok(f) = value.replace('_', "").parse::<f64>() {
+        Ok(ConfigValue::Float(f))
+    } else {
+        Err(format!("invalid value '{value}'"))
+    }
+}
+
+fn split_array(inner: &str) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match (quote, c) {
+            (None, '"') | (None, '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                items.push(&inner[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    items.push(&inner[start..]);
+    items
+}
@@ -0,0 +1,367 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::fd::{FromRawFd, RawFd};
+
+use color_eyre::Report;
+
+use crate::json::Json;
+use crate::shell_base::{Fd, Shell};
+
+const THREAD_ID: i64 = 1;
+const FRAME_ID: i64 = 1;
+const LOCALS_REF: i64 = 1;
+const ARGUMENTS_REF: i64 = 2;
+const ENVIRONMENT_REF: i64 = 3;
+
+#[derive(PartialEq)]
+enum RunMode {
+    Continue,
+    Step,
+}
+
+/// Script debugger speaking Debug Adapter Protocol over a file descriptor.
+///
+/// Breakpoints and stepping work on top-level commands: a breakpoint set on
+/// any line of a multi-line command (e.g. a loop) stops before that command.
+pub struct Debugger {
+    reader: BufReader<File>,
+    writer: File,
+    seq: i64,
+    mode: RunMode,
+    breakpoints: HashMap<String, HashSet<usize>>,
+    current_source: String,
+    current_line: usize,
+    terminated: bool,
+}
+
+fn normalize_path(path: &str) -> String {
+    fs::canonicalize(path)
+        .map(|p| p.display().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+impl Debugger {
+    pub fn new(fd: Fd) -> Result<Self, Report> {
+        let input = unsafe { File::from_raw_fd(fd as RawFd) };
+        let writer = input.try_clone()?;
+        Ok(Debugger {
+            reader: BufReader::new(input),
+            writer,
+            seq: 1,
+            mode: RunMode::Continue,
+            breakpoints: HashMap::new(),
+            current_source: String::new(),
+            current_line: 0,
+            terminated: false,
+        })
+    }
+
+    fn read_message(&mut self) -> Result<Option<Json>, Report> {
+        let mut content_length = None;
+        loop {
+            let mut header = String::new();
+            if self.reader.read_line(&mut header)? == 0 {
+                return Ok(None);
+            }
+            let header = header.trim();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(length) = header.strip_prefix("Content-Length:") {
+                content_length = length.trim().parse::<usize>().ok();
+            }
+        }
+
+        let length = content_length
+            .ok_or_else(|| Report::msg("DAP message without Content-Length header"))?;
+        let mut body = vec![0u8; length];
+        self.reader.read_exact(&mut body)?;
+        Json::parse(&String::from_utf8_lossy(&body))
+            .map(Some)
+            .map_err(|e| Report::msg(format!("invalid DAP message: {e}")))
+    }
+
+    fn send(&mut self, mut message: Vec<(&str, Json)>) -> Result<(), Report> {
+        message.insert(0, ("seq", Json::from(self.seq)));
+        self.seq += 1;
+        let body = Json::object(message).to_string();
+        write!(
+            self.writer,
+            "Content-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> Result<(), Report> {
+        let request_seq = request.get("seq").and_then(Json::as_i64).unwrap_or(0);
+        let command = request
+            .get("command")
+            .and_then(Json::as_str)
+            .unwrap_or("")
+            .to_string();
+        self.send(vec![
+            ("type", Json::from("response")),
+            ("request_seq", Json::from(request_seq)),
+            ("success", Json::from(true)),
+            ("command", Json::from(command)),
+            ("body", body),
+        ])
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> Result<(), Report> {
+        self.send(vec![
+            ("type", Json::from("event")),
+            ("event", Json::from(event)),
+            ("body", body),
+        ])
+    }
+
+    fn stopped(&mut self, reason: &str) -> Result<(), Report> {
+        self.event(
+            "stopped",
+            Json::object(vec![
+                ("reason", Json::from(reason)),
+                ("threadId", Json::from(THREAD_ID)),
+                ("allThreadsStopped", Json::from(true)),
+            ]),
+        )
+    }
+
+    fn variables(shell: &Shell, reference: i64) -> Json {
+        let mut vars: Vec<(String, String)> = match reference {
+            LOCALS_REF => shell
+                .vars
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            ARGUMENTS_REF => shell
+                .args
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (format!("${i}"), v.clone()))
+                .collect(),
+            ENVIRONMENT_REF => env::vars().collect(),
+            _ => Vec::new(),
+        };
+        if reference != ARGUMENTS_REF {
+            vars.sort();
+        }
+        Json::Array(
+            vars.into_iter()
+                .map(|(name, value)| {
+                    Json::object(vec![
+                        ("name", Json::from(name)),
+                        ("value", Json::from(value)),
+                        ("variablesReference", Json::from(0)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    /// Handles a single request, returns false when execution should resume.
+    fn handle_request(&mut self, request: &Json, shell: Option<&Shell>) -> Result<bool, Report> {
+        let command = request.get("command").and_then(Json::as_str).unwrap_or("");
+        let arguments = request.get("arguments").cloned().unwrap_or(Json::Null);
+        match command {
+            "initialize" => {
+                self.respond(
+                    request,
+                    Json::object(vec![
+                        ("supportsConfigurationDoneRequest", Json::from(true)),
+                        ("supportsEvaluateForHovers", Json::from(false)),
+                    ]),
+                )?;
+                self.event("initialized", Json::Null)?;
+            }
+            "launch" | "attach" => {
+                if arguments.get("stopOnEntry").and_then(Json::as_bool) == Some(true) {
+                    self.mode = RunMode::Step;
+                }
+                self.respond(request, Json::Null)?;
+            }
+            "setBreakpoints" => {
+                let path = arguments
+                    .get("source")
+                    .and_then(|s| s.get("path"))
+                    .and_then(Json::as_str)
+                    .map(normalize_path)
+                    .unwrap_or_default();
+                let lines: Vec<usize> = arguments
+                    .get("breakpoints")
+                    .and_then(Json::as_array)
+                    .map(|bps| {
+                        bps.iter()
+                            .filter_map(|bp| bp.get("line").and_then(Json::as_i64))
+                            .map(|line| line as usize)
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let verified = Json::Array(
+                    lines
+                        .iter()
+                        .map(|line| {
+                            Json::object(vec![
+                                ("verified", Json::from(true)),
+                                ("line", Json::from(*line as i64)),
+                            ])
+                        })
+                        .collect(),
+                );
+                self.breakpoints.insert(path, lines.into_iter().collect());
+                self.respond(request, Json::object(vec![("breakpoints", verified)]))?;
+            }
+            "configurationDone" => {
+                self.respond(request, Json::Null)?;
+                return Ok(false);
+            }
+            "threads" => {
+                let threads = Json::Array(vec![Json::object(vec![
+                    ("id", Json::from(THREAD_ID)),
+                    ("name", Json::from(env!("CARGO_PKG_NAME"))),
+                ])]);
+                self.respond(request, Json::object(vec![("threads", threads)]))?;
+            }
+            "stackTrace" => {
+                let frame = Json::object(vec![
+                    ("id", Json::from(FRAME_ID)),
+                    ("name", Json::from(self.current_source.clone())),
+                    (
+                        "source",
+                        Json::object(vec![("path", Json::from(self.current_source.clone()))]),
+                    ),
+                    ("line", Json::from(self.current_line as i64)),
+                    ("column", Json::from(1)),
+                ]);
+                self.respond(
+                    request,
+                    Json::object(vec![
+                        ("stackFrames", Json::Array(vec![frame])),
+                        ("totalFrames", Json::from(1)),
+                    ]),
+                )?;
+            }
+            "scopes" => {
+                let scope = |name: &str, reference: i64| {
+                    Json::object(vec![
+                        ("name", Json::from(name)),
+                        ("variablesReference", Json::from(reference)),
+                        ("expensive", Json::from(false)),
+                    ])
+                };
+                let scopes = Json::Array(vec![
+                    scope("Locals", LOCALS_REF),
+                    scope("Arguments", ARGUMENTS_REF),
+                    scope("Environment", ENVIRONMENT_REF),
+                ]);
+                self.respond(request, Json::object(vec![("scopes", scopes)]))?;
+            }
+            "variables" => {
+                let reference = arguments
+                    .get("variablesReference")
+                    .and_then(Json::as_i64)
+                    .unwrap_or(0);
+                let variables = match shell {
+                    Some(shell) => Self::variables(shell, reference),
+                    None => Json::Array(Vec::new()),
+                };
+                self.respond(request, Json::object(vec![("variables", variables)]))?;
+            }
+            "continue" => {
+                self.mode = RunMode::Continue;
+                self.respond(
+                    request,
+                    Json::object(vec![("allThreadsContinued", Json::from(true))]),
+                )?;
+                return Ok(false);
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.mode = RunMode::Step;
+                self.respond(request, Json::Null)?;
+                return Ok(false);
+            }
+            "pause" => {
+                self.mode = RunMode::Step;
+                self.respond(request, Json::Null)?;
+            }
+            "disconnect" | "terminate" => {
+                self.respond(request, Json::Null)?;
+                self.terminated = true;
+                return Ok(false);
+            }
+            _ => {
+                self.respond(request, Json::Null)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Processes requests until the client finishes configuration.
+    pub fn wait_for_configuration(&mut self) -> Result<(), Report> {
+        while let Some(request) = self.read_message()? {
+            if !self.handle_request(&request, None)? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Called before every top-level command, blocks while execution is stopped.
+    pub fn on_command(
+        &mut self,
+        shell: &Shell,
+        source: &str,
+        lines: (usize, usize),
+    ) -> Result<(), Report> {
+        if self.terminated {
+            return Ok(());
+        }
+        self.current_source = normalize_path(source);
+        self.current_line = lines.0;
+
+        let hit_breakpoint = self
+            .breakpoints
+            .get(&self.current_source)
+            .is_some_and(|bps| bps.iter().any(|l| *l >= lines.0 && *l <= lines.1));
+
+        let reason = if self.mode == RunMode::Step {
+            "step"
+        } else if hit_breakpoint {
+            "breakpoint"
+        } else {
+            return Ok(());
+        };
+
+        self.stopped(reason)?;
+        while let Some(request) = self.read_message()? {
+            if !self.handle_request(&request, Some(shell))? {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+
+    pub fn finish(&mut self, exit_code: i32) -> Result<(), Report> {
+        self.event(
+            "exited",
+            Json::object(vec![("exitCode", Json::from(exit_code as i64))]),
+        )?;
+        self.event("terminated", Json::Null)
+    }
+}
@@ -0,0 +1,281 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::io::{self, Read};
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const MD5_K: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+/// Splits data fed in arbitrary pieces into 64 byte blocks
+#[derive(Default)]
+struct BlockBuffer {
+    pending: Vec<u8>,
+    len: u64,
+}
+
+impl BlockBuffer {
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8])) {
+        self.len = self.len.wrapping_add(data.len() as u64);
+        if !self.pending.is_empty() {
+            let missing = (64 - self.pending.len()).min(data.len());
+            self.pending.extend_from_slice(&data[..missing]);
+            data = &data[missing..];
+            if self.pending.len() < 64 {
+                return;
+            }
+            compress(&self.pending);
+            self.pending.clear();
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            compress(block);
+        }
+        self.pending.extend_from_slice(blocks.remainder());
+    }
+
+    /// Appends Merkle–Damgård padding with message length in bits
+    fn finish(self, big_endian: bool, mut compress: impl FnMut(&[u8])) {
+        let bit_len = self.len.wrapping_mul(8);
+        let mut tail = self.pending;
+        tail.push(0x80);
+        while tail.len() % 64 != 56 {
+            tail.push(0);
+        }
+        if big_endian {
+            tail.extend_from_slice(&bit_len.to_be_bytes());
+        } else {
+            tail.extend_from_slice(&bit_len.to_le_bytes());
+        }
+        for block in tail.chunks(64) {
+            compress(block);
+        }
+    }
+}
+
+fn sha256_compress(h: &mut [u32; 8], block: &[u8]) {
+    let mut w = [0u32; 64];
+    for (i, word) in block.chunks(4).enumerate() {
+        w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+    }
+    for i in 16..64 {
+        let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+        let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16]
+            .wrapping_add(s0)
+            .wrapping_add(w[i - 7])
+            .wrapping_add(s1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut hh] = *h;
+    for (k, wi) in SHA256_K.iter().zip(w.iter()) {
+        let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+        let ch = (e & f) ^ (!e & g);
+        let t1 = hh
+            .wrapping_add(s1)
+            .wrapping_add(ch)
+            .wrapping_add(*k)
+            .wrapping_add(*wi);
+        let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+        let maj = (a & b) ^ (a & c) ^ (b & c);
+        let t2 = s0.wrapping_add(maj);
+        hh = g;
+        g = f;
+        f = e;
+        e = d.wrapping_add(t1);
+        d = c;
+        c = b;
+        b = a;
+        a = t1.wrapping_add(t2);
+    }
+    for (state, value) in h.iter_mut().zip([a, b, c, d, e, f, g, hh]) {
+        *state = state.wrapping_add(value);
+    }
+}
+
+fn md5_compress(h: &mut [u32; 4], block: &[u8]) {
+    let mut m = [0u32; 16];
+    for (i, word) in block.chunks(4).enumerate() {
+        m[i] = u32::from_le_bytes([word[0], word[1], word[2], word[3]]);
+    }
+
+    let [mut a, mut b, mut c, mut d] = *h;
+    for (i, k) in MD5_K.iter().enumerate() {
+        let (f, g) = match i / 16 {
+            0 => ((b & c) | (!b & d), i),
+            1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+            2 => (b ^ c ^ d, (3 * i + 5) % 16),
+            _ => (c ^ (b | !d), (7 * i) % 16),
+        };
+        let shift = MD5_SHIFTS[(i / 16) * 4 + i % 4];
+        let rotated = a
+            .wrapping_add(f)
+            .wrapping_add(*k)
+            .wrapping_add(m[g])
+            .rotate_left(shift);
+        a = d;
+        d = c;
+        c = b;
+        b = b.wrapping_add(rotated);
+    }
+    for (state, value) in h.iter_mut().zip([a, b, c, d]) {
+        *state = state.wrapping_add(value);
+    }
+}
+
+/// SHA-256 of data fed in pieces, e.g. while reading a large file
+pub struct Sha256 {
+    h: [u32; 8],
+    buffer: BlockBuffer,
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Sha256 {
+            h: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            buffer: BlockBuffer::default(),
+        }
+    }
+}
+
+impl Sha256 {
+    pub fn update(&mut self, data: &[u8]) {
+        let h = &mut self.h;
+        self.buffer.update(data, |block| sha256_compress(h, block));
+    }
+
+    pub fn finish(mut self) -> [u8; 32] {
+        let h = &mut self.h;
+        self.buffer.finish(true, |block| sha256_compress(h, block));
+        let mut digest = [0u8; 32];
+        for (i, word) in self.h.iter().enumerate() {
+            digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_be_bytes());
+        }
+        digest
+    }
+}
+
+/// MD5 of data fed in pieces, only for comparing with published checksums
+pub struct Md5 {
+    h: [u32; 4],
+    buffer: BlockBuffer,
+}
+
+impl Default for Md5 {
+    fn default() -> Self {
+        Md5 {
+            h: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            buffer: BlockBuffer::default(),
+        }
+    }
+}
+
+impl Md5 {
+    pub fn update(&mut self, data: &[u8]) {
+        let h = &mut self.h;
+        self.buffer.update(data, |block| md5_compress(h, block));
+    }
+
+    pub fn finish(mut self) -> [u8; 16] {
+        let h = &mut self.h;
+        self.buffer.finish(false, |block| md5_compress(h, block));
+        let mut digest = [0u8; 16];
+        for (i, word) in self.h.iter().enumerate() {
+            digest[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        digest
+    }
+}
+
+pub fn sha256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::default();
+    hasher.update(data);
+    hasher.finish()
+}
+
+/// Hash functions available to the `checksum` internal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Sha256,
+    Md5,
+}
+
+impl Algorithm {
+    pub const ALL: [Algorithm; 2] = [Algorithm::Sha256, Algorithm::Md5];
+
+    pub fn parse(name: &str) -> Option<Algorithm> {
+        Algorithm::ALL
+            .iter()
+            .copied()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Md5 => "md5",
+        }
+    }
+
+    /// Length of the digest written as hex
+    pub fn hex_len(self) -> usize {
+        match self {
+            Algorithm::Sha256 => 64,
+            Algorithm::Md5 => 32,
+        }
+    }
+
+    /// Hex digest of everything read from `reader`, read in chunks so that
+    /// large files are not loaded into memory
+    pub fn digest_reader(self, reader: &mut dyn Read) -> io::Result<String> {
+        let mut sha256 = Sha256::default();
+        let mut md5 = Md5::default();
+        let mut chunk = vec![0u8; 64 * 1024];
+        loop {
+            let read = match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+            match self {
+                Algorithm::Sha256 => sha256.update(&chunk[..read]),
+                Algorithm::Md5 => md5.update(&chunk[..read]),
+            }
+        }
+        Ok(match self {
+            Algorithm::Sha256 => to_hex(&sha256.finish()),
+            Algorithm::Md5 => to_hex(&md5.finish()),
+        })
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
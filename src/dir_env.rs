@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::trust::{Trust, TrustStore};
+
+const ENV_FILES: [&str; 2] = [".envrc", ".env"];
+
+/// Environment loaded from a directory, remembered so it can be undone on leave
+#[derive(Debug)]
+struct LoadedEnv {
+    file: PathBuf,
+    dir: PathBuf,
+    saved: Vec<(String, Option<String>)>,
+}
+
+/// Per-directory environment loading in the spirit of direnv.
+///
+/// Only `[export] NAME=VALUE` assignments are read from `.envrc`/`.env`,
+/// files are never executed. A file is loaded only after it has been
+/// allowed with the `allow` builtin.
+#[derive(Debug, Default)]
+pub struct DirEnv {
+    enabled: bool,
+    loaded: Option<LoadedEnv>,
+}
+
+/// Returns the env file closest to `dir`, searching parent directories
+pub fn find_env_file(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .flat_map(|d| ENV_FILES.iter().map(move |name| d.join(name)))
+        .find(|file| file.is_file())
+}
+
+fn set_env(key: &str, value: Option<&str>) {
+    #[cfg(target_os = "wasi")]
+    {
+        _ = wasi_ext_lib::set_env(key, value);
+    }
+    #[cfg(not(target_os = "wasi"))]
+    match value {
+        Some(value) => env::set_var(key, value),
+        None => env::remove_var(key),
+    }
+}
+
+fn unquote(value: &str) -> String {
+    let value = value.trim();
+    if let Some(inner) = value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')) {
+        inner.to_string()
+    } else if let Some(inner) = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+        inner.replace("\\\"", "\"").replace("\\\\", "\\")
+    } else {
+        // unquoted values end at an inline comment
+        value
+            .split(" #")
+            .next()
+            .unwrap_or("")
+            .trim_end()
+            .to_string()
+    }
+}
+
+pub fn parse_env_file(content: &str) -> Vec<(String, String)> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let key = key.trim();
+            let valid = !key.is_empty()
+                && !key.starts_with(|c: char| c.is_ascii_digit())
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            valid.then(|| (key.to_string(), unquote(value)))
+        })
+        .collect()
+}
+
+impl DirEnv {
+    /// Turns loading on, it is only done in interactive sessions
+    pub fn enable(&mut self, pwd: &Path) {
+        self.enabled = true;
+        self.update(pwd);
+    }
+
+    /// Unloads environment of the previous directory and loads the one for `pwd`
+    pub fn update(&mut self, pwd: &Path) {
+        if !self.enabled {
+            return;
+        }
+        let file = find_env_file(pwd);
+        if let Some(loaded) = &self.loaded {
+            if Some(&loaded.file) == file.as_ref() && pwd.starts_with(&loaded.dir) {
+                return;
+            }
+        }
+        self.unload();
+
+        let file = match file {
+            Some(file) => file,
+            None => return,
+        };
+        match TrustStore::load().check(&file) {
+            Trust::Allowed => self.load(file),
+            Trust::Denied => {}
+            Trust::Unknown => eprintln!(
+                "{}: {} is blocked, run `allow` to approve its content",
+                env!("CARGO_PKG_NAME"),
+                file.display()
+            ),
+        }
+    }
+
+    /// Forgets the loaded file so that the next update reloads it
+    pub fn reset(&mut self) {
+        self.unload();
+    }
+
+    fn load(&mut self, file: PathBuf) {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(error) => {
+                eprintln!("{}: {}: {}", env!("CARGO_PKG_NAME"), file.display(), error);
+                return;
+            }
+        };
+        let mut saved = Vec::new();
+        for (key, value) in parse_env_file(&content) {
+            if !saved.iter().any(|(k, _)| *k == key) {
+                saved.push((key.clone(), env::var(&key).ok()));
+            }
+            set_env(&key, Some(&value));
+        }
+        self.loaded = Some(LoadedEnv {
+            dir: file
+                .parent()
+                .unwrap_or_else(|| Path::new("/"))
+                .to_path_buf(),
+            file,
+            saved,
+        });
+    }
+
+    fn unload(&mut self) {
+        if let Some(loaded) = self.loaded.take() {
+            for (key, value) in loaded.saved {
+                set_env(&key, value.as_deref());
+            }
+        }
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::xdg;
+
+/// Once ranks add up to this value all of them are aged
+const MAX_TOTAL_RANK: f64 = 9000.0;
+const AGING_FACTOR: f64 = 0.9;
+/// Visits collected before the database is saved
+const VISIT_BATCH: usize = 16;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    path: PathBuf,
+    rank: f64,
+    last_visit: u64,
+}
+
+impl Entry {
+    /// Visit count weighted by how recently the directory was visited
+    fn frecency(&self, now: u64) -> f64 {
+        let age = now.saturating_sub(self.last_visit);
+        let weight = if age < 60 * 60 {
+            4.0
+        } else if age < 24 * 60 * 60 {
+            2.0
+        } else if age < 7 * 24 * 60 * 60 {
+            0.5
+        } else {
+            0.25
+        };
+        self.rank * weight
+    }
+
+    /// All patterns must appear in order in the path, the last one in its
+    /// final component, case-insensitively
+    fn matches(&self, patterns: &[String]) -> bool {
+        let path = self.path.to_string_lossy().to_lowercase();
+        let mut rest = path.as_str();
+        for pattern in patterns.iter().map(|pattern| pattern.to_lowercase()) {
+            match rest.find(&pattern) {
+                Some(pos) => rest = &rest[pos + pattern.len()..],
+                None => return false,
+            }
+        }
+        let last_component = &path[path.rfind('/').map_or(0, |pos| pos + 1)..];
+        match patterns.last() {
+            Some(last) => last_component.contains(&last.to_lowercase()),
+            None => true,
+        }
+    }
+}
+
+/// Visited directories ranked by frequency and recency, used by `z`
+#[derive(Debug)]
+pub struct DirDatabase {
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl DirDatabase {
+    pub fn load() -> DirDatabase {
+        let path = xdg::state_dir().join("dirs");
+        let entries = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.splitn(3, '\t');
+                Some(Entry {
+                    rank: fields.next()?.parse().ok()?,
+                    last_visit: fields.next()?.parse().ok()?,
+                    path: PathBuf::from(fields.next()?),
+                })
+            })
+            .collect();
+        DirDatabase { path, entries }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content: String = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{}\t{}\t{}\n",
+                    entry.rank,
+                    entry.last_visit,
+                    entry.path.display()
+                )
+            })
+            .collect();
+        xdg::write_atomic(&self.path, content.as_bytes())
+    }
+
+    /// Bumps ranks of directories visited at the given times, ages all
+    /// entries once their total grows too large
+    pub fn visit(&mut self, visits: &[(PathBuf, u64)]) -> io::Result<()> {
+        for (dir, time) in visits {
+            match self.entries.iter_mut().find(|entry| entry.path == *dir) {
+                Some(entry) => {
+                    entry.rank += 1.0;
+                    entry.last_visit = *time;
+                }
+                None => self.entries.push(Entry {
+                    path: dir.clone(),
+                    rank: 1.0,
+                    last_visit: *time,
+                }),
+            }
+            if self.entries.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+                for entry in self.entries.iter_mut() {
+                    entry.rank *= AGING_FACTOR;
+                }
+                self.entries.retain(|entry| entry.rank >= 1.0);
+            }
+        }
+        self.save()
+    }
+
+    /// Existing directories matching `patterns`, best match first
+    pub fn matches(&self, patterns: &[String]) -> Vec<(f64, &Path)> {
+        let now = now();
+        let mut matches: Vec<(f64, &Path)> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.matches(patterns) && entry.path.is_dir())
+            .map(|entry| (entry.frecency(now), entry.path.as_path()))
+            .collect();
+        matches.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        matches
+    }
+
+    /// Forgets directories that no longer exist
+    pub fn prune(&mut self) -> io::Result<()> {
+        self.entries.retain(|entry| entry.path.is_dir());
+        self.save()
+    }
+}
+
+/// Visits not written to the database yet, saving rewrites the whole file
+/// so it is done in batches
+#[derive(Debug, Default)]
+pub struct PendingVisits {
+    visits: Vec<(PathBuf, u64)>,
+}
+
+impl PendingVisits {
+    /// Records a visit of `dir`, returns whether the batch is full
+    pub fn push(&mut self, dir: &Path) -> bool {
+        self.visits.push((dir.to_path_buf(), now()));
+        self.visits.len() >= VISIT_BATCH
+    }
+
+    /// Writes the visits to the database
+    pub fn save(&mut self) -> io::Result<()> {
+        if self.visits.is_empty() {
+            return Ok(());
+        }
+        DirDatabase::load().visit(&self.visits)?;
+        self.visits.clear();
+        Ok(())
+    }
+}
@@ -4,79 +4,310 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::env;
-use std::fs;
-use std::path::PathBuf;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use color_eyre::Report;
+use glob::{MatchOptions, Pattern};
 use lazy_static::lazy_static;
+use regex::{Regex, RegexBuilder};
 
+use crate::archive;
+use crate::argparse::{Matches, Opt, Spec};
+use crate::arith;
+use crate::array::{Array, Subscript};
+use crate::bookmarks::{self, Bookmarks};
+#[cfg(feature = "busybox")]
+use crate::busybox;
+use crate::completion::CompletionSpec;
+use crate::config::{self, ConfigValue};
+use crate::digest::Algorithm;
+use crate::dir_env::find_env_file;
+use crate::frecency::DirDatabase;
+use crate::jobs::Job;
+use crate::json::{self, Json};
+use crate::locale::tr;
 use crate::output_device::OutputDevice;
-use crate::shell_base::path_exists;
-use crate::shell_base::Shell;
-use crate::shell_base::{CLEAR_ESCAPE_CODE, EXIT_FAILURE, EXIT_SUCCESS};
+use crate::profiling::BenchStats;
+use crate::random;
+use crate::schedule;
+use crate::shell_base::{is_fd_tty, parse_duration, path_exists, shell_quote};
+use crate::shell_base::{Attributes, CommandKind, LoopControl, Shell, DEFAULT_PATH};
+use crate::shell_base::{
+    CLEAR_ESCAPE_CODE, EXIT_CMD_NOT_FOUND, EXIT_FAILURE, EXIT_INTERRUPTED, EXIT_SUCCESS, STDERR,
+    STDIN,
+};
+use crate::spell;
+use crate::terminal::ColorSupport;
+use crate::theme::{Role, Style, Theme};
+use crate::trap;
+use crate::trust::TrustStore;
+use crate::watch::FileWatcher;
+use crate::xdg;
 
 type Internal = fn(&mut Shell, &mut [String], &mut OutputDevice) -> Result<i32, Report>;
 
+/// Internals that only print output and do not change shell state, safe to run
+/// in-process for command substitutions. Ones that can read standard input are
+/// left out, the substitution's input is not set up for them.
+const PURE_INTERNALS: [&str; 5] = ["pwd", "type", "string", "uuid", "ls"];
+
+pub fn is_pure(name: &str) -> bool {
+    PURE_INTERNALS.contains(&name) && INTERNALS_MAP.contains_key(name)
+}
+
 fn clear(
     _shell: &mut Shell,
-    _args: &mut [String],
+    args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "clear",
+        about: "Clear the terminal screen",
+        operands: "",
+        options: &[],
+    };
+    if let Err(exit_status) = SPEC.parse(args, output_device) {
+        return Ok(exit_status);
+    }
     output_device.print(CLEAR_ESCAPE_CODE);
     Ok(EXIT_SUCCESS)
 }
 
 fn exit(
-    _shell: &mut Shell,
+    shell: &mut Shell,
     args: &mut [String],
-    _output_device: &mut OutputDevice,
+    output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
-    let exit_code: i32 = {
-        if args.is_empty() {
-            EXIT_SUCCESS
-        } else {
-            args[0].parse().unwrap()
+    const SPEC: Spec = Spec {
+        name: "exit",
+        about: "Exit the shell with a status, 0 by default",
+        operands: "[STATUS]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let exit_code: i32 = match matches.operands.first() {
+        Some(status) => status.parse().unwrap(),
+        None => EXIT_SUCCESS,
+    };
+    shell.exit(exit_code);
+}
+
+/// Sets commands run when the shell receives a signal or exits
+fn trap(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "trap",
+        about: "Run ACTION when the shell receives SIGNAL or exits (EXIT), '-' resets\n\
+                and '' ignores the signal, list traps without arguments",
+        operands: "[ACTION] [SIGNAL...]",
+        options: &[
+            Opt::flag('l', "list", "List signal names and numbers"),
+            Opt::flag('p', "print", "Print traps as commands setting them"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.flag("list") {
+        for (name, number) in trap::SIGNALS.iter().skip(1) {
+            output_device.println(&format!("{number:>2}) SIG{name}"));
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    if matches.flag("print") || matches.operands.is_empty() {
+        let mut exit_status = EXIT_SUCCESS;
+        let mut signals = Vec::new();
+        for spec in &matches.operands {
+            match trap::parse_signal(spec) {
+                Some(signal) => signals.push(signal),
+                None => {
+                    output_device.eprintln(&format!(
+                        "trap: {spec}: {}",
+                        tr("invalid signal specification")
+                    ));
+                    exit_status = EXIT_FAILURE;
+                }
+            }
+        }
+        let mut traps: Vec<(&i32, &String)> = shell
+            .traps
+            .iter()
+            .filter(|(signal, _)| matches.operands.is_empty() || signals.contains(signal))
+            .collect();
+        traps.sort();
+        for (signal, command) in traps {
+            output_device.println(&format!(
+                "trap -- {} {}",
+                shell_quote(command),
+                trap::signal_name(*signal)
+            ));
         }
+        return Ok(exit_status);
+    }
+    let (action, signals) = match matches.operands.as_slice() {
+        [] => unreachable!("traps are listed without operands"),
+        // a lone signal resets it
+        [signal] => ("-", std::slice::from_ref(signal)),
+        [action, signals @ ..] => (action.as_str(), signals),
     };
-    std::process::exit(exit_code);
+    let mut exit_status = EXIT_SUCCESS;
+    for spec in signals {
+        let signal = match trap::parse_signal(spec) {
+            Some(signal) => signal,
+            None => {
+                output_device.eprintln(&format!(
+                    "trap: {spec}: {}",
+                    tr("invalid signal specification")
+                ));
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+        };
+        let disposition = match action {
+            "-" => trap::Disposition::Default,
+            "" => trap::Disposition::Ignore,
+            _ => trap::Disposition::Catch,
+        };
+        if let Err(error) = trap::set_disposition(signal, disposition) {
+            output_device.eprintln(&format!("trap: {spec}: {error}"));
+            exit_status = EXIT_FAILURE;
+            continue;
+        }
+        if disposition == trap::Disposition::Default {
+            shell.traps.remove(&signal);
+        } else {
+            shell.traps.insert(signal, action.to_string());
+        }
+    }
+    Ok(exit_status)
 }
 
 fn pwd(
     _shell: &mut Shell,
-    _args: &mut [String],
+    args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "pwd",
+        about: "Print the working directory",
+        operands: "",
+        options: &[],
+    };
+    if let Err(exit_status) = SPEC.parse(args, output_device) {
+        return Ok(exit_status);
+    }
     output_device.println(&env::current_dir().unwrap().display().to_string());
     Ok(EXIT_SUCCESS)
 }
 
+/// With `shopt -s cdspell`, fixes typos in components of a missing `cd`
+/// target once the user confirms the corrected path
+fn correct_dir(shell: &Shell, path: PathBuf) -> PathBuf {
+    if !shell.shopt.cdspell || path.exists() {
+        return path;
+    }
+    let mut corrected = PathBuf::new();
+    for component in path.components() {
+        let candidate = corrected.join(component);
+        if candidate.exists() {
+            corrected = candidate;
+            continue;
+        }
+        let name = match component {
+            Component::Normal(name) => name.to_string_lossy(),
+            _ => return path,
+        };
+        let dirs = match fs::read_dir(&corrected) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| entry.file_name().into_string().ok()),
+            Err(_) => return path,
+        };
+        match spell::suggest(&name, dirs) {
+            Some(fixed) => corrected.push(fixed),
+            None => return path,
+        }
+    }
+    if shell.confirm(&format!("cd: did you mean {}?", corrected.display())) {
+        corrected
+    } else {
+        path
+    }
+}
+
 fn cd(
     shell: &mut Shell,
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "cd",
+        about: "Change the working directory to DIR (HOME by default), - is the previous\n\
+                directory and @NAME a bookmark",
+        operands: "[DIR]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let args = &matches.operands;
     let path = if args.is_empty() {
-        PathBuf::from(env::var("HOME").unwrap())
+        PathBuf::from(env::var("HOME").unwrap_or_else(|_| String::from("/")))
     } else if args[0] == "-" {
-        PathBuf::from(env::var("OLDPWD").unwrap())
+        match env::var("OLDPWD") {
+            Ok(oldpwd) => PathBuf::from(oldpwd),
+            Err(_) => {
+                output_device.eprintln("cd: OLDPWD not set");
+                return Ok(EXIT_FAILURE);
+            }
+        }
+    } else if args[0].starts_with('@') {
+        match Bookmarks::load().expand(&args[0]) {
+            Some(path) => path,
+            None => {
+                output_device.eprintln(&format!("cd: {}: no such bookmark", args[0]));
+                return Ok(EXIT_FAILURE);
+            }
+        }
     } else if args[0].starts_with('/') {
         PathBuf::from(&args[0])
     } else {
         PathBuf::from(&shell.pwd).join(&args[0])
     };
 
+    let path = correct_dir(shell, path);
+
     if !path_exists(path.to_str().unwrap())? {
         output_device.eprintln(&format!(
-            "cd: {}: No such file or directory",
-            path.display()
+            "cd: {}: {}",
+            path.display(),
+            tr("No such file or directory")
         ));
         Ok(EXIT_FAILURE)
     } else {
         let metadata = fs::metadata(&path).unwrap();
         if metadata.is_file() {
-            output_device.eprintln(&format!("cd: {}: Not a directory", path.display()));
+            output_device.eprintln(&format!(
+                "cd: {}: Not a directory",
+                output_device.file_link(STDERR, &path)
+            ));
             Ok(EXIT_FAILURE)
         } else {
             // TODO: for both targets, chain the commands and exit early if previous
@@ -99,16 +330,137 @@ fn cd(
                 env::set_var("PWD", &shell.pwd);
                 env::set_current_dir(&shell.pwd).unwrap();
             }
+            shell.chpwd();
             Ok(EXIT_SUCCESS)
         }
     }
 }
 
+/// Jumps to the most frecent visited directory matching all patterns
+fn z(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "z",
+        about: "Jump to the most frecent visited directory matching all patterns",
+        operands: "[PATTERN...]",
+        options: &[
+            Opt::flag('l', "list", "List matching directories with their scores"),
+            Opt::flag('x', "prune", "Forget directories that no longer exist"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    shell.save_dir_visits();
+    if matches.flag("prune") {
+        if let Err(error) = DirDatabase::load().prune() {
+            output_device.eprintln(&format!("z: {error}"));
+            return Ok(EXIT_FAILURE);
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    let patterns = &matches.operands;
+
+    let database = DirDatabase::load();
+    let dirs = database.matches(patterns);
+    if matches.flag("list") || patterns.is_empty() {
+        for (score, dir) in dirs.iter().rev() {
+            output_device.println(&format!("{:<10.1} {}", score, dir.display()));
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    match dirs.first() {
+        Some((_, dir)) => cd(shell, &mut [dir.display().to_string()], output_device),
+        None => {
+            output_device.eprintln(&format!("z: {}: no match found", patterns.join(" ")));
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+fn bookmark(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "bookmark",
+        about: "Manage directory bookmarks used as cd @NAME",
+        operands: "[list | add <NAME> [PATH] | remove <NAME>]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let args = &matches.operands;
+    let mut bookmarks = Bookmarks::load();
+    let result = match args.first().map(String::as_str) {
+        None | Some("list") if args.len() <= 1 => {
+            for (name, dir) in bookmarks.iter() {
+                output_device.println(&format!("@{:<15} {}", name, dir.display()));
+            }
+            return Ok(EXIT_SUCCESS);
+        }
+        Some("add") if args.len() == 2 || args.len() == 3 => {
+            let name = &args[1];
+            if !bookmarks::is_valid_name(name) {
+                output_device.eprintln(&format!("bookmark: {name}: invalid bookmark name"));
+                return Ok(EXIT_FAILURE);
+            }
+            let dir = match args.get(2) {
+                Some(dir) => shell.pwd.join(dir),
+                None => shell.pwd.clone(),
+            };
+            if !dir.is_dir() {
+                output_device.eprintln(&format!(
+                    "bookmark: {}: Not a directory",
+                    output_device.file_link(STDERR, &dir)
+                ));
+                return Ok(EXIT_FAILURE);
+            }
+            bookmarks.add(name, &fs::canonicalize(&dir).unwrap_or(dir))
+        }
+        Some("remove") if args.len() == 2 => match bookmarks.remove(&args[1]) {
+            Ok(false) => {
+                output_device.eprintln(&format!("bookmark: {}: no such bookmark", args[1]));
+                return Ok(EXIT_FAILURE);
+            }
+            result => result.map(|_| ()),
+        },
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    match result {
+        Ok(()) => Ok(EXIT_SUCCESS),
+        Err(error) => {
+            output_device.eprintln(&format!("bookmark: {error}"));
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
 fn history(
     shell: &mut Shell,
-    _args: &mut [String],
+    args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "history",
+        about: "Print the command history",
+        operands: "",
+        options: &[],
+    };
+    if let Err(exit_status) = SPEC.parse(args, output_device) {
+        return Ok(exit_status);
+    }
+    shell.load_history();
     for (i, history_entry) in shell.cli.history.iter().enumerate() {
         output_device.println(&format!(
             "{}: {}",
@@ -124,24 +476,204 @@ fn unset(
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
-    if args.is_empty() {
-        output_device.eprintln("unset: help: unset <VAR> [<VAR>] ...");
+    const SPEC: Spec = Spec {
+        name: "unset",
+        about: "Remove shell and environment variables",
+        operands: "<VAR>...",
+        options: &[
+            Opt::flag('v', "vars", "Remove variables (default)"),
+            Opt::flag('f', "functions", "Remove functions"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
         Ok(EXIT_FAILURE)
+    } else if matches.flag("functions") && !matches.flag("vars") {
+        // wash has no shell functions, there is nothing to remove
+        Ok(EXIT_SUCCESS)
     } else {
-        for arg in args {
+        let mut exit_status = EXIT_SUCCESS;
+        for arg in &matches.operands {
             if arg == "PWD" || arg == "HOME" {
                 output_device.println(&format!("unset: cannot unset {}", &arg));
+            } else if shell.attributes.get(arg).is_some_and(|a| a.readonly) {
+                output_device.eprintln(&format!(
+                    "unset: {arg}: {}",
+                    tr("cannot unset: readonly variable")
+                ));
+                exit_status = EXIT_FAILURE;
             } else {
                 shell.vars.remove(arg);
-                if env::var(&arg).is_ok() {
-                    env::remove_var(&arg);
+                shell.arrays.remove(arg);
+                shell.attributes.remove(arg);
+                if env::var(arg).is_ok() {
+                    env::remove_var(arg);
                     #[cfg(target_os = "wasi")]
                     wasi_ext_lib::set_env(arg, None).unwrap();
                 }
             }
         }
-        Ok(EXIT_SUCCESS)
+        Ok(exit_status)
+    }
+}
+
+/// Moves a variable to the environment of executed commands
+fn export_var(shell: &mut Shell, name: &str, value: &str) -> Result<(), Report> {
+    #[cfg(not(target_os = "wasi"))]
+    env::set_var(name, value);
+    #[cfg(target_os = "wasi")]
+    wasi_ext_lib::set_env(name, Some(value)).map_err(io::Error::from_raw_os_error)?;
+    shell.vars.remove(name);
+    Ok(())
+}
+
+/// Moves an exported variable back to the shell, `value` is assigned to it
+/// like `name=value`
+fn unexport_var(shell: &mut Shell, name: &str, value: Option<String>) -> Result<(), Report> {
+    let value = match value {
+        Some(value) => shell.assigned_value(name, value).map_err(Report::msg)?,
+        None => match env::var(name) {
+            Ok(value) => value,
+            Err(_) => return Ok(()),
+        },
+    };
+    env::remove_var(name);
+    #[cfg(target_os = "wasi")]
+    wasi_ext_lib::set_env(name, None).map_err(io::Error::from_raw_os_error)?;
+    shell.vars.insert(name.to_string(), value);
+    Ok(())
+}
+
+/// `declare` command recreating variable `name` with its attributes. Arrays
+/// are assigned by a separate `name=(...)` command, which is only parsed at
+/// the start of a command, and made readonly after that.
+fn declaration(shell: &Shell, name: &str) -> Option<String> {
+    let attributes = shell.attributes.get(name).copied().unwrap_or_default();
+    let array = shell.arrays.get(name);
+    let value = match array {
+        Some(_) => None,
+        None => shell
+            .vars
+            .get(name)
+            .cloned()
+            .or_else(|| env::var(name).ok())
+            .map(|value| format!("={}", shell_quote(&value))),
+    };
+    if array.is_none() && value.is_none() && attributes == Attributes::default() {
+        return None;
+    }
+    let exported = !shell.vars.contains_key(name) && env::var(name).is_ok();
+    let mut flags: String = [
+        ('a', array.is_some_and(|array| !array.is_associative())),
+        ('A', array.is_some_and(Array::is_associative)),
+        ('i', attributes.integer),
+        ('l', attributes.lowercase),
+        ('r', attributes.readonly && array.is_none()),
+        ('u', attributes.uppercase),
+        ('x', exported),
+    ]
+    .iter()
+    .filter(|(_, set)| *set)
+    .map(|(flag, _)| flag)
+    .collect();
+    if flags.is_empty() {
+        flags.push('-');
+    }
+    let mut declaration = format!("declare -{flags} {name}{}", value.unwrap_or_default());
+    if let Some(array) = array {
+        let elements: Vec<_> = array
+            .keys()
+            .iter()
+            .zip(array.values())
+            .map(|(key, value)| format!("[{}]={}", shell_quote(key), shell_quote(&value)))
+            .collect();
+        declaration.push_str(&format!("; {name}=({})", elements.join(" ")));
+        if attributes.readonly {
+            declaration.push_str(&format!("; declare -r {name}"));
+        }
+    }
+    Some(declaration)
+}
+
+/// Prints `declare` commands for `names`, for all variables if there are
+/// none
+fn print_declarations(
+    shell: &Shell,
+    command: &str,
+    names: &[String],
+    output_device: &mut OutputDevice,
+) -> i32 {
+    let names: Vec<String> = if names.is_empty() {
+        let all: BTreeSet<String> = shell
+            .vars
+            .keys()
+            .cloned()
+            .chain(env::vars().map(|(key, _)| key))
+            .chain(shell.arrays.keys().cloned())
+            .chain(shell.attributes.keys().cloned())
+            .collect();
+        all.into_iter().collect()
+    } else {
+        names.to_vec()
+    };
+    let mut exit_status = EXIT_SUCCESS;
+    for name in &names {
+        match declaration(shell, name) {
+            Some(declaration) => output_device.println(&declaration),
+            None => {
+                output_device.eprintln(&format!("{command}: {name}: {}", tr("not found")));
+                exit_status = EXIT_FAILURE;
+            }
+        }
+    }
+    exit_status
+}
+
+/// Commands recreating what a subshell inherits apart from the
+/// environment: shell variables, aliases, ignored signals and options
+pub fn subshell_state(shell: &mut Shell) -> String {
+    let names: BTreeSet<String> = shell
+        .vars
+        .keys()
+        .chain(shell.arrays.keys())
+        .chain(shell.attributes.keys())
+        // set again by every command the subshell runs
+        .filter(|name| *name != "PIPESTATUS")
+        .cloned()
+        .collect();
+    let mut commands: Vec<String> = names
+        .iter()
+        .filter_map(|name| declaration(shell, name))
+        .collect();
+    for (name, value) in &shell.aliases {
+        commands.push(format!("alias {name}={}", shell_quote(value)));
+    }
+    let mut ignored: Vec<i32> = shell
+        .traps
+        .iter()
+        .filter(|(_, command)| command.is_empty())
+        .map(|(signal, _)| *signal)
+        .collect();
+    ignored.sort();
+    for signal in ignored {
+        commands.push(format!("trap -- '' {}", trap::signal_name(signal)));
+    }
+    for name in SHOPT_OPTIONS {
+        if matches!(shopt_option(shell, name), Some(true)) {
+            commands.push(format!("shopt -s {name}"));
+        }
+    }
+    // last, so the commands above are neither traced nor stopped on errors
+    for name in SET_OPTIONS {
+        if matches!(shell_option(shell, name), Some(true)) {
+            commands.push(format!("set -o {name}"));
+        }
     }
+    commands.join("\n")
 }
 
 fn declare(
@@ -149,44 +681,212 @@ fn declare(
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "declare",
+        about: "Set shell variables and their attributes, print all variables without arguments",
+        operands: "[<VAR>[=<VALUE>]...]",
+        options: &[
+            Opt::flag(
+                'x',
+                "export",
+                "Export variables, +x makes exported ones local",
+            )
+            .plus(),
+            Opt::flag('a', "array", "Make variables indexed arrays"),
+            Opt::flag('A', "assoc", "Make variables associative arrays"),
+            Opt::flag('i', "integer", "Evaluate assigned values arithmetically").plus(),
+            Opt::flag('l', "lowercase", "Convert assigned values to lowercase").plus(),
+            Opt::flag('u', "uppercase", "Convert assigned values to uppercase").plus(),
+            Opt::flag('r', "readonly", "Make variables readonly"),
+            Opt::flag(
+                'p',
+                "print",
+                "Print variables with their attributes in a reusable format",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.flag("print") {
+        return Ok(print_declarations(
+            shell,
+            "declare",
+            &matches.operands,
+            output_device,
+        ));
+    }
+    let export = matches.flag("export");
+    let args = &matches.operands;
     if args.is_empty() {
-        // TODO: we should join and sort the variables!
-        for (key, value) in shell.vars.iter() {
-            output_device.println(&format!("{key}={value}"));
-        }
-        for (key, value) in env::vars() {
-            output_device.println(&format!("{key}={value}"));
-        }
-    } else if args[0] == "-x" || args[0] == "+x" {
-        // if -x is provided declare works as export
-        // if +x then makes global var local
-        for arg in args.iter().skip(1) {
-            if args[0] == "-x" {
-                if let Some((key, value)) = arg.split_once('=') {
-                    #[cfg(target_os = "wasi")]
-                    wasi_ext_lib::set_env(key, Some(value)).unwrap();
-                    #[cfg(not(target_os = "wasi"))]
-                    env::set_var(key, value);
+        if !export && !matches.plus("export") {
+            // TODO: we should join and sort the variables!
+            for (key, value) in shell.vars.iter() {
+                output_device.println(&format!("{key}={value}"));
+            }
+            for (key, value) in env::vars() {
+                output_device.println(&format!("{key}={value}"));
+            }
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    // only exporting keeps readonly variables as they are
+    let modifies = ["array", "assoc", "integer", "lowercase", "uppercase"]
+        .iter()
+        .any(|option| matches.flag(option) || matches.plus(option));
+    let mut exit_status = EXIT_SUCCESS;
+    for arg in args {
+        let (name, value) = match arg.split_once('=') {
+            Some((name, value)) => (name, Some(value.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let mut attributes = shell.attributes.get(name).copied().unwrap_or_default();
+        if attributes.readonly && (modifies || value.is_some()) {
+            output_device.eprintln(&format!("declare: {name}: {}", tr("readonly variable")));
+            exit_status = EXIT_FAILURE;
+            continue;
+        }
+        let array = match shell.arrays.remove(name) {
+            Some(array) if matches.flag("assoc") && !array.is_associative() => {
+                output_device.eprintln(&format!(
+                    "declare: {name}: cannot convert indexed to associative array"
+                ));
+                shell.arrays.insert(name.to_string(), array);
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+            Some(array) if matches.flag("array") && array.is_associative() => {
+                output_device.eprintln(&format!(
+                    "declare: {name}: cannot convert associative to indexed array"
+                ));
+                shell.arrays.insert(name.to_string(), array);
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+            Some(array) => Some(array),
+            None if matches.flag("assoc") => Some(Array::associative()),
+            None if matches.flag("array") => Some(Array::default()),
+            None => None,
+        };
+        if let Some(mut array) = array {
+            // a scalar becomes the first element, like in `name[0]=value`
+            if let Some(value) = shell.vars.remove(name) {
+                array.set(Subscript::Index(0), value).map_err(Report::msg)?;
+            }
+            shell.arrays.insert(name.to_string(), array);
+        }
+        if matches.flag("integer") || matches.plus("integer") {
+            attributes.integer = matches.flag("integer");
+        }
+        if matches.flag("lowercase") || matches.plus("lowercase") {
+            attributes.lowercase = matches.flag("lowercase");
+            attributes.uppercase &= !attributes.lowercase;
+        }
+        if matches.flag("uppercase") || matches.plus("uppercase") {
+            attributes.uppercase = matches.flag("uppercase");
+            attributes.lowercase &= !attributes.uppercase;
+        }
+        if attributes == Attributes::default() {
+            shell.attributes.remove(name);
+        } else {
+            shell.attributes.insert(name.to_string(), attributes);
+        }
+        let value = match value.map(|value| shell.assigned_value(name, value)) {
+            Some(Err(err)) => {
+                output_device.eprintln(&format!("declare: {err}"));
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+            Some(Ok(value)) => Some(value),
+            None => None,
+        };
+        if export {
+            // if -x is provided declare works as export
+            if let Some(value) = value.or_else(|| shell.vars.get(name).cloned()) {
+                export_var(shell, name, &value)?;
+            }
+        } else if matches.plus("export") {
+            // if +x then makes global var local
+            if let Some(value) = value.or_else(|| env::var(name).ok()) {
+                unexport_var(shell, name, Some(value))?;
+            }
+        } else if let Some(value) = value {
+            match shell.arrays.get_mut(name) {
+                Some(array) => array.set(Subscript::Index(0), value).map_err(Report::msg)?,
+                None if env::var(name).is_ok() && !shell.vars.contains_key(name) => {
+                    export_var(shell, name, &value)?
+                }
+                None => {
+                    shell.vars.insert(name.to_string(), value);
                 }
-            } else if let Some((key, value)) = arg.split_once('=') {
-                #[cfg(target_os = "wasi")]
-                wasi_ext_lib::set_env(key, None).unwrap();
-                shell.vars.insert(key.to_string(), value.to_string());
-            } else {
-                let value = env::var(arg).unwrap();
-                #[cfg(target_os = "wasi")]
-                wasi_ext_lib::set_env(arg, None).unwrap();
-                shell.vars.insert(arg.clone(), value.clone());
             }
         }
-    } else {
-        for arg in args {
-            if let Some((key, value)) = arg.split_once('=') {
-                shell.vars.insert(key.to_string(), value.to_string());
+        if matches.flag("readonly") {
+            shell
+                .attributes
+                .entry(name.to_string())
+                .or_default()
+                .readonly = true;
+        }
+    }
+    Ok(exit_status)
+}
+
+fn readonly(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "readonly",
+        about: "Make variables readonly, print readonly ones without arguments",
+        operands: "[<VAR>[=<VALUE>]...]",
+        options: &[Opt::flag(
+            'p',
+            "print",
+            "Print readonly variables in a reusable format",
+        )],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() || matches.flag("print") {
+        let mut names: Vec<String> = shell
+            .attributes
+            .iter()
+            .filter(|(_, attributes)| attributes.readonly)
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        for name in names {
+            if let Some(declaration) = declaration(shell, &name) {
+                output_device.println(&declaration);
             }
         }
+        return Ok(EXIT_SUCCESS);
     }
-    Ok(EXIT_SUCCESS)
+    let mut exit_status = EXIT_SUCCESS;
+    for arg in &matches.operands {
+        let name = match arg.split_once('=') {
+            Some((name, value)) => {
+                if let Err(err) = shell.set_var(name, value.to_string()) {
+                    output_device.eprintln(&format!("readonly: {err}"));
+                    exit_status = EXIT_FAILURE;
+                    continue;
+                }
+                name
+            }
+            None => arg.as_str(),
+        };
+        shell
+            .attributes
+            .entry(name.to_string())
+            .or_default()
+            .readonly = true;
+    }
+    Ok(exit_status)
 }
 
 fn export(
@@ -197,113 +897,2689 @@ fn export(
     // export creates an env value if A=B notation is used,
     // or just copies a local var to env if "=" is not used.
     // Export on nonexisting local var exports empty variable.
-    if args.is_empty() {
-        output_device.eprintln("export: help: export <VAR>[=<VALUE>] [<VAR>[=<VALUE>]] ...");
-        Ok(EXIT_FAILURE)
+    const SPEC: Spec = Spec {
+        name: "export",
+        about: "Export variables to the environment of executed commands, print exported ones without arguments",
+        operands: "[<VAR>[=<VALUE>]...]",
+        options: &[
+            Opt::flag('p', "print", "Print exported variables in a reusable format"),
+            Opt::flag('n', "unexport", "Make variables local to the shell"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() || matches.flag("print") {
+        let mut vars: Vec<_> = env::vars().collect();
+        vars.sort();
+        for (key, value) in vars {
+            output_device.println(&format!("export {key}={}", shell_quote(&value)));
+        }
+        Ok(EXIT_SUCCESS)
+    } else if matches.flag("unexport") {
+        let mut exit_status = EXIT_SUCCESS;
+        for arg in &matches.operands {
+            let result = match arg.split_once('=') {
+                Some((key, value)) => unexport_var(shell, key, Some(value.to_string())),
+                None => unexport_var(shell, arg, None),
+            };
+            if let Err(err) = result {
+                output_device.eprintln(&format!("export: {err}"));
+                exit_status = EXIT_FAILURE;
+            }
+        }
+        Ok(exit_status)
     } else {
-        for arg in args {
-            if let Some((key, value)) = arg.split_once('=') {
-                shell.vars.remove(key);
-                #[cfg(not(target_os = "wasi"))]
-                env::set_var(key, value);
-                #[cfg(target_os = "wasi")]
-                wasi_ext_lib::set_env(key, Some(value)).unwrap();
-            } else if let Some(value) = shell.vars.remove(arg) {
-                #[cfg(not(target_os = "wasi"))]
-                env::set_var(arg, value);
-                #[cfg(target_os = "wasi")]
-                wasi_ext_lib::set_env(arg, Some(&value)).unwrap();
-            } else {
-                #[cfg(not(target_os = "wasi"))]
-                env::set_var(arg, "");
-                #[cfg(target_os = "wasi")]
-                wasi_ext_lib::set_env(arg, Some("")).unwrap();
+        let mut exit_status = EXIT_SUCCESS;
+        for arg in &matches.operands {
+            let (key, value) = match arg.split_once('=') {
+                Some((key, value)) => match shell.assigned_value(key, value.to_string()) {
+                    Ok(value) => (key, value),
+                    Err(err) => {
+                        output_device.eprintln(&format!("export: {err}"));
+                        exit_status = EXIT_FAILURE;
+                        continue;
+                    }
+                },
+                None => (
+                    arg.as_str(),
+                    shell
+                        .vars
+                        .get(arg)
+                        .cloned()
+                        .or_else(|| env::var(arg).ok())
+                        .unwrap_or_default(),
+                ),
+            };
+            if let Err(err) = export_var(shell, key, &value) {
+                output_device.eprintln(&format!("export: {key}: {err}"));
+                exit_status = EXIT_FAILURE;
             }
         }
-        Ok(EXIT_SUCCESS)
+        Ok(exit_status)
     }
 }
 
-fn source(
+fn let_(
     shell: &mut Shell,
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
-    if let Some(filename) = args.first() {
-        shell.run_script(filename).unwrap();
-        Ok(EXIT_SUCCESS)
-    } else {
-        output_device.eprintln("source: help: source <filename>");
-        Ok(EXIT_FAILURE)
+    const SPEC: Spec = Spec {
+        name: "let",
+        about: "Evaluate arithmetic EXPRESSIONs like in $((...)), succeeds when the\n\
+                last one is not 0",
+        operands: "<EXPRESSION>...",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut value = 0;
+    for expression in &matches.operands {
+        value = match arith::eval_text(shell, expression) {
+            Ok(value) => value,
+            Err(err) => {
+                output_device.eprintln(&format!("let: {err}"));
+                return Ok(EXIT_FAILURE);
+            }
+        };
     }
+    Ok(if value == 0 {
+        EXIT_FAILURE
+    } else {
+        EXIT_SUCCESS
+    })
 }
 
-fn write(
-    _shell: &mut Shell,
+fn alias(
+    shell: &mut Shell,
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
-    if args.len() < 2 {
-        output_device.eprintln("write: help: write <filename> <contents>");
-        Ok(EXIT_FAILURE)
-    } else {
-        let filename = &args[1];
-        let content = args.join(" ");
-        match fs::write(filename, content) {
-            Ok(_) => Ok(EXIT_SUCCESS),
-            Err(error) => {
-                output_device.eprintln(&format!(
-                    "write: failed to write to file '{filename}': {error}"
-                ));
-                Ok(EXIT_FAILURE)
+    const SPEC: Spec = Spec {
+        name: "alias",
+        about: "Define aliases, print all aliases without arguments",
+        operands: "[<NAME>[=<VALUE>]...]",
+        options: &[
+            Opt::flag('p', "print", "Print all aliases in a reusable format"),
+            Opt::flag('s', "save", "Also append definitions to the rc file"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let definition = |name: &str, value: &str| format!("alias {name}={}", shell_quote(value));
+    if matches.operands.is_empty() || matches.flag("print") {
+        for (name, value) in &shell.aliases {
+            output_device.println(&definition(name, value));
+        }
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    let mut saved = String::new();
+    for arg in &matches.operands {
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                if name.is_empty()
+                    || name.contains(|c: char| c.is_whitespace() || "/$\\=`'\"".contains(c))
+                {
+                    output_device.eprintln(&format!("alias: `{name}': invalid alias name"));
+                    exit_status = EXIT_FAILURE;
+                    continue;
+                }
+                if matches.flag("save") {
+                    saved.push_str(&definition(name, value));
+                    saved.push('\n');
+                }
+                shell.aliases.insert(name.to_string(), value.to_string());
             }
+            None => match shell.aliases.get(arg) {
+                Some(value) => output_device.println(&definition(arg, value)),
+                None => {
+                    output_device.eprintln(&format!("alias: {arg}: {}", tr("not found")));
+                    exit_status = EXIT_FAILURE;
+                }
+            },
+        }
+    }
+    if !saved.is_empty() {
+        let path = xdg::rc_file();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
         }
+        let mut file = OpenOptions::new().create(true).append(true).open(&path)?;
+        file.write_all(saved.as_bytes())?;
     }
+    Ok(exit_status)
 }
 
-fn shift(
+fn unalias(
     shell: &mut Shell,
     args: &mut [String],
     output_device: &mut OutputDevice,
 ) -> Result<i32, Report> {
-    if args.len() > 1 {
-        output_device.eprintln("shift: too many arguments");
-        Ok(EXIT_FAILURE)
-    } else if let Some(n) = &args.first() {
-        if let Ok(m) = n.parse::<i32>() {
-            if m < 0 {
-                output_device.eprintln(&format!("shift: {m}: shift count out of range"));
-                Ok(EXIT_FAILURE)
-            } else if m as usize <= shell.args.len() {
-                _ = shell.args.drain(0..m as usize);
-                Ok(EXIT_SUCCESS)
-            } else {
-                Ok(EXIT_FAILURE)
-            }
-        } else {
-            output_device.eprintln(&format!("shift: {n}: numeric argument required"));
-            Ok(EXIT_FAILURE)
+    const SPEC: Spec = Spec {
+        name: "unalias",
+        about: "Remove aliases",
+        operands: "<NAME>...",
+        options: &[Opt::flag('a', "all", "Remove all aliases")],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.flag("all") {
+        shell.aliases.clear();
+        return Ok(EXIT_SUCCESS);
+    }
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for arg in &matches.operands {
+        if shell.aliases.remove(arg).is_none() {
+            output_device.eprintln(&format!("unalias: {arg}: {}", tr("not found")));
+            exit_status = EXIT_FAILURE;
         }
+    }
+    Ok(exit_status)
+}
+
+/// Options of `command`, running COMMAND is handled by `Shell::execute_command`
+pub const COMMAND_SPEC: Spec = Spec {
+    name: "command",
+    about: "Run COMMAND with ARGS without looking it up in aliases, or describe COMMANDs",
+    operands: "<COMMAND> [ARG]...",
+    options: &[
+        Opt::flag(
+            'p',
+            "default-path",
+            "Search for COMMAND in PATH of standard utilities",
+        ),
+        Opt::flag(
+            'v',
+            "print",
+            "Print the path, alias, builtin or keyword COMMAND runs",
+        ),
+        Opt::flag('V', "describe", "Describe what COMMAND is"),
+    ],
+};
+
+/// `name` followed by what it runs, as printed by `command -V` and `type`
+fn describe_command(name: &str, kind: &CommandKind) -> String {
+    match kind {
+        CommandKind::Alias(value) => format!("{name} is aliased to `{value}'"),
+        CommandKind::Keyword => format!("{name} is a shell keyword"),
+        CommandKind::Internal => format!("{name} is a shell builtin"),
+        CommandKind::File(path) => format!("{name} is {}", path.display()),
+    }
+}
+
+fn command(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    let matches = match COMMAND_SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let verbose = matches.flag("describe");
+    if matches.operands.is_empty() || !(verbose || matches.flag("print")) {
+        COMMAND_SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let saved_path = if matches.flag("default-path") {
+        shell.command_path.replace(String::from(DEFAULT_PATH))
     } else {
-        _ = shell.args.pop_front();
-        Ok(EXIT_SUCCESS)
+        shell.command_path.clone()
+    };
+    let mut exit_status = EXIT_SUCCESS;
+    for name in &matches.operands {
+        // names whose lookup fails on an unreadable directory are not found
+        match shell.command_kind(name).ok().flatten() {
+            Some(kind) if verbose => output_device.println(&describe_command(name, &kind)),
+            Some(CommandKind::Alias(value)) => {
+                output_device.println(&format!("alias {name}={}", shell_quote(&value)))
+            }
+            Some(CommandKind::File(path)) => output_device.println(&path.display().to_string()),
+            Some(_) => output_device.println(name),
+            None => {
+                if verbose {
+                    output_device.eprintln(&format!("command: {name}: {}", tr("not found")));
+                }
+                exit_status = EXIT_FAILURE;
+            }
+        }
     }
+    shell.command_path = saved_path;
+    Ok(exit_status)
 }
 
-lazy_static! {
-    pub static ref INTERNALS_MAP: HashMap<&'static str, Internal> = {
-        let mut m: HashMap<&'static str, Internal> = HashMap::new();
-        m.insert("clear", clear);
-        m.insert("shift", shift);
-        m.insert("exit", exit);
-        m.insert("pwd", pwd);
-        m.insert("cd", cd);
-        m.insert("history", history);
-        m.insert("unset", unset);
-        m.insert("declare", declare);
+fn type_(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "type",
+        about: "Describe how each NAME would run as a command",
+        operands: "<NAME>...",
+        options: &[
+            Opt::flag(
+                'a',
+                "all",
+                "List every alias, keyword, builtin and file named NAME",
+            ),
+            Opt::flag('t', "type", "Print alias, keyword, builtin or file"),
+            Opt::flag('p', "path", "Print the path of NAME if it runs a file"),
+            Opt::flag(
+                'P',
+                "path-only",
+                "Search PATH even for aliases, keywords and builtins",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let path_only = matches.flag("path-only");
+    let mut exit_status = EXIT_SUCCESS;
+    for name in &matches.operands {
+        let mut kinds = shell.command_kinds(name).unwrap_or_default();
+        if path_only {
+            kinds.retain(|kind| matches!(kind, CommandKind::File(_)));
+        }
+        if !matches.flag("all") {
+            kinds.truncate(1);
+        }
+        if kinds.is_empty() {
+            if !(matches.flag("type") || matches.flag("path") || path_only) {
+                output_device.eprintln(&format!("type: {name}: {}", tr("not found")));
+            }
+            exit_status = EXIT_FAILURE;
+        }
+        for kind in kinds {
+            if matches.flag("type") {
+                output_device.println(match kind {
+                    CommandKind::Alias(_) => "alias",
+                    CommandKind::Keyword => "keyword",
+                    CommandKind::Internal => "builtin",
+                    CommandKind::File(_) => "file",
+                });
+            } else if matches.flag("path") || path_only {
+                if let CommandKind::File(path) = kind {
+                    output_device.println(&path.display().to_string());
+                }
+            } else {
+                output_device.println(&describe_command(name, &kind));
+            }
+        }
+    }
+    Ok(exit_status)
+}
+
+fn hash(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "hash",
+        about: "Remember where NAMEs are found in PATH, or list remembered commands",
+        operands: "[NAME]...",
+        options: &[
+            Opt::flag('r', "reset", "Forget all remembered commands"),
+            Opt::flag('d', "delete", "Forget NAMEs"),
+            Opt::flag('t', "print", "Print remembered paths of NAMEs"),
+            Opt::value('p', "path", "PATH", "Remember PATH as the file NAMEs run"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if matches.flag("reset") {
+        shell.command_hash.entries().clear();
+    }
+    if let Some(path) = matches.value("path") {
+        for name in &matches.operands {
+            shell
+                .command_hash
+                .entries()
+                .insert(name.clone(), (PathBuf::from(path), 0));
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    if matches.operands.is_empty() {
+        if shell.command_hash.entries().is_empty() {
+            if !matches.flag("reset") {
+                output_device.println("hash: hash table empty");
+            }
+            return Ok(EXIT_SUCCESS);
+        }
+        output_device.println("hits\tcommand");
+        for (path, hits) in shell.command_hash.entries().values() {
+            output_device.println(&format!("{hits:>4}\t{}", path.display()));
+        }
+        return Ok(EXIT_SUCCESS);
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for name in &matches.operands {
+        let found = if matches.flag("delete") {
+            shell.command_hash.entries().remove(name).is_some()
+        } else if matches.flag("print") {
+            match shell.command_hash.entries().get(name) {
+                Some((path, _)) => {
+                    output_device.println(&path.display().to_string());
+                    true
+                }
+                None => false,
+            }
+        } else {
+            INTERNALS_MAP.contains_key(name.as_str())
+                || shell.lookup_command(name).ok().flatten().is_some()
+        };
+        if !found {
+            output_device.eprintln(&format!("hash: {name}: {}", tr("not found")));
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+fn complete(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "complete",
+        about: "Set how Tab completes arguments of NAMEs, or print completion specs",
+        operands: "[NAME]...",
+        options: &[
+            Opt::flag('a', "aliases", "Complete alias names"),
+            Opt::flag('b', "builtins", "Complete internal command names"),
+            Opt::flag('c', "commands", "Complete command names"),
+            Opt::flag('d', "directories", "Complete directory names"),
+            Opt::flag('f', "files", "Complete file names"),
+            Opt::value('W', "words", "WORDS", "Complete the blank separated WORDS"),
+            Opt::value(
+                'F',
+                "function",
+                "COMMAND",
+                "Complete elements of the COMPREPLY array set by COMMAND",
+            ),
+            Opt::flag(
+                'p',
+                "print",
+                "Print specs of NAMEs, or all, as complete commands",
+            ),
+            Opt::flag('r', "remove", "Remove specs of NAMEs, or all"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let spec = CompletionSpec {
+        aliases: matches.flag("aliases"),
+        builtins: matches.flag("builtins"),
+        commands: matches.flag("commands"),
+        directories: matches.flag("directories"),
+        files: matches.flag("files"),
+        words: matches.value("words").map(String::from),
+        function: matches.value("function").map(String::from),
+    };
+    let mut exit_status = EXIT_SUCCESS;
+    if matches.flag("remove") {
+        if matches.operands.is_empty() {
+            shell.completions.clear();
+        }
+        for name in &matches.operands {
+            if shell.completions.remove(name).is_none() {
+                output_device.eprintln(&format!(
+                    "complete: {name}: {}",
+                    tr("no completion specification")
+                ));
+                exit_status = EXIT_FAILURE;
+            }
+        }
+    } else if matches.flag("print") || spec == CompletionSpec::default() {
+        if matches.operands.is_empty() {
+            for (name, spec) in &shell.completions {
+                output_device.println(&spec.to_command(name));
+            }
+        }
+        for name in &matches.operands {
+            match shell.completions.get(name) {
+                Some(spec) => output_device.println(&spec.to_command(name)),
+                None => {
+                    output_device.eprintln(&format!(
+                        "complete: {name}: {}",
+                        tr("no completion specification")
+                    ));
+                    exit_status = EXIT_FAILURE;
+                }
+            }
+        }
+    } else if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        exit_status = EXIT_FAILURE;
+    } else {
+        for name in &matches.operands {
+            shell.completions.insert(name.clone(), spec.clone());
+        }
+    }
+    Ok(exit_status)
+}
+
+fn source(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "source",
+        about: "Run commands from a file in the current shell, ARGS replace positional\n\
+                parameters while it runs. FILE without a slash is searched for in PATH.",
+        operands: "<FILE> [ARGS...]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let (filename, script_args) = match matches.operands.split_first() {
+        Some(operands) => operands,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let path = if filename.contains('/') {
+        PathBuf::from(filename)
+    } else {
+        env::var("PATH")
+            .unwrap_or_default()
+            .split(':')
+            .filter(|dir| !dir.is_empty())
+            .map(|dir| PathBuf::from(dir).join(filename))
+            .find(|path| path.is_file())
+            .unwrap_or_else(|| PathBuf::from(filename))
+    };
+
+    if script_args.is_empty() {
+        return run_sourced(shell, path);
+    }
+    // `$0` stays the name of the shell or the sourcing script
+    let name = shell
+        .args
+        .front()
+        .cloned()
+        .unwrap_or_else(|| env!("CARGO_PKG_NAME").to_string());
+    let saved = std::mem::replace(
+        &mut shell.args,
+        std::iter::once(name)
+            .chain(script_args.iter().cloned())
+            .collect(),
+    );
+    let saved_cursor = std::mem::take(&mut shell.getopts_cursor);
+    let result = run_sourced(shell, path);
+    shell.args = saved;
+    shell.getopts_cursor = saved_cursor;
+    result
+}
+
+/// Runs a script in the current shell, `return` in it stops the script
+fn run_sourced(shell: &mut Shell, path: PathBuf) -> Result<i32, Report> {
+    shell.return_depth += 1;
+    let result = shell.run_script(path);
+    shell.return_depth -= 1;
+    if shell.loop_control == Some(LoopControl::Return) {
+        shell.loop_control = None;
+    }
+    result
+}
+
+fn return_(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "return",
+        about: "Stop a sourced script with status N, the last status by default",
+        operands: "[N]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let status = match matches.operands.as_slice() {
+        [] => shell.last_exit_status,
+        // statuses are 8 bit, `return -1` is 255
+        [n] => match n.parse::<i64>() {
+            Ok(n) => n.rem_euclid(256) as i32,
+            Err(_) => {
+                output_device.eprintln(&format!("return: {n}: numeric argument required"));
+                return Ok(EXIT_FAILURE);
+            }
+        },
+        _ => {
+            output_device.eprintln("return: too many arguments");
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if shell.return_depth == 0 {
+        output_device.eprintln("return: can only `return' from a sourced script");
+        return Ok(EXIT_FAILURE);
+    }
+    shell.loop_control = Some(LoopControl::Return);
+    Ok(status)
+}
+
+fn write(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "write",
+        about: "Write contents to a file",
+        operands: "<FILE> <CONTENTS>",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let args = &matches.operands;
+    if args.len() < 2 {
+        SPEC.print_usage(output_device);
+        Ok(EXIT_FAILURE)
+    } else {
+        let filename = &args[1];
+        let content = args.join(" ");
+        match fs::write(filename, content) {
+            Ok(_) => Ok(EXIT_SUCCESS),
+            Err(error) => {
+                output_device.eprintln(&format!(
+                    "write: failed to write to file '{filename}': {error}"
+                ));
+                Ok(EXIT_FAILURE)
+            }
+        }
+    }
+}
+
+/// Reads a line for `read`, with `raw` unset backslashes escape the next
+/// character and a backslash-newline continues the line. Returns the
+/// characters with whether each was escaped and whether input ended first.
+fn read_line(
+    input: &mut dyn Read,
+    raw: bool,
+    limit: Option<usize>,
+) -> io::Result<(Vec<(char, bool)>, bool)> {
+    let mut line = Vec::new();
+    let mut bytes = Vec::new();
+    let mut escaped = false;
+    let mut byte = [0];
+    loop {
+        if limit.is_some_and(|limit| line.len() >= limit) {
+            return Ok((line, false));
+        }
+        if input.read(&mut byte)? == 0 {
+            return Ok((line, true));
+        }
+        // characters are decoded once all of their bytes are read
+        bytes.push(byte[0]);
+        let c = match std::str::from_utf8(&bytes) {
+            Ok(text) => text.chars().next().unwrap(),
+            Err(err) if err.error_len().is_none() => continue,
+            Err(_) => char::REPLACEMENT_CHARACTER,
+        };
+        bytes.clear();
+        match c {
+            '\\' if !raw && !escaped => escaped = true,
+            '\n' if escaped => escaped = false,
+            '\n' => return Ok((line, false)),
+            c => line.push((c, mem::take(&mut escaped))),
+        }
+    }
+}
+
+/// Fields of a line read by `read`, the last of `count` fields takes the
+/// rest of the line. Escaped characters never separate fields.
+fn read_fields(line: &[(char, bool)], ifs: &str, count: Option<usize>) -> Vec<String> {
+    let is_blank = |&(c, escaped): &(char, bool)| !escaped && c.is_whitespace() && ifs.contains(c);
+    let is_separator = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let start = line.iter().position(|c| !is_blank(c)).unwrap_or(line.len());
+    let end = line
+        .iter()
+        .rposition(|c| !is_blank(c))
+        .map_or(start, |end| end + 1);
+    let mut rest = &line[start..end.max(start)];
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        if count.is_some_and(|count| fields.len() + 1 == count) {
+            fields.push(rest.iter().map(|(c, _)| c).collect());
+            break;
+        }
+        let length = rest.iter().position(is_separator).unwrap_or(rest.len());
+        fields.push(rest[..length].iter().map(|(c, _)| c).collect());
+        rest = &rest[length..];
+        // a separator is blanks around at most one other IFS character
+        let mut skipped = rest.iter().take_while(|c| is_blank(c)).count();
+        if rest
+            .get(skipped)
+            .is_some_and(|c| is_separator(c) && !is_blank(c))
+        {
+            skipped += 1;
+            skipped += rest[skipped..].iter().take_while(|c| is_blank(c)).count();
+        }
+        rest = &rest[skipped..];
+    }
+    fields
+}
+
+fn read(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "read",
+        about: "Read a line from standard input and split it into variables, REPLY by default",
+        operands: "[<VAR>...]",
+        options: &[
+            Opt::flag('r', "raw", "Do not treat backslashes as escapes"),
+            Opt::value('p', "prompt", "PROMPT", "Print PROMPT on a terminal first"),
+            Opt::value('n', "nchars", "N", "Return after reading N characters"),
+            Opt::value('a', "array", "ARRAY", "Assign the fields to ARRAY"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let limit = match matches.value("nchars").map(str::parse::<usize>) {
+        Some(Ok(limit)) => Some(limit),
+        Some(Err(_)) => {
+            output_device.eprintln(&format!(
+                "read: {}: invalid character count",
+                matches.value("nchars").unwrap()
+            ));
+            return Ok(EXIT_FAILURE);
+        }
+        None => None,
+    };
+    if let Some(prompt) = matches.value("prompt") {
+        if is_fd_tty(STDIN).unwrap_or(false) {
+            output_device.eprint(prompt);
+            output_device.flush()?;
+        }
+    }
+
+    let mut input = output_device.unbuffered_stdin()?;
+    let (line, ended) = read_line(&mut input, matches.flag("raw"), limit)?;
+    let ifs = shell
+        .vars
+        .get("IFS")
+        .cloned()
+        .or_else(|| env::var("IFS").ok())
+        .unwrap_or_else(|| String::from(" \t\n"));
+    if let Some(name) = matches.value("array") {
+        let fields: Result<Vec<_>, _> = read_fields(&line, &ifs, None)
+            .into_iter()
+            .map(|field| shell.assigned_value(name, field))
+            .collect();
+        match fields {
+            Ok(fields) => {
+                shell.vars.remove(name);
+                shell
+                    .arrays
+                    .insert(name.to_string(), Array::from_values(fields));
+            }
+            Err(err) => {
+                output_device.eprintln(&format!("read: {err}"));
+                return Ok(EXIT_FAILURE);
+            }
+        }
+    } else {
+        let assignments: Vec<_> = if matches.operands.is_empty() {
+            // REPLY keeps the line as it is
+            vec![(String::from("REPLY"), line.iter().map(|(c, _)| c).collect())]
+        } else {
+            let mut fields = read_fields(&line, &ifs, Some(matches.operands.len())).into_iter();
+            matches
+                .operands
+                .iter()
+                .map(|name| (name.clone(), fields.next().unwrap_or_default()))
+                .collect()
+        };
+        for (name, value) in assignments {
+            if let Err(err) = shell.set_var(&name, value) {
+                output_device.eprintln(&format!("read: {err}"));
+                return Ok(EXIT_FAILURE);
+            }
+        }
+    }
+    // a last line without a newline is assigned but reading it fails
+    Ok(if ended { EXIT_FAILURE } else { EXIT_SUCCESS })
+}
+
+fn getopts(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "getopts",
+        about: "Parse the next option of ARGS, positional parameters by default, into NAME",
+        operands: "<OPTSTRING> <NAME> [ARGS...]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let (optstring, name, params) = match matches.operands.as_slice() {
+        [optstring, name] => (
+            optstring,
+            name,
+            shell.args.iter().skip(1).cloned().collect::<Vec<_>>(),
+        ),
+        [optstring, name, params @ ..] => (optstring, name, params.to_vec()),
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    // a leading `:` reports errors through NAME and OPTARG only
+    let (silent, optstring) = match optstring.strip_prefix(':') {
+        Some(optstring) => (true, optstring),
+        None => (false, optstring.as_str()),
+    };
+    let mut optind = shell
+        .vars
+        .get("OPTIND")
+        .and_then(|optind| optind.parse::<usize>().ok())
+        .unwrap_or(1)
+        .max(1);
+    // the cursor is stale when the arguments were changed behind its back
+    let offset = match (shell.getopts_cursor, params.get(optind - 1)) {
+        ((cursor, offset), Some(arg))
+            if cursor == optind && offset < arg.len() && arg.is_char_boundary(offset) =>
+        {
+            offset
+        }
+        _ => 1,
+    };
+    shell.vars.remove("OPTARG");
+
+    let arg = match params.get(optind - 1) {
+        Some(arg) if arg == "--" => {
+            optind += 1;
+            None
+        }
+        Some(arg) if arg.len() > 1 && arg.starts_with('-') && offset < arg.len() => Some(arg),
+        _ => None,
+    };
+    let arg = match arg {
+        Some(arg) => arg,
+        None => {
+            shell
+                .vars
+                .insert(String::from("OPTIND"), optind.to_string());
+            if let Err(err) = shell.set_var(name, String::from("?")) {
+                output_device.eprintln(&format!("getopts: {err}"));
+            }
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let option = arg[offset..].chars().next().unwrap();
+    let rest = &arg[offset + option.len_utf8()..];
+    // 1 moves to the next argument
+    let mut next_offset = if rest.is_empty() {
+        1
+    } else {
+        arg.len() - rest.len()
+    };
+    let value = match optstring.find(option).filter(|_| option != ':') {
+        None => {
+            if silent {
+                shell
+                    .vars
+                    .insert(String::from("OPTARG"), option.to_string());
+            } else {
+                output_device.eprintln(&format!("getopts: illegal option -- {option}"));
+            }
+            String::from("?")
+        }
+        Some(position) if optstring[position + 1..].starts_with(':') => {
+            next_offset = 1;
+            let argument = if !rest.is_empty() {
+                Some(rest.to_string())
+            } else {
+                optind += 1;
+                params.get(optind - 1).cloned()
+            };
+            match argument {
+                Some(argument) => {
+                    shell.vars.insert(String::from("OPTARG"), argument);
+                    option.to_string()
+                }
+                None if silent => {
+                    shell
+                        .vars
+                        .insert(String::from("OPTARG"), option.to_string());
+                    String::from(":")
+                }
+                None => {
+                    output_device
+                        .eprintln(&format!("getopts: option requires an argument -- {option}"));
+                    String::from("?")
+                }
+            }
+        }
+        Some(_) => option.to_string(),
+    };
+    if next_offset == 1 {
+        optind += 1;
+    }
+    shell.getopts_cursor = (optind, next_offset);
+    shell
+        .vars
+        .insert(String::from("OPTIND"), optind.to_string());
+    if let Err(err) = shell.set_var(name, value) {
+        output_device.eprintln(&format!("getopts: {err}"));
+        return Ok(EXIT_FAILURE);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn shift(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "shift",
+        about: "Drop the first N positional parameters, 1 by default",
+        operands: "[N]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let n = match matches.operands.as_slice() {
+        [] => 1,
+        [n] => match n.parse::<usize>() {
+            Ok(n) => n,
+            Err(_) if n.parse::<i64>().is_ok() => {
+                output_device.eprintln(&format!("shift: {n}: shift count out of range"));
+                return Ok(EXIT_FAILURE);
+            }
+            Err(_) => {
+                output_device.eprintln(&format!("shift: {n}: numeric argument required"));
+                return Ok(EXIT_FAILURE);
+            }
+        },
+        _ => {
+            output_device.eprintln("shift: too many arguments");
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    // `$0` is not a positional parameter and stays in place
+    let count = shell.args.len().saturating_sub(1);
+    if n > count {
+        output_device.eprintln(&format!("shift: {n}: shift count out of range"));
+        return Ok(EXIT_FAILURE);
+    }
+    if n > 0 {
+        shell.args.drain(1..=n);
+        // the option `getopts` stopped at is in a different argument now
+        shell.getopts_cursor = (0, 0);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+/// Shared by `break` and `continue`, N counts enclosing loops, 1 by default
+fn loop_control(
+    name: &'static str,
+    about: &'static str,
+    control: fn(usize) -> LoopControl,
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    let spec = Spec {
+        name,
+        about,
+        operands: "[N]",
+        options: &[],
+    };
+    let matches = match spec.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let count = match matches.operands.as_slice() {
+        [] => 1,
+        [n] => match n.parse::<usize>() {
+            Ok(count) if count > 0 => count,
+            _ => {
+                output_device.eprintln(&format!("{name}: {n}: loop count out of range"));
+                return Ok(EXIT_FAILURE);
+            }
+        },
+        _ => {
+            output_device.eprintln(&format!("{name}: too many arguments"));
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if shell.loop_depth == 0 {
+        output_device.eprintln(&format!(
+            "{name}: only meaningful in a `for', `while', or `until' loop"
+        ));
+        return Ok(EXIT_SUCCESS);
+    }
+    shell.loop_control = Some(control(count.min(shell.loop_depth)));
+    Ok(EXIT_SUCCESS)
+}
+
+fn break_(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    loop_control(
+        "break",
+        "Exit from N enclosing loops, 1 by default",
+        LoopControl::Break,
+        shell,
+        args,
+        output_device,
+    )
+}
+
+fn continue_(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    loop_control(
+        "continue",
+        "Resume the next iteration of the Nth enclosing loop, 1 by default",
+        LoopControl::Continue,
+        shell,
+        args,
+        output_device,
+    )
+}
+
+fn config(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "config",
+        about: "Show or change configuration",
+        operands: "[list | get <KEY> | set <KEY> <VALUE>]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let args = &matches.operands;
+    match args.first().map(String::as_str) {
+        None | Some("list") => {
+            for (key, value) in shell.config.iter() {
+                output_device.println(&format!("{key} = {value}"));
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        Some("get") if args.len() == 2 => match shell.config.get(&args[1]) {
+            Some(ConfigValue::String(value)) => {
+                output_device.println(value);
+                Ok(EXIT_SUCCESS)
+            }
+            Some(value) => {
+                output_device.println(&value.to_string());
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                output_device.eprintln(&format!("config: {}: no such key", args[1]));
+                Ok(EXIT_FAILURE)
+            }
+        },
+        Some("set") if args.len() >= 3 => {
+            let key = &args[1];
+            if !config::is_known_key(key) {
+                output_device.eprintln(&format!("config: {key}: unknown key"));
+            }
+            let value = ConfigValue::parse_lenient(&args[2..].join(" "));
+            if let (Some(name), ConfigValue::String(value)) = (key.strip_prefix("aliases."), &value)
+            {
+                shell.aliases.insert(name.to_string(), value.clone());
+            }
+            shell.config.set(key, value);
+            shell.apply_config();
+            Ok(EXIT_SUCCESS)
+        }
+        _ => {
+            SPEC.print_usage(output_device);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+/// Resolves the file `allow`/`deny` act on: given file, env file of given
+/// directory or env file of the working directory
+fn trust_target(shell: &Shell, args: &[String]) -> Option<PathBuf> {
+    let path = match args.first() {
+        Some(path) => shell.pwd.join(path),
+        None => shell.pwd.clone(),
+    };
+    if path.is_dir() {
+        find_env_file(&path)
+    } else {
+        Some(path).filter(|path| path.is_file())
+    }
+}
+
+fn allow(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "allow",
+        about: "Trust the .envrc or .env file of PATH, the working directory by default",
+        operands: "[PATH]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let file = match trust_target(shell, &matches.operands) {
+        Some(file) => file,
+        None => {
+            output_device.eprintln("allow: no .envrc or .env file found");
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if let Err(error) = TrustStore::load().allow(&file) {
+        output_device.eprintln(&format!(
+            "allow: {}: {}",
+            output_device.file_link(STDERR, &file),
+            error
+        ));
+        return Ok(EXIT_FAILURE);
+    }
+    shell.dir_env.reset();
+    shell.dir_env.update(&shell.pwd.clone());
+    Ok(EXIT_SUCCESS)
+}
+
+fn deny(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "deny",
+        about: "Revoke trust in the .envrc or .env file of PATH, the working directory by default",
+        operands: "[PATH]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let file = match trust_target(shell, &matches.operands) {
+        Some(file) => file,
+        None => {
+            output_device.eprintln("deny: no .envrc or .env file found");
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if let Err(error) = TrustStore::load().deny(&file) {
+        output_device.eprintln(&format!(
+            "deny: {}: {}",
+            output_device.file_link(STDERR, &file),
+            error
+        ));
+        return Ok(EXIT_FAILURE);
+    }
+    shell.dir_env.reset();
+    Ok(EXIT_SUCCESS)
+}
+
+fn theme(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "theme",
+        about: "Show or change the color theme",
+        operands: "[list | show | set <NAME> | color <ROLE> <COLOR> | colors [none | basic | 256 | truecolor]]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let args = &matches.operands;
+    match args.first().map(String::as_str) {
+        None => {
+            output_device.println(&shell.theme.name);
+            Ok(EXIT_SUCCESS)
+        }
+        Some("list") => {
+            for name in Theme::BUILTIN {
+                let marker = if name == shell.theme.name { "*" } else { " " };
+                output_device.println(&format!("{marker} {name}"));
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        Some("show") => {
+            for role in Role::ALL {
+                output_device.println(&shell.theme.paint(role, role.name()));
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        Some("set") if args.len() == 2 => {
+            if Theme::builtin(&args[1]).is_none() {
+                output_device.eprintln(&format!("theme: {}: no such theme", args[1]));
+                return Ok(EXIT_FAILURE);
+            }
+            shell
+                .config
+                .set("theme.name", ConfigValue::String(args[1].clone()));
+            shell.apply_config();
+            Ok(EXIT_SUCCESS)
+        }
+        Some("colors") if args.len() == 1 => {
+            output_device.println(shell.theme.colors.name());
+            Ok(EXIT_SUCCESS)
+        }
+        Some("colors") if args.len() == 2 => {
+            if ColorSupport::parse(&args[1]).is_none() {
+                output_device.eprintln(&format!("theme: {}: invalid color support", args[1]));
+                return Ok(EXIT_FAILURE);
+            }
+            shell
+                .config
+                .set("terminal.colors", ConfigValue::String(args[1].clone()));
+            shell.apply_config();
+            Ok(EXIT_SUCCESS)
+        }
+        Some("color") if args.len() >= 3 => {
+            if Role::from_name(&args[1]).is_none() {
+                output_device.eprintln(&format!("theme: {}: unknown color role", args[1]));
+                return Ok(EXIT_FAILURE);
+            }
+            let spec = args[2..].join(" ");
+            if Style::parse(&spec).is_none() {
+                output_device.eprintln(&format!("theme: {spec}: invalid color"));
+                return Ok(EXIT_FAILURE);
+            }
+            shell.config.set(
+                &format!("theme.colors.{}", args[1]),
+                ConfigValue::String(spec),
+            );
+            shell.apply_config();
+            Ok(EXIT_SUCCESS)
+        }
+        _ => {
+            SPEC.print_usage(output_device);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+fn bench(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "bench",
+        about: "Run a command repeatedly and report timing statistics",
+        operands: "<command>",
+        options: &[
+            Opt::value(
+                'n',
+                "runs",
+                "RUNS",
+                "Number of measured runs, 10 by default",
+            ),
+            Opt::value(
+                'w',
+                "warmup",
+                "WARMUP",
+                "Number of unmeasured runs, 1 by default",
+            ),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let count = |name: &str, default: usize| match matches.value(name) {
+        Some(value) => value.parse::<usize>().map_err(|_| value.to_string()),
+        None => Ok(default),
+    };
+    let (runs, warmup) = match (count("runs", 10), count("warmup", 1)) {
+        (Ok(runs), Ok(warmup)) => (runs.max(1), warmup),
+        (Err(value), _) | (_, Err(value)) => {
+            output_device.eprintln(&format!("bench: {value}: invalid number"));
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let command = matches.operands.join(" ");
+
+    let mut samples = Vec::with_capacity(runs);
+    let mut failures = 0;
+    for run in 0..warmup + runs {
+        let started = Instant::now();
+        let exit_status = shell.run_command(&command)?;
+        let elapsed = started.elapsed();
+        if exit_status == EXIT_INTERRUPTED {
+            return Ok(EXIT_INTERRUPTED);
+        }
+        if run >= warmup {
+            samples.push(elapsed);
+            if exit_status != EXIT_SUCCESS {
+                failures += 1;
+            }
+        }
+    }
+
+    let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    if let Some(stats) = BenchStats::from_samples(&samples) {
+        output_device.println(&format!(
+            "bench: {command}\n  \
+             runs:  {runs} ({warmup} warmup)\n  \
+             mean:  {:.3} ms +/- {:.3} ms\n  \
+             range: {:.3} ms .. {:.3} ms",
+            ms(stats.mean),
+            ms(stats.stddev),
+            ms(stats.min),
+            ms(stats.max)
+        ));
+    }
+    if failures > 0 {
+        output_device.eprintln(&format!(
+            "bench: warning: {failures} of {runs} runs exited with non-zero status"
+        ));
+        return Ok(EXIT_FAILURE);
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+/// Re-runs a command whenever files matching any of the patterns change
+fn onchange(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "onchange",
+        about: "Run a command, then again whenever files matching any of the patterns change",
+        operands: "<PATTERN>... -- <command>",
+        options: &[Opt::value(
+            'i',
+            "interval",
+            "INTERVAL",
+            "Polling interval where file events are unavailable, 500ms by default",
+        )],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let interval = match matches.value("interval").map(parse_duration) {
+        Some(Some(interval)) => interval,
+        Some(None) => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+        None => Duration::from_millis(500),
+    };
+    let patterns = &matches.operands;
+    let (patterns, command) = match patterns.iter().position(|arg| arg == "--") {
+        Some(separator) if separator > 0 && separator + 1 < patterns.len() => (
+            patterns[..separator].to_vec(),
+            patterns[separator + 1..].join(" "),
+        ),
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    output_device.flush()?;
+
+    let mut watcher = FileWatcher::new(patterns, interval);
+    loop {
+        if shell.run_command(&command)? == EXIT_INTERRUPTED {
+            return Ok(EXIT_INTERRUPTED);
+        }
+        // ignore changes made by the command itself
+        watcher.rescan();
+        let changed = match watcher.wait(&mut || shell.interrupted()) {
+            Some(changed) => changed,
+            None => return Ok(EXIT_INTERRUPTED),
+        };
+        eprintln!(
+            "onchange: {} changed{}",
+            changed[0].display(),
+            match changed.len() {
+                1 => String::new(),
+                n => format!(" (and {} more)", n - 1),
+            }
+        );
+    }
+}
+
+fn list_scheduled(shell: &Shell, output_device: &mut OutputDevice) -> Result<i32, Report> {
+    let now = Instant::now();
+    for job in shell.scheduler.jobs() {
+        let schedule = match job.every {
+            Some(interval) => format!("every {}", schedule::format_duration(interval)),
+            None => String::from("once"),
+        };
+        output_device.println(&format!(
+            "[{}]  in {:<8} {:<12} {}",
+            job.id,
+            schedule::format_duration(job.next_run.saturating_duration_since(now)),
+            schedule,
+            job.command
+        ));
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn cancel_scheduled(
+    shell: &mut Shell,
+    name: &str,
+    id: &str,
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    if matches!(id.parse(), Ok(id) if shell.scheduler.cancel(id)) {
+        Ok(EXIT_SUCCESS)
+    } else {
+        output_device.eprintln(&format!("{name}: {id}: {}", tr("no such job")));
+        Ok(EXIT_FAILURE)
+    }
+}
+
+/// Runs a command periodically while the interactive shell waits for input
+fn every(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "every",
+        about: "Run a command periodically while the interactive shell waits for input,\n\
+                list scheduled jobs without arguments",
+        operands: "[<INTERVAL> <command>]",
+        options: &[Opt::value('c', "cancel", "ID", "Cancel a scheduled job")],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if let Some(id) = matches.value("cancel") {
+        return cancel_scheduled(shell, "every", id, output_device);
+    }
+    match matches.operands.as_slice() {
+        [] => list_scheduled(shell, output_device),
+        [interval, command @ ..] if !command.is_empty() => match parse_duration(interval) {
+            Some(interval) if !interval.is_zero() => {
+                let id = shell.scheduler.every(interval, command.join(" "));
+                output_device.println(&format!("[{id}]"));
+                Ok(EXIT_SUCCESS)
+            }
+            _ => {
+                output_device.eprintln(&format!("every: {interval}: invalid interval"));
+                Ok(EXIT_FAILURE)
+            }
+        },
+        _ => {
+            SPEC.print_usage(output_device);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+/// Runs a command once at a local time (`HH:MM[:SS]`) or after a delay (`+DURATION`)
+fn at(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "at",
+        about: "Run a command once at a local time or after a delay, list scheduled jobs\n\
+                without arguments",
+        operands: "[<HH:MM[:SS]> | +<DURATION>] <command>",
+        options: &[Opt::value('c', "cancel", "ID", "Cancel a scheduled job")],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if let Some(id) = matches.value("cancel") {
+        return cancel_scheduled(shell, "at", id, output_device);
+    }
+    match matches.operands.as_slice() {
+        [] => list_scheduled(shell, output_device),
+        [time, command @ ..] if !command.is_empty() => match schedule::parse_at(time) {
+            Some(delay) => {
+                let id = shell.scheduler.at(delay, command.join(" "));
+                output_device.println(&format!("[{id}]"));
+                Ok(EXIT_SUCCESS)
+            }
+            None => {
+                output_device.eprintln(&format!("at: {time}: invalid time"));
+                Ok(EXIT_FAILURE)
+            }
+        },
+        _ => {
+            SPEC.print_usage(output_device);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+/// State of a finished job as listed by `jobs`
+pub fn job_state(exit_status: i32) -> String {
+    match exit_status {
+        EXIT_SUCCESS => String::from("Done"),
+        exit_status => format!("Exit {exit_status}"),
+    }
+}
+
+/// Lists background jobs, finished ones are reported once
+fn jobs(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "jobs",
+        about: "List background jobs started with &",
+        operands: "",
+        options: &[Opt::flag('l', "long", "Show process ids of jobs")],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if !matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut finished = Vec::new();
+    for (job, exit_status) in shell.jobs.reap() {
+        match exit_status {
+            Ok(exit_status) => finished.push((job, exit_status)),
+            Err(err) => output_device.eprintln(&format!("jobs: {err}")),
+        }
+    }
+    let mut listed: Vec<(&Job, String)> = shell
+        .jobs
+        .jobs()
+        .iter()
+        .map(|job| (job, String::from("Running")))
+        .collect();
+    listed.extend(
+        finished
+            .iter()
+            .map(|(job, exit_status)| (job, job_state(*exit_status))),
+    );
+    listed.sort_by_key(|(job, _)| job.id);
+    for (job, state) in listed {
+        if matches.flag("long") {
+            output_device.println(&format!(
+                "[{}]  {:<8} {:<10} {}",
+                job.id, job.pid, state, job.command
+            ));
+        } else {
+            output_device.println(&format!("[{}]  {:<10} {}", job.id, state, job.command));
+        }
+    }
+    Ok(EXIT_SUCCESS)
+}
+
+fn wait(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "wait",
+        about: "Wait for background jobs to finish, all of them without arguments",
+        operands: "[<PID>|%<JOB>...]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let targets: Vec<Result<usize, String>> = if matches.operands.is_empty() {
+        shell.jobs.jobs().iter().map(|job| Ok(job.id)).collect()
+    } else {
+        matches
+            .operands
+            .iter()
+            .map(|spec| {
+                shell
+                    .jobs
+                    .find(spec)
+                    .ok_or_else(|| match spec.strip_prefix('%') {
+                        Some(_) => format!("{spec}: {}", tr("no such job")),
+                        None => format!("pid {spec} is not a child of this shell"),
+                    })
+            })
+            .collect()
+    };
+    // like in bash, it is the status of the last operand, 0 without them
+    let mut exit_status = EXIT_SUCCESS;
+    for target in targets {
+        match target.and_then(|id| shell.jobs.wait(id)) {
+            Ok((_, job_status)) => {
+                if !matches.operands.is_empty() {
+                    exit_status = job_status;
+                }
+            }
+            Err(err) => {
+                output_device.eprintln(&format!("wait: {err}"));
+                exit_status = EXIT_CMD_NOT_FOUND;
+            }
+        }
+    }
+    Ok(exit_status)
+}
+
+/// Shell options toggled with `set -o NAME` / `set +o NAME`
+const SET_OPTIONS: [&str; 6] = [
+    "errexit",
+    "noclobber",
+    "nounset",
+    "pipefail",
+    "profile",
+    "xtrace",
+];
+
+fn shell_option<'a>(shell: &'a mut Shell, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "errexit" => Some(&mut shell.errexit),
+        "noclobber" => Some(&mut shell.noclobber),
+        "nounset" => Some(&mut shell.nounset),
+        "pipefail" => Some(&mut shell.pipefail),
+        "profile" => Some(&mut shell.profile.enabled),
+        "xtrace" => Some(&mut shell.xtrace),
+        _ => None,
+    }
+}
+
+fn set(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "set",
+        about: "Change shell options, list them without arguments",
+        operands: "",
+        options: &[
+            Opt::value('o', "option", "OPTION", "Enable an option, +o disables it").plus(),
+            Opt::flag('e', "errexit", "Exit when a command fails, +e stops").plus(),
+            Opt::flag(
+                'C',
+                "noclobber",
+                "Refuse to overwrite existing files with >, +C stops",
+            )
+            .plus(),
+            Opt::flag(
+                'u',
+                "nounset",
+                "Fail on expansion of unset parameters, +u stops",
+            )
+            .plus(),
+            Opt::flag(
+                'x',
+                "xtrace",
+                "Print commands before running them, +x stops",
+            )
+            .plus(),
+        ],
+    };
+    // `set -o` and `set +o` without a name list the options
+    match args {
+        [flag] if flag == "-o" => return Ok(list_set_options(shell, output_device, false)),
+        [flag] if flag == "+o" => return Ok(list_set_options(shell, output_device, true)),
+        _ => {}
+    }
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if !matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    let mut changed = false;
+    for name in ["errexit", "noclobber", "nounset", "xtrace"] {
+        let enable = matches.flag(name);
+        if enable || matches.plus(name) {
+            if let Some(option) = shell_option(shell, name) {
+                *option = enable;
+            }
+            changed = true;
+        }
+    }
+    let mut exit_status = EXIT_SUCCESS;
+    for (enable, name) in matches.values("option") {
+        match shell_option(shell, name) {
+            Some(option) => *option = enable,
+            None => {
+                output_device.eprintln(&format!("set: {name}: invalid option name"));
+                exit_status = EXIT_FAILURE;
+            }
+        }
+    }
+    if !changed && matches.values("option").next().is_none() {
+        list_set_options(shell, output_device, false);
+    }
+    Ok(exit_status)
+}
+
+/// Prints state of `set -o` options, as commands restoring it with `reusable`
+fn list_set_options(shell: &mut Shell, output_device: &mut OutputDevice, reusable: bool) -> i32 {
+    for name in SET_OPTIONS {
+        let enabled = matches!(shell_option(shell, name), Some(true));
+        if reusable {
+            output_device.println(&format!(
+                "set {}o {}",
+                if enabled { '-' } else { '+' },
+                name
+            ));
+        } else {
+            output_device.println(&format!(
+                "{:<15} {}",
+                name,
+                if enabled { "on" } else { "off" }
+            ));
+        }
+    }
+    EXIT_SUCCESS
+}
+
+/// Options toggled with `shopt -s NAME` / `shopt -u NAME`
+const SHOPT_OPTIONS: [&str; 3] = ["cdspell", "correct", "nullglob"];
+
+fn shopt_option<'a>(shell: &'a mut Shell, name: &str) -> Option<&'a mut bool> {
+    match name {
+        "cdspell" => Some(&mut shell.shopt.cdspell),
+        "correct" => Some(&mut shell.shopt.correct),
+        "nullglob" => Some(&mut shell.shopt.nullglob),
+        _ => None,
+    }
+}
+
+fn shopt(
+    shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "shopt",
+        about: "Change optional shell behavior, list options and their state without arguments",
+        operands: "[OPTION...]",
+        options: &[
+            Opt::flag('s', "set", "Enable options, or list enabled ones"),
+            Opt::flag('u', "unset", "Disable options, or list disabled ones"),
+        ],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let enable = match (matches.flag("set"), matches.flag("unset")) {
+        (true, true) => {
+            output_device.eprintln("shopt: cannot set and unset options at the same time");
+            return Ok(EXIT_FAILURE);
+        }
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    };
+    let names = &matches.operands;
+    let names: Vec<&str> = if names.is_empty() {
+        SHOPT_OPTIONS.to_vec()
+    } else {
+        names.iter().map(String::as_str).collect()
+    };
+
+    let mut exit_status = EXIT_SUCCESS;
+    for name in names {
+        let option = match shopt_option(shell, name) {
+            Some(option) => option,
+            None => {
+                output_device.eprintln(&format!("shopt: {name}: invalid shell option name"));
+                exit_status = EXIT_FAILURE;
+                continue;
+            }
+        };
+        match enable {
+            Some(enable) if !matches.operands.is_empty() => *option = enable,
+            // `shopt -s` alone lists enabled options, `shopt -u` disabled ones
+            Some(enable) if *option != enable => {}
+            _ => output_device.println(&format!(
+                "{:<15} {}",
+                name,
+                if *option { "on" } else { "off" }
+            )),
+        }
+    }
+    Ok(exit_status)
+}
+
+/// Subcommands of `string`, named `string <subcommand>` in messages
+const STRING_COMMANDS: [Spec; 8] = [
+    Spec {
+        name: "string length",
+        about: "Print the number of characters of each string",
+        operands: "[STRING...]",
+        options: &[],
+    },
+    Spec {
+        name: "string sub",
+        about: "Print substrings, START counts from 1 or from the end when negative",
+        operands: "[STRING...]",
+        options: &[
+            Opt::value('s', "start", "START", "First character, 1 by default"),
+            Opt::value(
+                'l',
+                "length",
+                "LENGTH",
+                "Number of characters, all by default",
+            ),
+        ],
+    },
+    Spec {
+        name: "string trim",
+        about: "Remove leading and trailing whitespace or CHARS",
+        operands: "[STRING...]",
+        options: &[
+            Opt::flag('l', "left", "Only trim the start"),
+            Opt::flag('r', "right", "Only trim the end"),
+            Opt::value('c', "chars", "CHARS", "Characters to remove"),
+        ],
+    },
+    Spec {
+        name: "string split",
+        about: "Print parts of strings split at SEPARATOR, an empty one splits characters",
+        operands: "<SEPARATOR> [STRING...]",
+        options: &[
+            Opt::value('m', "max", "MAX", "Split at most MAX times"),
+            Opt::flag('r', "right", "Split from the end"),
+        ],
+    },
+    Spec {
+        name: "string join",
+        about: "Join strings with SEPARATOR",
+        operands: "<SEPARATOR> [STRING...]",
+        options: &[],
+    },
+    Spec {
+        name: "string replace",
+        about: "Replace PATTERN in strings, $1 refers to a regex capture group",
+        operands: "<PATTERN> <REPLACEMENT> [STRING...]",
+        options: &[
+            Opt::flag('a', "all", "Replace every occurrence"),
+            Opt::flag('r', "regex", "PATTERN is a regular expression"),
+            Opt::flag('i', "ignore-case", "Match case-insensitively"),
+            Opt::flag('f', "filter", "Only print strings that were changed"),
+        ],
+    },
+    Spec {
+        name: "string match",
+        about: "Print strings matching a glob PATTERN, or regex matches and capture groups",
+        operands: "<PATTERN> [STRING...]",
+        options: &[
+            Opt::flag('r', "regex", "PATTERN is a regular expression"),
+            Opt::flag('i', "ignore-case", "Match case-insensitively"),
+            Opt::flag('v', "invert", "Print strings that do not match"),
+        ],
+    },
+    Spec {
+        name: "string pad",
+        about: "Pad strings to the same width, the longest one or WIDTH",
+        operands: "[STRING...]",
+        options: &[
+            Opt::flag('r', "right", "Pad at the end instead of the start"),
+            Opt::value('w', "width", "WIDTH", "Minimum width"),
+            Opt::value('c', "char", "CHAR", "Padding character, space by default"),
+        ],
+    },
+];
+
+const MISSING_OPERANDS: &str = "missing operands";
+
+fn string_int(matches: &Matches, name: &str) -> Result<Option<i64>, String> {
+    matches
+        .value(name)
+        .map(|value| {
+            value
+                .parse::<i64>()
+                .map_err(|_| format!("{value}: invalid number"))
+        })
+        .transpose()
+}
+
+/// `string sub`, `start` is 1-based and counts from the end when negative
+fn substring(string: &str, start: i64, length: Option<i64>) -> String {
+    let chars: Vec<char> = string.chars().collect();
+    let len = chars.len() as i64;
+    let start = if start > 0 {
+        (start - 1).min(len)
+    } else {
+        (len + start).max(0)
+    };
+    let end = match length {
+        Some(length) => (start + length.max(0)).min(len),
+        None => len,
+    };
+    chars[start as usize..end as usize].iter().collect()
+}
+
+fn string_regex(pattern: &str, literal: bool, ignore_case: bool) -> Result<Regex, String> {
+    let pattern = if literal {
+        regex::escape(pattern)
+    } else {
+        pattern.to_string()
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|err| err.to_string())
+}
+
+/// Runs `string` subcommand `name`, printed lines go to `out`; returns whether
+/// any operand matched or was changed
+fn string_subcommand(name: &str, matches: &Matches, out: &mut Vec<String>) -> Result<bool, String> {
+    let operands = matches.operands.as_slice();
+    let has = |name: &str| matches.flag(name);
+
+    match name {
+        "string length" => {
+            for operand in operands {
+                out.push(operand.chars().count().to_string());
+            }
+            Ok(operands.iter().any(|operand| !operand.is_empty()))
+        }
+        "string sub" => {
+            let start = string_int(matches, "start")?.unwrap_or(1);
+            if start == 0 {
+                return Err("0: start index must not be 0".to_string());
+            }
+            let length = string_int(matches, "length")?;
+            for operand in operands {
+                out.push(substring(operand, start, length));
+            }
+            Ok(!operands.is_empty())
+        }
+        "string trim" => {
+            let chars: Vec<char> = matches
+                .value("chars")
+                .map_or(Vec::new(), |c| c.chars().collect());
+            let trimmed = |c: char| {
+                if chars.is_empty() {
+                    c.is_whitespace()
+                } else {
+                    chars.contains(&c)
+                }
+            };
+            let (left, right) = match (has("left"), has("right")) {
+                (false, false) => (true, true),
+                sides => sides,
+            };
+            let mut changed = false;
+            for operand in operands {
+                let mut result = operand.as_str();
+                if left {
+                    result = result.trim_start_matches(trimmed);
+                }
+                if right {
+                    result = result.trim_end_matches(trimmed);
+                }
+                changed |= result.len() != operand.len();
+                out.push(result.to_string());
+            }
+            Ok(changed)
+        }
+        "string split" => {
+            let (separator, operands) = operands.split_first().ok_or(MISSING_OPERANDS)?;
+            let max = match string_int(matches, "max")? {
+                Some(max) if max >= 0 => max as usize,
+                Some(max) => return Err(format!("{max}: invalid number")),
+                None => usize::MAX,
+            };
+            let mut split = false;
+            for operand in operands {
+                let mut pieces: Vec<String> = if separator.is_empty() {
+                    operand.chars().map(String::from).collect()
+                } else if has("right") {
+                    let mut pieces: Vec<String> = operand
+                        .rsplitn(max.saturating_add(1), separator.as_str())
+                        .map(String::from)
+                        .collect();
+                    pieces.reverse();
+                    pieces
+                } else {
+                    operand
+                        .splitn(max.saturating_add(1), separator.as_str())
+                        .map(String::from)
+                        .collect()
+                };
+                split |= pieces.len() > 1;
+                out.append(&mut pieces);
+            }
+            Ok(split)
+        }
+        "string join" => {
+            let (separator, operands) = operands.split_first().ok_or(MISSING_OPERANDS)?;
+            out.push(operands.join(separator));
+            Ok(operands.len() > 1)
+        }
+        "string replace" => {
+            let (pattern, replacement, operands) = match operands {
+                [pattern, replacement, operands @ ..] => (pattern, replacement, operands),
+                _ => return Err(MISSING_OPERANDS.to_string()),
+            };
+            let regex = string_regex(pattern, !has("regex"), has("ignore-case"))?;
+            // `$` only refers to capture groups in regex mode
+            let replacement = if has("regex") {
+                replacement.to_string()
+            } else {
+                replacement.replace('$', "$$")
+            };
+            let mut replaced = false;
+            for operand in operands {
+                let result = if has("all") {
+                    regex.replace_all(operand, replacement.as_str())
+                } else {
+                    regex.replace(operand, replacement.as_str())
+                };
+                let changed = regex.is_match(operand);
+                replaced |= changed;
+                if changed || !has("filter") {
+                    out.push(result.into_owned());
+                }
+            }
+            Ok(replaced)
+        }
+        "string match" => {
+            let (pattern, operands) = operands.split_first().ok_or(MISSING_OPERANDS)?;
+            let mut matched = false;
+            if has("regex") {
+                let regex = string_regex(pattern, false, has("ignore-case"))?;
+                for operand in operands {
+                    match regex.captures(operand) {
+                        Some(captures) if !has("invert") => {
+                            matched = true;
+                            // the whole match followed by capture groups
+                            out.extend(
+                                captures
+                                    .iter()
+                                    .flatten()
+                                    .map(|group| group.as_str().to_string()),
+                            );
+                        }
+                        None if has("invert") => {
+                            matched = true;
+                            out.push(operand.clone());
+                        }
+                        _ => {}
+                    }
+                }
+            } else {
+                let glob = Pattern::new(pattern).map_err(|err| format!("{pattern}: {err}"))?;
+                let options = MatchOptions {
+                    case_sensitive: !has("ignore-case"),
+                    require_literal_separator: false,
+                    require_literal_leading_dot: false,
+                };
+                for operand in operands {
+                    if glob.matches_with(operand, options) != has("invert") {
+                        matched = true;
+                        out.push(operand.clone());
+                    }
+                }
+            }
+            Ok(matched)
+        }
+        "string pad" => {
+            let fill = match matches.value("char") {
+                None => ' ',
+                Some(fill) if fill.chars().count() == 1 => fill.chars().next().unwrap(),
+                Some(fill) => return Err(format!("{fill}: padding must be one character")),
+            };
+            let longest = operands
+                .iter()
+                .map(|operand| operand.chars().count())
+                .max()
+                .unwrap_or(0);
+            let width = match string_int(matches, "width")? {
+                Some(width) => longest.max(width.max(0) as usize),
+                None => longest,
+            };
+            let mut padded = false;
+            for operand in operands {
+                let padding = fill.to_string().repeat(width - operand.chars().count());
+                padded |= !padding.is_empty();
+                out.push(if has("right") {
+                    format!("{operand}{padding}")
+                } else {
+                    format!("{padding}{operand}")
+                });
+            }
+            Ok(padded)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn string(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "string",
+        about: "Manipulate strings, see string <SUBCOMMAND> --help",
+        operands: "<length | sub | trim | split | join | replace | match | pad> [STRING...]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let command = matches.operands.first().and_then(|name| {
+        STRING_COMMANDS
+            .iter()
+            .find(|command| command.name.strip_prefix("string ") == Some(name.as_str()))
+    });
+    let command = match command {
+        Some(command) => command,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let matches = match command.parse(&matches.operands[1..], output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let mut out = Vec::new();
+    match string_subcommand(command.name, &matches, &mut out) {
+        Ok(success) => {
+            for line in out {
+                output_device.println(&line);
+            }
+            Ok(if success { EXIT_SUCCESS } else { EXIT_FAILURE })
+        }
+        Err(error) => {
+            output_device.eprintln(&format!("{}: {}", command.name, error));
+            if error == MISSING_OPERANDS {
+                command.print_usage(output_device);
+            }
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+fn mktemp(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "mktemp",
+        about: "Create a unique temporary file and print its path, trailing Xs in TEMPLATE are replaced",
+        operands: "[TEMPLATE]",
+        options: &[
+            Opt::flag('d', "directory", "Create a directory instead of a file"),
+            Opt::value(
+                'p',
+                "tmpdir",
+                "DIR",
+                "Create TEMPLATE in DIR, $TMPDIR or /tmp by default when TEMPLATE is not given",
+            ),
+        ],
+    };
+    const ATTEMPTS: usize = 100;
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let (template, dir) = match matches.operands.as_slice() {
+        [] => (
+            "tmp.XXXXXXXXXX",
+            Some(
+                matches
+                    .value("tmpdir")
+                    .map(String::from)
+                    .or_else(|| env::var("TMPDIR").ok().filter(|dir| !dir.is_empty()))
+                    .unwrap_or_else(|| String::from("/tmp")),
+            ),
+        ),
+        [template] => (template.as_str(), matches.value("tmpdir").map(String::from)),
+        _ => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    if template.len() - template.trim_end_matches('X').len() < 3 {
+        output_device.eprintln(&format!("mktemp: {template}: too few Xs in template"));
+        return Ok(EXIT_FAILURE);
+    }
+
+    for _ in 0..ATTEMPTS {
+        let name = random::fill_template(template)?;
+        let path = match &dir {
+            Some(dir) => Path::new(dir).join(name),
+            None => PathBuf::from(name),
+        };
+        // only the owner may access the result, like GNU mktemp
+        let result = if matches.flag("directory") {
+            let mut builder = fs::DirBuilder::new();
+            #[cfg(unix)]
+            builder.mode(0o700);
+            builder.create(&path)
+        } else {
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            options.open(&path).map(|_| ())
+        };
+        match result {
+            Ok(()) => {
+                output_device.println(&path.display().to_string());
+                return Ok(EXIT_SUCCESS);
+            }
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(error) => {
+                let path = output_device.file_link(STDERR, &path);
+                output_device.eprintln(&format!("mktemp: {path}: {error}"));
+                return Ok(EXIT_FAILURE);
+            }
+        }
+    }
+    output_device.eprintln(&format!(
+        "mktemp: {template}: no unused name found after {ATTEMPTS} attempts"
+    ));
+    Ok(EXIT_FAILURE)
+}
+
+fn random(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "random",
+        about: "Print a random integer between MIN and MAX inclusive, 0 and 32767 by default",
+        operands: "[MIN MAX]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let bounds = match matches.operands.as_slice() {
+        [] => Some((0, 32767)),
+        [min, max] => match (min.parse::<i64>(), max.parse::<i64>()) {
+            (Ok(min), Ok(max)) if min <= max => Some((min, max)),
+            _ => None,
+        },
+        _ => None,
+    };
+    match bounds {
+        Some((min, max)) => {
+            output_device.println(&random::in_range(min, max)?.to_string());
+            Ok(EXIT_SUCCESS)
+        }
+        None => {
+            SPEC.print_usage(output_device);
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+fn uuid(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "uuid",
+        about: "Print a random version 4 UUID",
+        operands: "",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    if !matches.operands.is_empty() {
+        SPEC.print_usage(output_device);
+        return Ok(EXIT_FAILURE);
+    }
+    output_device.println(&random::uuid()?);
+    Ok(EXIT_SUCCESS)
+}
+
+const JSON_COMMANDS: [Spec; 3] = [
+    Spec {
+        name: "json get",
+        about: "Print values selected by PATH, e.g. .items[0].name or .items[].id",
+        operands: "<PATH> [FILE]",
+        options: &[Opt::flag('r', "raw", "Print strings without quotes")],
+    },
+    Spec {
+        name: "json keys",
+        about: "Print keys of objects or indexes of arrays selected by PATH",
+        operands: "<PATH> [FILE]",
+        options: &[],
+    },
+    Spec {
+        name: "json length",
+        about: "Print lengths of arrays, objects and strings selected by PATH",
+        operands: "<PATH> [FILE]",
+        options: &[],
+    },
+];
+
+/// Lines printed by a `json` subcommand for one selected value
+fn json_subcommand(name: &str, value: &Json, raw: bool) -> Result<Vec<String>, String> {
+    Ok(match (name, value) {
+        ("json get", Json::String(string)) if raw => vec![string.clone()],
+        ("json get", value) => vec![value.to_string()],
+        ("json keys", Json::Object(entries)) => {
+            entries.iter().map(|(key, _)| key.clone()).collect()
+        }
+        ("json keys", Json::Array(items)) => (0..items.len()).map(|i| i.to_string()).collect(),
+        ("json length", Json::Null) => vec![String::from("0")],
+        ("json length", Json::Array(items)) => vec![items.len().to_string()],
+        ("json length", Json::Object(entries)) => vec![entries.len().to_string()],
+        ("json length", Json::String(string)) => vec![string.chars().count().to_string()],
+        (_, value) => return Err(format!("{} has no {}", value.type_name(), &name[5..])),
+    })
+}
+
+fn json(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    const SPEC: Spec = Spec {
+        name: "json",
+        about: "Query JSON documents, see json <SUBCOMMAND> --help",
+        operands: "<get | keys | length> <PATH> [FILE]",
+        options: &[],
+    };
+    let matches = match SPEC.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let command = matches.operands.first().and_then(|name| {
+        JSON_COMMANDS
+            .iter()
+            .find(|command| command.name.strip_prefix("json ") == Some(name.as_str()))
+    });
+    let command = match command {
+        Some(command) => command,
+        None => {
+            SPEC.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let matches = match command.parse(&matches.operands[1..], output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let (path, file) = match matches.operands.as_slice() {
+        [path] => (path, "-"),
+        [path, file] => (path, file.as_str()),
+        _ => {
+            command.print_usage(output_device);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let path = match json::parse_path(path) {
+        Ok(path) => path,
+        Err(error) => {
+            output_device.eprintln(&format!("{}: {}", command.name, error));
+            return Ok(EXIT_FAILURE);
+        }
+    };
+
+    let mut content = String::new();
+    let result = output_device
+        .open_input(file)
+        .and_then(|mut reader| reader.read_to_string(&mut content));
+    if let Err(error) = result {
+        let file = output_device.file_link(STDERR, Path::new(file));
+        output_device.eprintln(&format!("{}: {}: {}", command.name, file, error));
+        return Ok(EXIT_FAILURE);
+    }
+    let document = match Json::parse(content.trim()) {
+        Ok(document) => document,
+        Err(error) => {
+            output_device.eprintln(&format!("{}: invalid JSON: {}", command.name, error));
+            return Ok(EXIT_FAILURE);
+        }
+    };
+    let lines = document.query(&path).and_then(|values| {
+        values.iter().try_fold(Vec::new(), |mut lines, value| {
+            lines.extend(json_subcommand(command.name, value, matches.flag("raw"))?);
+            Ok(lines)
+        })
+    });
+    match lines {
+        Ok(lines) => {
+            for line in lines {
+                output_device.println(&line);
+            }
+            Ok(EXIT_SUCCESS)
+        }
+        Err(error) => {
+            output_device.eprintln(&format!("{}: {}", command.name, error));
+            Ok(EXIT_FAILURE)
+        }
+    }
+}
+
+const ALGORITHM_OPTION: Opt = Opt::value(
+    'a',
+    "algorithm",
+    "NAME",
+    "Hash function, sha256 (default) or md5",
+);
+const CHECK_OPTION: Opt = Opt::flag(
+    'c',
+    "check",
+    "Verify files listed in FILEs in the format printed without -c",
+);
+
+/// Verifies `<digest>  <path>` lines read from `operand`, algorithm is
+/// guessed from the digest length unless given. Returns numbers of
+/// mismatched, unreadable and malformed entries
+fn verify_checksums(
+    name: &str,
+    operand: &str,
+    algorithm: Option<Algorithm>,
+    output_device: &mut OutputDevice,
+) -> io::Result<[usize; 3]> {
+    let mut counts = [0; 3];
+    let mut content = String::new();
+    output_device
+        .open_input(operand)?
+        .read_to_string(&mut content)?;
+    for line in content.lines().filter(|line| !line.trim().is_empty()) {
+        let entry = line.split_once(' ').and_then(|(digest, rest)| {
+            let path = rest.strip_prefix(' ').or_else(|| rest.strip_prefix('*'))?;
+            let algorithm = algorithm.or_else(|| {
+                Algorithm::ALL
+                    .iter()
+                    .copied()
+                    .find(|algorithm| algorithm.hex_len() == digest.len())
+            })?;
+            let valid = digest.len() == algorithm.hex_len()
+                && digest.chars().all(|c| c.is_ascii_hexdigit());
+            valid.then(|| (digest.to_ascii_lowercase(), path, algorithm))
+        });
+        let (expected, path, algorithm) = match entry {
+            Some(entry) => entry,
+            None => {
+                counts[2] += 1;
+                continue;
+            }
+        };
+        let result = output_device
+            .open_input(path)
+            .and_then(|mut reader| algorithm.digest_reader(&mut reader));
+        match result {
+            Ok(digest) if digest == expected => output_device.println(&format!("{path}: OK")),
+            Ok(_) => {
+                output_device.println(&format!("{path}: FAILED"));
+                counts[0] += 1;
+            }
+            Err(error) => {
+                output_device.eprintln(&format!("{name}: {path}: {error}"));
+                output_device.println(&format!("{path}: FAILED open or read"));
+                counts[1] += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// `checksum`, or `sha256sum` and `md5sum` when `algorithm` is fixed
+fn run_checksum(
+    name: &'static str,
+    algorithm: Option<Algorithm>,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    let spec = Spec {
+        name,
+        about: "Print or verify checksums of files, standard input without FILEs",
+        operands: "[FILE...]",
+        options: if algorithm.is_some() {
+            &[CHECK_OPTION]
+        } else {
+            &[ALGORITHM_OPTION, CHECK_OPTION]
+        },
+    };
+    let matches = match spec.parse(args, output_device) {
+        Ok(matches) => matches,
+        Err(exit_status) => return Ok(exit_status),
+    };
+    let algorithm = match matches.value("algorithm") {
+        None => algorithm,
+        Some(value) => match Algorithm::parse(value) {
+            Some(algorithm) => Some(algorithm),
+            None => {
+                output_device.eprintln(&format!(
+                    "{name}: {value}: unknown algorithm, expected sha256 or md5"
+                ));
+                return Ok(EXIT_FAILURE);
+            }
+        },
+    };
+    let check = matches.flag("check");
+    let operands = if matches.operands.is_empty() {
+        vec![String::from("-")]
+    } else {
+        matches.operands
+    };
+
+    let mut exit_status = EXIT_SUCCESS;
+    if !check {
+        let algorithm = algorithm.unwrap_or(Algorithm::Sha256);
+        for operand in operands {
+            let result = output_device
+                .open_input(&operand)
+                .and_then(|mut reader| algorithm.digest_reader(&mut reader));
+            match result {
+                Ok(digest) => output_device.println(&format!("{digest}  {operand}")),
+                Err(error) => {
+                    let path = output_device.file_link(STDERR, Path::new(&operand));
+                    output_device.eprintln(&format!("{name}: {path}: {error}"));
+                    exit_status = EXIT_FAILURE;
+                }
+            }
+        }
+        return Ok(exit_status);
+    }
+
+    let mut totals = [0; 3];
+    for operand in operands {
+        match verify_checksums(name, &operand, algorithm, output_device) {
+            Ok(counts) => {
+                for (total, count) in totals.iter_mut().zip(counts.iter()) {
+                    *total += count;
+                }
+            }
+            Err(error) => {
+                let path = output_device.file_link(STDERR, Path::new(&operand));
+                output_device.eprintln(&format!("{name}: {path}: {error}"));
+                exit_status = EXIT_FAILURE;
+            }
+        }
+    }
+    let warnings = [
+        "computed checksum(s) did NOT match",
+        "listed file(s) could not be read",
+        "line(s) improperly formatted",
+    ];
+    for (count, warning) in totals.iter().zip(warnings.iter()) {
+        if *count > 0 {
+            output_device.eprintln(&format!("{name}: WARNING: {count} {warning}"));
+            exit_status = EXIT_FAILURE;
+        }
+    }
+    Ok(exit_status)
+}
+
+fn checksum(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    run_checksum("checksum", None, args, output_device)
+}
+
+#[cfg(feature = "busybox")]
+fn sha256sum(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    run_checksum("sha256sum", Some(Algorithm::Sha256), args, output_device)
+}
+
+#[cfg(feature = "busybox")]
+fn md5sum(
+    _shell: &mut Shell,
+    args: &mut [String],
+    output_device: &mut OutputDevice,
+) -> Result<i32, Report> {
+    run_checksum("md5sum", Some(Algorithm::Md5), args, output_device)
+}
+
+lazy_static! {
+    pub static ref INTERNALS_MAP: HashMap<&'static str, Internal> = {
+        let mut m: HashMap<&'static str, Internal> = HashMap::new();
+        m.insert("clear", clear);
+        m.insert("shift", shift);
+        m.insert("break", break_);
+        m.insert("continue", continue_);
+        m.insert("return", return_);
+        m.insert("exit", exit);
+        m.insert("trap", trap);
+        m.insert("pwd", pwd);
+        m.insert("cd", cd);
+        m.insert("history", history);
+        m.insert("unset", unset);
+        m.insert("declare", declare);
+        m.insert("readonly", readonly);
         m.insert("export", export);
+        m.insert("alias", alias);
+        m.insert("unalias", unalias);
+        m.insert("command", command);
+        m.insert("type", type_);
+        m.insert("hash", hash);
+        m.insert("let", let_);
+        m.insert("complete", complete);
         m.insert("source", source);
+        m.insert(".", source);
+        m.insert("read", read);
+        m.insert("getopts", getopts);
         m.insert("write", write);
+        m.insert("config", config);
+        m.insert("theme", theme);
+        m.insert("allow", allow);
+        m.insert("deny", deny);
+        m.insert("set", set);
+        m.insert("bench", bench);
+        m.insert("shopt", shopt);
+        m.insert("z", z);
+        m.insert("bookmark", bookmark);
+        m.insert("onchange", onchange);
+        m.insert("every", every);
+        m.insert("at", at);
+        m.insert("jobs", jobs);
+        m.insert("wait", wait);
+        m.insert("string", string);
+        m.insert("mktemp", mktemp);
+        m.insert("random", random);
+        m.insert("uuid", uuid);
+        m.insert("checksum", checksum);
+        m.insert("json", json);
+        m.insert("tar", archive::tar);
+        m.insert("untar", archive::untar);
+        m.insert("unzip", archive::unzip);
+        #[cfg(feature = "busybox")]
+        {
+            m.insert("ls", busybox::ls);
+            m.insert("cat", busybox::cat);
+            m.insert("cp", busybox::cp);
+            m.insert("mv", busybox::mv);
+            m.insert("rm", busybox::rm);
+            m.insert("mkdir", busybox::mkdir);
+            m.insert("touch", busybox::touch);
+            m.insert("head", busybox::head);
+            m.insert("tail", busybox::tail);
+            m.insert("wc", busybox::wc);
+            m.insert("grep", busybox::grep);
+            m.insert("sha256sum", sha256sum);
+            m.insert("md5sum", md5sum);
+            m.insert("base64", busybox::base64);
+            m.insert("xxd", busybox::xxd);
+        }
         m.insert("shift", shift);
         m
     };
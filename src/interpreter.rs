@@ -4,92 +4,664 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::env;
-#[cfg(target_os = "wasi")]
-use std::fs;
-#[cfg(target_os = "wasi")]
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
 #[cfg(target_os = "wasi")]
 use std::io::Read;
+use std::io::{self, Write};
+use std::mem;
 #[cfg(target_os = "wasi")]
 use std::os::fd::AsRawFd;
 use std::os::fd::IntoRawFd;
-#[cfg(target_os = "wasi")]
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
 use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Instant;
 
 use conch_parser::ast::{
     self, ComplexWord::Single, GuardBodyPair, PatternBodyPair, SimpleWord::Param, TopLevelCommand,
     TopLevelWord, Word::Simple,
 };
-use conch_parser::lexer::Lexer;
-use conch_parser::parse::{DefaultParser, ParseError, SourcePos};
 
 use glob::Pattern;
+use tracing::{debug, trace};
 
 #[cfg(not(target_os = "wasi"))]
 use nix;
 
 use crate::shell_base::{
-    preprocess_redirects, Fd, Redirect, Shell, EXIT_FAILURE, EXIT_INTERRUPTED, EXIT_SUCCESS, STDIN,
-    STDOUT,
+    preprocess_redirects, shell_quote, Fd, LoopControl, Redirect, Shell, EXIT_FAILURE,
+    EXIT_INTERRUPTED, EXIT_SUCCESS, STDERR, STDIN, STDOUT,
 };
 
+use crate::arith;
+use crate::array::{Array, Subscript};
+use crate::brace::{self, Piece};
+use crate::cond::{self, CondWord};
+use crate::internals;
+use crate::locale::tr;
 use crate::output_device::OutputDevice;
+use crate::random;
 
 #[cfg(not(target_os = "wasi"))]
 use crate::shell_base::{apply_redirects, wait_for_child};
 
 use crate::saved_fd::SavedFd;
+use crate::syntax::{
+    self, describe_error, SourcePos, ARITH_MARKER, ARRAY_MARKER, SUBSTRING_MARKER, TEST_MARKER,
+};
+use crate::timing::{self, Clock};
+
+/// Char iterator feeding the lexer that keeps count of consumed bytes,
+/// used to map parsed commands back to source lines.
+struct TrackedChars<'a> {
+    chars: std::str::Chars<'a>,
+    consumed: Rc<Cell<usize>>,
+}
+
+impl<'a> Iterator for TrackedChars<'a> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        self.consumed.set(self.consumed.get() + c.len_utf8());
+        Some(c)
+    }
+}
+
+/// Whether a command substitution body only runs pure internals (see
+/// `internals::is_pure`), possibly chained with `;`, `&&` and `||`
+fn runs_pure_internals(body: &[TopLevelCommand<String>]) -> bool {
+    let is_pure = |command: &ast::DefaultListableCommand| {
+        let simple = match command {
+            ast::ListableCommand::Single(ast::PipeableCommand::Simple(simple)) => simple,
+            _ => return false,
+        };
+        let mut words = simple.redirects_or_cmd_words.iter();
+        simple.redirects_or_env_vars.is_empty()
+            && matches!(
+                words.next(),
+                Some(ast::RedirectOrCmdWord::CmdWord(TopLevelWord(Single(Simple(
+                    ast::SimpleWord::Literal(name)
+                ))))) if internals::is_pure(name)
+            )
+            && words.all(|word| matches!(word, ast::RedirectOrCmdWord::CmdWord(_)))
+    };
+    !body.is_empty()
+        && body.iter().all(|command| match &command.0 {
+            ast::Command::List(list) => {
+                is_pure(&list.first)
+                    && list.rest.iter().all(|next| match next {
+                        ast::AndOr::And(command) | ast::AndOr::Or(command) => is_pure(command),
+                    })
+            }
+            ast::Command::Job(_) => false,
+        })
+}
+
+/// Directory named by `~`, `~+`, `~-` or `~user`
+fn tilde_directory(shell: &Shell, name: &str) -> Option<String> {
+    match name {
+        "" => Some(env::var("HOME").unwrap_or_else(|_| String::from("/"))),
+        "+" => Some(shell.pwd.display().to_string()),
+        "-" => env::var("OLDPWD").ok(),
+        #[cfg(not(target_os = "wasi"))]
+        user => nix::unistd::User::from_name(user)
+            .ok()
+            .flatten()
+            .map(|user| user.dir.display().to_string()),
+        #[cfg(target_os = "wasi")]
+        _ => None,
+    }
+}
+
+/// Expansion of a leading `~` followed by literal text like `~user/dir`,
+/// with the number of `words` it covers
+fn tilde_prefix(shell: &Shell, words: &[ast::DefaultWord]) -> Option<(String, usize)> {
+    match words {
+        [Simple(ast::SimpleWord::Tilde), Simple(ast::SimpleWord::Literal(literal)), ..] => {
+            let (name, rest) = match literal.find('/') {
+                Some(slash) => literal.split_at(slash),
+                None => (literal.as_str(), ""),
+            };
+            match tilde_directory(shell, name) {
+                Some(dir) => Some((format!("{dir}{rest}"), 2)),
+                // unknown users are left as they are, like in other shells
+                None => Some((format!("~{literal}"), 2)),
+            }
+        }
+        [Simple(ast::SimpleWord::Tilde), ..] => Some((tilde_directory(shell, "")?, 1)),
+        _ => None,
+    }
+}
+
+/// Field separators used when `IFS` is unset
+const DEFAULT_IFS: &str = " \t\n";
+
+/// `text` split on `IFS` characters, runs of `IFS` whitespace separate
+/// fields once and are ignored at both ends
+fn split_fields(text: &str, ifs: &str) -> Vec<String> {
+    let pieces = vec![Piece {
+        value: text.to_string(),
+        pattern: text.to_string(),
+        active: false,
+        split: true,
+        quoted: false,
+    }];
+    split_pieces(pieces, ifs)
+        .iter()
+        .map(|field| join_pieces(field).0)
+        .collect()
+}
+
+/// Fields of a word made of `pieces`, only unquoted results of expansions
+/// are split. Quoted empty strings make a field, empty expansions do not.
+fn split_pieces(pieces: Vec<Piece>, ifs: &str) -> Vec<Vec<Piece>> {
+    let mut fields = Vec::new();
+    let mut field = Vec::new();
+    let mut started = false;
+    // the last field was ended by IFS whitespace
+    let mut after_whitespace = false;
+    for piece in pieces {
+        if !piece.split {
+            field.push(piece);
+            started = true;
+            after_whitespace = false;
+            continue;
+        }
+        let mut text = String::new();
+        for c in piece.value.chars() {
+            if !ifs.contains(c) {
+                text.push(c);
+                after_whitespace = false;
+                continue;
+            }
+            if !text.is_empty() {
+                field.push(Piece::unquoted(mem::take(&mut text)));
+                started = true;
+            }
+            if c.is_whitespace() {
+                if started {
+                    fields.push(mem::take(&mut field));
+                    started = false;
+                    after_whitespace = true;
+                }
+            } else if after_whitespace {
+                // `a : b` with `IFS=' :'` has a single delimiter after `a`
+                after_whitespace = false;
+            } else {
+                // `a::b` with `IFS=:` has an empty field between the colons
+                fields.push(mem::take(&mut field));
+                started = false;
+            }
+        }
+        if !text.is_empty() {
+            field.push(Piece::unquoted(text));
+            started = true;
+        }
+    }
+    if started {
+        fields.push(field);
+    }
+    fields
+}
+
+/// Text and glob pattern of a word made of `pieces`
+fn join_pieces(pieces: &[Piece]) -> (String, String) {
+    pieces
+        .iter()
+        .fold((String::new(), String::new()), |(value, pattern), piece| {
+            (value + &piece.value, pattern + &piece.pattern)
+        })
+}
+
+/// Whether `word` is `$@`, `$*` or `"$@"`, which expand to one word per
+/// positional parameter
+fn is_positional_list(word: &TopLevelWord<String>) -> bool {
+    match word {
+        TopLevelWord(Single(Simple(Param(ast::Parameter::At | ast::Parameter::Star)))) => true,
+        TopLevelWord(Single(ast::Word::DoubleQuoted(words))) => {
+            matches!(words.as_slice(), [Param(ast::Parameter::At)])
+        }
+        _ => false,
+    }
+}
+
+/// First literal of `word`, array syntax rewritten by `syntax::Bashisms` is
+/// recognized by it
+fn first_literal(word: &TopLevelWord<String>) -> Option<&str> {
+    let first = match &word.0 {
+        Single(word) => word,
+        ast::ComplexWord::Concat(words) => words.first()?,
+    };
+    match first {
+        Simple(ast::SimpleWord::Literal(text)) => Some(text),
+        _ => None,
+    }
+}
+
+/// Whether `word` is the unquoted word `text`
+fn is_literal(
+    word: Option<&ast::RedirectOrCmdWord<ast::DefaultRedirect, TopLevelWord<String>>>,
+    text: &str,
+) -> bool {
+    matches!(
+        word,
+        Some(ast::RedirectOrCmdWord::CmdWord(TopLevelWord(Single(Simple(
+            ast::SimpleWord::Literal(literal)
+        ))))) if literal == text
+    )
+}
+
+/// Pipeline `cmd` without a leading `time` reserved word, and whether `-p`
+/// selected the POSIX output format
+fn untimed(cmd: &ast::DefaultListableCommand) -> Option<(ast::DefaultListableCommand, bool)> {
+    let first = match cmd {
+        ast::ListableCommand::Single(first) => first,
+        ast::ListableCommand::Pipe(_, cmds) => cmds.first()?,
+    };
+    match first {
+        ast::PipeableCommand::Simple(simple)
+            if simple.redirects_or_env_vars.is_empty()
+                && is_literal(simple.redirects_or_cmd_words.first(), "time") => {}
+        _ => return None,
+    }
+    let mut cmd = cmd.clone();
+    let first = match &mut cmd {
+        ast::ListableCommand::Single(first) => first,
+        ast::ListableCommand::Pipe(_, cmds) => &mut cmds[0],
+    };
+    let words = match first {
+        ast::PipeableCommand::Simple(simple) => &mut simple.redirects_or_cmd_words,
+        _ => unreachable!("the first command is checked above"),
+    };
+    words.remove(0);
+    let posix = is_literal(words.first(), "-p");
+    if posix {
+        words.remove(0);
+    }
+    Some((cmd, posix))
+}
+
+/// Whether the word of a `${name-word}` substitution is the subscript of
+/// `${name[sub]}`
+fn is_subscript(word: &Option<TopLevelWord<String>>) -> bool {
+    word.as_ref()
+        .and_then(first_literal)
+        .is_some_and(|text| text.starts_with(ARRAY_MARKER))
+}
+
+/// Whether the word of a `${name-word}` substitution is the range of
+/// `${name:offset:length}`
+fn is_substring(word: &Option<TopLevelWord<String>>) -> bool {
+    word.as_ref()
+        .and_then(first_literal)
+        .is_some_and(|text| text.starts_with(SUBSTRING_MARKER))
+}
+
+/// Whether `word` is the expression of `((expr))`
+fn is_arith_command(word: &TopLevelWord<String>) -> bool {
+    first_literal(word).is_some_and(|text| text.starts_with(ARITH_MARKER))
+}
+
+/// Whether `word` is the `[[` starting an extended conditional
+fn is_test_command(word: &TopLevelWord<String>) -> bool {
+    first_literal(word).is_some_and(|text| text.starts_with(TEST_MARKER))
+}
+
+/// Text of a `[[` operand that has neither quotes nor expansions
+fn unquoted_literal(word: &TopLevelWord<String>) -> Option<String> {
+    let words = match &word.0 {
+        Single(word) => std::slice::from_ref(word),
+        ast::ComplexWord::Concat(words) => words.as_slice(),
+    };
+    words
+        .iter()
+        .map(|word| match word {
+            Simple(ast::SimpleWord::Literal(text)) => Some(text.as_str()),
+            Simple(ast::SimpleWord::Colon) => Some(":"),
+            Simple(ast::SimpleWord::Tilde) => Some("~"),
+            Simple(ast::SimpleWord::Star) => Some("*"),
+            Simple(ast::SimpleWord::Question) => Some("?"),
+            Simple(ast::SimpleWord::SquareOpen) => Some("["),
+            Simple(ast::SimpleWord::SquareClose) => Some("]"),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Whether `word` is the `]]` closing an extended conditional
+fn is_test_end(word: &TopLevelWord<String>) -> bool {
+    match &word.0 {
+        Single(Simple(ast::SimpleWord::Literal(text))) => text == "]]",
+        ast::ComplexWord::Concat(words) => matches!(
+            words.as_slice(),
+            [
+                Simple(ast::SimpleWord::SquareClose),
+                Simple(ast::SimpleWord::SquareClose)
+            ]
+        ),
+        _ => false,
+    }
+}
+
+/// Whether `word` is `name[sub]=value`
+fn is_element_assignment(word: &TopLevelWord<String>) -> bool {
+    first_literal(word)
+        .is_some_and(|text| !text.starts_with(ARRAY_MARKER) && text.contains(ARRAY_MARKER))
+}
+
+/// Name of the array assigned by `name=(...)`, prefixed with `+` for
+/// `name+=(...)`
+fn compound_assignment(word: &TopLevelWord<String>) -> Option<&str> {
+    match word {
+        TopLevelWord(Single(Simple(ast::SimpleWord::Literal(text)))) => {
+            text.strip_prefix(ARRAY_MARKER)
+        }
+        _ => None,
+    }
+}
+
+/// Text of a word without expansions, `None` when it has some
+fn static_text(word: &ast::DefaultWord) -> Option<String> {
+    let simple = |word: &ast::DefaultSimpleWord| match word {
+        ast::SimpleWord::Literal(text) => Some(text.clone()),
+        ast::SimpleWord::Star => Some(String::from("*")),
+        _ => None,
+    };
+    match word {
+        Simple(word) => simple(word),
+        ast::Word::SingleQuoted(text) => Some(text.clone()),
+        ast::Word::DoubleQuoted(words) => words.iter().map(simple).collect(),
+    }
+}
+
+/// Name of the array of `${name[@]}`, `${name[*]}` or `"${name[@]}"`,
+/// which expand to one word per element, and whether it is quoted
+fn array_list(word: &TopLevelWord<String>) -> Option<(&str, bool)> {
+    let (subst, quoted) = match word {
+        TopLevelWord(Single(Simple(ast::SimpleWord::Subst(subst)))) => (subst, false),
+        TopLevelWord(Single(ast::Word::DoubleQuoted(words))) => match words.as_slice() {
+            [ast::SimpleWord::Subst(subst)] => (subst, true),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (name, subscript) = match subst.as_ref() {
+        ast::ParameterSubstitution::Default(false, ast::Parameter::Var(name), Some(word)) => {
+            (name, word)
+        }
+        _ => return None,
+    };
+    let words = match &subscript.0 {
+        Single(word) => std::slice::from_ref(word),
+        ast::ComplexWord::Concat(words) => words.as_slice(),
+    };
+    let subscript = words.iter().map(static_text).collect::<Option<String>>()?;
+    let subscript = subscript.strip_prefix(ARRAY_MARKER)?;
+    match subscript {
+        "@" => Some((name, quoted)),
+        "*" if !quoted => Some((name, quoted)),
+        _ => None,
+    }
+}
+
+/// Name of a parameter as written after `$`, used in error messages
+fn parameter_name(p: &ast::DefaultParameter) -> String {
+    match p {
+        ast::Parameter::At => String::from("@"),
+        ast::Parameter::Star => String::from("*"),
+        ast::Parameter::Pound => String::from("#"),
+        ast::Parameter::Question => String::from("?"),
+        ast::Parameter::Dash => String::from("-"),
+        ast::Parameter::Dollar => String::from("$"),
+        ast::Parameter::Bang => String::from("!"),
+        ast::Parameter::Positional(n) => n.to_string(),
+        ast::Parameter::Var(name) => name.clone(),
+    }
+}
+
+/// Command line replacing a simple command whose name is an alias. Values
+/// ending with a blank make the next word an alias too.
+fn alias_command(
+    shell: &Shell,
+    env: &HashMap<String, String>,
+    args: &[String],
+    background: bool,
+) -> Option<String> {
+    let expandable = |name: &String| {
+        shell
+            .aliases
+            .get(name)
+            .filter(|_| !shell.alias_stack.contains(name))
+    };
+    let value = expandable(args.first()?)?;
+    let mut assignments: Vec<_> = env.iter().collect();
+    assignments.sort();
+    let mut words: Vec<String> = assignments
+        .into_iter()
+        .map(|(key, value)| format!("{key}={}", shell_quote(value)))
+        .collect();
+    words.push(value.clone());
+    let mut chained = value.ends_with([' ', '\t']);
+    for arg in &args[1..] {
+        match expandable(arg) {
+            Some(value) if chained => {
+                words.push(value.clone());
+                chained = value.ends_with([' ', '\t']);
+            }
+            _ => {
+                words.push(shell_quote(arg));
+                chained = false;
+            }
+        }
+    }
+    if background {
+        words.push(String::from("&"));
+    }
+    Some(words.join(" "))
+}
+
+/// Variable of a `{name}>file` redirection, see `syntax::NAMED_FD_MARKER`
+fn named_fd(redirect_type: &ast::Redirect<TopLevelWord<String>>) -> Option<&str> {
+    let word = match redirect_type {
+        ast::Redirect::Read(_, word)
+        | ast::Redirect::Write(_, word)
+        | ast::Redirect::ReadWrite(_, word)
+        | ast::Redirect::Append(_, word)
+        | ast::Redirect::Clobber(_, word)
+        | ast::Redirect::Heredoc(_, word)
+        | ast::Redirect::DupRead(_, word)
+        | ast::Redirect::DupWrite(_, word) => word,
+    };
+    let (name, _) = first_literal(word)?
+        .strip_prefix(syntax::NAMED_FD_MARKER)?
+        .split_once(syntax::NAMED_FD_MARKER)?;
+    Some(name)
+}
+
+/// Moves an fd of a `{name}>file` redirection to 10 or above, out of the
+/// way of fds scripts use explicitly
+#[cfg(not(target_os = "wasi"))]
+fn high_fd(fd: Fd) -> io::Result<Fd> {
+    let high = nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_DUPFD(10))?;
+    let _ = nix::unistd::close(fd);
+    Ok(high)
+}
+
+/// WASI cannot pick the number of a duplicated fd, opened files keep theirs
+#[cfg(target_os = "wasi")]
+fn high_fd(fd: Fd) -> io::Result<Fd> {
+    Ok(fd)
+}
+
+/// Duplicate of `fd` for `{name}>&fd`
+#[cfg(not(target_os = "wasi"))]
+fn duplicate_fd(fd: Fd) -> io::Result<Fd> {
+    Ok(nix::fcntl::fcntl(fd, nix::fcntl::FcntlArg::F_DUPFD(10))?)
+}
+
+#[cfg(target_os = "wasi")]
+fn duplicate_fd(_fd: Fd) -> io::Result<Fd> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "cannot duplicate file descriptors on WASI",
+    ))
+}
+
+fn close_fd(fd: Fd) -> io::Result<()> {
+    #[cfg(target_os = "wasi")]
+    unsafe { wasi::fd_close(fd) }.map_err(|err| io::Error::from_raw_os_error(err.raw() as i32))?;
+    #[cfg(not(target_os = "wasi"))]
+    nix::unistd::close(fd)?;
+    Ok(())
+}
+
+/// Records statuses of pipeline stages in the `PIPESTATUS` array
+fn set_pipe_status(shell: &mut Shell, statuses: &[i32]) {
+    let statuses = statuses.iter().map(|status| status.to_string()).collect();
+    shell
+        .arrays
+        .insert(String::from("PIPESTATUS"), Array::from_values(statuses));
+}
+
+/// Handles `break` and `continue` at the end of a loop iteration, `true`
+/// when the loop has to stop. Counts above 1 are passed on to outer loops,
+/// `return` to all of them.
+fn loop_finished(shell: &mut Shell) -> bool {
+    match shell.loop_control.take() {
+        None | Some(LoopControl::Continue(1)) => false,
+        Some(LoopControl::Return) => {
+            shell.loop_control = Some(LoopControl::Return);
+            true
+        }
+        Some(LoopControl::Break(1)) => true,
+        Some(LoopControl::Break(n)) => {
+            shell.loop_control = Some(LoopControl::Break(n - 1));
+            true
+        }
+        Some(LoopControl::Continue(n)) => {
+            shell.loop_control = Some(LoopControl::Continue(n - 1));
+            true
+        }
+    }
+}
 
 pub struct InputInterpreter<'a> {
     input: &'a str,
+    source: Option<&'a str>,
+    first_line: usize,
+    /// Set when a word of the current command could not be expanded
+    expansion_failed: Cell<bool>,
 }
 
 impl<'a> InputInterpreter<'a> {
     pub fn from_input(input: &str) -> InputInterpreter {
-        InputInterpreter { input }
+        InputInterpreter {
+            input,
+            source: None,
+            first_line: 1,
+            expansion_failed: Cell::new(false),
+        }
+    }
+
+    pub fn from_source(input: &'a str, source: &'a str) -> InputInterpreter<'a> {
+        InputInterpreter {
+            input,
+            source: Some(source),
+            first_line: 1,
+            expansion_failed: Cell::new(false),
+        }
+    }
+
+    /// Sets line number of the first input line, used when a script is run in chunks
+    pub fn starting_at_line(mut self, line: usize) -> Self {
+        self.first_line = line;
+        self
+    }
+
+    fn line_at(&self, byte: usize) -> usize {
+        self.input[..byte.min(self.input.len())]
+            .matches('\n')
+            .count()
+            + self.first_line
+    }
+
+    /// Finds lines spanned by a command parsed from `start..end` bytes of input,
+    /// skipping leading blanks and comments the parser consumed with it.
+    fn command_lines(&self, start: usize, end: usize) -> (usize, usize) {
+        let bytes = self.input.as_bytes();
+        let end = end.min(bytes.len());
+        let mut pos = start.saturating_sub(1).min(end);
+        while pos < end {
+            match bytes[pos] {
+                b' ' | b'\t' | b'\n' | b'\r' | b';' => pos += 1,
+                b'#' => {
+                    while pos < end && bytes[pos] != b'\n' {
+                        pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+        let mut last = end;
+        while last > pos && bytes[last - 1].is_ascii_whitespace() {
+            last -= 1;
+        }
+        (self.line_at(pos), self.line_at(last.max(pos)))
     }
 
     pub fn interpret(&mut self, shell: &mut Shell) -> i32 {
-        let lex = Lexer::new(self.input.chars());
-        let parser = DefaultParser::new(lex);
+        let consumed = Rc::new(Cell::new(0));
+        let parser = syntax::parser(TrackedChars {
+            chars: self.input.chars(),
+            consumed: consumed.clone(),
+        });
         let mut exit_status = EXIT_SUCCESS;
+        let mut start = 0;
+        let mut started = Instant::now();
 
         for cmd in parser {
+            let end = consumed.get();
+            let lines = self.command_lines(start, end);
+            start = end;
+            shell
+                .vars
+                .insert(String::from("LINENO"), lines.0.to_string());
+
+            if let (Some(mut debugger), Some(source)) = (shell.debugger.take(), self.source) {
+                if let Err(err) = debugger.on_command(shell, source, lines) {
+                    eprintln!("{}: debugger: {}", env!("CARGO_PKG_NAME"), err);
+                }
+                let terminated = debugger.is_terminated();
+                shell.debugger = Some(debugger);
+                if terminated {
+                    break;
+                }
+            }
+
             exit_status = match cmd {
-                Ok(cmd) => self.handle_top_level_command(shell, &cmd),
+                Ok(cmd) => {
+                    trace!(target: "parse", source = self.source, line = lines.0, "{:?}", cmd);
+                    self.handle_top_level_command(shell, &cmd)
+                }
                 Err(e) => {
-                    let err_msg = match e {
-                        /*
-                        TODO: Most of these errors will never occur due to
-                        unimplemented shell features so error messages are
-                        kind of general.
-                        */
-                        ParseError::BadFd(pos_start, pos_end) => {
-                            let idx_start = pos_start.byte;
-                            let idx_end = pos_end.byte;
-                            format!(
-                                "{}: ambiguous redirect",
-                                self.input[idx_start..idx_end].to_owned()
-                            )
-                        }
-                        ParseError::BadIdent(_, _) => "bad idenftifier".to_string(),
-                        ParseError::BadSubst(_, _) => "bad substitution".to_string(),
-                        ParseError::Unmatched(_, _) => "unmached expression".to_string(),
-                        ParseError::IncompleteCmd(_, _, _, _) => "incomplete command".to_string(),
-                        ParseError::Unexpected(_, _) => "unexpected token".to_string(),
-                        ParseError::UnexpectedEOF => "unexpected end of file".to_string(),
-                        ParseError::Custom(t) => {
-                            format!("custom AST error: {t:?}")
-                        }
-                    };
+                    let err_msg = describe_error(self.input, &e);
+                    debug!(target: "parse", source = self.source, line = lines.0, "{}", err_msg);
                     eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err_msg);
                     shell.last_exit_status = EXIT_FAILURE;
                     EXIT_FAILURE
                 }
             };
+            // per-line timings let `--profile-startup` point at slow rc commands
+            if let (Some(profile), Some(source)) = (&mut shell.startup_profile, self.source) {
+                profile.record(format!("{}:{}", source, lines.0), started.elapsed());
+            }
+            started = Instant::now();
             if exit_status == EXIT_INTERRUPTED {
                 break;
             }
@@ -103,10 +675,63 @@ impl<'a> InputInterpreter<'a> {
         shell: &mut Shell,
         top_level_command: &ast::TopLevelCommand<String>,
     ) -> i32 {
-        match &top_level_command.0 {
+        if let Err(reason) = shell.guard.check_time() {
+            shell.guard.trip(&reason);
+        }
+        if shell.guard.is_tripped() {
+            return EXIT_INTERRUPTED;
+        }
+        shell.run_traps();
+        // the rest of a loop body is skipped after `break` and `continue`
+        if shell.loop_control.is_some() {
+            return shell.last_exit_status;
+        }
+        let heredocs = shell.heredocs.len();
+        let jobs = shell.jobs.jobs().len();
+        let exit_status = match &top_level_command.0 {
             ast::Command::Job(list) => self.handle_listable_command(shell, list, true),
             ast::Command::List(list) => self.handle_listable_command(shell, list, false),
+        };
+        let paths: Vec<PathBuf> = shell.heredocs.drain(heredocs..).collect();
+        if shell.jobs.jobs().len() > jobs {
+            // the background job may still read its here-documents
+            shell.jobs.attach_files(paths);
+        } else {
+            for path in paths {
+                let _ = fs::remove_file(path);
+            }
         }
+        exit_status
+    }
+
+    /// Writes a here-document body to a temporary file that is read by
+    /// the command instead of a pipe, which could fill up before the
+    /// command starts reading
+    fn heredoc_file(&self, shell: &mut Shell, body: &str) -> io::Result<PathBuf> {
+        const ATTEMPTS: usize = 100;
+        let dir = env::var("TMPDIR")
+            .ok()
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| String::from("/tmp"));
+        for _ in 0..ATTEMPTS {
+            let path = PathBuf::from(&dir).join(random::fill_template("wash-heredoc.XXXXXXXXXX")?);
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = match options.open(&path) {
+                Ok(file) => file,
+                Err(error) if error.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(error) => return Err(error),
+            };
+            shell.heredocs.push(path.clone());
+            file.write_all(body.as_bytes())?;
+            return Ok(path);
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "cannot create a unique temporary file",
+        ))
     }
 
     fn handle_listable_command(
@@ -115,37 +740,24 @@ impl<'a> InputInterpreter<'a> {
         list: &ast::DefaultAndOrList,
         background: bool,
     ) -> i32 {
-        let mut status_code = match &list.first {
-            ast::ListableCommand::Single(cmd) => {
-                self.handle_pipeable_command(shell, cmd, background, &mut Vec::new())
-            }
-            ast::ListableCommand::Pipe(negate, cmds) => {
-                self.handle_pipe(shell, *negate, cmds, background)
-            }
-        };
+        let mut status_code = self.handle_and_or_part(shell, &list.first, background);
+        // only a failure of the last part of `&&`/`||` lists stops `set -e`
+        // shells, and not of negated pipelines
+        let mut checked = Some(&list.first).filter(|_| list.rest.is_empty());
 
-        for next_cmd in &list.rest {
+        for (i, next_cmd) in list.rest.iter().enumerate() {
+            if shell.loop_control.is_some() {
+                break;
+            }
             match (status_code, next_cmd) {
                 (EXIT_INTERRUPTED, _) => return status_code,
                 (EXIT_SUCCESS, ast::AndOr::And(cmd)) => {
-                    status_code = match &cmd {
-                        ast::ListableCommand::Single(cmd) => {
-                            self.handle_pipeable_command(shell, cmd, background, &mut Vec::new())
-                        }
-                        ast::ListableCommand::Pipe(negate, cmds) => {
-                            self.handle_pipe(shell, *negate, cmds, background)
-                        }
-                    }
+                    status_code = self.handle_and_or_part(shell, cmd, background);
+                    checked = Some(cmd).filter(|_| i == list.rest.len() - 1);
                 }
                 (x, ast::AndOr::Or(cmd)) if x != EXIT_SUCCESS => {
-                    status_code = match &cmd {
-                        ast::ListableCommand::Single(cmd) => {
-                            self.handle_pipeable_command(shell, cmd, background, &mut Vec::new())
-                        }
-                        ast::ListableCommand::Pipe(negate, cmds) => {
-                            self.handle_pipe(shell, *negate, cmds, background)
-                        }
-                    }
+                    status_code = self.handle_and_or_part(shell, cmd, background);
+                    checked = Some(cmd).filter(|_| i == list.rest.len() - 1);
                 }
                 (_, _) => {
                     // either (fail, And) or (success, Or)
@@ -153,6 +765,77 @@ impl<'a> InputInterpreter<'a> {
             }
         }
 
+        let negated = matches!(checked, Some(ast::ListableCommand::Pipe(true, _)));
+        if shell.errexit
+            && shell.condition_depth == 0
+            && !background
+            && checked.is_some()
+            && !negated
+            && status_code != EXIT_SUCCESS
+            && status_code != EXIT_INTERRUPTED
+        {
+            shell.exit(status_code);
+        }
+
+        status_code
+    }
+
+    /// Runs a part of an `&&`/`||` list, its status decides which of the
+    /// following parts run and is visible as `$?` to them
+    fn handle_and_or_part(
+        &self,
+        shell: &mut Shell,
+        cmd: &ast::DefaultListableCommand,
+        background: bool,
+    ) -> i32 {
+        if let Some((cmd, posix)) = untimed(cmd) {
+            return self.handle_timed(shell, &cmd, posix, background);
+        }
+        let status_code = match cmd {
+            ast::ListableCommand::Single(cmd) => {
+                let status = self.handle_pipeable_command(shell, cmd, background, &mut Vec::new());
+                set_pipe_status(shell, &[status]);
+                status
+            }
+            ast::ListableCommand::Pipe(negate, cmds) => {
+                // `set -e` does not apply inside of negated pipelines
+                shell.condition_depth += usize::from(*negate);
+                let status = self.handle_pipe(shell, *negate, cmds, background);
+                shell.condition_depth -= usize::from(*negate);
+                status
+            }
+        };
+        if status_code != EXIT_INTERRUPTED {
+            shell.last_exit_status = status_code;
+        }
+        status_code
+    }
+
+    /// Runs a pipeline of `time` and reports how long it took on stderr,
+    /// pipelines in the background are not measured
+    fn handle_timed(
+        &self,
+        shell: &mut Shell,
+        cmd: &ast::DefaultListableCommand,
+        posix: bool,
+        background: bool,
+    ) -> i32 {
+        let clock = Clock::start();
+        let status_code = self.handle_and_or_part(shell, cmd, background);
+        if background || status_code == EXIT_INTERRUPTED {
+            return status_code;
+        }
+        let times = clock.stop();
+        let format = if posix {
+            Some(String::from(timing::POSIX_FORMAT))
+        } else {
+            self.handle_parameter(shell, &ast::Parameter::Var(String::from("TIMEFORMAT")))
+        };
+        let format = format.unwrap_or_else(|| String::from(timing::DEFAULT_FORMAT));
+        // an empty `TIMEFORMAT` disables the report
+        if !format.is_empty() {
+            eprintln!("{}", timing::format(&format, &times));
+        }
         status_code
     }
 
@@ -163,135 +846,165 @@ impl<'a> InputInterpreter<'a> {
         cmds: &[ast::DefaultPipeableCommand],
         background: bool,
     ) -> i32 {
-        let exit_status = {
-            #[cfg(target_os = "wasi")]
-            // TODO: name of the virtual file should be uniquely generated
-            // TODO: add virtual mode that won't create files but in-memory strings
-            let fd_writer = OpenOptions::new()
-                .write(true)
-                .create(true)
-                .truncate(true)
-                .open("/tmp/pipe0.txt")
-                .expect("Cannot create pipe")
-                .into_raw_fd() as Fd;
-
-            #[cfg(not(target_os = "wasi"))]
-            let (fd_reader, fd_writer) = {
-                let pipe = os_pipe::pipe().expect("Cannot create pipe.");
-                (pipe.0.into_raw_fd() as Fd, pipe.1.into_raw_fd() as Fd)
-            };
-
-            let mut exit_code = self.handle_pipeable_command(
-                shell,
-                &cmds[0],
-                background,
-                &mut vec![Redirect::PipeOut(fd_writer)],
-            );
-
-            #[cfg(target_os = "wasi")]
-            unsafe { wasi::fd_close(fd_writer) }.expect("Cannot close pipe write end!");
+        let exit_status = match cmds {
+            // `! cmd` is a pipeline of one command
+            [cmd] => {
+                let status = self.handle_pipeable_command(shell, cmd, background, &mut Vec::new());
+                set_pipe_status(shell, &[status]);
+                status
+            }
+            _ => self.handle_pipeline(shell, cmds, background),
+        };
 
-            #[cfg(not(target_os = "wasi"))]
-            let mut saved_reader = fd_reader;
-            #[cfg(not(target_os = "wasi"))]
-            nix::unistd::close(fd_writer).expect("Cannot close pipe write end!");
+        // if ! was present at the beginning of the pipe, return logical negation of last command status
+        if negate && exit_status != EXIT_INTERRUPTED {
+            (exit_status == EXIT_SUCCESS) as i32
+        } else {
+            exit_status
+        }
+    }
 
-            for (i, cmd) in cmds.iter().enumerate().skip(1).take(cmds.len() - 2) {
-                if exit_code == EXIT_INTERRUPTED {
-                    break;
-                }
+    /// Runs commands connected with pipes, the status is the one of the
+    /// last command or of the last failed one with `set -o pipefail`
+    fn handle_pipeline(
+        &self,
+        shell: &mut Shell,
+        cmds: &[ast::DefaultPipeableCommand],
+        background: bool,
+    ) -> i32 {
+        #[cfg(target_os = "wasi")]
+        // TODO: name of the virtual file should be uniquely generated
+        // TODO: add virtual mode that won't create files but in-memory strings
+        let fd_writer = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open("/tmp/pipe0.txt")
+            .expect("Cannot create pipe")
+            .into_raw_fd() as Fd;
+
+        #[cfg(not(target_os = "wasi"))]
+        let (fd_reader, fd_writer) = {
+            let pipe = os_pipe::pipe().expect("Cannot create pipe.");
+            (pipe.0.into_raw_fd() as Fd, pipe.1.into_raw_fd() as Fd)
+        };
 
-                let (fd_reader, fd_writer) = {
-                    #[cfg(target_os = "wasi")]
-                    {
-                        let read_end = OpenOptions::new()
-                            .read(true)
-                            .open(format!("/tmp/pipe{}.txt", i - 1))
-                            .expect("Cannot create pipe read end!");
-                        let write_end = OpenOptions::new()
-                            .write(true)
-                            .create(true)
-                            .truncate(true)
-                            .open(format!("/tmp/pipe{i}.txt"))
-                            .expect("Cannot create pipe write end!");
+        let mut exit_code = self.handle_pipeable_command(
+            shell,
+            &cmds[0],
+            background,
+            &mut vec![Redirect::PipeOut(fd_writer)],
+        );
+        let mut statuses = vec![exit_code];
 
-                        (read_end.into_raw_fd() as Fd, write_end.into_raw_fd() as Fd)
-                    }
+        #[cfg(target_os = "wasi")]
+        unsafe { wasi::fd_close(fd_writer) }.expect("Cannot close pipe write end!");
 
-                    #[cfg(not(target_os = "wasi"))]
-                    {
-                        let _ = i;
-                        let pipe = os_pipe::pipe().expect("Cannot create pipe.");
-                        let fds = (saved_reader, pipe.1.into_raw_fd() as Fd);
-                        saved_reader = pipe.0.into_raw_fd() as Fd;
-                        fds
-                    }
-                };
+        #[cfg(not(target_os = "wasi"))]
+        let mut saved_reader = fd_reader;
+        #[cfg(not(target_os = "wasi"))]
+        nix::unistd::close(fd_writer).expect("Cannot close pipe write end!");
 
-                exit_code = self.handle_pipeable_command(
-                    shell,
-                    cmd,
-                    background,
-                    &mut vec![Redirect::PipeIn(fd_reader), Redirect::PipeOut(fd_writer)],
-                );
+        for (i, cmd) in cmds.iter().enumerate().skip(1).take(cmds.len() - 2) {
+            if exit_code == EXIT_INTERRUPTED {
+                break;
+            }
 
-                // Close reader and writer
+            let (fd_reader, fd_writer) = {
                 #[cfg(target_os = "wasi")]
-                unsafe {
-                    wasi::fd_close(fd_reader).expect("Cannot close pipe read end!");
-                    wasi::fd_close(fd_writer).expect("Cannot close pipe write end!");
+                {
+                    let read_end = OpenOptions::new()
+                        .read(true)
+                        .open(format!("/tmp/pipe{}.txt", i - 1))
+                        .expect("Cannot create pipe read end!");
+                    let write_end = OpenOptions::new()
+                        .write(true)
+                        .create(true)
+                        .truncate(true)
+                        .open(format!("/tmp/pipe{i}.txt"))
+                        .expect("Cannot create pipe write end!");
+
+                    (read_end.into_raw_fd() as Fd, write_end.into_raw_fd() as Fd)
                 }
+
                 #[cfg(not(target_os = "wasi"))]
                 {
-                    nix::unistd::close(fd_reader).expect("Cannot close pipe read end!");
-                    nix::unistd::close(fd_writer).expect("Cannot close pipe write end!");
+                    let _ = i;
+                    let pipe = os_pipe::pipe().expect("Cannot create pipe.");
+                    let fds = (saved_reader, pipe.1.into_raw_fd() as Fd);
+                    saved_reader = pipe.0.into_raw_fd() as Fd;
+                    fds
                 }
-            }
-
-            if exit_code != EXIT_INTERRUPTED {
-                let fd_reader = {
-                    #[cfg(target_os = "wasi")]
-                    {
-                        OpenOptions::new()
-                            .read(true)
-                            .open(format!("/tmp/pipe{}.txt", cmds.len() - 2))
-                            .expect("Cannot create pipe")
-                            .into_raw_fd() as Fd
-                    }
+            };
 
-                    #[cfg(not(target_os = "wasi"))]
-                    saved_reader
-                };
+            exit_code = self.handle_pipeable_command(
+                shell,
+                cmd,
+                background,
+                &mut vec![Redirect::PipeIn(fd_reader), Redirect::PipeOut(fd_writer)],
+            );
+            statuses.push(exit_code);
 
-                exit_code = self.handle_pipeable_command(
-                    shell,
-                    cmds.last().unwrap(),
-                    background,
-                    &mut vec![Redirect::PipeIn(fd_reader)],
-                );
+            // Close reader and writer
+            #[cfg(target_os = "wasi")]
+            unsafe {
+                wasi::fd_close(fd_reader).expect("Cannot close pipe read end!");
+                wasi::fd_close(fd_writer).expect("Cannot close pipe write end!");
+            }
+            #[cfg(not(target_os = "wasi"))]
+            {
+                nix::unistd::close(fd_reader).expect("Cannot close pipe read end!");
+                nix::unistd::close(fd_writer).expect("Cannot close pipe write end!");
+            }
+        }
 
+        if exit_code != EXIT_INTERRUPTED {
+            let fd_reader = {
                 #[cfg(target_os = "wasi")]
-                unsafe { wasi::fd_close(fd_reader) }.expect("Cannot close pipe read end!");
+                {
+                    OpenOptions::new()
+                        .read(true)
+                        .open(format!("/tmp/pipe{}.txt", cmds.len() - 2))
+                        .expect("Cannot create pipe")
+                        .into_raw_fd() as Fd
+                }
+
                 #[cfg(not(target_os = "wasi"))]
-                nix::unistd::close(fd_reader).expect("Cannot close pipe write end!");
-            }
+                saved_reader
+            };
 
-            // TODO: temporary solution before in-memory files get implemented
-            #[cfg(target_os = "wasi")]
-            for i in 0..cmds.len() - 1 {
-                let pipe_name = format!("/tmp/pipe{i}.txt");
-                if Path::new(pipe_name.as_str()).exists() {
-                    fs::remove_file(pipe_name.as_str()).unwrap();
-                }
-            }
-            exit_code
-        };
+            exit_code = self.handle_pipeable_command(
+                shell,
+                cmds.last().unwrap(),
+                background,
+                &mut vec![Redirect::PipeIn(fd_reader)],
+            );
+            statuses.push(exit_code);
+
+            #[cfg(target_os = "wasi")]
+            unsafe { wasi::fd_close(fd_reader) }.expect("Cannot close pipe read end!");
+            #[cfg(not(target_os = "wasi"))]
+            nix::unistd::close(fd_reader).expect("Cannot close pipe write end!");
+        }
 
-        // if ! was present at the beginning of the pipe, return logical negation of last command status
-        if negate {
-            (exit_status != EXIT_SUCCESS) as i32
+        // TODO: temporary solution before in-memory files get implemented
+        #[cfg(target_os = "wasi")]
+        for i in 0..cmds.len() - 1 {
+            let pipe_name = format!("/tmp/pipe{i}.txt");
+            if Path::new(pipe_name.as_str()).exists() {
+                fs::remove_file(pipe_name.as_str()).unwrap();
+            }
+        }
+        set_pipe_status(shell, &statuses);
+        if shell.pipefail && exit_code != EXIT_INTERRUPTED {
+            statuses
+                .iter()
+                .rev()
+                .find(|status| **status != EXIT_SUCCESS)
+                .copied()
+                .unwrap_or(EXIT_SUCCESS)
         } else {
-            exit_status
+            exit_code
         }
     }
 
@@ -326,8 +1039,8 @@ impl<'a> InputInterpreter<'a> {
         let ast::CompoundCommand { kind, io } = cmd;
 
         for redirect_type in io.iter() {
-            if let Some(redirect) = self.handle_redirect_type(shell, redirect_type) {
-                redirects.push(redirect);
+            if let Some(redirect) = self.handle_redirect(shell, redirect_type) {
+                redirects.extend(redirect);
             } else {
                 eprintln!("{}: cannot handle redirect!", env!("CARGO_PKG_NAME"));
                 return EXIT_FAILURE;
@@ -335,7 +1048,7 @@ impl<'a> InputInterpreter<'a> {
         }
 
         let mut output_device = OutputDevice::new();
-        if let Err(err) = preprocess_redirects(redirects, &mut output_device) {
+        if let Err(err) = preprocess_redirects(redirects, shell.noclobber, &mut output_device) {
             output_device.eprintln(format!("{}: {}", env!("CARGO_PKG_NAME"), err).as_str());
             if let Err(err) = output_device.flush() {
                 eprintln!("Cannot flush output_device: {}", err)
@@ -353,6 +1066,12 @@ impl<'a> InputInterpreter<'a> {
                 .handle_compound_subshell(shell, body, start_pos, end_pos, background, redirects);
         }
 
+        // a backgrounded group runs in a child like `( ... ) &`
+        #[cfg(not(target_os = "wasi"))]
+        if let (ast::CompoundCommandKind::Brace(body), true) = (kind, background) {
+            return self.fork_subshell(shell, body, "{ ...; }", background, redirects);
+        }
+
         let mut fds_to_restore: Vec<SavedFd> = Vec::new();
 
         for redirect in redirects.iter() {
@@ -369,24 +1088,28 @@ impl<'a> InputInterpreter<'a> {
                 start_pos: _,
                 end_pos: _,
             } => unreachable!(),
-            ast::CompoundCommandKind::For { var, words, body } => match words {
-                Some(words) => self.handle_compound_for(shell, var, words, body, background),
-                None => EXIT_SUCCESS,
-            },
+            ast::CompoundCommandKind::For { var, words, body } => {
+                shell.loop_depth += 1;
+                let exit_status = self.handle_compound_for(shell, var, words, body, background);
+                shell.loop_depth -= 1;
+                exit_status
+            }
             ast::CompoundCommandKind::If {
                 conditionals,
                 else_branch,
             } => self.handle_compound_if(shell, conditionals, else_branch, background),
-            ast::CompoundCommandKind::While(guard_body) => {
-                self.handle_compound_while(shell, guard_body, background)
+            ast::CompoundCommandKind::While(guard_body)
+            | ast::CompoundCommandKind::Until(guard_body) => {
+                let until = matches!(kind, ast::CompoundCommandKind::Until(_));
+                shell.loop_depth += 1;
+                let exit_status = self.handle_compound_while(shell, guard_body, until, background);
+                shell.loop_depth -= 1;
+                exit_status
             }
             ast::CompoundCommandKind::Case { word, arms } => {
                 self.handle_compound_case(shell, word, arms, background)
             }
-            any => {
-                eprintln!("CompoundCommandKind not handled: {any:#?}");
-                EXIT_FAILURE
-            }
+            ast::CompoundCommandKind::Brace(body) => self.handle_compound_brace(shell, body),
         };
 
         SavedFd::restore_fds(fds_to_restore);
@@ -405,7 +1128,12 @@ impl<'a> InputInterpreter<'a> {
     ) -> i32 {
         let subshell_cmds = &self.input[(start_pos.byte + 1)..(end_pos.byte)];
 
-        let mut args_vec = vec!["-c".to_string(), subshell_cmds.to_string()];
+        // the child gets the environment and positional parameters, the
+        // rest of the shell state is recreated by commands run before the
+        // subshell's own ones, its changes stay in it
+        let script = format!("{}\n{}", internals::subshell_state(shell), subshell_cmds);
+        let mut args_vec = vec!["-c".to_string(), script];
+        args_vec.extend(shell.args.iter().cloned());
 
         match shell.execute_command(
             "wash",
@@ -426,9 +1154,23 @@ impl<'a> InputInterpreter<'a> {
     fn handle_compound_subshell(
         &self,
         shell: &mut Shell,
-        body: &Vec<TopLevelCommand<String>>,
-        _start_pos: &SourcePos,
-        _end_pos: &SourcePos,
+        body: &[TopLevelCommand<String>],
+        start_pos: &SourcePos,
+        end_pos: &SourcePos,
+        background: bool,
+        redirects: &[Redirect],
+    ) -> i32 {
+        let command = &self.input[start_pos.byte..=end_pos.byte];
+        self.fork_subshell(shell, body, command, background, redirects)
+    }
+
+    /// Runs `body` in a forked child so that it cannot change the shell
+    #[cfg(not(target_os = "wasi"))]
+    fn fork_subshell(
+        &self,
+        shell: &mut Shell,
+        body: &[TopLevelCommand<String>],
+        command: &str,
         background: bool,
         redirects: &[Redirect],
     ) -> i32 {
@@ -437,10 +1179,12 @@ impl<'a> InputInterpreter<'a> {
                 if !background {
                     wait_for_child(child)
                 } else {
+                    shell.add_job(child.as_raw() as u32, command.to_string());
                     EXIT_SUCCESS
                 }
             }
             Ok(nix::unistd::ForkResult::Child) => {
+                shell.reset_traps();
                 // Apply all redirects passed to subshell
                 if let Err(err) = apply_redirects(redirects) {
                     eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
@@ -457,7 +1201,7 @@ impl<'a> InputInterpreter<'a> {
                     }
                 }
 
-                std::process::exit(exit_status);
+                shell.exit(exit_status);
             }
             Err(err) => {
                 eprintln!(
@@ -470,11 +1214,118 @@ impl<'a> InputInterpreter<'a> {
         }
     }
 
+    /// `coproc COMMAND [ARGS...]` runs the command in the background with its
+    /// stdin and stdout connected to pipes, the shell reads its output from
+    /// fd `${COPROC[0]}` and writes its input to fd `${COPROC[1]}`
+    #[cfg(not(target_os = "wasi"))]
+    fn handle_coproc(
+        &self,
+        shell: &mut Shell,
+        mut args: Vec<String>,
+        env: &HashMap<String, String>,
+        redirects: &[Redirect],
+    ) -> i32 {
+        if args.is_empty() {
+            eprintln!("{}: coproc: missing command", env!("CARGO_PKG_NAME"));
+            return EXIT_FAILURE;
+        }
+        // both pipes are close-on-exec, only the child's ends get dup2'ed
+        let pipes = os_pipe::pipe().and_then(|input| Ok((input, os_pipe::pipe()?)));
+        let (input_reader, input_writer, output_reader, output_writer) = match pipes {
+            Ok((input, output)) => (
+                input.0.into_raw_fd() as Fd,
+                input.1.into_raw_fd() as Fd,
+                output.0.into_raw_fd() as Fd,
+                output.1.into_raw_fd() as Fd,
+            ),
+            Err(err) => {
+                eprintln!("{}: coproc: {}", env!("CARGO_PKG_NAME"), err);
+                return EXIT_FAILURE;
+            }
+        };
+        let command = args.join(" ");
+        match unsafe { nix::unistd::fork() } {
+            Ok(nix::unistd::ForkResult::Parent { child }) => {
+                let _ = nix::unistd::close(input_reader);
+                let _ = nix::unistd::close(output_writer);
+                let fds = vec![output_reader.to_string(), input_writer.to_string()];
+                shell.vars.remove("COPROC");
+                shell
+                    .arrays
+                    .insert(String::from("COPROC"), Array::from_values(fds));
+                shell
+                    .vars
+                    .insert(String::from("COPROC_PID"), child.as_raw().to_string());
+                shell.add_job(child.as_raw() as u32, format!("coproc {command}"));
+                EXIT_SUCCESS
+            }
+            Ok(nix::unistd::ForkResult::Child) => {
+                shell.reset_traps();
+                // internals run in this process, so the shell's ends are
+                // closed explicitly
+                let _ = nix::unistd::close(input_writer);
+                let _ = nix::unistd::close(output_reader);
+                let pipes = [
+                    Redirect::PipeIn(input_reader),
+                    Redirect::PipeOut(output_writer),
+                ];
+                if let Err(err) = apply_redirects(&pipes).and_then(|()| apply_redirects(redirects))
+                {
+                    eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                    std::process::exit(EXIT_FAILURE);
+                }
+                let name = args.remove(0);
+                let exit_status = shell
+                    .execute_command(&name, &mut args, env, false, &[])
+                    .unwrap_or_else(|error| {
+                        eprintln!("{}: {:?}", env!("CARGO_PKG_NAME"), error);
+                        EXIT_FAILURE
+                    });
+                shell.exit(exit_status);
+            }
+            Err(err) => {
+                eprintln!(
+                    "{} error: coproc fork failed: {}",
+                    env!("CARGO_PKG_NAME"),
+                    err
+                );
+                EXIT_FAILURE
+            }
+        }
+    }
+
+    #[cfg(target_os = "wasi")]
+    fn handle_coproc(
+        &self,
+        _shell: &mut Shell,
+        _args: Vec<String>,
+        _env: &HashMap<String, String>,
+        _redirects: &[Redirect],
+    ) -> i32 {
+        eprintln!(
+            "{}: coproc: coprocesses are not supported on WASI",
+            env!("CARGO_PKG_NAME")
+        );
+        EXIT_FAILURE
+    }
+
+    /// `{ ...; }` group, run in the current shell unlike `( ... )`
+    fn handle_compound_brace(&self, shell: &mut Shell, body: &[TopLevelCommand<String>]) -> i32 {
+        let mut exit_status = EXIT_SUCCESS;
+        for command in body {
+            exit_status = self.handle_top_level_command(shell, command);
+            if exit_status == EXIT_INTERRUPTED {
+                break;
+            }
+        }
+        exit_status
+    }
+
     fn handle_compound_for(
         &self,
         shell: &mut Shell,
-        var: &String,
-        word_list: &Vec<TopLevelWord<String>>,
+        var: &str,
+        word_list: &Option<Vec<TopLevelWord<String>>>,
         body: &Vec<TopLevelCommand<String>>,
         // TODO: implement background jobs in compounds
         _background: bool,
@@ -482,37 +1333,51 @@ impl<'a> InputInterpreter<'a> {
         let mut exit_status = EXIT_SUCCESS;
         let mut finall_list: Vec<String> = vec![];
 
-        for word in word_list {
-            match word {
-                TopLevelWord(Single(Simple(Param(_)))) => {
-                    if let TopLevelWord(Single(Simple(param_word))) = word {
-                        if let Some(value) = self.handle_simple_word(shell, param_word) {
-                            finall_list.append(
-                                &mut value
-                                    .split_whitespace()
-                                    .map(String::from)
-                                    .collect::<Vec<String>>(),
-                            );
-                        }
-                    }
-                }
-                word => {
-                    if let Some(w) = self.handle_top_level_word(shell, word) {
-                        finall_list.push(w);
-                    }
+        match word_list {
+            // `for x; do` iterates over the positional parameters
+            None => finall_list.extend(shell.args.iter().skip(1).cloned()),
+            Some(word_list) => {
+                for word in word_list {
+                    finall_list.extend(self.expand_word(shell, word));
                 }
             }
         }
 
-        for word in finall_list {
-            env::set_var(var, word);
+        if let Err(reason) = shell
+            .guard
+            .check_expansion(finall_list.iter().map(String::len).sum())
+        {
+            shell.guard.trip(&reason);
+            return EXIT_INTERRUPTED;
+        }
+
+        let saved = shell.save_var(var);
+        for (iteration, word) in finall_list.into_iter().enumerate() {
+            if let Err(reason) = shell.guard.check_loop_iterations(iteration as u64 + 1) {
+                shell.guard.trip(&reason);
+                exit_status = EXIT_INTERRUPTED;
+                break;
+            }
+            if shell.interrupted() {
+                exit_status = EXIT_INTERRUPTED;
+                break;
+            }
+            if let Err(err) = shell.set_var(var, word) {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                exit_status = EXIT_FAILURE;
+                break;
+            }
             for command in body {
                 exit_status = self.handle_top_level_command(shell, command);
                 if exit_status == EXIT_INTERRUPTED {
-                    return exit_status;
+                    break;
                 }
             }
+            if exit_status == EXIT_INTERRUPTED || loop_finished(shell) {
+                break;
+            }
         }
+        shell.restore_var(var, saved);
         exit_status
     }
 
@@ -525,13 +1390,10 @@ impl<'a> InputInterpreter<'a> {
         _background: bool,
     ) -> i32 {
         let mut exit_status = EXIT_SUCCESS;
-        let mut guard_status = EXIT_SUCCESS;
         for guard_body in conditionals {
-            for command in &guard_body.guard {
-                guard_status = self.handle_top_level_command(shell, command);
-                if guard_status == EXIT_INTERRUPTED {
-                    return guard_status;
-                }
+            let guard_status = self.handle_guard(shell, &guard_body.guard);
+            if guard_status == EXIT_INTERRUPTED {
+                return guard_status;
             }
             if guard_status == EXIT_SUCCESS {
                 for command in &guard_body.body {
@@ -541,12 +1403,11 @@ impl<'a> InputInterpreter<'a> {
                     }
                 }
                 return exit_status;
-            } else {
-                shell.last_exit_status = EXIT_SUCCESS;
             }
+            // `$?` keeps the failed guard status for the next `elif` or `else`
         }
-        if guard_status != EXIT_SUCCESS && guard_status != EXIT_INTERRUPTED {
-            if let Some(els) = else_branch {
+        match else_branch {
+            Some(els) => {
                 for command in els {
                     exit_status = self.handle_top_level_command(shell, command);
                     if exit_status == EXIT_INTERRUPTED {
@@ -554,29 +1415,61 @@ impl<'a> InputInterpreter<'a> {
                     }
                 }
             }
-        };
+            // an `if` without a taken branch succeeds
+            None => shell.last_exit_status = EXIT_SUCCESS,
+        }
         exit_status
     }
 
+    /// Condition of `if`, `while` or `until`, its failure does not stop the
+    /// shell with `set -e`
+    fn handle_guard(&self, shell: &mut Shell, guard: &[TopLevelCommand<String>]) -> i32 {
+        let mut guard_status = EXIT_SUCCESS;
+        shell.condition_depth += 1;
+        for command in guard {
+            guard_status = self.handle_top_level_command(shell, command);
+            if guard_status == EXIT_INTERRUPTED {
+                break;
+            }
+        }
+        shell.condition_depth -= 1;
+        guard_status
+    }
+
+    /// `while` loop, or `until` loop running as long as the guard fails
     fn handle_compound_while(
         &self,
         shell: &mut Shell,
         guard_body: &GuardBodyPair<TopLevelCommand<String>>,
+        until: bool,
         // TODO: implement background jobs in compounds
         _background: bool,
     ) -> i32 {
+        // status of the last body command, 0 when the body never ran
         let mut exit_status = EXIT_SUCCESS;
+        let mut iterations: u64 = 0;
         loop {
-            for cmd in guard_body.guard.iter() {
-                exit_status = self.handle_top_level_command(shell, cmd);
-                if exit_status == EXIT_INTERRUPTED {
-                    return exit_status;
+            iterations += 1;
+            if let Err(reason) = shell.guard.check_loop_iterations(iterations) {
+                shell.guard.trip(&reason);
+                return EXIT_INTERRUPTED;
+            }
+            if shell.interrupted() {
+                return EXIT_INTERRUPTED;
+            }
+            let guard_status = self.handle_guard(shell, &guard_body.guard);
+            if guard_status == EXIT_INTERRUPTED {
+                return guard_status;
+            }
+            if shell.loop_control.is_some() {
+                if loop_finished(shell) {
+                    break;
                 }
+                continue;
             }
 
-            if exit_status != EXIT_SUCCESS {
-                exit_status = EXIT_SUCCESS;
-                shell.last_exit_status = EXIT_SUCCESS;
+            if (guard_status == EXIT_SUCCESS) == until {
+                shell.last_exit_status = exit_status;
                 break;
             }
 
@@ -586,6 +1479,9 @@ impl<'a> InputInterpreter<'a> {
                     return exit_status;
                 }
             }
+            if loop_finished(shell) {
+                break;
+            }
         }
         exit_status
     }
@@ -606,7 +1502,7 @@ impl<'a> InputInterpreter<'a> {
             if arm.patterns.iter().any(|pattern| {
                 // TODO: Ctrl-C is not handled during processing pattern because `Subst`
                 // is not handled and we cannot execute any command in pattern
-                if let Some(handled_pattern) = self.handle_top_level_word(shell, pattern) {
+                if let Some(handled_pattern) = self.handle_pattern(shell, pattern) {
                     if let Ok(pat) = Pattern::new(&handled_pattern) {
                         pat.matches(&handled_word)
                     } else {
@@ -627,103 +1523,580 @@ impl<'a> InputInterpreter<'a> {
                         break;
                     }
                 }
-                break;
+                return exit_status;
+            }
+        }
+        // a `case` without a matching arm succeeds
+        shell.last_exit_status = EXIT_SUCCESS;
+        exit_status
+    }
+
+    /// `case` pattern with quoted and escaped parts matching only literally,
+    /// e.g. `"$prefix"*` matches words starting with the value of `prefix`
+    fn handle_pattern(&self, shell: &mut Shell, pattern: &TopLevelWord<String>) -> Option<String> {
+        self.handle_word_and_pattern(shell, pattern)
+            .map(|(_, pattern)| pattern)
+    }
+
+    /// Expanded `word` together with the same word as a glob pattern
+    fn handle_word_and_pattern(
+        &self,
+        shell: &mut Shell,
+        word: &TopLevelWord<String>,
+    ) -> Option<(String, String)> {
+        self.handle_word_pieces(shell, word)
+            .map(|pieces| join_pieces(&pieces))
+    }
+
+    /// Expanded parts of `word`, quoted and escaped parts are matched
+    /// literally by globs and cannot form brace expansions
+    fn handle_word_pieces(
+        &self,
+        shell: &mut Shell,
+        word: &TopLevelWord<String>,
+    ) -> Option<Vec<Piece>> {
+        let words = match &word.0 {
+            ast::ComplexWord::Single(word) => std::slice::from_ref(word),
+            ast::ComplexWord::Concat(words) => words.as_slice(),
+        };
+        let mut pieces: Option<Vec<Piece>> = None;
+        let mut words = words;
+        if let Some((prefix, consumed)) = tilde_prefix(shell, words) {
+            pieces = Some(vec![Piece {
+                pattern: Pattern::escape(&prefix),
+                value: prefix,
+                active: false,
+                split: false,
+                quoted: true,
+            }]);
+            words = &words[consumed..];
+        }
+        for word in words {
+            let quoted = matches!(
+                word,
+                ast::Word::SingleQuoted(_)
+                    | ast::Word::DoubleQuoted(_)
+                    | ast::Word::Simple(ast::SimpleWord::Escaped(_))
+            );
+            let active = matches!(word, ast::Word::Simple(ast::SimpleWord::Literal(_)));
+            let split = matches!(
+                word,
+                ast::Word::Simple(ast::SimpleWord::Param(_))
+                    | ast::Word::Simple(ast::SimpleWord::Subst(_))
+            );
+            let value = match self.handle_single(shell, word) {
+                Some(value) => value,
+                None => continue,
+            };
+            pieces.get_or_insert_with(Vec::new).push(Piece {
+                pattern: if quoted {
+                    Pattern::escape(&value)
+                } else {
+                    value.clone()
+                },
+                value,
+                active,
+                split,
+                quoted,
+            });
+        }
+        if pieces.is_none() && words.len() > 1 {
+            pieces = Some(Vec::new());
+        }
+        trace!(target: "expand", ?pieces, "{:?}", word);
+        pieces
+    }
+
+    /// Fields of `word` after brace expansion, parameter and command
+    /// substitution, field splitting and pathname expansion. `"$@"` expands
+    /// to one field per positional parameter.
+    fn expand_word(&self, shell: &mut Shell, word: &TopLevelWord<String>) -> Vec<String> {
+        let ifs = self
+            .handle_parameter(shell, &ast::Parameter::Var(String::from("IFS")))
+            .unwrap_or_else(|| String::from(DEFAULT_IFS));
+        if let Some((name, quoted)) = array_list(word) {
+            let values = self.array_values(shell, name);
+            return if quoted {
+                values
+            } else {
+                values
+                    .iter()
+                    .flat_map(|value| split_fields(value, &ifs))
+                    .collect()
+            };
+        }
+        if is_positional_list(word) {
+            let args = shell.args.iter().skip(1).cloned();
+            return match &word.0 {
+                Single(Simple(_)) => args.flat_map(|arg| split_fields(&arg, &ifs)).collect(),
+                _ => args.collect(),
+            };
+        }
+        let pieces = match self.handle_word_pieces(shell, word) {
+            Some(pieces) => pieces,
+            None => return Vec::new(),
+        };
+        let words = match brace::expand(pieces, &shell.guard) {
+            Ok(words) => words,
+            Err(reason) => {
+                shell.guard.trip(&reason);
+                return Vec::new();
+            }
+        };
+        words
+            .into_iter()
+            .flat_map(|pieces| split_pieces(pieces, &ifs))
+            .flat_map(|pieces| {
+                let (value, pattern) = join_pieces(&pieces);
+                self.expand_glob(shell, value, pattern)
+            })
+            .collect()
+    }
+
+    /// Pathname expansion of unquoted `*`, `?` and `[...]` in `pattern`,
+    /// relative to the shell working directory. Patterns matching nothing
+    /// are kept as they are, or removed with `shopt -s nullglob`.
+    fn expand_glob(&self, shell: &Shell, value: String, pattern: String) -> Vec<String> {
+        if pattern == Pattern::escape(&value) {
+            return vec![value];
+        }
+        // glob crate strips ./ prefix, it is added back to the results
+        let (dot_prefix, pattern) = match pattern.strip_prefix("./") {
+            Some(rest) => ("./", rest),
+            None => ("", pattern.as_str()),
+        };
+        let absolute = pattern.starts_with('/');
+        let full_pattern = if absolute {
+            pattern.to_string()
+        } else {
+            format!(
+                "{}/{}",
+                Pattern::escape(&shell.pwd.display().to_string()),
+                pattern
+            )
+        };
+        let paths = match glob::glob_with(
+            &full_pattern,
+            glob::MatchOptions {
+                case_sensitive: true,
+                require_literal_leading_dot: true,
+                require_literal_separator: true,
+            },
+        ) {
+            Ok(paths) => paths,
+            Err(_) => return vec![value],
+        };
+        let globbed = paths
+            .filter_map(Result::ok)
+            .map(|path| {
+                let path = if absolute {
+                    path
+                } else {
+                    path.strip_prefix(&shell.pwd)
+                        .map(PathBuf::from)
+                        .unwrap_or(path)
+                };
+                format!("{}{}", dot_prefix, path.display())
+            })
+            .collect::<Vec<_>>();
+        if !globbed.is_empty() {
+            globbed
+        } else if shell.shopt.nullglob {
+            Vec::new()
+        } else {
+            vec![value]
+        }
+    }
+
+    fn handle_simple_command(
+        &self,
+        shell: &mut Shell,
+        cmd: &ast::DefaultSimpleCommand,
+        background: bool,
+        redirects: &mut Vec<Redirect>,
+    ) -> i32 {
+        self.expansion_failed.set(false);
+        if let Some(ast::RedirectOrCmdWord::CmdWord(word)) = cmd.redirects_or_cmd_words.first() {
+            if is_arith_command(word) {
+                return self.handle_arith_command(shell, word);
+            }
+            if is_test_command(word) {
+                return self.handle_test_command(shell, &cmd.redirects_or_cmd_words[1..]);
+            }
+        }
+        let mut env = cmd
+            .redirects_or_env_vars
+            .iter()
+            .filter_map(|redirect_or_env_var| match redirect_or_env_var {
+                ast::RedirectOrEnvVar::EnvVar(key, value) => {
+                    let value = match value {
+                        None => Some("".to_string()),
+                        Some(top_level_word) => self.handle_top_level_word(shell, top_level_word),
+                    };
+                    value.map(|value| (key.clone(), value))
+                }
+                ast::RedirectOrEnvVar::Redirect(redirect_type) => {
+                    // TODO: in case of None break execution?
+                    if let Some(redirect) = self.handle_redirect(shell, redirect_type) {
+                        redirects.extend(redirect);
+                    }
+                    None
+                }
+            })
+            .collect::<HashMap<_, _>>();
+
+        let mut args = Vec::new();
+        // `name[sub]=value` words are assignments until the first other word
+        let mut assignments = true;
+        let mut compound = None;
+        // only unquoted command names are aliases
+        let mut aliased = false;
+        for redirect_or_cmd_word in &cmd.redirects_or_cmd_words {
+            match redirect_or_cmd_word {
+                ast::RedirectOrCmdWord::Redirect(redirect_type) => {
+                    // TODO: in case of None break execution?
+                    if let Some(redirect) = self.handle_redirect(shell, redirect_type) {
+                        redirects.extend(redirect);
+                    }
+                }
+                ast::RedirectOrCmdWord::CmdWord(cmd_word)
+                    if assignments && is_element_assignment(cmd_word) =>
+                {
+                    // the value is neither split nor globbed, like in `x=$y`
+                    let assignment = match self.handle_top_level_word(shell, cmd_word) {
+                        Some(assignment) => assignment,
+                        None => continue,
+                    };
+                    if let Err(err) = self.assign_element(shell, &assignment) {
+                        eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                        return EXIT_FAILURE;
+                    }
+                }
+                ast::RedirectOrCmdWord::CmdWord(cmd_word) => {
+                    if assignments && compound.is_none() {
+                        compound = compound_assignment(cmd_word);
+                        if compound.is_some() {
+                            continue;
+                        }
+                    }
+                    assignments = false;
+                    if args.is_empty() {
+                        aliased = matches!(
+                            cmd_word,
+                            TopLevelWord(Single(Simple(ast::SimpleWord::Literal(_))))
+                        );
+                    }
+                    args.extend(self.expand_word(shell, cmd_word));
+                }
+            }
+        }
+
+        if self.expansion_failed.replace(false) {
+            return EXIT_FAILURE;
+        }
+
+        if let Some(target) = compound {
+            let (append, name) = match target.strip_prefix('+') {
+                Some(name) => (true, name),
+                None => (false, target),
+            };
+            if shell.attributes.get(name).is_some_and(|a| a.readonly) {
+                eprintln!(
+                    "{}: {name}: {}",
+                    env!("CARGO_PKG_NAME"),
+                    tr("readonly variable")
+                );
+                return EXIT_FAILURE;
+            }
+            let mut array = match shell.arrays.get(name) {
+                _ if append => self.take_array(shell, name),
+                Some(array) if array.is_associative() => Array::associative(),
+                _ => Array::default(),
+            };
+            let assigned = self.assign_elements(shell, name, &mut array, args);
+            shell.vars.remove(name);
+            shell.arrays.insert(name.to_string(), array);
+            return match assigned {
+                Ok(()) => EXIT_SUCCESS,
+                Err(err) => {
+                    eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                    EXIT_FAILURE
+                }
+            };
+        }
+
+        if aliased {
+            if let Some(command) = alias_command(shell, &env, &args, background) {
+                return self.handle_alias(shell, &args[0], &command, redirects);
+            }
+        }
+
+        // values of assignments may be secrets, only their names are logged
+        debug!(
+            target: "expand",
+            ?args,
+            assigned = ?env.keys().collect::<Vec<_>>(),
+            "expanded simple command"
+        );
+        // brace expansion stops words that would exceed the limit
+        if shell.guard.is_tripped() {
+            return EXIT_INTERRUPTED;
+        }
+        let expansion_size = args.iter().chain(env.values()).map(String::len).sum();
+        if let Err(reason) = shell.guard.check_expansion(expansion_size) {
+            shell.guard.trip(&reason);
+            return EXIT_INTERRUPTED;
+        }
+
+        if shell.xtrace && !(args.is_empty() && env.is_empty()) {
+            shell.trace_command(&env, &args);
+        }
+
+        if !args.is_empty() {
+            // assignments before a command are checked like plain ones
+            for (key, value) in env.iter_mut() {
+                match shell.assigned_value(key, mem::take(value)) {
+                    Ok(assigned) => *value = assigned,
+                    Err(err) => {
+                        eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                        return EXIT_FAILURE;
+                    }
+                }
+            }
+            if args[0] == "coproc" {
+                args.remove(0);
+                return self.handle_coproc(shell, args, &env, redirects);
+            }
+            let command = args.remove(0);
+            let started = Instant::now();
+            let exit_status =
+                match shell.execute_command(&command, &mut args, &env, background, redirects) {
+                    Ok(result) => result,
+                    Err(error) => {
+                        eprintln!("{}: {:?}", env!("CARGO_PKG_NAME"), error);
+                        EXIT_FAILURE
+                    }
+                };
+            shell.profile.record(&command, started.elapsed());
+            exit_status
+        } else {
+            let mut exit_status = EXIT_SUCCESS;
+            for (key, value) in env.into_iter() {
+                if let Err(err) = shell.set_var(&key, value) {
+                    eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                    exit_status = EXIT_FAILURE;
+                }
+            }
+            exit_status
+        }
+    }
+
+    /// Runs the command an alias expanded to with the redirects of the
+    /// aliased one
+    fn handle_alias(
+        &self,
+        shell: &mut Shell,
+        name: &str,
+        command: &str,
+        redirects: &[Redirect],
+    ) -> i32 {
+        let mut fds_to_restore: Vec<SavedFd> = Vec::new();
+        for redirect in redirects.iter() {
+            if let Err(err) = SavedFd::process_redirect(redirect, &mut fds_to_restore) {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                SavedFd::restore_fds(fds_to_restore);
+                return EXIT_FAILURE;
             }
         }
+        let line = shell.vars.get("LINENO").cloned();
+        shell.alias_stack.push(name.to_string());
+        let exit_status = InputInterpreter::from_input(command).interpret(shell);
+        shell.alias_stack.pop();
+        if let Some(line) = line {
+            shell.vars.insert(String::from("LINENO"), line);
+        }
+        SavedFd::restore_fds(fds_to_restore);
         exit_status
     }
 
-    fn handle_simple_command(
+    /// `((expr))` succeeds when `expr` is not 0
+    fn handle_arith_command(&self, shell: &mut Shell, word: &TopLevelWord<String>) -> i32 {
+        let expression = match self.handle_top_level_word(shell, word) {
+            Some(expression) => expression,
+            None => return EXIT_FAILURE,
+        };
+        let expression = expression.trim_start_matches(ARITH_MARKER);
+        match arith::eval_text(shell, expression) {
+            Ok(0) => EXIT_FAILURE,
+            Ok(_) => EXIT_SUCCESS,
+            Err(err) => {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                EXIT_FAILURE
+            }
+        }
+    }
+
+    /// `[[ ... ]]`, words are neither split nor globbed. Fails with 2 on
+    /// syntax errors.
+    fn handle_test_command(
         &self,
         shell: &mut Shell,
-        cmd: &ast::DefaultSimpleCommand,
-        background: bool,
-        redirects: &mut Vec<Redirect>,
+        words: &[ast::RedirectOrCmdWord<ast::DefaultRedirect, TopLevelWord<String>>],
     ) -> i32 {
-        let env = cmd
-            .redirects_or_env_vars
-            .iter()
-            .filter_map(|redirect_or_env_var| match redirect_or_env_var {
-                ast::RedirectOrEnvVar::EnvVar(key, value) => {
-                    let value = match value {
-                        None => Some("".to_string()),
-                        Some(top_level_word) => self.handle_top_level_word(shell, top_level_word),
-                    };
-                    value.map(|value| (key.clone(), value))
+        let mut cond_words = Vec::new();
+        let mut operands = Vec::new();
+        let mut closed = false;
+        for word in words {
+            let word = match word {
+                ast::RedirectOrCmdWord::CmdWord(word) => word,
+                ast::RedirectOrCmdWord::Redirect(_) => continue,
+            };
+            if closed {
+                eprintln!("{}: [[: unexpected word after `]]'", env!("CARGO_PKG_NAME"));
+                return 2;
+            }
+            if is_test_end(word) {
+                closed = true;
+                continue;
+            }
+            // operators are literals only when `syntax::Bashisms` made them
+            if let TopLevelWord(Single(Simple(ast::SimpleWord::Literal(text)))) = word {
+                if ["&&", "||", "!", "(", ")"].contains(&text.as_str()) {
+                    cond_words.push(CondWord::Operator(text.clone()));
+                    continue;
                 }
-                ast::RedirectOrEnvVar::Redirect(redirect_type) => {
-                    // TODO: in case of None break execution?
-                    if let Some(redirect) = self.handle_redirect_type(shell, redirect_type) {
-                        redirects.push(redirect);
+            }
+            cond_words.push(CondWord::Operand {
+                literal: unquoted_literal(word),
+                index: operands.len(),
+            });
+            operands.push(word);
+        }
+        if !closed {
+            eprintln!("{}: [[: missing `]]'", env!("CARGO_PKG_NAME"));
+            return 2;
+        }
+        let mut expand = |shell: &mut Shell, index: usize| {
+            let pieces = self
+                .handle_word_pieces(shell, operands[index])
+                .unwrap_or_default();
+            let (value, pattern) = join_pieces(&pieces);
+            let regex = pieces
+                .iter()
+                .map(|piece| {
+                    if piece.quoted {
+                        regex::escape(&piece.value)
+                    } else {
+                        piece.value.clone()
                     }
-                    None
-                }
-            })
-            .collect::<HashMap<_, _>>();
+                })
+                .collect();
+            cond::Operand {
+                value,
+                pattern,
+                regex,
+            }
+        };
+        match cond::eval(shell, &cond_words, &mut expand) {
+            Ok(true) => EXIT_SUCCESS,
+            Ok(false) => EXIT_FAILURE,
+            Err(err) => {
+                eprintln!("{}: [[: {}", env!("CARGO_PKG_NAME"), err);
+                2
+            }
+        }
+    }
 
-        let mut args = Vec::new();
-        for redirect_or_cmd_word in &cmd.redirects_or_cmd_words {
-            match redirect_or_cmd_word {
-                ast::RedirectOrCmdWord::Redirect(redirect_type) => {
-                    // TODO: in case of None break execution?
-                    if let Some(redirect) = self.handle_redirect_type(shell, redirect_type) {
-                        redirects.push(redirect);
-                    }
-                }
-                ast::RedirectOrCmdWord::CmdWord(cmd_word) => {
-                    if let Some(arg) = self.handle_top_level_word(shell, &cmd_word.0) {
-                        if let Ok(paths) = glob::glob_with(
-                            &arg,
-                            glob::MatchOptions {
-                                case_sensitive: true,
-                                require_literal_leading_dot: true,
-                                require_literal_separator: true,
-                            },
-                        ) {
-                            let mut globbed = paths
-                                .map(|s| {
-                                    if arg.starts_with("./") {
-                                        // glob crate strips ./ prefix, if it is a bug, maybe we could fix it and submit a PR
-                                        format!("./{}", s.unwrap().display())
-                                    } else {
-                                        s.unwrap().into_os_string().into_string().unwrap()
-                                    }
-                                })
-                                .peekable();
-                            if globbed.peek().is_none() {
-                                args.push(arg);
-                            } else {
-                                args.extend(globbed);
-                            }
-                        } else {
-                            args.push(arg);
-                        }
-                    }
-                }
+    /// `{name}>file` and the like open a new fd that stays open after the
+    /// command and store its number in `name`, `{name}>&-` closes it
+    fn handle_named_fd(
+        &self,
+        shell: &mut Shell,
+        name: &str,
+        redirect_type: &ast::Redirect<ast::TopLevelWord<String>>,
+    ) -> Result<(), String> {
+        let word = match redirect_type {
+            ast::Redirect::Read(_, word)
+            | ast::Redirect::Write(_, word)
+            | ast::Redirect::ReadWrite(_, word)
+            | ast::Redirect::Append(_, word)
+            | ast::Redirect::Clobber(_, word)
+            | ast::Redirect::Heredoc(_, word)
+            | ast::Redirect::DupRead(_, word)
+            | ast::Redirect::DupWrite(_, word) => word,
+        };
+        let target = self
+            .handle_top_level_word(shell, word)
+            .unwrap_or_default()
+            .splitn(3, syntax::NAMED_FD_MARKER)
+            .nth(2)
+            .unwrap_or_default()
+            .to_string();
+        let mut options = OpenOptions::new();
+        match redirect_type {
+            ast::Redirect::DupRead(..) | ast::Redirect::DupWrite(..) if target == "-" => {
+                let fd = shell
+                    .vars
+                    .get(name)
+                    .and_then(|fd| fd.parse::<Fd>().ok())
+                    .ok_or_else(|| format!("{name}: not a file descriptor"))?;
+                return close_fd(fd).map_err(|err| format!("{fd}: {err}"));
+            }
+            ast::Redirect::DupRead(..) | ast::Redirect::DupWrite(..) => {
+                let fd = target
+                    .parse::<Fd>()
+                    .map_err(|_| format!("{target}: ambiguous redirect"))?;
+                let fd = duplicate_fd(fd).map_err(|err| format!("{fd}: {err}"))?;
+                return shell.set_var(name, fd.to_string());
             }
+            ast::Redirect::Read(..) => options.read(true),
+            ast::Redirect::Write(..) | ast::Redirect::Clobber(..) => {
+                options.write(true).truncate(true).create(true)
+            }
+            ast::Redirect::Append(..) => options.append(true).create(true),
+            ast::Redirect::ReadWrite(..) => options.read(true).write(true).create(true),
+            ast::Redirect::Heredoc(..) => return Err(format!("{name}: cannot name here-document")),
+        };
+        let path = shell.pwd.join(&target);
+        if shell.noclobber && matches!(redirect_type, ast::Redirect::Write(..)) && path.is_file() {
+            return Err(format!("{target}: cannot overwrite existing file"));
         }
+        let file = options
+            .open(&path)
+            .map_err(|err| format!("{target}: {err}"))?;
+        let fd = high_fd(file.into_raw_fd() as Fd).map_err(|err| format!("{target}: {err}"))?;
+        shell.set_var(name, fd.to_string())
+    }
 
-        if !args.is_empty() {
-            match shell.execute_command(&args.remove(0), &mut args, &env, background, redirects) {
-                Ok(result) => result,
-                Err(error) => {
-                    eprintln!("{}: {:?}", env!("CARGO_PKG_NAME"), error);
-                    EXIT_FAILURE
-                }
+    /// Redirects a redirection results in, `&>` and `&>>` also send stderr
+    /// where stdout goes
+    fn handle_redirect(
+        &self,
+        shell: &mut Shell,
+        redirect_type: &ast::Redirect<ast::TopLevelWord<String>>,
+    ) -> Option<Vec<Redirect>> {
+        if let Some(name) = named_fd(redirect_type) {
+            if let Err(err) = self.handle_named_fd(shell, name, redirect_type) {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                return None;
             }
-        } else {
-            for (key, value) in env.iter() {
-                // if it's a global update env, if shell variable update only vars
-                if env::var(key).is_ok() {
-                    env::set_var(key, value);
-                    #[cfg(target_os = "wasi")]
-                    let _ = wasi_ext_lib::set_env(key, Some(value));
-                } else {
-                    shell.vars.insert(key.clone(), value.clone());
-                }
+            return Some(Vec::new());
+        }
+        let redirect = self.handle_redirect_type(shell, redirect_type)?;
+        let combined = match redirect_type {
+            ast::Redirect::Write(None, word) | ast::Redirect::Append(None, word) => {
+                first_literal(word).is_some_and(|text| text.starts_with(syntax::COMBINED_MARKER))
             }
-            EXIT_SUCCESS
+            _ => false,
+        };
+        let mut redirects = vec![redirect];
+        if combined {
+            redirects.push(Redirect::Duplicate {
+                fd_src: STDOUT,
+                fd_dst: STDERR,
+            });
         }
+        Some(redirects)
     }
 
     fn handle_redirect_type(
@@ -732,6 +2105,10 @@ impl<'a> InputInterpreter<'a> {
         redirect_type: &ast::Redirect<ast::TopLevelWord<String>>,
     ) -> Option<Redirect> {
         let get_absolute_path = |filename: String, sh: &Shell| {
+            let filename = match filename.strip_prefix(syntax::COMBINED_MARKER) {
+                Some(filename) => filename.to_string(),
+                None => filename,
+            };
             if !filename.starts_with('/') {
                 PathBuf::from(&sh.pwd).join(&filename).display().to_string()
             } else {
@@ -739,9 +2116,8 @@ impl<'a> InputInterpreter<'a> {
             }
         };
 
-        match redirect_type {
+        let redirect = match redirect_type {
             ast::Redirect::Write(file_descriptor, top_level_word) => {
-                // TODO: check noclobber option is set
                 let file_descriptor = file_descriptor.map_or_else(|| STDOUT, |fd| fd as Fd);
                 if let Some(mut filename) = self.handle_top_level_word(shell, top_level_word) {
                     filename = get_absolute_path(filename, shell);
@@ -781,7 +2157,23 @@ impl<'a> InputInterpreter<'a> {
                 let file_descriptor = file_descriptor.map_or_else(|| STDOUT, |fd| fd as Fd);
                 if let Some(mut filename) = self.handle_top_level_word(shell, top_level_word) {
                     filename = get_absolute_path(filename, shell);
-                    Some(Redirect::Write(file_descriptor, filename))
+                    // `>|` overrides noclobber, an existing file is
+                    // truncated here and appended to like an empty one
+                    if !shell.noclobber || !Path::new(&filename).is_file() {
+                        Some(Redirect::Write(file_descriptor, filename))
+                    } else {
+                        match OpenOptions::new()
+                            .write(true)
+                            .truncate(true)
+                            .open(&filename)
+                        {
+                            Ok(_) => Some(Redirect::Append(file_descriptor, filename)),
+                            Err(err) => {
+                                eprintln!("{}: {}: {}", env!("CARGO_PKG_NAME"), filename, err);
+                                None
+                            }
+                        }
+                    }
                 } else {
                     None
                 }
@@ -790,6 +2182,23 @@ impl<'a> InputInterpreter<'a> {
                 let fd_dst = file_descriptor.map_or_else(|| STDIN, |fd| fd as Fd);
                 if let Some(fd) = self.handle_top_level_word(shell, top_level_word) {
                     match fd.as_str() {
+                        // `<<< word` here-string, see `syntax::Bashisms`
+                        text if text.starts_with(syntax::HERE_STRING_MARKER) => {
+                            let text = text.trim_start_matches(syntax::HERE_STRING_MARKER);
+                            match self.heredoc_file(shell, &format!("{text}\n")) {
+                                Ok(path) => {
+                                    Some(Redirect::Read(fd_dst, path.display().to_string()))
+                                }
+                                Err(err) => {
+                                    eprintln!(
+                                        "{}: cannot create here-string: {}",
+                                        env!("CARGO_PKG_NAME"),
+                                        err
+                                    );
+                                    None
+                                }
+                            }
+                        }
                         "-" => Some(Redirect::Close(fd_dst as Fd)),
                         fd => {
                             if let Ok(fd_src) = fd.parse::<Fd>() {
@@ -822,12 +2231,27 @@ impl<'a> InputInterpreter<'a> {
                     None
                 }
             }
-            // TODO: Heredoc (multiline command parsing) implementation
-            any => {
-                eprintln!("Redirect not handled: {any:?}");
-                None
+            // the parser collects the body and strips leading tabs for `<<-`
+            ast::Redirect::Heredoc(file_descriptor, top_level_word) => {
+                let file_descriptor = file_descriptor.map_or_else(|| STDIN, |fd| fd as Fd);
+                let body = self
+                    .handle_top_level_word(shell, top_level_word)
+                    .unwrap_or_default();
+                match self.heredoc_file(shell, &body) {
+                    Ok(path) => Some(Redirect::Read(file_descriptor, path.display().to_string())),
+                    Err(err) => {
+                        eprintln!(
+                            "{}: cannot create here-document: {}",
+                            env!("CARGO_PKG_NAME"),
+                            err
+                        );
+                        None
+                    }
+                }
             }
-        }
+        };
+        debug!(target: "redirect", resolved = redirect.is_some(), "{:?}", redirect_type);
+        redirect
     }
 
     fn handle_top_level_word(
@@ -835,16 +2259,23 @@ impl<'a> InputInterpreter<'a> {
         shell: &mut Shell,
         word: &ast::DefaultComplexWord,
     ) -> Option<String> {
-        match word {
+        let expanded = match word {
             ast::ComplexWord::Single(word) => self.handle_single(shell, word),
-            ast::ComplexWord::Concat(words) => Some(
-                words
-                    .iter()
-                    .filter_map(|w| self.handle_single(shell, w))
-                    .collect::<Vec<_>>()
-                    .join(""),
-            ),
-        }
+            ast::ComplexWord::Concat(words) => {
+                let (mut expanded, consumed) = match tilde_prefix(shell, words) {
+                    Some((prefix, consumed)) => (prefix, consumed),
+                    None => (String::new(), 0),
+                };
+                for w in &words[consumed..] {
+                    if let Some(part) = self.handle_single(shell, w) {
+                        expanded.push_str(&part);
+                    }
+                }
+                Some(expanded)
+            }
+        };
+        trace!(target: "expand", ?expanded, "{:?}", word);
+        expanded
     }
 
     fn handle_single(&self, shell: &mut Shell, word: &ast::DefaultWord) -> Option<String> {
@@ -869,9 +2300,128 @@ impl<'a> InputInterpreter<'a> {
         match word {
             ast::SimpleWord::Literal(w) => Some(w.clone()),
             ast::SimpleWord::Colon => Some(":".to_string()),
-            ast::SimpleWord::Tilde => Some(env::var("HOME").unwrap()),
-            #[cfg(target_os = "wasi")]
+            ast::SimpleWord::Tilde => Some(env::var("HOME").unwrap_or_else(|_| String::from("/"))),
             ast::SimpleWord::Subst(c) => match (*c).as_ref() {
+                ast::ParameterSubstitution::Command(cmd, _) if runs_pure_internals(cmd) => {
+                    Some(self.capture_internals(shell, cmd))
+                }
+                ast::ParameterSubstitution::Default(false, ast::Parameter::Var(name), word)
+                    if is_subscript(word) =>
+                {
+                    let subscript = self.handle_optional_word(shell, word)?;
+                    let subscript = subscript.trim_start_matches(ARRAY_MARKER);
+                    self.array_subscript(shell, name, subscript)
+                }
+                ast::ParameterSubstitution::Default(false, p, word) if is_substring(word) => {
+                    let range = self.handle_optional_word(shell, word)?;
+                    let value = self.substituted_parameter(shell, p).unwrap_or_default();
+                    self.substring(shell, &value, range.trim_start_matches(SUBSTRING_MARKER))
+                }
+                ast::ParameterSubstitution::Len(p) => Some(
+                    match p {
+                        ast::Parameter::At | ast::Parameter::Star => {
+                            shell.args.len().saturating_sub(1)
+                        }
+                        p => self
+                            .substituted_parameter(shell, p)
+                            .map_or(0, |value| value.chars().count()),
+                    }
+                    .to_string(),
+                ),
+                ast::ParameterSubstitution::Default(colon, p, word) => {
+                    match self.substituted_parameter(shell, p) {
+                        Some(value) if !(*colon && value.is_empty()) => Some(value),
+                        _ => self.handle_optional_word(shell, word),
+                    }
+                }
+                ast::ParameterSubstitution::Assign(colon, p, word) => {
+                    match self.substituted_parameter(shell, p) {
+                        Some(value) if !(*colon && value.is_empty()) => Some(value),
+                        _ => {
+                            let value = self.handle_optional_word(shell, word).unwrap_or_default();
+                            match p {
+                                ast::Parameter::Var(name) => {
+                                    match shell.set_var(name, value.clone()) {
+                                        Ok(()) => Some(value),
+                                        Err(err) => {
+                                            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                                            shell.last_exit_status = EXIT_FAILURE;
+                                            None
+                                        }
+                                    }
+                                }
+                                _ => {
+                                    eprintln!(
+                                        "{}: ${}: cannot assign in this way",
+                                        env!("CARGO_PKG_NAME"),
+                                        parameter_name(p)
+                                    );
+                                    shell.last_exit_status = EXIT_FAILURE;
+                                    None
+                                }
+                            }
+                        }
+                    }
+                }
+                ast::ParameterSubstitution::Error(colon, p, word) => {
+                    match self.substituted_parameter(shell, p) {
+                        Some(value) if !(*colon && value.is_empty()) => Some(value),
+                        _ => {
+                            let message = self
+                                .handle_optional_word(shell, word)
+                                .filter(|message| !message.is_empty())
+                                .unwrap_or_else(|| String::from("parameter null or not set"));
+                            eprintln!(
+                                "{}: {}: {}",
+                                env!("CARGO_PKG_NAME"),
+                                parameter_name(p),
+                                message
+                            );
+                            // the command is not run, scripts exit
+                            shell.last_exit_status = EXIT_FAILURE;
+                            if !shell.is_interactive() {
+                                shell.exit(EXIT_FAILURE);
+                            }
+                            self.expansion_failed.set(true);
+                            None
+                        }
+                    }
+                }
+                ast::ParameterSubstitution::Alternative(colon, p, word) => {
+                    match self.substituted_parameter(shell, p) {
+                        Some(value) if !(*colon && value.is_empty()) => {
+                            Some(self.handle_optional_word(shell, word).unwrap_or_default())
+                        }
+                        _ => Some(String::new()),
+                    }
+                }
+                ast::ParameterSubstitution::RemoveSmallestSuffix(p, word) => {
+                    Some(self.remove_pattern(shell, p, word, false, false))
+                }
+                ast::ParameterSubstitution::RemoveLargestSuffix(p, word) => {
+                    Some(self.remove_pattern(shell, p, word, false, true))
+                }
+                ast::ParameterSubstitution::RemoveSmallestPrefix(p, word) => {
+                    Some(self.remove_pattern(shell, p, word, true, false))
+                }
+                ast::ParameterSubstitution::RemoveLargestPrefix(p, word) => {
+                    Some(self.remove_pattern(shell, p, word, true, true))
+                }
+                ast::ParameterSubstitution::Arith(expr) => {
+                    let value = match expr {
+                        Some(expr) => arith::eval(shell, expr),
+                        None => Ok(0),
+                    };
+                    match value {
+                        Ok(value) => Some(value.to_string()),
+                        Err(err) => {
+                            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                            shell.last_exit_status = EXIT_FAILURE;
+                            None
+                        }
+                    }
+                }
+                #[cfg(target_os = "wasi")]
                 ast::ParameterSubstitution::Command(cmd, (start, end)) => {
                     let subshell_pipe_path =
                         format!("/dev/subshell_pipe.{}", wasi_ext_lib::getpid().unwrap_or(0));
@@ -906,71 +2456,382 @@ impl<'a> InputInterpreter<'a> {
 
                     Some(data)
                 }
-                _ => todo!(),
-            },
-            ast::SimpleWord::Escaped(w) => Some(w.replace('\\', "")),
-            ast::SimpleWord::Param(p) => match p {
-                ast::Parameter::Bang => shell.last_job_pid.map(|pid| pid.to_string()),
-                ast::Parameter::Var(key) => {
-                    if let Some(variable) = shell.vars.get(key) {
-                        Some(variable.clone())
-                    } else {
-                        env::var(key).ok()
-                    }
-                }
-                ast::Parameter::Question => Some(shell.last_exit_status.to_string()),
-                ast::Parameter::Dollar => {
-                    #[cfg(not(target_os = "wasi"))]
-                    {
-                        use std::process;
-                        Some(process::id().to_string())
-                    }
-                    #[cfg(target_os = "wasi")]
-                    Some(wasi_ext_lib::getpid().unwrap().to_string())
-                }
-                ast::Parameter::At => {
-                    if !shell.args.is_empty() {
-                        Some(
-                            shell
-                                .args
-                                .range(1..)
-                                .cloned()
-                                .collect::<Vec<String>>()
-                                .join(" "),
-                        )
-                    } else {
-                        Some(String::from(" "))
-                    }
-                }
-                ast::Parameter::Pound => Some(format!(
-                    "{}",
-                    if !shell.args.is_empty() {
-                        shell.args.len() - 1
-                    } else {
-                        0
-                    }
-                )),
-                ast::Parameter::Positional(n) => Some(String::from(
-                    if let Some(a) = &shell.args.get(*n as usize) {
-                        a
-                    } else {
-                        ""
-                    },
-                )),
-                any => {
-                    eprintln!("parameter not handled: {any:?}");
+                #[cfg(not(target_os = "wasi"))]
+                ast::ParameterSubstitution::Command(..) => {
+                    eprintln!(
+                        "{}: command substitution is only supported for internal commands",
+                        env!("CARGO_PKG_NAME")
+                    );
+                    shell.last_exit_status = EXIT_FAILURE;
+                    self.expansion_failed.set(true);
                     None
                 }
             },
+            // backslash-newline only continues the line
+            ast::SimpleWord::Escaped(w) if w == "\n" => Some(String::new()),
+            ast::SimpleWord::Escaped(w) => Some(w.clone()),
+            ast::SimpleWord::Param(p) => {
+                if shell.nounset && self.substituted_parameter(shell, p).is_none() {
+                    self.unbound_parameter(shell, p);
+                    return None;
+                }
+                self.handle_parameter(shell, p)
+            }
             ast::SimpleWord::Star => Some("*".to_string()),
             ast::SimpleWord::Question => Some("?".to_string()),
             ast::SimpleWord::SquareOpen => Some("[".to_string()),
             ast::SimpleWord::SquareClose => Some("]".to_string()),
-            #[cfg(not(target_os = "wasi"))]
+        }
+    }
+
+    /// Characters of `value` in the `offset:length` range of
+    /// `${name:offset:length}`, both arithmetic expressions. Negative values
+    /// count from the end.
+    fn substring(&self, shell: &mut Shell, value: &str, range: &str) -> Option<String> {
+        let chars: Vec<char> = value.chars().collect();
+        let len = chars.len() as i64;
+        let (offset, length) = match range.split_once(':') {
+            Some((offset, length)) => (offset, Some(length)),
+            None => (range, None),
+        };
+        let mut evaluate = |expr: &str| {
+            arith::eval_text(shell, expr).map_err(|err| {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                shell.last_exit_status = EXIT_FAILURE;
+            })
+        };
+        let start = match evaluate(offset) {
+            Ok(offset) if offset < 0 => (len + offset).max(0),
+            Ok(offset) => offset.min(len),
+            Err(()) => {
+                self.expansion_failed.set(true);
+                return None;
+            }
+        };
+        let end = match length.map(evaluate) {
+            None => len,
+            Some(Ok(length)) if length < 0 => len + length,
+            Some(Ok(length)) => start.saturating_add(length).min(len),
+            Some(Err(())) => {
+                self.expansion_failed.set(true);
+                return None;
+            }
+        };
+        if end < start {
+            eprintln!(
+                "{}: {}: substring expression < 0",
+                env!("CARGO_PKG_NAME"),
+                range
+            );
+            shell.last_exit_status = EXIT_FAILURE;
+            self.expansion_failed.set(true);
+            return None;
+        }
+        Some(chars[start as usize..end as usize].iter().collect())
+    }
+
+    /// Reports expansion of an unset parameter with `set -u`, scripts exit
+    /// and the interactive shell abandons the command
+    fn unbound_parameter(&self, shell: &mut Shell, p: &ast::DefaultParameter) {
+        eprintln!(
+            "{}: {}: unbound variable",
+            env!("CARGO_PKG_NAME"),
+            parameter_name(p)
+        );
+        if !shell.is_interactive() {
+            shell.exit(EXIT_FAILURE);
+        }
+        self.expansion_failed.set(true);
+    }
+
+    /// Value of a parameter, `None` when it is unset
+    fn handle_parameter(&self, shell: &Shell, p: &ast::DefaultParameter) -> Option<String> {
+        match p {
+            ast::Parameter::Bang => shell.last_job_pid.map(|pid| pid.to_string()),
+            ast::Parameter::Var(key) => {
+                if let Some(variable) = shell.dynamic_var(key) {
+                    Some(variable)
+                } else if let Some(variable) = shell.vars.get(key) {
+                    Some(variable.clone())
+                } else if let Some(array) = shell.arrays.get(key) {
+                    // `$name` of an array is its first element
+                    array.get(&Subscript::Index(0)).cloned()
+                } else {
+                    env::var(key).ok()
+                }
+            }
+            ast::Parameter::Question => Some(shell.last_exit_status.to_string()),
+            ast::Parameter::Dollar => Some(shell.pid.to_string()),
+            ast::Parameter::At => Some(
+                shell
+                    .args
+                    .iter()
+                    .skip(1)
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(" "),
+            ),
+            ast::Parameter::Star => Some(
+                shell
+                    .args
+                    .iter()
+                    .skip(1)
+                    .cloned()
+                    .collect::<Vec<String>>()
+                    .join(&self.star_separator(shell)),
+            ),
+            ast::Parameter::Pound => Some(format!(
+                "{}",
+                if !shell.args.is_empty() {
+                    shell.args.len() - 1
+                } else {
+                    0
+                }
+            )),
+            // `$0` is the shell name outside of scripts
+            ast::Parameter::Positional(0) if shell.args.is_empty() => {
+                Some(env!("CARGO_PKG_NAME").to_string())
+            }
+            ast::Parameter::Positional(n) => Some(String::from(
+                if let Some(a) = &shell.args.get(*n as usize) {
+                    a
+                } else {
+                    ""
+                },
+            )),
             any => {
-                eprintln!("simple word not handled: {any:?}");
+                eprintln!("parameter not handled: {any:?}");
                 None
             }
         }
     }
+
+    /// Separator of `"$*"` and `"${name[*]}"`, the first character of IFS
+    fn star_separator(&self, shell: &Shell) -> String {
+        match self.handle_parameter(shell, &ast::Parameter::Var(String::from("IFS"))) {
+            Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+            None => String::from(" "),
+        }
+    }
+
+    /// Elements of an array, a set variable is an array of one element
+    fn array_values(&self, shell: &Shell, name: &str) -> Vec<String> {
+        match shell.arrays.get(name) {
+            Some(array) => array.values(),
+            None => self
+                .handle_parameter(shell, &ast::Parameter::Var(name.to_string()))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    /// Removes an array to modify it, a variable becomes its first element
+    fn take_array(&self, shell: &mut Shell, name: &str) -> Array {
+        match shell.arrays.remove(name) {
+            Some(array) => array,
+            None => Array::from_values(self.array_values(shell, name)),
+        }
+    }
+
+    /// `${name[sub]}`, `${#name[sub]}` and `${!name[@]}`
+    fn array_subscript(&self, shell: &mut Shell, name: &str, subscript: &str) -> Option<String> {
+        match subscript {
+            "@" => return Some(self.array_values(shell, name).join(" ")),
+            "*" => {
+                let separator = self.star_separator(shell);
+                return Some(self.array_values(shell, name).join(&separator));
+            }
+            "#@" | "#*" => {
+                return Some(match shell.arrays.get(name) {
+                    Some(array) => array.len().to_string(),
+                    None => self.array_values(shell, name).len().to_string(),
+                })
+            }
+            "!@" | "!*" => {
+                return Some(match shell.arrays.get(name) {
+                    Some(array) => array.keys().join(" "),
+                    None if self.array_values(shell, name).is_empty() => String::new(),
+                    None => String::from("0"),
+                })
+            }
+            _ => {}
+        }
+        let (length, index) = match subscript.strip_prefix('#') {
+            Some(index) => (true, index),
+            None => (false, subscript),
+        };
+        let subscript = match self.subscript(shell, name, index) {
+            Ok(subscript) => subscript,
+            Err(err) => {
+                eprintln!("{}: {}", env!("CARGO_PKG_NAME"), err);
+                shell.last_exit_status = EXIT_FAILURE;
+                return None;
+            }
+        };
+        let element = match (shell.arrays.get(name), subscript) {
+            (Some(array), subscript) => array.get(&subscript).cloned(),
+            (None, Subscript::Index(0 | -1)) => {
+                self.handle_parameter(shell, &ast::Parameter::Var(name.to_string()))
+            }
+            (None, _) => None,
+        };
+        if length {
+            Some(
+                element
+                    .map_or(0, |element| element.chars().count())
+                    .to_string(),
+            )
+        } else {
+            element
+        }
+    }
+
+    /// Runs `name<MARKER>sub]=value` or `+=value`
+    fn assign_element(&self, shell: &mut Shell, assignment: &str) -> Result<(), String> {
+        let invalid = || format!("{assignment}: invalid array assignment");
+        let (name, rest) = assignment.split_once(ARRAY_MARKER).ok_or_else(invalid)?;
+        let (subscript, rest) = rest.split_once(']').ok_or_else(invalid)?;
+        let (append, value) = match rest.strip_prefix("+=") {
+            Some(value) => (true, value),
+            None => (false, rest.strip_prefix('=').ok_or_else(invalid)?),
+        };
+        if shell.attributes.get(name).is_some_and(|a| a.readonly) {
+            return Err(format!("{name}: {}", tr("readonly variable")));
+        }
+        let subscript = self.subscript(shell, name, subscript)?;
+        let mut array = self.take_array(shell, name);
+        let value = match array.get(&subscript) {
+            Some(old) if append => format!("{old}{value}"),
+            _ => value.to_string(),
+        };
+        let assigned = shell.assigned_value(name, value).and_then(|value| {
+            array
+                .set(subscript, value)
+                .map_err(|err| format!("{name}{err}"))
+        });
+        shell.vars.remove(name);
+        shell.arrays.insert(name.to_string(), array);
+        assigned
+    }
+
+    /// Element of array `name` given by `text`, it is evaluated
+    /// arithmetically unless the array is associative
+    fn subscript(&self, shell: &mut Shell, name: &str, text: &str) -> Result<Subscript, String> {
+        match shell.arrays.get(name) {
+            Some(array) if array.is_associative() => Ok(Subscript::Key(text.to_string())),
+            _ => arith::eval_text(shell, text).map(Subscript::Index),
+        }
+    }
+
+    /// Elements of `name=(...)`, `[sub]=value` ones set the given element
+    /// and the following ones are added after it
+    fn assign_elements(
+        &self,
+        shell: &mut Shell,
+        name: &str,
+        array: &mut Array,
+        elements: Vec<String>,
+    ) -> Result<(), String> {
+        for element in elements {
+            let keyed = element
+                .strip_prefix('[')
+                .and_then(|element| element.split_once("]="));
+            match keyed {
+                Some((subscript, value)) => {
+                    let subscript = match array {
+                        Array::Associative(_) => Subscript::Key(subscript.to_string()),
+                        Array::Indexed(_) => Subscript::Index(arith::eval_text(shell, subscript)?),
+                    };
+                    let value = shell.assigned_value(name, value.to_string())?;
+                    array
+                        .set(subscript, value)
+                        .map_err(|err| format!("{name}{err}"))?;
+                }
+                None => array
+                    .append(vec![shell.assigned_value(name, element)?])
+                    .map_err(|err| format!("{name}: {err}"))?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Parameter of a `${...}` substitution, positional parameters past the
+    /// last one are unset rather than empty
+    fn substituted_parameter(&self, shell: &Shell, p: &ast::DefaultParameter) -> Option<String> {
+        match p {
+            ast::Parameter::Positional(n) if *n > 0 => shell.args.get(*n as usize).cloned(),
+            p => self.handle_parameter(shell, p),
+        }
+    }
+
+    fn handle_optional_word(
+        &self,
+        shell: &mut Shell,
+        word: &Option<TopLevelWord<String>>,
+    ) -> Option<String> {
+        match word {
+            Some(word) => self.handle_top_level_word(shell, word),
+            None => Some(String::new()),
+        }
+    }
+
+    /// `${p#pat}`, `${p##pat}`, `${p%pat}` and `${p%%pat}`, the value of `p`
+    /// without the shortest or longest prefix or suffix matching `pat`
+    fn remove_pattern(
+        &self,
+        shell: &mut Shell,
+        p: &ast::DefaultParameter,
+        word: &Option<TopLevelWord<String>>,
+        prefix: bool,
+        largest: bool,
+    ) -> String {
+        let value = self.substituted_parameter(shell, p).unwrap_or_default();
+        let pattern = match word
+            .as_ref()
+            .and_then(|word| self.handle_pattern(shell, word))
+        {
+            Some(pattern) => pattern,
+            None => return value,
+        };
+        let pattern = match Pattern::new(&pattern) {
+            Ok(pattern) => pattern,
+            // invalid wildcards only match literally, like in `case`
+            Err(_) => Pattern::new(&Pattern::escape(&pattern)).unwrap(),
+        };
+        let mut bounds = value
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(value.len()))
+            .collect::<Vec<_>>();
+        // candidates are tried from the shortest match to the longest one
+        if prefix == largest {
+            bounds.reverse();
+        }
+        for i in bounds {
+            let (matched, rest) = if prefix {
+                (&value[..i], &value[i..])
+            } else {
+                (&value[i..], &value[..i])
+            };
+            if pattern.matches(matched) {
+                return rest.to_string();
+            }
+        }
+        value
+    }
+
+    /// Runs a command substitution in-process, collecting output of internals
+    /// instead of reading it from a subshell
+    fn capture_internals(&self, shell: &mut Shell, body: &[TopLevelCommand<String>]) -> String {
+        let outer = shell.capture.replace(String::new());
+        for command in body {
+            if self.handle_top_level_command(shell, command) == EXIT_INTERRUPTED {
+                break;
+            }
+        }
+        let mut output = std::mem::replace(&mut shell.capture, outer).unwrap_or_default();
+        // like for spawned commands, all trailing newlines are removed
+        output.truncate(output.trim_end_matches('\n').len());
+        output
+    }
 }
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::fs;
+use std::path::PathBuf;
+
+use crate::locale::tr;
+
+/// Command started in the background with `&`
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: usize,
+    pub pid: u32,
+    pub command: String,
+    /// Here-document files read by the job, removed once it finishes
+    pub files: Vec<PathBuf>,
+}
+
+impl Job {
+    fn remove_files(&self) {
+        for path in &self.files {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Number of finished jobs whose exit statuses are kept for `wait`
+const REMEMBERED: usize = 64;
+
+/// Background jobs of the shell, listed by the `jobs` builtin
+#[derive(Debug, Default)]
+pub struct JobList {
+    jobs: Vec<Job>,
+    /// Reaped jobs with their exit statuses, kept until waited for
+    finished: Vec<(Job, i32)>,
+}
+
+impl JobList {
+    /// Registers a started child, ids are reused once all jobs finished
+    pub fn add(&mut self, pid: u32, command: String) -> usize {
+        let id = self.jobs.last().map_or(0, |job| job.id) + 1;
+        self.finished.retain(|(job, _)| job.id != id);
+        self.jobs.push(Job {
+            id,
+            pid,
+            command,
+            files: Vec::new(),
+        });
+        id
+    }
+
+    /// Hands temporary files over to the last started job
+    pub fn attach_files(&mut self, files: Vec<PathBuf>) {
+        if let Some(job) = self.jobs.last_mut() {
+            job.files.extend(files);
+        }
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Id of the job given by `%ID`, `%%`, `%+` or the process id of its
+    /// child
+    pub fn find(&self, spec: &str) -> Option<usize> {
+        let job = match spec.strip_prefix('%') {
+            Some("" | "%" | "+") => self.jobs.last(),
+            Some(id) => {
+                let id = id.parse::<usize>().ok()?;
+                self.all().find(|job| job.id == id)
+            }
+            None => {
+                let pid = spec.parse::<u32>().ok()?;
+                self.all().find(|job| job.pid == pid)
+            }
+        };
+        job.map(|job| job.id)
+    }
+
+    /// Running jobs followed by finished ones that were not waited for
+    fn all(&self) -> impl Iterator<Item = &Job> {
+        self.jobs
+            .iter()
+            .chain(self.finished.iter().map(|(job, _)| job))
+    }
+
+    /// Blocks until the child of job `id` exits and removes the job,
+    /// returning it with its exit status. Fails on WASI, which has no way to
+    /// wait for children.
+    pub fn wait(&mut self, id: usize) -> Result<(Job, i32), String> {
+        if let Some(position) = self.finished.iter().position(|(job, _)| job.id == id) {
+            return Ok(self.finished.remove(position));
+        }
+        let position = self
+            .jobs
+            .iter()
+            .position(|job| job.id == id)
+            .ok_or_else(|| format!("%{id}: {}", tr("no such job")))?;
+        #[cfg(target_os = "wasi")]
+        {
+            let _ = position;
+            Err(String::from("waiting for jobs is not supported on WASI"))
+        }
+        #[cfg(not(target_os = "wasi"))]
+        {
+            use nix::sys::wait::waitpid;
+            let job = self.jobs.remove(position);
+            job.remove_files();
+            let pid = nix::unistd::Pid::from_raw(job.pid as i32);
+            loop {
+                match waitpid(pid, None) {
+                    Ok(status) => {
+                        if let Some(exit_status) = crate::shell_base::child_exit_status(status) {
+                            return Ok((job, exit_status));
+                        }
+                    }
+                    Err(nix::errno::Errno::EINTR) => {}
+                    Err(errno) => return Err(format!("%{id}: {errno}")),
+                }
+            }
+        }
+    }
+
+    /// Removes jobs whose children exited or cannot be waited for, returning
+    /// them with their exit statuses or the errors. WASI has no way to poll
+    /// children so jobs stay listed there.
+    pub fn reap(&mut self) -> Vec<(Job, Result<i32, String>)> {
+        let mut finished = Vec::new();
+        #[cfg(not(target_os = "wasi"))]
+        self.jobs.retain(|job| {
+            use nix::sys::wait::{waitpid, WaitPidFlag};
+            let pid = nix::unistd::Pid::from_raw(job.pid as i32);
+            let exit_status = match waitpid(pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(status) => crate::shell_base::child_exit_status(status).map(Ok),
+                Err(errno) => Some(Err(format!("%{}: {errno}", job.id))),
+            };
+            match exit_status {
+                Some(exit_status) => {
+                    job.remove_files();
+                    finished.push((job.clone(), exit_status));
+                    false
+                }
+                None => true,
+            }
+        });
+        for (job, exit_status) in &finished {
+            if let Ok(exit_status) = exit_status {
+                self.finished.push((job.clone(), *exit_status));
+            }
+        }
+        let forgotten = self.finished.len().saturating_sub(REMEMBERED);
+        self.finished.drain(..forgotten);
+        finished
+    }
+}
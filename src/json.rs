@@ -0,0 +1,536 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Minimal JSON value, object keys keep their insertion order
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    /// Integers are kept apart from other numbers, which lose precision
+    /// above 2^53
+    Integer(i64),
+    /// Integers above `i64::MAX`
+    Unsigned(u64),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+/// Step of a path like `.items[0].name` selecting values in a document
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    /// Array index, counted from the end when negative
+    Index(i64),
+    /// `[]`, every element of an array or value of an object
+    Iterate,
+}
+
+impl fmt::Display for PathSegment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PathSegment::Key(key) => write!(f, "\"{key}\""),
+            PathSegment::Index(index) => write!(f, "{index}"),
+            PathSegment::Iterate => write!(f, "[]"),
+        }
+    }
+}
+
+static NULL: Json = Json::Null;
+
+/// Arrays and objects nested deeper than this are rejected rather than
+/// overflowing the stack of the recursive parser
+const MAX_DEPTH: usize = 256;
+
+/// Parses paths in the jq syntax: `.`, `.key`, `.["key"]`, `.[0]`, `.[-1]`
+/// and `.[]`, e.g. `.items[].name`
+pub fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let chars: Vec<char> = path.chars().collect();
+    if chars.first() != Some(&'.') {
+        return Err(format!("{path}: path must start with ."));
+    }
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        match chars[pos] {
+            '.' => {
+                pos += 1;
+                let start = pos;
+                while pos < chars.len()
+                    && (chars[pos].is_alphanumeric() || chars[pos] == '_' || chars[pos] == '-')
+                {
+                    pos += 1;
+                }
+                if pos > start {
+                    segments.push(PathSegment::Key(chars[start..pos].iter().collect()));
+                } else if chars.get(pos) != Some(&'[') && !(pos == 1 && chars.len() == 1) {
+                    return Err(format!("{path}: expected a key after . at offset {start}"));
+                }
+            }
+            '[' => {
+                let close = chars[pos..]
+                    .iter()
+                    .position(|c| *c == ']')
+                    .map(|offset| pos + offset)
+                    .ok_or_else(|| format!("{path}: missing ]"))?;
+                let inner: String = chars[pos + 1..close].iter().collect();
+                let segment = if inner.is_empty() {
+                    PathSegment::Iterate
+                } else if let Some(key) = inner
+                    .strip_prefix('"')
+                    .and_then(|inner| inner.strip_suffix('"'))
+                {
+                    PathSegment::Key(key.to_string())
+                } else {
+                    PathSegment::Index(
+                        inner
+                            .trim()
+                            .parse()
+                            .map_err(|_| format!("{path}: {inner}: invalid index"))?,
+                    )
+                };
+                segments.push(segment);
+                pos = close + 1;
+            }
+            c => return Err(format!("{path}: unexpected {c} at offset {pos}")),
+        }
+    }
+    Ok(segments)
+}
+
+impl Json {
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut parser = JsonParser {
+            chars: input.chars().collect(),
+            pos: 0,
+            depth: 0,
+        };
+        let value = parser.parse_value()?;
+        parser.skip_whitespace();
+        if parser.pos != parser.chars.len() {
+            return Err(format!("trailing characters at offset {}", parser.pos));
+        }
+        Ok(value)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        if let Json::Object(entries) = self {
+            entries.iter().find(|(k, _)| k == key).map(|(_, v)| v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        if let Json::String(s) = self {
+            Some(s)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Json::Integer(n) => Some(*n),
+            Json::Unsigned(n) => i64::try_from(*n).ok(),
+            Json::Number(n) => Some(*n as i64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        if let Json::Bool(b) = self {
+            Some(*b)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        if let Json::Array(a) = self {
+            Some(a)
+        } else {
+            None
+        }
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Json::Null => "null",
+            Json::Bool(_) => "boolean",
+            Json::Integer(_) | Json::Unsigned(_) | Json::Number(_) => "number",
+            Json::String(_) => "string",
+            Json::Array(_) => "array",
+            Json::Object(_) => "object",
+        }
+    }
+
+    /// Values selected by `path`, missing keys and indexes select `null`
+    pub fn query<'a>(&'a self, path: &[PathSegment]) -> Result<Vec<&'a Json>, String> {
+        let mut values = vec![self];
+        for segment in path {
+            let mut selected = Vec::new();
+            for value in values {
+                match (segment, value) {
+                    (PathSegment::Key(_) | PathSegment::Index(_), Json::Null) => {
+                        selected.push(&NULL)
+                    }
+                    (PathSegment::Key(key), Json::Object(_)) => {
+                        selected.push(value.get(key).unwrap_or(&NULL))
+                    }
+                    (PathSegment::Index(index), Json::Array(items)) => {
+                        let index = if *index < 0 {
+                            items.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        let item = usize::try_from(index).ok().and_then(|i| items.get(i));
+                        selected.push(item.unwrap_or(&NULL));
+                    }
+                    (PathSegment::Iterate, Json::Array(items)) => selected.extend(items.iter()),
+                    (PathSegment::Iterate, Json::Object(entries)) => {
+                        selected.extend(entries.iter().map(|(_, value)| value))
+                    }
+                    (PathSegment::Iterate, value) => {
+                        return Err(format!("cannot iterate over {}", value.type_name()))
+                    }
+                    (segment, value) => {
+                        return Err(format!(
+                            "cannot index {} with {}",
+                            value.type_name(),
+                            segment
+                        ))
+                    }
+                }
+            }
+            values = selected;
+        }
+        Ok(values)
+    }
+
+    pub fn object(entries: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            entries
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v))
+                .collect(),
+        )
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Integer(n)
+    }
+}
+
+fn write_escaped(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Integer(n) => write!(f, "{n}"),
+            Json::Unsigned(n) => write!(f, "{n}"),
+            // infinities and NaN have no JSON representation
+            Json::Number(n) if !n.is_finite() => write!(f, "null"),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{n}")
+                }
+            }
+            Json::String(s) => write_escaped(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+struct JsonParser {
+    chars: Vec<char>,
+    pos: usize,
+    /// Arrays and objects the parser is in
+    depth: usize,
+}
+
+impl JsonParser {
+    fn skip_whitespace(&mut self) {
+        while self.pos < self.chars.len() && self.chars[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.chars.get(self.pos) == Some(&c) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(format!("expected '{}' at offset {}", c, self.pos))
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: Json) -> Result<Json, String> {
+        for c in word.chars() {
+            self.expect(c)?;
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self) -> Result<Json, String> {
+        self.skip_whitespace();
+        match self.chars.get(self.pos) {
+            None => Err("unexpected end of input".to_string()),
+            Some('n') => self.expect_word("null", Json::Null),
+            Some('t') => self.expect_word("true", Json::Bool(true)),
+            Some('f') => self.expect_word("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.parse_string()?)),
+            Some(&open @ ('[' | '{')) => {
+                if self.depth == MAX_DEPTH {
+                    return Err(format!("nesting too deep at offset {}", self.pos));
+                }
+                self.depth += 1;
+                let value = if open == '[' {
+                    self.parse_array()
+                } else {
+                    self.parse_object()
+                };
+                self.depth -= 1;
+                value
+            }
+            Some(_) => self.parse_number(),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&']') {
+            self.pos += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some(']') => {
+                    self.pos += 1;
+                    return Ok(Json::Array(items));
+                }
+                _ => return Err(format!("expected ',' or ']' at offset {}", self.pos)),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<Json, String> {
+        self.pos += 1;
+        let mut entries = Vec::new();
+        self.skip_whitespace();
+        if self.chars.get(self.pos) == Some(&'}') {
+            self.pos += 1;
+            return Ok(Json::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.parse_value()?;
+            entries.push((key, value));
+            self.skip_whitespace();
+            match self.chars.get(self.pos) {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(Json::Object(entries));
+                }
+                _ => return Err(format!("expected ',' or '}}' at offset {}", self.pos)),
+            }
+        }
+    }
+
+    /// Value of the 4 hex digits of a `\u` escape starting at `at`
+    fn hex4(&self, at: usize) -> Option<u32> {
+        let digits = self.chars.get(at..at + 4)?;
+        if !digits.iter().all(char::is_ascii_hexdigit) {
+            return None;
+        }
+        u32::from_str_radix(&digits.iter().collect::<String>(), 16).ok()
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.chars.get(self.pos) {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(result);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    let escaped = match self.chars.get(self.pos) {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let code = self.hex4(self.pos + 1).ok_or_else(|| {
+                                format!("bad unicode escape at offset {}", self.pos)
+                            })?;
+                            self.pos += 4;
+                            // characters above U+FFFF are escaped as a surrogate pair
+                            let low = match (
+                                self.chars.get(self.pos + 1),
+                                self.chars.get(self.pos + 2),
+                            ) {
+                                (Some('\\'), Some('u')) if (0xd800..0xdc00).contains(&code) => self
+                                    .hex4(self.pos + 3)
+                                    .filter(|low| (0xdc00..0xe000).contains(low)),
+                                _ => None,
+                            };
+                            match low {
+                                Some(low) => {
+                                    self.pos += 6;
+                                    let code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                                    char::from_u32(code).unwrap_or('\u{fffd}')
+                                }
+                                None => char::from_u32(code).unwrap_or('\u{fffd}'),
+                            }
+                        }
+                        Some(c) => *c,
+                        None => return Err("unterminated string".to_string()),
+                    };
+                    result.push(escaped);
+                    self.pos += 1;
+                }
+                Some(c) => {
+                    result.push(*c);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    /// Skips ASCII digits, returns how many there were
+    fn skip_digits(&mut self) -> usize {
+        let start = self.pos;
+        while self.chars.get(self.pos).is_some_and(char::is_ascii_digit) {
+            self.pos += 1;
+        }
+        self.pos - start
+    }
+
+    /// Number in the JSON grammar, which has no leading zeros, `+` signs,
+    /// bare `.` or infinities
+    fn parse_number(&mut self) -> Result<Json, String> {
+        let start = self.pos;
+        let invalid = || format!("invalid value at offset {start}");
+        if self.chars.get(self.pos) == Some(&'-') {
+            self.pos += 1;
+        }
+        match self.chars.get(self.pos) {
+            Some('0') => self.pos += 1,
+            Some('1'..='9') => {
+                self.skip_digits();
+            }
+            _ => return Err(invalid()),
+        }
+        let mut integer = true;
+        if self.chars.get(self.pos) == Some(&'.') {
+            self.pos += 1;
+            integer = false;
+            if self.skip_digits() == 0 {
+                return Err(invalid());
+            }
+        }
+        if matches!(self.chars.get(self.pos), Some('e' | 'E')) {
+            self.pos += 1;
+            integer = false;
+            if matches!(self.chars.get(self.pos), Some('+' | '-')) {
+                self.pos += 1;
+            }
+            if self.skip_digits() == 0 {
+                return Err(invalid());
+            }
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        if integer {
+            if let Ok(n) = number.parse() {
+                return Ok(Json::Integer(n));
+            }
+            if let Ok(n) = number.parse() {
+                return Ok(Json::Unsigned(n));
+            }
+        }
+        match number.parse::<f64>() {
+            Ok(n) if n.is_finite() => Ok(Json::Number(n)),
+            Ok(_) => Err(format!("number out of range at offset {start}")),
+            Err(_) => Err(invalid()),
+        }
+    }
+}
@@ -4,12 +4,48 @@
  * SPDX-License-Identifier: Apache-2.0
  */
 
+pub mod archive;
+pub mod argparse;
+pub mod arith;
+pub mod array;
+pub mod bookmarks;
+pub mod brace;
+#[cfg(feature = "busybox")]
+pub mod busybox;
 pub mod cli;
+pub mod completion;
+pub mod cond;
+pub mod config;
+pub mod debugger;
+pub mod digest;
+pub mod dir_env;
+pub mod frecency;
 pub mod internals;
 pub mod interpreter;
+pub mod jobs;
+pub mod json;
+pub mod limits;
+pub mod locale;
+pub mod logging;
 pub mod output_device;
+pub mod profiling;
+pub mod random;
 pub mod saved_fd;
+pub mod schedule;
 pub mod shell_base;
+pub mod spell;
+#[cfg(feature = "parser-api")]
+pub mod syntax;
+#[cfg(not(feature = "parser-api"))]
+#[allow(dead_code)]
+mod syntax;
+pub mod terminal;
+pub mod theme;
+pub mod timing;
+pub mod trap;
+pub mod trust;
+pub mod watch;
+pub mod xdg;
 
 pub use shell_base::spawn;
 pub use shell_base::Shell;
@@ -0,0 +1,151 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::time::{Duration, Instant};
+
+use crate::config::Config;
+
+const DEFAULT_MAX_DEPTH: usize = 256;
+const DEFAULT_MAX_EXPANSION: usize = 64 * 1024 * 1024;
+const DEFAULT_MAX_UNPACKED: usize = 1024 * 1024 * 1024;
+
+/// Resource limits read from the `[limits]` config table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Maximum nesting of sourced scripts and nested command strings
+    pub max_depth: usize,
+    /// Maximum number of iterations of a single loop, unlimited when `None`
+    pub max_loop_iterations: Option<u64>,
+    /// Maximum size in bytes of words produced for a single command
+    pub max_expansion: usize,
+    /// Maximum size in bytes of data decompressed from a single archive
+    pub max_unpacked: usize,
+    /// Wall-clock time allowed for a script or an interactive command line
+    pub time_budget: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_loop_iterations: None,
+            max_expansion: DEFAULT_MAX_EXPANSION,
+            max_unpacked: DEFAULT_MAX_UNPACKED,
+            time_budget: None,
+        }
+    }
+}
+
+impl Limits {
+    /// Zero or negative values disable a limit
+    pub fn from_config(config: &Config) -> Limits {
+        let limit = |key: &str, default: Option<usize>| match config.get_int(key) {
+            Some(value) if value > 0 => Some(value as usize),
+            Some(_) => None,
+            None => default,
+        };
+        let time_budget = config
+            .get_seconds("limits.time")
+            .filter(|secs| *secs > 0.0)
+            .map(Duration::from_secs_f64);
+        Limits {
+            max_depth: limit("limits.depth", Some(DEFAULT_MAX_DEPTH)).unwrap_or(usize::MAX),
+            max_loop_iterations: limit("limits.loop_iterations", None).map(|v| v as u64),
+            max_expansion: limit("limits.expansion_size", Some(DEFAULT_MAX_EXPANSION))
+                .unwrap_or(usize::MAX),
+            max_unpacked: limit("limits.unpacked_size", Some(DEFAULT_MAX_UNPACKED))
+                .unwrap_or(usize::MAX),
+            time_budget,
+        }
+    }
+}
+
+/// Tracks resource usage of the running script against `Limits`.
+///
+/// Once a limit is exceeded the guard stays tripped until the outermost
+/// script or command line finishes, so nested interpreters unwind as well.
+#[derive(Debug, Default)]
+pub struct ResourceGuard {
+    pub limits: Limits,
+    depth: usize,
+    deadline: Option<Instant>,
+    tripped: bool,
+}
+
+impl ResourceGuard {
+    /// Called when a script or command string starts executing
+    pub fn enter(&mut self) -> Result<(), String> {
+        if self.depth == 0 {
+            self.tripped = false;
+            self.deadline = self
+                .limits
+                .time_budget
+                .map(|budget| Instant::now() + budget);
+        }
+        self.depth += 1;
+        if self.depth > self.limits.max_depth {
+            return Err(format!(
+                "maximum nesting depth ({}) exceeded",
+                self.limits.max_depth
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+        if self.depth == 0 {
+            self.deadline = None;
+        }
+    }
+
+    /// Forgets nesting state after execution was aborted without unwinding through `leave`
+    pub fn reset(&mut self) {
+        self.depth = 0;
+        self.deadline = None;
+        self.tripped = false;
+    }
+
+    pub fn check_time(&self) -> Result<(), String> {
+        match (self.deadline, self.limits.time_budget) {
+            (Some(deadline), Some(budget)) if Instant::now() > deadline => Err(format!(
+                "time budget of {:.1}s exceeded",
+                budget.as_secs_f64()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_loop_iterations(&self, iterations: u64) -> Result<(), String> {
+        match self.limits.max_loop_iterations {
+            Some(max) if iterations > max => Err(format!("loop iteration limit ({max}) exceeded")),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_expansion(&self, size: usize) -> Result<(), String> {
+        if size > self.limits.max_expansion {
+            Err(format!(
+                "expansion size limit ({} bytes) exceeded",
+                self.limits.max_expansion
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Reports exceeded limit once and marks execution for abort
+    pub fn trip(&mut self, reason: &str) {
+        if !self.tripped {
+            eprintln!("{}: {}", env!("CARGO_PKG_NAME"), reason);
+            self.tripped = true;
+        }
+    }
+
+    pub fn is_tripped(&self) -> bool {
+        self.tripped
+    }
+}
@@ -0,0 +1,1019 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Translations of user-facing messages: errors shared by many commands and
+//! the help of builtins, messages specific to one builtin stay in English.
+//! Messages are looked up by their English text in the catalog of the
+//! language selected with `LC_ALL`, `LC_MESSAGES` or `LANG`. Catalogs are
+//! built in for a few languages and can be extended or overridden with JSON
+//! objects in `$XDG_DATA_HOME/wash/locale/<language>.json`.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::sync::Mutex;
+
+use lazy_static::lazy_static;
+use tracing::debug;
+
+use crate::json::Json;
+use crate::xdg;
+
+const PL: &[(&str, &str)] = &[
+    ("command not found", "nie znaleziono polecenia"),
+    (
+        "No such file or directory",
+        "Nie ma takiego pliku ani katalogu",
+    ),
+    ("Is a directory", "Jest katalogiem"),
+    (
+        "cannot overwrite existing file",
+        "nie można nadpisać istniejącego pliku",
+    ),
+    ("Permission denied", "Brak dostępu"),
+    (
+        "cannot execute binary file",
+        "nie można uruchomić pliku binarnego",
+    ),
+    (
+        "Is a directory (use -r to copy it)",
+        "Jest katalogiem (użyj -r, aby go skopiować)",
+    ),
+    (
+        "Is a directory (use -r to remove it)",
+        "Jest katalogiem (użyj -r, aby go usunąć)",
+    ),
+    ("Usage:", "Użycie:"),
+    ("Options:", "Opcje:"),
+    ("Print this help", "Wyświetla tę pomoc"),
+    ("invalid option", "nieprawidłowa opcja"),
+    ("option requires an argument", "opcja wymaga argumentu"),
+    (
+        "option does not take an argument",
+        "opcja nie przyjmuje argumentu",
+    ),
+    ("not found", "nie znaleziono"),
+    ("not a shell builtin", "nie jest poleceniem wbudowanym"),
+    ("readonly variable", "zmienna tylko do odczytu"),
+    (
+        "cannot unset: readonly variable",
+        "nie można usunąć: zmienna tylko do odczytu",
+    ),
+    ("no such job", "nie ma takiego zadania"),
+    (
+        "no completion specification",
+        "brak specyfikacji uzupełniania",
+    ),
+    (
+        "invalid signal specification",
+        "nieprawidłowe określenie sygnału",
+    ),    // help of builtins
+    (
+        "Extract a tar archive, gzipped or not, standard input when ARCHIVE is -",
+        "Wypakowuje archiwum tar, skompresowane gzipem lub nie, ze standardowego wejścia, gdy ARCHIVE to -",
+    ),
+    (
+        "Extract a zip archive, replacing existing files",
+        "Wypakowuje archiwum zip, zastępując istniejące pliki",
+    ),
+    (
+        "Create, list or extract tar archives, reading gzipped ones as well",
+        "Tworzy, wyświetla lub wypakowuje archiwa tar, odczytując też skompresowane gzipem",
+    ),
+    ("List entries instead of extracting them", "Wyświetla wpisy zamiast je wypakowywać"),
+    ("Print entries while extracting", "Wypisuje wpisy podczas wypakowywania"),
+    (
+        "Extract into DIR, the working directory by default",
+        "Wypakowuje do DIR, domyślnie do katalogu roboczego",
+    ),
+    ("Create ARCHIVE from PATHs", "Tworzy ARCHIVE z PATH"),
+    ("Extract ARCHIVE", "Wypakowuje ARCHIVE"),
+    ("List entries of ARCHIVE", "Wyświetla wpisy ARCHIVE"),
+    (
+        "Archive to use, - for standard input or output",
+        "Używane archiwum, - dla standardowego wejścia lub wyjścia",
+    ),
+    ("Print entries while processing them", "Wypisuje wpisy podczas ich przetwarzania"),
+    (
+        "Extract into, or add PATHs relative to, DIR",
+        "Wypakowuje do DIR lub dodaje PATH względem DIR",
+    ),
+    (
+        "List directory contents, one entry per line",
+        "Wyświetla zawartość katalogów, jeden wpis w wierszu",
+    ),
+    (
+        "Print contents of files, standard input without them or for -",
+        "Wypisuje zawartość plików, standardowego wejścia bez nich lub dla -",
+    ),
+    ("Copy files, into DEST if it is a directory", "Kopiuje pliki, do DEST, jeśli jest katalogiem"),
+    (
+        "Move or rename files, into DEST if it is a directory",
+        "Przenosi lub zmienia nazwy plików, do DEST, jeśli jest katalogiem",
+    ),
+    ("Remove files and directories", "Usuwa pliki i katalogi"),
+    ("Create directories", "Tworzy katalogi"),
+    (
+        "Create files or update their modification time",
+        "Tworzy pliki lub aktualizuje czas ich modyfikacji",
+    ),
+    (
+        "Print the first lines of files or standard input",
+        "Wypisuje pierwsze wiersze plików lub standardowego wejścia",
+    ),
+    (
+        "Print the last lines of files or standard input",
+        "Wypisuje ostatnie wiersze plików lub standardowego wejścia",
+    ),
+    (
+        "Count lines, words and bytes of files or standard input",
+        "Zlicza wiersze, słowa i bajty plików lub standardowego wejścia",
+    ),
+    (
+        "Print lines of files or standard input matching a pattern",
+        "Wypisuje wiersze plików lub standardowego wejścia pasujące do wzorca",
+    ),
+    (
+        "Encode or decode base64 data of a file or standard input",
+        "Koduje lub dekoduje dane base64 pliku lub standardowego wejścia",
+    ),
+    (
+        "Print a hex dump of a file or standard input, or revert one with -r",
+        "Wypisuje zrzut szesnastkowy pliku lub standardowego wejścia albo odwraca go z -r",
+    ),
+    ("Include entries starting with .", "Uwzględnia wpisy zaczynające się od ."),
+    ("Show entry types and sizes", "Pokazuje typy i rozmiary wpisów"),
+    ("Copy directories recursively", "Kopiuje katalogi rekurencyjnie"),
+    ("Remove directories and their contents", "Usuwa katalogi wraz z zawartością"),
+    ("Ignore missing files", "Pomija nieistniejące pliki"),
+    (
+        "Create missing parents, existing directories are not an error",
+        "Tworzy brakujące katalogi nadrzędne, istniejące katalogi nie są błędem",
+    ),
+    ("Number of lines to print, 10 by default", "Liczba wypisywanych wierszy, domyślnie 10"),
+    (
+        "Number of lines to print, 10 by default, +NUM starts at line NUM",
+        "Liczba wypisywanych wierszy, domyślnie 10, +NUM zaczyna od wiersza NUM",
+    ),
+    ("Keep printing data appended to files", "Wypisuje dalej dane dopisywane do plików"),
+    ("Print the number of lines", "Wypisuje liczbę wierszy"),
+    ("Print the number of words", "Wypisuje liczbę słów"),
+    ("Print the number of bytes", "Wypisuje liczbę bajtów"),
+    ("Use extended regular expressions", "Używa rozszerzonych wyrażeń regularnych"),
+    ("Ignore case distinctions", "Ignoruje wielkość liter"),
+    ("Prefix lines with their line numbers", "Poprzedza wiersze ich numerami"),
+    ("Print lines that do not match", "Wypisuje niepasujące wiersze"),
+    ("Decode data", "Dekoduje dane"),
+    (
+        "Wrap encoded lines after COLS characters, 76 by default, 0 disables wrapping",
+        "Łamie zakodowane wiersze po COLS znakach, domyślnie 76, 0 wyłącza łamanie",
+    ),
+    ("Print only hex digits, 60 per line", "Wypisuje tylko cyfry szesnastkowe, 60 w wierszu"),
+    ("Convert a dump back to binary data", "Zamienia zrzut z powrotem na dane binarne"),
+    ("Clear the terminal screen", "Czyści ekran terminala"),
+    ("Exit the shell with a status, 0 by default", "Kończy powłokę z kodem wyjścia, domyślnie 0"),
+    ("Print the working directory", "Wypisuje katalog roboczy"),
+    (
+        "Jump to the most frecent visited directory matching all patterns",
+        "Przechodzi do najczęściej i najświeżej odwiedzanego katalogu pasującego do wszystkich wzorców",
+    ),
+    (
+        "Manage directory bookmarks used as cd @NAME",
+        "Zarządza zakładkami katalogów używanymi jako cd @NAME",
+    ),
+    ("Print the command history", "Wypisuje historię poleceń"),
+    ("Remove shell and environment variables", "Usuwa zmienne powłoki i środowiska"),
+    (
+        "Set shell variables and their attributes, print all variables without arguments",
+        "Ustawia zmienne powłoki i ich atrybuty, bez argumentów wypisuje wszystkie zmienne",
+    ),
+    (
+        "Make variables readonly, print readonly ones without arguments",
+        "Ustawia zmienne tylko do odczytu, bez argumentów wypisuje takie zmienne",
+    ),
+    (
+        "Export variables to the environment of executed commands, print exported ones without arguments",
+        "Eksportuje zmienne do środowiska uruchamianych poleceń, bez argumentów wypisuje wyeksportowane",
+    ),
+    (
+        "Define aliases, print all aliases without arguments",
+        "Definiuje aliasy, bez argumentów wypisuje wszystkie aliasy",
+    ),
+    ("Remove aliases", "Usuwa aliasy"),
+    (
+        "Run COMMAND with ARGS without looking it up in aliases, or describe COMMANDs",
+        "Uruchamia COMMAND z ARGS bez szukania go wśród aliasów lub opisuje polecenia COMMAND",
+    ),
+    (
+        "Describe how each NAME would run as a command",
+        "Opisuje, jak każda NAME zostałaby uruchomiona jako polecenie",
+    ),
+    (
+        "Remember where NAMEs are found in PATH, or list remembered commands",
+        "Zapamiętuje, gdzie w PATH znajdują się NAME, lub wyświetla zapamiętane polecenia",
+    ),
+    (
+        "Set how Tab completes arguments of NAMEs, or print completion specs",
+        "Ustawia, jak Tab uzupełnia argumenty NAME, lub wypisuje specyfikacje uzupełniania",
+    ),
+    (
+        "Stop a sourced script with status N, the last status by default",
+        "Przerywa wczytany skrypt z kodem N, domyślnie ostatnim kodem wyjścia",
+    ),
+    ("Write contents to a file", "Zapisuje zawartość do pliku"),
+    (
+        "Read a line from standard input and split it into variables, REPLY by default",
+        "Czyta wiersz ze standardowego wejścia i dzieli go na zmienne, domyślnie REPLY",
+    ),
+    (
+        "Parse the next option of ARGS, positional parameters by default, into NAME",
+        "Analizuje następną opcję ARGS, domyślnie parametrów pozycyjnych, do NAME",
+    ),
+    (
+        "Drop the first N positional parameters, 1 by default",
+        "Usuwa pierwsze N parametrów pozycyjnych, domyślnie 1",
+    ),
+    ("Show or change configuration", "Pokazuje lub zmienia konfigurację"),
+    (
+        "Trust the .envrc or .env file of PATH, the working directory by default",
+        "Oznacza plik .envrc lub .env w PATH jako zaufany, domyślnie w katalogu roboczym",
+    ),
+    (
+        "Revoke trust in the .envrc or .env file of PATH, the working directory by default",
+        "Cofa zaufanie do pliku .envrc lub .env w PATH, domyślnie w katalogu roboczym",
+    ),
+    ("Show or change the color theme", "Pokazuje lub zmienia motyw kolorów"),
+    (
+        "Run a command repeatedly and report timing statistics",
+        "Uruchamia polecenie wielokrotnie i podaje statystyki czasu",
+    ),
+    (
+        "Run a command, then again whenever files matching any of the patterns change",
+        "Uruchamia polecenie, a potem ponownie przy każdej zmianie plików pasujących do któregoś ze wzorców",
+    ),
+    ("List background jobs started with &", "Wyświetla zadania w tle uruchomione z &"),
+    (
+        "Wait for background jobs to finish, all of them without arguments",
+        "Czeka na zakończenie zadań w tle, bez argumentów na wszystkie",
+    ),
+    (
+        "Change shell options, list them without arguments",
+        "Zmienia opcje powłoki, bez argumentów je wyświetla",
+    ),
+    (
+        "Change optional shell behavior, list options and their state without arguments",
+        "Zmienia opcjonalne zachowanie powłoki, bez argumentów wyświetla opcje i ich stan",
+    ),
+    ("Print the number of characters of each string", "Wypisuje liczbę znaków każdego napisu"),
+    (
+        "Print substrings, START counts from 1 or from the end when negative",
+        "Wypisuje podnapisy, START liczy się od 1 lub od końca, gdy jest ujemny",
+    ),
+    (
+        "Remove leading and trailing whitespace or CHARS",
+        "Usuwa białe znaki lub CHARS z początku i końca",
+    ),
+    (
+        "Print parts of strings split at SEPARATOR, an empty one splits characters",
+        "Wypisuje części napisów podzielonych na SEPARATOR, pusty dzieli na znaki",
+    ),
+    ("Join strings with SEPARATOR", "Łączy napisy za pomocą SEPARATOR"),
+    (
+        "Replace PATTERN in strings, $1 refers to a regex capture group",
+        "Zastępuje PATTERN w napisach, $1 oznacza grupę przechwytującą wyrażenia regularnego",
+    ),
+    (
+        "Print strings matching a glob PATTERN, or regex matches and capture groups",
+        "Wypisuje napisy pasujące do wzorca glob PATTERN albo dopasowania i grupy wyrażenia regularnego",
+    ),
+    (
+        "Pad strings to the same width, the longest one or WIDTH",
+        "Dopełnia napisy do tej samej szerokości, najdłuższego z nich lub WIDTH",
+    ),
+    (
+        "Manipulate strings, see string <SUBCOMMAND> --help",
+        "Przetwarza napisy, zobacz string <SUBCOMMAND> --help",
+    ),
+    (
+        "Create a unique temporary file and print its path, trailing Xs in TEMPLATE are replaced",
+        "Tworzy unikalny plik tymczasowy i wypisuje jego ścieżkę, końcowe X w TEMPLATE są zastępowane",
+    ),
+    (
+        "Print a random integer between MIN and MAX inclusive, 0 and 32767 by default",
+        "Wypisuje losową liczbę całkowitą od MIN do MAX włącznie, domyślnie od 0 do 32767",
+    ),
+    ("Print a random version 4 UUID", "Wypisuje losowy UUID w wersji 4"),
+    (
+        "Print values selected by PATH, e.g. .items[0].name or .items[].id",
+        "Wypisuje wartości wybrane przez PATH, np. .items[0].name lub .items[].id",
+    ),
+    (
+        "Print keys of objects or indexes of arrays selected by PATH",
+        "Wypisuje klucze obiektów lub indeksy tablic wybranych przez PATH",
+    ),
+    (
+        "Print lengths of arrays, objects and strings selected by PATH",
+        "Wypisuje długości tablic, obiektów i napisów wybranych przez PATH",
+    ),
+    (
+        "Query JSON documents, see json <SUBCOMMAND> --help",
+        "Odpytuje dokumenty JSON, zobacz json <SUBCOMMAND> --help",
+    ),
+    (
+        "Print or verify checksums of files, standard input without FILEs",
+        "Wypisuje lub sprawdza sumy kontrolne plików, standardowego wejścia bez FILE",
+    ),
+    ("List signal names and numbers", "Wyświetla nazwy i numery sygnałów"),
+    ("Print traps as commands setting them", "Wypisuje pułapki jako polecenia, które je ustawiają"),
+    ("List matching directories with their scores", "Wyświetla pasujące katalogi z ich wynikami"),
+    ("Forget directories that no longer exist", "Zapomina katalogi, które już nie istnieją"),
+    ("Remove variables (default)", "Usuwa zmienne (domyślnie)"),
+    ("Remove functions", "Usuwa funkcje"),
+    (
+        "Export variables, +x makes exported ones local",
+        "Eksportuje zmienne, +x czyni wyeksportowane lokalnymi",
+    ),
+    ("Make variables indexed arrays", "Czyni zmienne tablicami indeksowanymi"),
+    ("Make variables associative arrays", "Czyni zmienne tablicami asocjacyjnymi"),
+    ("Evaluate assigned values arithmetically", "Oblicza przypisywane wartości arytmetycznie"),
+    ("Convert assigned values to lowercase", "Zamienia przypisywane wartości na małe litery"),
+    ("Convert assigned values to uppercase", "Zamienia przypisywane wartości na wielkie litery"),
+    ("Make variables readonly", "Ustawia zmienne tylko do odczytu"),
+    (
+        "Print variables with their attributes in a reusable format",
+        "Wypisuje zmienne z atrybutami w formacie do ponownego użycia",
+    ),
+    (
+        "Print readonly variables in a reusable format",
+        "Wypisuje zmienne tylko do odczytu w formacie do ponownego użycia",
+    ),
+    (
+        "Print exported variables in a reusable format",
+        "Wypisuje wyeksportowane zmienne w formacie do ponownego użycia",
+    ),
+    ("Make variables local to the shell", "Czyni zmienne lokalnymi dla powłoki"),
+    (
+        "Print all aliases in a reusable format",
+        "Wypisuje wszystkie aliasy w formacie do ponownego użycia",
+    ),
+    ("Also append definitions to the rc file", "Dopisuje też definicje do pliku rc"),
+    ("Remove all aliases", "Usuwa wszystkie aliasy"),
+    (
+        "Search for COMMAND in PATH of standard utilities",
+        "Szuka COMMAND w PATH standardowych narzędzi",
+    ),
+    (
+        "Print the path, alias, builtin or keyword COMMAND runs",
+        "Wypisuje ścieżkę, alias, polecenie wbudowane lub słowo kluczowe uruchamiane przez COMMAND",
+    ),
+    ("Describe what COMMAND is", "Opisuje, czym jest COMMAND"),
+    (
+        "List every alias, keyword, builtin and file named NAME",
+        "Wyświetla każdy alias, słowo kluczowe, polecenie wbudowane i plik o nazwie NAME",
+    ),
+    (
+        "Print alias, keyword, builtin or file",
+        "Wypisuje alias, słowo kluczowe, polecenie wbudowane lub plik",
+    ),
+    ("Print the path of NAME if it runs a file", "Wypisuje ścieżkę NAME, jeśli uruchamia plik"),
+    (
+        "Search PATH even for aliases, keywords and builtins",
+        "Przeszukuje PATH także dla aliasów, słów kluczowych i poleceń wbudowanych",
+    ),
+    ("Forget all remembered commands", "Zapomina wszystkie zapamiętane polecenia"),
+    ("Forget NAMEs", "Zapomina NAME"),
+    ("Print remembered paths of NAMEs", "Wypisuje zapamiętane ścieżki NAME"),
+    ("Remember PATH as the file NAMEs run", "Zapamiętuje PATH jako plik uruchamiany przez NAME"),
+    ("Complete alias names", "Uzupełnia nazwy aliasów"),
+    ("Complete internal command names", "Uzupełnia nazwy poleceń wewnętrznych"),
+    ("Complete command names", "Uzupełnia nazwy poleceń"),
+    ("Complete directory names", "Uzupełnia nazwy katalogów"),
+    ("Complete file names", "Uzupełnia nazwy plików"),
+    ("Complete the blank separated WORDS", "Uzupełnia rozdzielone odstępami WORDS"),
+    (
+        "Complete elements of the COMPREPLY array set by COMMAND",
+        "Uzupełnia elementy tablicy COMPREPLY ustawionej przez COMMAND",
+    ),
+    (
+        "Print specs of NAMEs, or all, as complete commands",
+        "Wypisuje specyfikacje NAME lub wszystkie jako polecenia complete",
+    ),
+    ("Remove specs of NAMEs, or all", "Usuwa specyfikacje NAME lub wszystkie"),
+    (
+        "Do not treat backslashes as escapes",
+        "Nie traktuje ukośników wstecznych jako znaków ucieczki",
+    ),
+    ("Print PROMPT on a terminal first", "Najpierw wypisuje PROMPT na terminalu"),
+    ("Return after reading N characters", "Kończy po przeczytaniu N znaków"),
+    ("Assign the fields to ARRAY", "Przypisuje pola do ARRAY"),
+    ("Number of measured runs, 10 by default", "Liczba mierzonych uruchomień, domyślnie 10"),
+    ("Number of unmeasured runs, 1 by default", "Liczba niemierzonych uruchomień, domyślnie 1"),
+    (
+        "Polling interval where file events are unavailable, 500ms by default",
+        "Odstęp odpytywania tam, gdzie zdarzenia plików są niedostępne, domyślnie 500ms",
+    ),
+    ("Cancel a scheduled job", "Anuluje zaplanowane zadanie"),
+    ("Show process ids of jobs", "Pokazuje identyfikatory procesów zadań"),
+    ("Enable an option, +o disables it", "Włącza opcję, +o ją wyłącza"),
+    ("Exit when a command fails, +e stops", "Kończy, gdy polecenie zawiedzie, +e przestaje"),
+    (
+        "Refuse to overwrite existing files with >, +C stops",
+        "Odmawia nadpisania istniejących plików przez >, +C przestaje",
+    ),
+    (
+        "Fail on expansion of unset parameters, +u stops",
+        "Zgłasza błąd przy rozwinięciu nieustawionych parametrów, +u przestaje",
+    ),
+    (
+        "Print commands before running them, +x stops",
+        "Wypisuje polecenia przed ich uruchomieniem, +x przestaje",
+    ),
+    ("Enable options, or list enabled ones", "Włącza opcje lub wyświetla włączone"),
+    ("Disable options, or list disabled ones", "Wyłącza opcje lub wyświetla wyłączone"),
+    ("First character, 1 by default", "Pierwszy znak, domyślnie 1"),
+    ("Number of characters, all by default", "Liczba znaków, domyślnie wszystkie"),
+    ("Only trim the start", "Przycina tylko początek"),
+    ("Only trim the end", "Przycina tylko koniec"),
+    ("Characters to remove", "Znaki do usunięcia"),
+    ("Split at most MAX times", "Dzieli co najwyżej MAX razy"),
+    ("Split from the end", "Dzieli od końca"),
+    ("Replace every occurrence", "Zastępuje każde wystąpienie"),
+    ("PATTERN is a regular expression", "PATTERN jest wyrażeniem regularnym"),
+    ("Match case-insensitively", "Dopasowuje bez rozróżniania wielkości liter"),
+    ("Only print strings that were changed", "Wypisuje tylko zmienione napisy"),
+    ("Print strings that do not match", "Wypisuje niepasujące napisy"),
+    ("Pad at the end instead of the start", "Dopełnia na końcu zamiast na początku"),
+    ("Minimum width", "Minimalna szerokość"),
+    ("Padding character, space by default", "Znak dopełnienia, domyślnie spacja"),
+    ("Create a directory instead of a file", "Tworzy katalog zamiast pliku"),
+    (
+        "Create TEMPLATE in DIR, $TMPDIR or /tmp by default when TEMPLATE is not given",
+        "Tworzy TEMPLATE w DIR, domyślnie w $TMPDIR lub /tmp, gdy nie podano TEMPLATE",
+    ),
+    ("Print strings without quotes", "Wypisuje napisy bez cudzysłowów"),
+    ("Hash function, sha256 (default) or md5", "Funkcja skrótu, sha256 (domyślnie) lub md5"),
+    (
+        "Verify files listed in FILEs in the format printed without -c",
+        "Sprawdza pliki wymienione w FILE w formacie wypisywanym bez -c",
+    ),
+];
+
+const DE: &[(&str, &str)] = &[
+    ("command not found", "Befehl nicht gefunden"),
+    (
+        "No such file or directory",
+        "Datei oder Verzeichnis nicht gefunden",
+    ),
+    ("Is a directory", "Ist ein Verzeichnis"),
+    (
+        "cannot overwrite existing file",
+        "vorhandene Datei kann nicht überschrieben werden",
+    ),
+    ("Permission denied", "Keine Berechtigung"),
+    (
+        "cannot execute binary file",
+        "Binärdatei kann nicht ausgeführt werden",
+    ),
+    (
+        "Is a directory (use -r to copy it)",
+        "Ist ein Verzeichnis (mit -r kopieren)",
+    ),
+    (
+        "Is a directory (use -r to remove it)",
+        "Ist ein Verzeichnis (mit -r löschen)",
+    ),
+    ("Usage:", "Aufruf:"),
+    ("Options:", "Optionen:"),
+    ("Print this help", "Diese Hilfe anzeigen"),
+    ("invalid option", "ungültige Option"),
+    (
+        "option requires an argument",
+        "Option erfordert ein Argument",
+    ),
+    (
+        "option does not take an argument",
+        "Option erlaubt kein Argument",
+    ),
+    ("not found", "nicht gefunden"),
+    ("not a shell builtin", "ist kein eingebauter Befehl"),
+    ("readonly variable", "schreibgeschützte Variable"),
+    (
+        "cannot unset: readonly variable",
+        "Löschen nicht möglich: schreibgeschützte Variable",
+    ),
+    ("no such job", "kein solcher Job"),
+    (
+        "no completion specification",
+        "keine Vervollständigungsspezifikation",
+    ),
+    ("invalid signal specification", "ungültige Signalangabe"),    // help of builtins
+    (
+        "Extract a tar archive, gzipped or not, standard input when ARCHIVE is -",
+        "Ein tar-Archiv entpacken, mit gzip komprimiert oder nicht, die Standardeingabe, wenn ARCHIVE - ist",
+    ),
+    (
+        "Extract a zip archive, replacing existing files",
+        "Ein zip-Archiv entpacken und vorhandene Dateien ersetzen",
+    ),
+    (
+        "Create, list or extract tar archives, reading gzipped ones as well",
+        "tar-Archive erstellen, auflisten oder entpacken, auch mit gzip komprimierte lesen",
+    ),
+    ("List entries instead of extracting them", "Einträge auflisten statt sie zu entpacken"),
+    ("Print entries while extracting", "Einträge beim Entpacken ausgeben"),
+    (
+        "Extract into DIR, the working directory by default",
+        "In DIR entpacken, standardmäßig in das Arbeitsverzeichnis",
+    ),
+    ("Create ARCHIVE from PATHs", "ARCHIVE aus PATHs erstellen"),
+    ("Extract ARCHIVE", "ARCHIVE entpacken"),
+    ("List entries of ARCHIVE", "Einträge von ARCHIVE auflisten"),
+    (
+        "Archive to use, - for standard input or output",
+        "Zu verwendendes Archiv, - für die Standardein- oder -ausgabe",
+    ),
+    ("Print entries while processing them", "Einträge bei der Verarbeitung ausgeben"),
+    (
+        "Extract into, or add PATHs relative to, DIR",
+        "In DIR entpacken oder PATHs relativ zu DIR hinzufügen",
+    ),
+    (
+        "List directory contents, one entry per line",
+        "Verzeichnisinhalte auflisten, ein Eintrag pro Zeile",
+    ),
+    (
+        "Print contents of files, standard input without them or for -",
+        "Inhalte von Dateien ausgeben, die Standardeingabe ohne Dateien oder für -",
+    ),
+    (
+        "Copy files, into DEST if it is a directory",
+        "Dateien kopieren, nach DEST, wenn es ein Verzeichnis ist",
+    ),
+    (
+        "Move or rename files, into DEST if it is a directory",
+        "Dateien verschieben oder umbenennen, nach DEST, wenn es ein Verzeichnis ist",
+    ),
+    ("Remove files and directories", "Dateien und Verzeichnisse löschen"),
+    ("Create directories", "Verzeichnisse anlegen"),
+    (
+        "Create files or update their modification time",
+        "Dateien anlegen oder ihre Änderungszeit aktualisieren",
+    ),
+    (
+        "Print the first lines of files or standard input",
+        "Die ersten Zeilen von Dateien oder der Standardeingabe ausgeben",
+    ),
+    (
+        "Print the last lines of files or standard input",
+        "Die letzten Zeilen von Dateien oder der Standardeingabe ausgeben",
+    ),
+    (
+        "Count lines, words and bytes of files or standard input",
+        "Zeilen, Wörter und Bytes von Dateien oder der Standardeingabe zählen",
+    ),
+    (
+        "Print lines of files or standard input matching a pattern",
+        "Zeilen von Dateien oder der Standardeingabe ausgeben, die auf ein Muster passen",
+    ),
+    (
+        "Encode or decode base64 data of a file or standard input",
+        "base64-Daten einer Datei oder der Standardeingabe kodieren oder dekodieren",
+    ),
+    (
+        "Print a hex dump of a file or standard input, or revert one with -r",
+        "Einen Hexdump einer Datei oder der Standardeingabe ausgeben oder mit -r umkehren",
+    ),
+    ("Include entries starting with .", "Mit . beginnende Einträge einschließen"),
+    ("Show entry types and sizes", "Typen und Größen der Einträge anzeigen"),
+    ("Copy directories recursively", "Verzeichnisse rekursiv kopieren"),
+    ("Remove directories and their contents", "Verzeichnisse samt Inhalt löschen"),
+    ("Ignore missing files", "Fehlende Dateien ignorieren"),
+    (
+        "Create missing parents, existing directories are not an error",
+        "Fehlende übergeordnete Verzeichnisse anlegen, vorhandene Verzeichnisse sind kein Fehler",
+    ),
+    (
+        "Number of lines to print, 10 by default",
+        "Anzahl der auszugebenden Zeilen, standardmäßig 10",
+    ),
+    (
+        "Number of lines to print, 10 by default, +NUM starts at line NUM",
+        "Anzahl der auszugebenden Zeilen, standardmäßig 10, +NUM beginnt bei Zeile NUM",
+    ),
+    ("Keep printing data appended to files", "An Dateien angehängte Daten weiter ausgeben"),
+    ("Print the number of lines", "Die Anzahl der Zeilen ausgeben"),
+    ("Print the number of words", "Die Anzahl der Wörter ausgeben"),
+    ("Print the number of bytes", "Die Anzahl der Bytes ausgeben"),
+    ("Use extended regular expressions", "Erweiterte reguläre Ausdrücke verwenden"),
+    ("Ignore case distinctions", "Groß- und Kleinschreibung ignorieren"),
+    ("Prefix lines with their line numbers", "Zeilen ihre Zeilennummern voranstellen"),
+    ("Print lines that do not match", "Nicht passende Zeilen ausgeben"),
+    ("Decode data", "Daten dekodieren"),
+    (
+        "Wrap encoded lines after COLS characters, 76 by default, 0 disables wrapping",
+        "Kodierte Zeilen nach COLS Zeichen umbrechen, standardmäßig 76, 0 schaltet den Umbruch ab",
+    ),
+    ("Print only hex digits, 60 per line", "Nur Hexziffern ausgeben, 60 pro Zeile"),
+    ("Convert a dump back to binary data", "Einen Dump zurück in Binärdaten umwandeln"),
+    ("Clear the terminal screen", "Den Terminalbildschirm löschen"),
+    (
+        "Exit the shell with a status, 0 by default",
+        "Die Shell mit einem Status beenden, standardmäßig 0",
+    ),
+    ("Print the working directory", "Das Arbeitsverzeichnis ausgeben"),
+    (
+        "Jump to the most frecent visited directory matching all patterns",
+        "Zum am häufigsten und zuletzt besuchten Verzeichnis wechseln, das auf alle Muster passt",
+    ),
+    (
+        "Manage directory bookmarks used as cd @NAME",
+        "Verzeichnislesezeichen verwalten, die als cd @NAME verwendet werden",
+    ),
+    ("Print the command history", "Den Befehlsverlauf ausgeben"),
+    ("Remove shell and environment variables", "Shell- und Umgebungsvariablen löschen"),
+    (
+        "Set shell variables and their attributes, print all variables without arguments",
+        "Shellvariablen und ihre Attribute setzen, ohne Argumente alle Variablen ausgeben",
+    ),
+    (
+        "Make variables readonly, print readonly ones without arguments",
+        "Variablen schreibschützen, ohne Argumente schreibgeschützte ausgeben",
+    ),
+    (
+        "Export variables to the environment of executed commands, print exported ones without arguments",
+        "Variablen in die Umgebung ausgeführter Befehle exportieren, ohne Argumente exportierte ausgeben",
+    ),
+    (
+        "Define aliases, print all aliases without arguments",
+        "Aliase definieren, ohne Argumente alle Aliase ausgeben",
+    ),
+    ("Remove aliases", "Aliase löschen"),
+    (
+        "Run COMMAND with ARGS without looking it up in aliases, or describe COMMANDs",
+        "COMMAND mit ARGS ausführen, ohne in Aliasen nachzusehen, oder COMMANDs beschreiben",
+    ),
+    (
+        "Describe how each NAME would run as a command",
+        "Beschreiben, wie jeder NAME als Befehl ausgeführt würde",
+    ),
+    (
+        "Remember where NAMEs are found in PATH, or list remembered commands",
+        "Merken, wo NAMEs in PATH gefunden werden, oder gemerkte Befehle auflisten",
+    ),
+    (
+        "Set how Tab completes arguments of NAMEs, or print completion specs",
+        "Festlegen, wie Tab Argumente von NAMEs vervollständigt, oder Vervollständigungsspezifikationen ausgeben",
+    ),
+    (
+        "Stop a sourced script with status N, the last status by default",
+        "Ein eingelesenes Skript mit Status N beenden, standardmäßig mit dem letzten Status",
+    ),
+    ("Write contents to a file", "Inhalte in eine Datei schreiben"),
+    (
+        "Read a line from standard input and split it into variables, REPLY by default",
+        "Eine Zeile von der Standardeingabe lesen und in Variablen aufteilen, standardmäßig REPLY",
+    ),
+    (
+        "Parse the next option of ARGS, positional parameters by default, into NAME",
+        "Die nächste Option von ARGS, standardmäßig der Positionsparameter, in NAME einlesen",
+    ),
+    (
+        "Drop the first N positional parameters, 1 by default",
+        "Die ersten N Positionsparameter verwerfen, standardmäßig 1",
+    ),
+    ("Show or change configuration", "Konfiguration anzeigen oder ändern"),
+    (
+        "Trust the .envrc or .env file of PATH, the working directory by default",
+        "Der .envrc- oder .env-Datei von PATH vertrauen, standardmäßig im Arbeitsverzeichnis",
+    ),
+    (
+        "Revoke trust in the .envrc or .env file of PATH, the working directory by default",
+        "Das Vertrauen in die .envrc- oder .env-Datei von PATH entziehen, standardmäßig im Arbeitsverzeichnis",
+    ),
+    ("Show or change the color theme", "Das Farbschema anzeigen oder ändern"),
+    (
+        "Run a command repeatedly and report timing statistics",
+        "Einen Befehl wiederholt ausführen und Zeitstatistiken ausgeben",
+    ),
+    (
+        "Run a command, then again whenever files matching any of the patterns change",
+        "Einen Befehl ausführen und erneut, sobald sich auf eines der Muster passende Dateien ändern",
+    ),
+    ("List background jobs started with &", "Mit & gestartete Hintergrundjobs auflisten"),
+    (
+        "Wait for background jobs to finish, all of them without arguments",
+        "Auf das Ende von Hintergrundjobs warten, ohne Argumente auf alle",
+    ),
+    (
+        "Change shell options, list them without arguments",
+        "Shelloptionen ändern, ohne Argumente auflisten",
+    ),
+    (
+        "Change optional shell behavior, list options and their state without arguments",
+        "Optionales Verhalten der Shell ändern, ohne Argumente Optionen und ihren Zustand auflisten",
+    ),
+    (
+        "Print the number of characters of each string",
+        "Die Anzahl der Zeichen jeder Zeichenkette ausgeben",
+    ),
+    (
+        "Print substrings, START counts from 1 or from the end when negative",
+        "Teilzeichenketten ausgeben, START zählt ab 1 oder, wenn negativ, vom Ende",
+    ),
+    (
+        "Remove leading and trailing whitespace or CHARS",
+        "Führende und abschließende Leerzeichen oder CHARS entfernen",
+    ),
+    (
+        "Print parts of strings split at SEPARATOR, an empty one splits characters",
+        "An SEPARATOR geteilte Teile von Zeichenketten ausgeben, ein leerer teilt in Zeichen",
+    ),
+    ("Join strings with SEPARATOR", "Zeichenketten mit SEPARATOR verbinden"),
+    (
+        "Replace PATTERN in strings, $1 refers to a regex capture group",
+        "PATTERN in Zeichenketten ersetzen, $1 verweist auf eine Gruppe des regulären Ausdrucks",
+    ),
+    (
+        "Print strings matching a glob PATTERN, or regex matches and capture groups",
+        "Auf ein Glob-PATTERN passende Zeichenketten oder Treffer und Gruppen regulärer Ausdrücke ausgeben",
+    ),
+    (
+        "Pad strings to the same width, the longest one or WIDTH",
+        "Zeichenketten auf dieselbe Breite auffüllen, die der längsten oder WIDTH",
+    ),
+    (
+        "Manipulate strings, see string <SUBCOMMAND> --help",
+        "Zeichenketten bearbeiten, siehe string <SUBCOMMAND> --help",
+    ),
+    (
+        "Create a unique temporary file and print its path, trailing Xs in TEMPLATE are replaced",
+        "Eine eindeutige temporäre Datei anlegen und ihren Pfad ausgeben, abschließende X in TEMPLATE werden ersetzt",
+    ),
+    (
+        "Print a random integer between MIN and MAX inclusive, 0 and 32767 by default",
+        "Eine zufällige ganze Zahl von MIN bis einschließlich MAX ausgeben, standardmäßig 0 und 32767",
+    ),
+    ("Print a random version 4 UUID", "Eine zufällige UUID der Version 4 ausgeben"),
+    (
+        "Print values selected by PATH, e.g. .items[0].name or .items[].id",
+        "Durch PATH ausgewählte Werte ausgeben, z. B. .items[0].name oder .items[].id",
+    ),
+    (
+        "Print keys of objects or indexes of arrays selected by PATH",
+        "Schlüssel von Objekten oder Indizes von Arrays ausgeben, die durch PATH ausgewählt sind",
+    ),
+    (
+        "Print lengths of arrays, objects and strings selected by PATH",
+        "Längen von durch PATH ausgewählten Arrays, Objekten und Zeichenketten ausgeben",
+    ),
+    (
+        "Query JSON documents, see json <SUBCOMMAND> --help",
+        "JSON-Dokumente abfragen, siehe json <SUBCOMMAND> --help",
+    ),
+    (
+        "Print or verify checksums of files, standard input without FILEs",
+        "Prüfsummen von Dateien ausgeben oder prüfen, der Standardeingabe ohne FILEs",
+    ),
+    ("List signal names and numbers", "Signalnamen und -nummern auflisten"),
+    ("Print traps as commands setting them", "Traps als Befehle ausgeben, die sie setzen"),
+    (
+        "List matching directories with their scores",
+        "Passende Verzeichnisse mit ihren Bewertungen auflisten",
+    ),
+    ("Forget directories that no longer exist", "Nicht mehr vorhandene Verzeichnisse vergessen"),
+    ("Remove variables (default)", "Variablen löschen (Standard)"),
+    ("Remove functions", "Funktionen löschen"),
+    (
+        "Export variables, +x makes exported ones local",
+        "Variablen exportieren, +x macht exportierte lokal",
+    ),
+    ("Make variables indexed arrays", "Variablen zu indizierten Arrays machen"),
+    ("Make variables associative arrays", "Variablen zu assoziativen Arrays machen"),
+    ("Evaluate assigned values arithmetically", "Zugewiesene Werte arithmetisch auswerten"),
+    ("Convert assigned values to lowercase", "Zugewiesene Werte in Kleinbuchstaben umwandeln"),
+    ("Convert assigned values to uppercase", "Zugewiesene Werte in Großbuchstaben umwandeln"),
+    ("Make variables readonly", "Variablen schreibschützen"),
+    (
+        "Print variables with their attributes in a reusable format",
+        "Variablen mit ihren Attributen in wiederverwendbarer Form ausgeben",
+    ),
+    (
+        "Print readonly variables in a reusable format",
+        "Schreibgeschützte Variablen in wiederverwendbarer Form ausgeben",
+    ),
+    (
+        "Print exported variables in a reusable format",
+        "Exportierte Variablen in wiederverwendbarer Form ausgeben",
+    ),
+    ("Make variables local to the shell", "Variablen lokal zur Shell machen"),
+    ("Print all aliases in a reusable format", "Alle Aliase in wiederverwendbarer Form ausgeben"),
+    ("Also append definitions to the rc file", "Definitionen auch an die rc-Datei anhängen"),
+    ("Remove all aliases", "Alle Aliase löschen"),
+    (
+        "Search for COMMAND in PATH of standard utilities",
+        "COMMAND im PATH der Standardprogramme suchen",
+    ),
+    (
+        "Print the path, alias, builtin or keyword COMMAND runs",
+        "Pfad, Alias, eingebauten Befehl oder Schlüsselwort ausgeben, das COMMAND ausführt",
+    ),
+    ("Describe what COMMAND is", "Beschreiben, was COMMAND ist"),
+    (
+        "List every alias, keyword, builtin and file named NAME",
+        "Jeden Alias, jedes Schlüsselwort, jeden eingebauten Befehl und jede Datei namens NAME auflisten",
+    ),
+    (
+        "Print alias, keyword, builtin or file",
+        "Alias, Schlüsselwort, eingebauten Befehl oder Datei ausgeben",
+    ),
+    (
+        "Print the path of NAME if it runs a file",
+        "Den Pfad von NAME ausgeben, wenn er eine Datei ausführt",
+    ),
+    (
+        "Search PATH even for aliases, keywords and builtins",
+        "PATH auch für Aliase, Schlüsselwörter und eingebaute Befehle durchsuchen",
+    ),
+    ("Forget all remembered commands", "Alle gemerkten Befehle vergessen"),
+    ("Forget NAMEs", "NAMEs vergessen"),
+    ("Print remembered paths of NAMEs", "Gemerkte Pfade von NAMEs ausgeben"),
+    ("Remember PATH as the file NAMEs run", "PATH als die von NAMEs ausgeführte Datei merken"),
+    ("Complete alias names", "Aliasnamen vervollständigen"),
+    ("Complete internal command names", "Namen interner Befehle vervollständigen"),
+    ("Complete command names", "Befehlsnamen vervollständigen"),
+    ("Complete directory names", "Verzeichnisnamen vervollständigen"),
+    ("Complete file names", "Dateinamen vervollständigen"),
+    (
+        "Complete the blank separated WORDS",
+        "Die durch Leerzeichen getrennten WORDS vervollständigen",
+    ),
+    (
+        "Complete elements of the COMPREPLY array set by COMMAND",
+        "Elemente des von COMMAND gesetzten Arrays COMPREPLY vervollständigen",
+    ),
+    (
+        "Print specs of NAMEs, or all, as complete commands",
+        "Spezifikationen von NAMEs oder alle als complete-Befehle ausgeben",
+    ),
+    ("Remove specs of NAMEs, or all", "Spezifikationen von NAMEs oder alle löschen"),
+    ("Do not treat backslashes as escapes", "Backslashes nicht als Escapezeichen behandeln"),
+    ("Print PROMPT on a terminal first", "Zuerst PROMPT auf einem Terminal ausgeben"),
+    ("Return after reading N characters", "Nach dem Lesen von N Zeichen zurückkehren"),
+    ("Assign the fields to ARRAY", "Die Felder ARRAY zuweisen"),
+    ("Number of measured runs, 10 by default", "Anzahl gemessener Durchläufe, standardmäßig 10"),
+    ("Number of unmeasured runs, 1 by default", "Anzahl ungemessener Durchläufe, standardmäßig 1"),
+    (
+        "Polling interval where file events are unavailable, 500ms by default",
+        "Abfrageintervall, wo Dateiereignisse nicht verfügbar sind, standardmäßig 500ms",
+    ),
+    ("Cancel a scheduled job", "Einen geplanten Job abbrechen"),
+    ("Show process ids of jobs", "Prozess-IDs von Jobs anzeigen"),
+    ("Enable an option, +o disables it", "Eine Option einschalten, +o schaltet sie aus"),
+    (
+        "Exit when a command fails, +e stops",
+        "Beenden, wenn ein Befehl fehlschlägt, +e hört damit auf",
+    ),
+    (
+        "Refuse to overwrite existing files with >, +C stops",
+        "Vorhandene Dateien nicht mit > überschreiben, +C hört damit auf",
+    ),
+    (
+        "Fail on expansion of unset parameters, +u stops",
+        "Bei Expansion nicht gesetzter Parameter fehlschlagen, +u hört damit auf",
+    ),
+    (
+        "Print commands before running them, +x stops",
+        "Befehle vor der Ausführung ausgeben, +x hört damit auf",
+    ),
+    ("Enable options, or list enabled ones", "Optionen einschalten oder eingeschaltete auflisten"),
+    (
+        "Disable options, or list disabled ones",
+        "Optionen ausschalten oder ausgeschaltete auflisten",
+    ),
+    ("First character, 1 by default", "Erstes Zeichen, standardmäßig 1"),
+    ("Number of characters, all by default", "Anzahl der Zeichen, standardmäßig alle"),
+    ("Only trim the start", "Nur den Anfang kürzen"),
+    ("Only trim the end", "Nur das Ende kürzen"),
+    ("Characters to remove", "Zu entfernende Zeichen"),
+    ("Split at most MAX times", "Höchstens MAX-mal teilen"),
+    ("Split from the end", "Vom Ende her teilen"),
+    ("Replace every occurrence", "Jedes Vorkommen ersetzen"),
+    ("PATTERN is a regular expression", "PATTERN ist ein regulärer Ausdruck"),
+    ("Match case-insensitively", "Ohne Beachtung der Groß- und Kleinschreibung vergleichen"),
+    ("Only print strings that were changed", "Nur geänderte Zeichenketten ausgeben"),
+    ("Print strings that do not match", "Nicht passende Zeichenketten ausgeben"),
+    ("Pad at the end instead of the start", "Am Ende statt am Anfang auffüllen"),
+    ("Minimum width", "Mindestbreite"),
+    ("Padding character, space by default", "Füllzeichen, standardmäßig Leerzeichen"),
+    ("Create a directory instead of a file", "Ein Verzeichnis statt einer Datei anlegen"),
+    (
+        "Create TEMPLATE in DIR, $TMPDIR or /tmp by default when TEMPLATE is not given",
+        "TEMPLATE in DIR anlegen, standardmäßig in $TMPDIR oder /tmp, wenn TEMPLATE nicht angegeben ist",
+    ),
+    ("Print strings without quotes", "Zeichenketten ohne Anführungszeichen ausgeben"),
+    ("Hash function, sha256 (default) or md5", "Hashfunktion, sha256 (Standard) oder md5"),
+    (
+        "Verify files listed in FILEs in the format printed without -c",
+        "In FILEs aufgeführte Dateien im ohne -c ausgegebenen Format prüfen",
+    ),
+];
+
+const BUILTIN_CATALOGS: [(&str, &[(&str, &str)]); 2] = [("pl", PL), ("de", DE)];
+
+#[derive(Default)]
+struct Catalog {
+    language: String,
+    messages: HashMap<String, String>,
+}
+
+lazy_static! {
+    static ref CATALOG: Mutex<Catalog> = Mutex::new(Catalog::default());
+}
+
+/// Language of messages, e.g. `pl_PL` for `LANG=pl_PL.UTF-8`, `None` for
+/// the `C` and `POSIX` locales
+pub fn language() -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))?;
+    match locale.split(['.', '@']).next() {
+        None | Some("") | Some("C") | Some("POSIX") => None,
+        Some(language) => Some(language.to_string()),
+    }
+}
+
+/// Messages of `pl` and then `pl_PL`, so that regional catalogs override
+/// the general one, and user catalogs override built-in ones
+fn load(language: &str) -> HashMap<String, String> {
+    let general = language.split('_').next().unwrap_or(language);
+    let mut candidates = vec![general];
+    if general != language {
+        candidates.push(language);
+    }
+
+    let mut messages = HashMap::new();
+    for candidate in candidates {
+        if let Some((_, builtin)) = BUILTIN_CATALOGS.iter().find(|(name, _)| *name == candidate) {
+            messages.extend(
+                builtin
+                    .iter()
+                    .map(|(message, translation)| (message.to_string(), translation.to_string())),
+            );
+        }
+        let path = xdg::data_dir()
+            .join("locale")
+            .join(format!("{candidate}.json"));
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        match Json::parse(&content) {
+            Ok(Json::Object(entries)) => {
+                messages.extend(entries.into_iter().filter_map(|(message, translation)| {
+                    Some((message, translation.as_str()?.to_string()))
+                }))
+            }
+            Ok(_) => debug!(target: "locale", ?path, "catalog is not an object"),
+            Err(error) => debug!(target: "locale", ?path, "{}", error),
+        }
+    }
+    messages
+}
+
+/// `message` translated to the current language, unchanged when there is no
+/// translation
+pub fn tr(message: &str) -> String {
+    let language = match language() {
+        Some(language) => language,
+        None => return message.to_string(),
+    };
+    let mut catalog = match CATALOG.lock() {
+        Ok(catalog) => catalog,
+        Err(_) => return message.to_string(),
+    };
+    // locale variables can change at runtime, e.g. `export LANG=pl_PL.UTF-8`
+    if catalog.language != language {
+        *catalog = Catalog {
+            messages: load(&language),
+            language,
+        };
+    }
+    catalog
+        .messages
+        .get(message)
+        .cloned()
+        .unwrap_or_else(|| message.to_string())
+}
+
+/// Translated description of an I/O error, without the OS error code
+pub fn io_error(error: &io::Error) -> String {
+    match error.kind() {
+        io::ErrorKind::NotFound => tr("No such file or directory"),
+        io::ErrorKind::PermissionDenied => tr("Permission denied"),
+        _ => tr(&error.to_string()),
+    }
+}
@@ -0,0 +1,208 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::env;
+use std::fmt;
+use std::fmt::Write as _;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::Write;
+use std::os::fd::{FromRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use tracing::field::{Field, Visit};
+use tracing::span;
+use tracing::{Event, Level, Metadata, Subscriber};
+
+/// Targets used to instrument interpreter stages, usable as `WASH_LOG` keys
+pub const TARGETS: [&str; 4] = ["parse", "expand", "redirect", "spawn"];
+
+/// Higher value means more verbose, `tracing::Level` ordering is easy to misread
+fn verbosity(level: &Level) -> u8 {
+    if *level == Level::ERROR {
+        1
+    } else if *level == Level::WARN {
+        2
+    } else if *level == Level::INFO {
+        3
+    } else if *level == Level::DEBUG {
+        4
+    } else {
+        5
+    }
+}
+
+fn parse_level(level: &str) -> Result<u8, String> {
+    match level.to_lowercase().as_str() {
+        "off" => Ok(0),
+        "error" => Ok(1),
+        "warn" => Ok(2),
+        "info" => Ok(3),
+        "debug" => Ok(4),
+        "trace" => Ok(5),
+        _ => Err(format!("invalid log level: {level}")),
+    }
+}
+
+/// `WASH_LOG` filter, e.g. `info` or `spawn=trace,expand=debug`
+#[derive(Debug, Default, PartialEq)]
+pub struct Filter {
+    default: u8,
+    targets: Vec<(String, u8)>,
+}
+
+impl Filter {
+    pub fn parse(spec: &str) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    if !TARGETS.contains(&target) {
+                        return Err(format!("unknown log target: {target}"));
+                    }
+                    filter
+                        .targets
+                        .push((target.to_string(), parse_level(level)?));
+                }
+                None => filter.default = parse_level(directive)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    fn allows(&self, metadata: &Metadata<'_>) -> bool {
+        let max = self
+            .targets
+            .iter()
+            .rev()
+            .find(|(target, _)| target == metadata.target())
+            .map_or(self.default, |(_, level)| *level);
+        verbosity(metadata.level()) <= max
+    }
+
+    fn is_off(&self) -> bool {
+        self.default == 0 && self.targets.iter().all(|(_, level)| *level == 0)
+    }
+}
+
+/// Collects event message and fields into a single line
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            let _ = write!(self.rest, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+struct Logger {
+    filter: Filter,
+    output: Mutex<Box<dyn Write + Send>>,
+    next_span: AtomicU64,
+    started: Instant,
+}
+
+impl Subscriber for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        self.filter.allows(metadata)
+    }
+
+    fn new_span(&self, _span: &span::Attributes<'_>) -> span::Id {
+        span::Id::from_u64(self.next_span.fetch_add(1, Ordering::Relaxed))
+    }
+
+    fn record(&self, _span: &span::Id, _values: &span::Record<'_>) {}
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let metadata = event.metadata();
+        if let Ok(mut output) = self.output.lock() {
+            let _ = writeln!(
+                output,
+                "{:>12.6} {:<5} {}: {}{}",
+                self.started.elapsed().as_secs_f64(),
+                metadata.level(),
+                metadata.target(),
+                fields.message,
+                fields.rest
+            );
+            let _ = output.flush();
+        }
+    }
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+/// Log destination: `WASH_LOG_FILE` path, `WASH_LOG_FD` descriptor or stderr
+fn open_output() -> io::Result<Box<dyn Write + Send>> {
+    if let Ok(path) = env::var("WASH_LOG_FILE") {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        return Ok(Box::new(file));
+    }
+    if let Ok(fd) = env::var("WASH_LOG_FD") {
+        let fd = fd
+            .parse::<RawFd>()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "invalid WASH_LOG_FD"))?;
+        return Ok(Box::new(unsafe { std::fs::File::from_raw_fd(fd) }));
+    }
+    Ok(Box::new(io::stderr()))
+}
+
+/// Installs the global logger when `WASH_LOG` is set
+pub fn init() {
+    let spec = match env::var("WASH_LOG") {
+        Ok(spec) if !spec.is_empty() => spec,
+        _ => return,
+    };
+    let filter = match Filter::parse(&spec) {
+        Ok(filter) if !filter.is_off() => filter,
+        Ok(_) => return,
+        Err(err) => {
+            eprintln!("{}: WASH_LOG: {}", env!("CARGO_PKG_NAME"), err);
+            return;
+        }
+    };
+    let output = match open_output() {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!(
+                "{}: cannot open log output: {}",
+                env!("CARGO_PKG_NAME"),
+                err
+            );
+            return;
+        }
+    };
+    let logger = Logger {
+        filter,
+        output: Mutex::new(output),
+        next_span: AtomicU64::new(1),
+        started: Instant::now(),
+    };
+    let _ = tracing::subscriber::set_global_default(logger);
+}
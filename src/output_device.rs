@@ -5,20 +5,43 @@
  */
 
 // #[cfg(target_os = "wasi")]
-use crate::shell_base::{Fd, Redirect, STDERR, STDOUT};
+use crate::shell_base::{is_fd_tty, Fd, Redirect, STDERR, STDIN, STDOUT};
+use crate::terminal;
+use crate::theme::Style;
 
 use color_eyre::Report;
+use std::env;
 use std::fs::{File, OpenOptions};
-use std::io::Write;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem::ManuallyDrop;
 use std::os::fd::{FromRawFd, IntoRawFd, RawFd};
+use std::path::Path;
+
+/// Descriptor read by an internal that stays open afterwards, pipes are
+/// closed by the interpreter
+struct BorrowedFd(ManuallyDrop<File>);
+
+impl Read for BorrowedFd {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
 
 #[derive(Debug)]
 /// Wrapper for stdout/stderr operations from shell builtins so that they are redirects-aware
 pub struct OutputDevice<'a> {
+    stdin_redirect: Option<&'a Redirect>,
     stdout_redirect: Option<&'a Redirect>,
     stderr_redirect: Option<&'a Redirect>,
-    stdout_data: String,
+    /// Bytes rather than text so that internals can output binary data
+    stdout_data: Vec<u8>,
     stderr_data: String,
+    /// Whether output was already written out, so that `>` does not truncate
+    /// it on later flushes
+    stdout_flushed: bool,
+    stderr_flushed: bool,
+    error_style: Style,
+    hyperlinks: bool,
 }
 
 impl<'a> Default for OutputDevice<'a> {
@@ -30,13 +53,22 @@ impl<'a> Default for OutputDevice<'a> {
 impl<'a> OutputDevice<'a> {
     pub fn new() -> Self {
         OutputDevice {
+            stdin_redirect: None,
             stdout_redirect: None,
             stderr_redirect: None,
-            stdout_data: String::new(),
+            stdout_data: Vec::new(),
             stderr_data: String::new(),
+            stdout_flushed: false,
+            stderr_flushed: false,
+            error_style: Style::default(),
+            hyperlinks: false,
         }
     }
 
+    pub fn set_redirect_in(&mut self, redirect: &'a Redirect) {
+        self.stdin_redirect = Some(redirect);
+    }
+
     pub fn set_redirect_out(&mut self, redirect: &'a Redirect) {
         self.stdout_redirect = Some(redirect);
     }
@@ -45,18 +77,94 @@ impl<'a> OutputDevice<'a> {
         self.stderr_redirect = Some(redirect);
     }
 
+    pub fn set_error_style(&mut self, style: Style) {
+        self.error_style = style;
+    }
+
+    pub fn set_hyperlinks(&mut self, enabled: bool) {
+        self.hyperlinks = enabled;
+    }
+
+    /// `text` as an OSC 8 link to `url` when hyperlinks are enabled and `fd`
+    /// is a terminal that is not redirected, plain `text` otherwise
+    pub fn link(&self, fd: Fd, url: &str, text: &str) -> String {
+        let redirected = if fd == STDOUT {
+            self.stdout_redirect.is_some()
+        } else {
+            self.stderr_redirect.is_some()
+        };
+        if self.hyperlinks && !redirected && matches!(is_fd_tty(fd), Ok(true)) {
+            terminal::hyperlink(url, text)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Path shown as given, linked to its absolute `file://` URL
+    pub fn file_link(&self, fd: Fd, path: &Path) -> String {
+        let absolute = env::current_dir()
+            .map(|cwd| cwd.join(path))
+            .unwrap_or_else(|_| path.to_path_buf());
+        self.link(
+            fd,
+            &terminal::file_url(&absolute),
+            &path.display().to_string(),
+        )
+    }
+
+    /// Standard input of the internal, honoring `<` redirects and pipes
+    pub fn stdin(&self) -> io::Result<Box<dyn BufRead>> {
+        Ok(Box::new(BufReader::new(self.unbuffered_stdin()?)))
+    }
+
+    /// Standard input read without buffering, so that nothing past what is
+    /// read is consumed from pipes, e.g. by `read`
+    pub fn unbuffered_stdin(&self) -> io::Result<Box<dyn Read>> {
+        let fd = match self.stdin_redirect {
+            None => STDIN,
+            Some(Redirect::Read(_, path)) => {
+                return Ok(Box::new(File::open(path)?));
+            }
+            Some(Redirect::PipeIn(fd)) => *fd,
+            Some(Redirect::Duplicate { fd_src, fd_dst: _ }) => *fd_src,
+            Some(redirect) => {
+                return Err(io::Error::other(format!(
+                    "Wrong redirection type '{:?}' for reading.",
+                    redirect
+                )))
+            }
+        };
+        let file = unsafe { File::from_raw_fd(fd as RawFd) };
+        Ok(Box::new(BorrowedFd(ManuallyDrop::new(file))))
+    }
+
+    /// Input named by a FILE operand, `-` is standard input
+    pub fn open_input(&self, operand: &str) -> io::Result<Box<dyn BufRead>> {
+        if operand == "-" {
+            self.stdin()
+        } else {
+            Ok(Box::new(BufReader::new(File::open(operand)?)))
+        }
+    }
+
     // TODO: ensure this gets called, maybe move it to custom Drop implementation
+    /// Writes out buffered output, can be called repeatedly by internals that
+    /// stream their output
     pub fn flush(&mut self) -> Result<(), Report> {
         if !self.stdout_data.is_empty() {
-            self.flush_fd(STDOUT, &self.stdout_data)?;
+            self.flush_fd(STDOUT, &self.stdout_data, self.stdout_flushed)?;
+            self.stdout_data.clear();
+            self.stdout_flushed = true;
         }
         if !self.stderr_data.is_empty() {
-            self.flush_fd(STDERR, &self.stderr_data)?;
+            self.flush_fd(STDERR, self.stderr_data.as_bytes(), self.stderr_flushed)?;
+            self.stderr_data.clear();
+            self.stderr_flushed = true;
         }
         Ok(())
     }
 
-    fn flush_fd(&self, to_fd: Fd, output: &str) -> Result<(), Report> {
+    fn flush_fd(&self, to_fd: Fd, output: &[u8], append: bool) -> Result<(), Report> {
         let redirect = if to_fd == STDOUT {
             self.stdout_redirect
         } else {
@@ -67,7 +175,8 @@ impl<'a> OutputDevice<'a> {
             None => unsafe { File::from_raw_fd(to_fd as RawFd) },
             Some(Redirect::Write(_, path)) => OpenOptions::new()
                 .write(true)
-                .truncate(true)
+                .truncate(!append)
+                .append(append)
                 .create(true)
                 .open(path)?,
             Some(Redirect::Append(_, path)) => {
@@ -76,7 +185,8 @@ impl<'a> OutputDevice<'a> {
             Some(Redirect::ReadWrite(_, path)) => OpenOptions::new()
                 .write(true)
                 .create(true)
-                .truncate(true)
+                .truncate(!append)
+                .append(append)
                 .open(path)?,
             Some(Redirect::PipeOut(fd)) => unsafe { File::from_raw_fd(*fd as RawFd) },
             Some(Redirect::Duplicate { fd_src, fd_dst: _ }) => unsafe {
@@ -90,7 +200,7 @@ impl<'a> OutputDevice<'a> {
             }
         };
 
-        let res = write!(finall_file, "{}", output);
+        let res = finall_file.write_all(output);
         finall_file.flush().unwrap();
         match redirect {
             Some(Redirect::Write(_, _))
@@ -121,13 +231,23 @@ impl<'a> OutputDevice<'a> {
         }
     }
 
+    /// Removes buffered standard output so that it is not flushed
+    pub fn take_stdout(&mut self) -> String {
+        String::from_utf8_lossy(&std::mem::take(&mut self.stdout_data)).into_owned()
+    }
+
     pub fn print(&mut self, output: &str) {
-        self.stdout_data.push_str(output);
+        self.stdout_data.extend_from_slice(output.as_bytes());
     }
 
     pub fn println(&mut self, output: &str) {
-        self.stdout_data.push_str(output);
-        self.stdout_data.push('\n');
+        self.stdout_data.extend_from_slice(output.as_bytes());
+        self.stdout_data.push(b'\n');
+    }
+
+    /// Prints data that may not be valid UTF-8, e.g. decoded by `base64 -d`
+    pub fn write_bytes(&mut self, output: &[u8]) {
+        self.stdout_data.extend_from_slice(output);
     }
 
     pub fn eprint(&mut self, output: &str) {
@@ -138,4 +258,13 @@ impl<'a> OutputDevice<'a> {
         self.stderr_data.push_str(output);
         self.stderr_data.push('\n');
     }
+
+    /// Prints error message styled with the theme error color when stderr is a terminal
+    pub fn eprintln_error(&mut self, output: &str) {
+        if self.stderr_redirect.is_none() && matches!(is_fd_tty(STDERR), Ok(true)) {
+            self.eprintln(&self.error_style.paint(output));
+        } else {
+            self.eprintln(output);
+        }
+    }
 }
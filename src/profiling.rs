@@ -0,0 +1,145 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Timing breakdown of startup, printed with `--profile-startup`
+#[derive(Debug)]
+pub struct StartupProfile {
+    started: Instant,
+    phases: Vec<(String, Duration)>,
+}
+
+impl Default for StartupProfile {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StartupProfile {
+    pub fn new() -> Self {
+        StartupProfile {
+            started: Instant::now(),
+            phases: Vec::new(),
+        }
+    }
+
+    pub fn record(&mut self, phase: impl Into<String>, duration: Duration) {
+        self.phases.push((phase.into(), duration));
+    }
+
+    /// Formats all phases followed by total time until `milestone`, the
+    /// first prompt or the first command of a script
+    pub fn report(&self, milestone: &str) -> String {
+        let width = self
+            .phases
+            .iter()
+            .map(|(phase, _)| phase.len())
+            .max()
+            .unwrap_or(0)
+            .max(milestone.len());
+        let mut report = String::from("startup profile:\n");
+        for (phase, duration) in &self.phases {
+            report.push_str(&format!(
+                "  {:<width$}  {:>10.3} ms\n",
+                phase,
+                duration.as_secs_f64() * 1000.0
+            ));
+        }
+        report.push_str(&format!(
+            "  {:<width$}  {:>10.3} ms\n",
+            milestone,
+            self.started.elapsed().as_secs_f64() * 1000.0
+        ));
+        report
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct CommandStats {
+    calls: u64,
+    total: Duration,
+    max: Duration,
+}
+
+/// Per-command wall time and invocation counts, collected with `--profile`
+/// or `set -o profile` and reported at exit
+#[derive(Debug, Default)]
+pub struct CommandProfile {
+    pub enabled: bool,
+    commands: HashMap<String, CommandStats>,
+}
+
+impl CommandProfile {
+    pub fn record(&mut self, command: &str, duration: Duration) {
+        if !self.enabled {
+            return;
+        }
+        let stats = self.commands.entry(command.to_string()).or_default();
+        stats.calls += 1;
+        stats.total += duration;
+        stats.max = stats.max.max(duration);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Formats commands sorted by total time, slowest first
+    pub fn report(&self) -> String {
+        let mut commands: Vec<_> = self.commands.iter().collect();
+        commands.sort_by(|(a_name, a), (b_name, b)| {
+            b.total.cmp(&a.total).then_with(|| a_name.cmp(b_name))
+        });
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        let mut report = format!(
+            "{:>12}  {:>8}  {:>10}  {:>10}  command\n",
+            "total ms", "calls", "avg ms", "max ms"
+        );
+        for (command, stats) in commands {
+            report.push_str(&format!(
+                "{:>12.3}  {:>8}  {:>10.3}  {:>10.3}  {}\n",
+                ms(stats.total),
+                stats.calls,
+                ms(stats.total) / stats.calls as f64,
+                ms(stats.max),
+                command
+            ));
+        }
+        report
+    }
+}
+
+/// Summary of repeated timings measured by the `bench` builtin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchStats {
+    pub min: Duration,
+    pub mean: Duration,
+    pub max: Duration,
+    pub stddev: Duration,
+}
+
+impl BenchStats {
+    pub fn from_samples(samples: &[Duration]) -> Option<BenchStats> {
+        let min = *samples.iter().min()?;
+        let max = *samples.iter().max()?;
+        let secs: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        let mean = secs.iter().sum::<f64>() / secs.len() as f64;
+        // sample standard deviation, zero for a single run
+        let variance = if secs.len() > 1 {
+            secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (secs.len() - 1) as f64
+        } else {
+            0.0
+        };
+        Some(BenchStats {
+            min,
+            mean: Duration::from_secs_f64(mean),
+            max,
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+        })
+    }
+}
@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+#[cfg(not(target_os = "wasi"))]
+use std::fs::File;
+use std::io;
+#[cfg(not(target_os = "wasi"))]
+use std::io::Read;
+
+/// Fills `buf` with random bytes from the host, WASI runtimes may not provide
+/// `/dev/urandom`
+pub fn fill(buf: &mut [u8]) -> io::Result<()> {
+    #[cfg(target_os = "wasi")]
+    {
+        unsafe { wasi::random_get(buf.as_mut_ptr(), buf.len()) }
+            .map_err(|errno| io::Error::other(errno.to_string()))
+    }
+    #[cfg(not(target_os = "wasi"))]
+    {
+        File::open("/dev/urandom")?.read_exact(buf)
+    }
+}
+
+pub fn next_u64() -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    fill(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+/// Uniformly distributed number from `min..=max`
+pub fn in_range(min: i64, max: i64) -> io::Result<i64> {
+    let span = max.wrapping_sub(min) as u64;
+    if span == u64::MAX {
+        return Ok(next_u64()? as i64);
+    }
+    let span = span + 1;
+    // reject the incomplete last cycle so that no value is more likely
+    let limit = u64::MAX - u64::MAX % span;
+    loop {
+        let value = next_u64()?;
+        if value < limit {
+            return Ok(min.wrapping_add((value % span) as i64));
+        }
+    }
+}
+
+/// Random version 4 UUID, e.g. `3f2b8c1e-9a4d-4f6b-8e2a-5c7d9b1f0a3e`
+pub fn uuid() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    fill(&mut bytes)?;
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+    let hex: String = bytes.iter().map(|byte| format!("{byte:02x}")).collect();
+    Ok(format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    ))
+}
+
+/// `template` with its trailing `X`s replaced by random letters and digits
+pub fn fill_template(template: &str) -> io::Result<String> {
+    const ALPHABET: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let stem = template.trim_end_matches('X');
+    let mut bytes = vec![0u8; template.len() - stem.len()];
+    fill(&mut bytes)?;
+    let suffix: String = bytes
+        .iter()
+        .map(|byte| ALPHABET[*byte as usize % ALPHABET.len()] as char)
+        .collect();
+    Ok(format!("{stem}{suffix}"))
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Offset of local time from UTC in seconds, WASI has no time zones so it
+/// always uses UTC
+fn utc_offset(epoch_secs: u64) -> i64 {
+    #[cfg(not(target_os = "wasi"))]
+    {
+        let time = epoch_secs as nix::libc::time_t;
+        let mut tm = unsafe { std::mem::zeroed::<nix::libc::tm>() };
+        if !unsafe { nix::libc::localtime_r(&time, &mut tm) }.is_null() {
+            return tm.tm_gmtoff as i64;
+        }
+    }
+    #[cfg(target_os = "wasi")]
+    let _ = epoch_secs;
+    0
+}
+
+/// Time until the next local wall-clock `HH:MM[:SS]`, `+DURATION` is relative
+pub fn parse_at(time: &str) -> Option<Duration> {
+    if let Some(delay) = time.strip_prefix('+') {
+        return crate::shell_base::parse_duration(delay);
+    }
+    let mut fields = time.split(':').map(|field| field.parse::<u64>().ok());
+    let hours = fields.next()??;
+    let minutes = fields.next()??;
+    let seconds = fields.next().unwrap_or(Some(0))?;
+    if fields.next().is_some() || hours > 23 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+    let local = (now as i64 + utc_offset(now)).rem_euclid(SECONDS_PER_DAY as i64) as u64;
+    let target = hours * 3600 + minutes * 60 + seconds;
+    // a time that already passed today means tomorrow
+    let delay = (target + SECONDS_PER_DAY - local - 1) % SECONDS_PER_DAY + 1;
+    Some(Duration::from_secs(delay))
+}
+
+/// Formats `duration` as e.g. `1h2m`, `45s`
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, 0) => format!("{m}m"),
+        (0, m, s) => format!("{m}m{s}s"),
+        (h, 0, _) => format!("{h}h"),
+        (h, m, _) => format!("{h}h{m}m"),
+    }
+}
+
+#[derive(Debug)]
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    /// Interval of `every` jobs, `at` jobs run once
+    pub every: Option<Duration>,
+    pub next_run: Instant,
+}
+
+/// Commands scheduled with `every` and `at`, run by the interactive loop
+/// while it waits for input
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    jobs: Vec<Job>,
+    last_id: usize,
+}
+
+impl Scheduler {
+    fn add(&mut self, command: String, every: Option<Duration>, delay: Duration) -> usize {
+        self.last_id += 1;
+        self.jobs.push(Job {
+            id: self.last_id,
+            command,
+            every,
+            next_run: Instant::now() + delay,
+        });
+        self.last_id
+    }
+
+    pub fn every(&mut self, interval: Duration, command: String) -> usize {
+        self.add(command, Some(interval), interval)
+    }
+
+    pub fn at(&mut self, delay: Duration, command: String) -> usize {
+        self.add(command, None, delay)
+    }
+
+    /// Returns whether a job with `id` was scheduled
+    pub fn cancel(&mut self, id: usize) -> bool {
+        let count = self.jobs.len();
+        self.jobs.retain(|job| job.id != id);
+        self.jobs.len() != count
+    }
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    pub fn next_run(&self) -> Option<Instant> {
+        self.jobs.iter().map(|job| job.next_run).min()
+    }
+
+    /// Commands of jobs due at `now`; one-shot jobs are dropped and periodic
+    /// ones rescheduled, skipping runs missed while a command was running
+    pub fn take_due(&mut self, now: Instant) -> Vec<String> {
+        let mut due = Vec::new();
+        for job in self.jobs.iter_mut().filter(|job| job.next_run <= now) {
+            due.push(job.command.clone());
+            if let Some(interval) = job.every {
+                while job.next_run <= now {
+                    job.next_run += interval;
+                }
+            }
+        }
+        self.jobs
+            .retain(|job| job.every.is_some() || job.next_run > now);
+        due
+    }
+}
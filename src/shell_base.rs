@@ -9,20 +9,28 @@ use lazy_static::lazy_static;
 #[cfg(not(target_os = "wasi"))]
 use nix;
 use regex::Regex;
-use std::collections::{HashMap, VecDeque};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::env;
 use std::fs;
 use std::fs::{File, OpenOptions};
 use std::io;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::io::{Error, ErrorKind};
-#[cfg(target_os = "wasi")]
 use std::mem;
+use std::mem::ManuallyDrop;
 #[cfg(not(target_os = "wasi"))]
-use std::os::fd::IntoRawFd;
+use std::os::fd::{FromRawFd, IntoRawFd};
 #[cfg(target_os = "wasi")]
 use std::os::wasi::io::{AsRawFd, FromRawFd};
+use std::panic;
+#[cfg(not(target_os = "wasi"))]
+use std::panic::AssertUnwindSafe;
 use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Once};
+#[cfg(not(target_os = "wasi"))]
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
 #[cfg(target_os = "wasi")]
 use wasi;
 
@@ -31,10 +39,30 @@ use wasi_ext_lib::termios;
 
 use vte::Parser;
 
-use crate::cli::Cli;
-use crate::internals::INTERNALS_MAP;
+use crate::arith;
+use crate::array::{Array, Subscript};
+use crate::cli::{self, Cli};
+use crate::completion::{self, CompletionSpec};
+use crate::config::{Config, ConfigValue};
+use crate::debugger::Debugger;
+use crate::dir_env::DirEnv;
+use crate::frecency::PendingVisits;
+use crate::internals::{self, INTERNALS_MAP};
 use crate::interpreter::InputInterpreter;
+use crate::jobs::JobList;
+use crate::limits::{Limits, ResourceGuard};
+use crate::locale::tr;
 use crate::output_device::OutputDevice;
+use crate::profiling::{CommandProfile, StartupProfile};
+use crate::random;
+use crate::schedule::{self, Scheduler};
+use crate::spell;
+use crate::syntax;
+use crate::terminal;
+use crate::theme::{Role, Theme};
+use crate::trap;
+use crate::trust::{Trust, TrustStore};
+use crate::xdg;
 
 #[cfg(target_os = "wasi")]
 pub type Fd = wasi::Fd;
@@ -44,14 +72,85 @@ pub type Fd = std::os::fd::RawFd;
 pub const EXIT_SUCCESS: i32 = 0;
 pub const EXIT_FAILURE: i32 = 1;
 pub const EXIT_CRITICAL_FAILURE: i32 = 2;
+pub const EXIT_CMD_NOT_EXECUTABLE: i32 = 126;
 pub const EXIT_CMD_NOT_FOUND: i32 = 127;
 pub const EXIT_INTERRUPTED: i32 = 130;
+pub const EXIT_TIMEOUT: i32 = 124;
+
+/// Time between SIGTERM and SIGKILL for timed out commands
+#[cfg(not(target_os = "wasi"))]
+const TIMEOUT_KILL_GRACE: Duration = Duration::from_secs(2);
+
+pub const SYSTEM_RC_PATH: &str = concat!(
+    "/etc/",
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_NAME"),
+    "rc"
+);
+
+/// Window title template used unless `terminal.title` is set
+const DEFAULT_TITLE: &str = "\\u@\\h: \\w";
+
+/// Longest part of a command line shown in a notification
+const NOTIFY_COMMAND_LEN: usize = 64;
+
+/// Scripts larger than this are parsed and run in chunks instead of being read whole
+const SCRIPT_STREAM_THRESHOLD: u64 = 4 * 1024 * 1024;
+const SCRIPT_CHUNK_SIZE: usize = 1024 * 1024;
+/// Number of leading bytes inspected to tell binary files from scripts
+const BINARY_CHECK_LEN: usize = 4096;
+
+/// Paths of commands found in `PATH`, forgotten when `PATH` changes
+#[derive(Debug, Default)]
+pub struct CommandHash {
+    /// Value of `PATH` the commands were found in
+    path: String,
+    /// Path of each command and the number of times it ran
+    entries: BTreeMap<String, (PathBuf, usize)>,
+}
+
+impl CommandHash {
+    /// Commands found in the current `PATH`
+    pub fn entries(&mut self) -> &mut BTreeMap<String, (PathBuf, usize)> {
+        let path = env::var("PATH").unwrap_or_default();
+        if self.path != path {
+            self.path = path;
+            self.entries.clear();
+        }
+        &mut self.entries
+    }
+}
+
+/// Internals `execute_command` handles itself, they run another command with
+/// its environment and redirects
+const PREFIX_INTERNALS: [&str; 2] = ["builtin", "timeout"];
+
+/// Directories of standard utilities searched by `command -p`
+pub const DEFAULT_PATH: &str = "/usr/bin:/bin";
+
+/// What a command name runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommandKind {
+    /// Alias with its value
+    Alias(String),
+    Keyword,
+    Internal,
+    File(PathBuf),
+}
 
 pub const STDIN: Fd = 0;
 pub const STDOUT: Fd = 1;
 pub const STDERR: Fd = 2;
 pub const CLEAR_ESCAPE_CODE: &str = "\x1b[2J\x1b[H";
 
+enum HistoryState {
+    Unloaded,
+    #[cfg(not(target_os = "wasi"))]
+    Loading(thread::JoinHandle<Vec<u8>>),
+    Loaded,
+}
+
 enum HistoryExpansion {
     Expanded(String),
     EventNotFound(String),
@@ -95,8 +194,11 @@ pub fn is_fd_tty(fd: Fd) -> Result<bool, Error> {
     }
 }
 
+/// Checks redirects of a command before it runs, with `noclobber` writing
+/// to an existing regular file fails
 pub fn preprocess_redirects<'a>(
     redirects: &'a [Redirect],
+    noclobber: bool,
     output_device: &mut OutputDevice<'a>,
 ) -> Result<(), Report> {
     enum DescriptorState<'a> {
@@ -113,7 +215,11 @@ pub fn preprocess_redirects<'a>(
                 // Check file exist
                 let file_path = Path::new(path);
                 if !file_path.exists() {
-                    return Err(Report::msg(format!("{}: No such file or directory", path)));
+                    return Err(Report::msg(format!(
+                        "{}: {}",
+                        path,
+                        tr("No such file or directory")
+                    )));
                 }
                 red_map.insert(*fd, DescriptorState::Redirect(redirect));
                 *fd
@@ -123,7 +229,14 @@ pub fn preprocess_redirects<'a>(
             | Redirect::ReadWrite(fd, path) => {
                 let file_path = Path::new(path);
                 if file_path.is_dir() {
-                    return Err(Report::msg(format!("{}: Is a directory", path)));
+                    return Err(Report::msg(format!("{}: {}", path, tr("Is a directory"))));
+                }
+                if noclobber && matches!(redirect, Redirect::Write(..)) && file_path.is_file() {
+                    return Err(Report::msg(format!(
+                        "{}: {}",
+                        path,
+                        tr("cannot overwrite existing file")
+                    )));
                 }
                 red_map.insert(*fd, DescriptorState::Redirect(redirect));
                 *fd
@@ -194,7 +307,9 @@ pub fn preprocess_redirects<'a>(
             }
         };
 
-        if affected_fd == STDOUT {
+        if affected_fd == STDIN {
+            output_device.set_redirect_in(redirect);
+        } else if affected_fd == STDOUT {
             output_device.set_redirect_out(redirect);
         } else if affected_fd == STDERR {
             output_device.set_redirect_err(redirect);
@@ -264,6 +379,33 @@ pub fn apply_redirects(redirects: &[Redirect]) -> io::Result<()> {
     Ok(())
 }
 
+/// Quotes `word` so the interpreter reads it back as a single literal word
+pub fn shell_quote(word: &str) -> String {
+    let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c);
+    if !word.is_empty() && word.chars().all(is_safe) {
+        word.to_string()
+    } else {
+        format!("'{}'", word.replace('\'', "'\\''"))
+    }
+}
+
+/// Installs a panic hook restoring the terminal mode from before interactive
+/// mode was enabled. WASI builds abort on panic, so this is the only chance
+/// to give the terminal its echo back there.
+fn restore_mode_on_panic(mode: Termios) {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let mode = Mutex::new(mode);
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if let Ok(mode) = mode.lock() {
+                let _ = Shell::set_termios(STDIN, &mode);
+            }
+            default_hook(info);
+        }));
+    });
+}
+
 pub fn spawn(
     path: &str,
     args: &[&str],
@@ -271,6 +413,21 @@ pub fn spawn(
     background: bool,
     redirects: &[Redirect],
 ) -> Result<(i32, i32), i32> {
+    spawn_with_timeout(path, args, env, background, redirects, None)
+}
+
+/// Spawns a command, a foreground child still running after `timeout` is
+/// terminated and `EXIT_TIMEOUT` is returned. Timeouts are not supported on
+/// WASI where the runtime waits for the child itself.
+pub fn spawn_with_timeout(
+    path: &str,
+    args: &[&str],
+    env: &HashMap<String, String>,
+    background: bool,
+    redirects: &[Redirect],
+    #[allow(unused_variables)] timeout: Option<Duration>,
+) -> Result<(i32, i32), i32> {
+    debug!(target: "spawn", ?args, background, redirects = redirects.len(), ?timeout, "spawning {}", path);
     #[cfg(target_os = "wasi")]
     {
         wasi_ext_lib::spawn(path, args, env, background, redirects)
@@ -279,10 +436,21 @@ pub fn spawn(
     #[cfg(not(target_os = "wasi"))]
     match unsafe { nix::unistd::fork() } {
         Ok(nix::unistd::ForkResult::Parent { child }) => {
-            if !background {
-                Ok((wait_for_child(child), i32::from(child)))
-            } else {
+            if background {
                 Ok((EXIT_SUCCESS, i32::from(child)))
+            } else if let Some(timeout) = timeout {
+                let exit_status = wait_for_child_timeout(child, timeout).unwrap_or_else(|| {
+                    eprintln!(
+                        "{}: {}: timed out after {}s",
+                        env!("CARGO_PKG_NAME"),
+                        path,
+                        timeout.as_secs_f64()
+                    );
+                    EXIT_TIMEOUT
+                });
+                Ok((exit_status, i32::from(child)))
+            } else {
+                Ok((wait_for_child(child), i32::from(child)))
             }
         }
         Ok(nix::unistd::ForkResult::Child) => {
@@ -331,22 +499,232 @@ pub fn spawn(
     }
 }
 
+fn shell_pid() -> u32 {
+    #[cfg(target_os = "wasi")]
+    {
+        wasi_ext_lib::getpid().unwrap_or(0) as u32
+    }
+    #[cfg(not(target_os = "wasi"))]
+    std::process::id()
+}
+
+/// Exit status of a finished child, signals are reported as 128 + signal number
+#[cfg(not(target_os = "wasi"))]
+pub(crate) fn child_exit_status(status: nix::sys::wait::WaitStatus) -> Option<i32> {
+    match status {
+        nix::sys::wait::WaitStatus::Exited(_, exit_code) => Some(exit_code),
+        nix::sys::wait::WaitStatus::Signaled(_, signal, _) => Some(128 + signal as i32),
+        _ => None,
+    }
+}
+
 #[cfg(not(target_os = "wasi"))]
 pub fn wait_for_child(child_pid: nix::unistd::Pid) -> i32 {
     loop {
-        let wait_status = nix::sys::wait::waitpid(child_pid, None)
-            .unwrap_or_else(|err| panic!("{}: waitpid error: {}", env!("CARGO_PKG_NAME"), err));
-        match wait_status {
-            nix::sys::wait::WaitStatus::Exited(waited_pid, exit_code)
-                if waited_pid == child_pid =>
-            {
-                return exit_code;
+        let wait_status = match nix::sys::wait::waitpid(child_pid, None) {
+            Ok(wait_status) => wait_status,
+            // interrupted by a trapped signal
+            Err(nix::errno::Errno::EINTR) => continue,
+            // e.g. ECHILD when the child was already reaped
+            Err(_) => return EXIT_FAILURE,
+        };
+        if wait_status.pid() != Some(child_pid) {
+            continue;
+        }
+        if let Some(exit_status) = child_exit_status(wait_status) {
+            return exit_status;
+        }
+    }
+}
+
+/// Waits for child up to `timeout`, then sends SIGTERM, and SIGKILL if the child
+/// is still alive after a grace period. Returns `None` when the child was killed.
+#[cfg(not(target_os = "wasi"))]
+pub fn wait_for_child_timeout(child_pid: nix::unistd::Pid, timeout: Duration) -> Option<i32> {
+    use nix::sys::signal::{kill, Signal};
+    use nix::sys::wait::{waitpid, WaitPidFlag};
+
+    let poll_until = |deadline: Instant| -> Option<i32> {
+        let mut delay = Duration::from_millis(1);
+        loop {
+            match waitpid(child_pid, Some(WaitPidFlag::WNOHANG)) {
+                Ok(status) => {
+                    if let Some(exit_status) = child_exit_status(status) {
+                        return Some(exit_status);
+                    }
+                }
+                Err(nix::errno::Errno::EINTR) => {}
+                Err(_) => return Some(EXIT_FAILURE),
             }
-            nix::sys::wait::WaitStatus::Exited(_, _) => {
-                continue;
+            let now = Instant::now();
+            if now >= deadline {
+                return None;
+            }
+            thread::sleep(delay.min(deadline - now));
+            delay = (delay * 2).min(Duration::from_millis(50));
+        }
+    };
+
+    if let Some(exit_status) = poll_until(Instant::now() + timeout) {
+        return Some(exit_status);
+    }
+    _ = kill(child_pid, Signal::SIGTERM);
+    if poll_until(Instant::now() + TIMEOUT_KILL_GRACE).is_none() {
+        _ = kill(child_pid, Signal::SIGKILL);
+        wait_for_child(child_pid);
+    }
+    None
+}
+
+/// Parses durations like `10`, `1.5s`, `2m`, `1h` or `1d`
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+    let (number, multiplier) = match duration.chars().last()? {
+        's' => (&duration[..duration.len() - 1], 1.0),
+        'm' => (&duration[..duration.len() - 1], 60.0),
+        'h' => (&duration[..duration.len() - 1], 3600.0),
+        'd' => (&duration[..duration.len() - 1], 86400.0),
+        _ => (duration, 1.0),
+    };
+    let seconds = number.parse::<f64>().ok()? * multiplier;
+    if seconds.is_finite() && seconds >= 0.0 {
+        Some(Duration::from_secs_f64(seconds))
+    } else {
+        None
+    }
+}
+
+/// Path of the running shell binary, used as `$SHELL` when it is not set
+pub fn shell_path() -> String {
+    env::current_exe()
+        .ok()
+        .map(|path| path.display().to_string())
+        .or_else(|| env::args().next().filter(|arg0| arg0.contains('/')))
+        .unwrap_or_else(|| format!("/usr/bin/{}", env!("CARGO_PKG_NAME")))
+}
+
+/// Fills in `HOME`, `PWD`, `SHELL` and `USER` when the runtime did not set them,
+/// minimal WASI images commonly start with an empty environment
+pub fn bootstrap_env(pwd: &str) {
+    let defaults = [
+        ("PWD", pwd.to_string()),
+        ("HOME", String::from("/")),
+        ("SHELL", shell_path()),
+        ("USER", String::from("user")),
+    ];
+    for (key, value) in defaults {
+        if env::var(key).map_or(true, |current| current.is_empty()) {
+            env::set_var(key, value);
+        }
+    }
+}
+
+/// Decodes script contents, falling back to Latin-1 for files that are not valid
+/// UTF-8. Returns whether the fallback was used.
+fn decode_script(content: Vec<u8>) -> (String, bool) {
+    match String::from_utf8(content) {
+        Ok(input) => (input, false),
+        Err(error) => (
+            error.into_bytes().into_iter().map(char::from).collect(),
+            true,
+        ),
+    }
+}
+
+fn warn_latin1(source: &str) {
+    eprintln!(
+        "{}: {}: not valid UTF-8, reading as Latin-1",
+        env!("CARGO_PKG_NAME"),
+        source
+    );
+}
+
+/// First line of history files in the format of `history_line`, files
+/// without it have one entry per line
+const HISTORY_HEADER: &str = "# wash history 2";
+
+/// Parses history file contents. Lines torn by an interrupted write (NUL
+/// bytes, missing final newline) are dropped or fixed and reported so the
+/// file can be rewritten, like files in the old format. Invalid UTF-8 is
+/// replaced rather than dropped. See `history_line` for the format.
+fn parse_history(content: &[u8]) -> (Vec<Vec<char>>, bool) {
+    let mut rewrite = !content.is_empty() && !content.ends_with(b"\n");
+    let mut history = Vec::new();
+    let mut entry = String::new();
+    let content = content.strip_suffix(b"\n").unwrap_or(content);
+    let mut lines = content.split(|byte| *byte == b'\n').peekable();
+    let escaped = lines.peek() == Some(&HISTORY_HEADER.as_bytes());
+    if escaped {
+        lines.next();
+    } else {
+        rewrite |= !content.is_empty();
+    }
+    for line in lines {
+        if line.contains(&b'\0') {
+            rewrite = true;
+            entry.clear();
+            continue;
+        }
+        let line = String::from_utf8_lossy(line);
+        if !escaped {
+            if !line.is_empty() {
+                history.push(line.chars().collect());
             }
-            _ => unreachable!(),
+            continue;
+        }
+        let content = line.trim_end_matches('\\');
+        let trailing_backslashes = line.len() - content.len();
+        entry.push_str(content);
+        entry.push_str(&"\\".repeat(trailing_backslashes / 2));
+        if trailing_backslashes % 2 == 1 {
+            entry.push('\n');
+            continue;
         }
+        if !entry.is_empty() {
+            history.push(entry.chars().collect::<Vec<char>>());
+        }
+        entry.clear();
+    }
+    (history, rewrite)
+}
+
+/// History file line(s) of `entry`. Entries of several lines get a backslash
+/// after every line but the last, backslashes ending a line are doubled so
+/// that their count tells the two apart.
+fn history_line(entry: &str) -> String {
+    let mut encoded = String::new();
+    let mut lines = entry.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        let content = line.trim_end_matches('\\');
+        encoded.push_str(content);
+        encoded.push_str(&"\\".repeat(2 * (line.len() - content.len())));
+        if lines.peek().is_some() {
+            encoded.push('\\');
+        }
+        encoded.push('\n');
+    }
+    encoded
+}
+
+/// Command to run from the edited `lines`, with backslash-newline line
+/// continuations removed, None while more lines are needed
+fn command_text(lines: &[char]) -> Option<String> {
+    let mut text = String::new();
+    let mut complete = true;
+    for line in lines.split(|c| *c == '\n') {
+        text.extend(line);
+        text.push('\n');
+        complete = if syntax::continues_line(&text) {
+            // the backslash and the newline are removed, like in other shells
+            text.truncate(text.len() - 2);
+            false
+        } else {
+            syntax::is_complete(&text)
+        };
+    }
+    if complete {
+        Some(text)
+    } else {
+        None
     }
 }
 
@@ -363,7 +741,7 @@ pub fn path_exists(path: &str) -> io::Result<bool> {
 #[cfg(target_os = "wasi")]
 struct InternalEventSource {
     subs: [wasi::Subscription; 2],
-    events: [wasi::Event; 2],
+    events: [wasi::Event; 3],
     tty_input: File,
     event_src: File,
 }
@@ -372,17 +750,17 @@ struct InternalEventSource {
 impl InternalEventSource {
     const TTY_TOKEN: u64 = 1;
     const SIGINT_TOKEN: u64 = 2;
+    const TIMER_TOKEN: u64 = 3;
 
-    pub fn read_byte(&mut self) -> Result<Option<u8>, Report> {
+    fn read_byte(&mut self, timeout: Option<Duration>) -> Result<ReadEvent, Report> {
         // subscribe and wait
         let mut byte: [u8; 1] = [0];
-        let result = unsafe {
-            wasi::poll_oneoff(
-                self.subs.as_ptr(),
-                self.events.as_mut_ptr(),
-                self.subs.len(),
-            )
-        };
+        let mut subs = self.subs.to_vec();
+        if let Some(timeout) = timeout {
+            subs.push(clock_subscription(Self::TIMER_TOKEN, timeout));
+        }
+        let result =
+            unsafe { wasi::poll_oneoff(subs.as_ptr(), self.events.as_mut_ptr(), subs.len()) };
 
         let events_count = match result {
             Ok(n) => n,
@@ -400,10 +778,12 @@ impl InternalEventSource {
             }
         }
 
+        let mut read = false;
         for event in self.events[0..events_count].iter() {
             match (event.userdata, event.type_) {
                 (Self::TTY_TOKEN, wasi::EVENTTYPE_FD_READ) => {
-                    self.tty_input.read_exact(&mut byte)?
+                    self.tty_input.read_exact(&mut byte)?;
+                    read = true;
                 }
                 (Self::SIGINT_TOKEN, wasi::EVENTTYPE_FD_READ) => {
                     let mut read_buff: [u8; wasi_ext_lib::WASI_EVENTS_MASK_SIZE] =
@@ -414,18 +794,106 @@ impl InternalEventSource {
                     let events = u32::from_le_bytes(read_buff) as wasi_ext_lib::WasiEvents;
 
                     if events & wasi_ext_lib::WASI_EVENT_SIGINT != 0 {
-                        return Ok(None);
+                        return Ok(ReadEvent::Interrupted);
                     } else {
                         return Err(Report::msg(
                             "Event_source did not return subsribed SigInt event!",
                         ));
                     }
                 }
+                (Self::TIMER_TOKEN, wasi::EVENTTYPE_CLOCK) => {}
                 _ => unreachable!(),
             }
         }
 
-        Ok(Some(byte[0]))
+        Ok(if read {
+            ReadEvent::Byte(byte[0])
+        } else {
+            ReadEvent::TimedOut
+        })
+    }
+}
+
+#[cfg(target_os = "wasi")]
+impl InternalEventSource {
+    /// Consumes a pending SIGINT event without waiting for one
+    fn poll_sigint(&mut self) -> Result<bool, Report> {
+        let subs = [
+            self.subs[1],
+            clock_subscription(Self::TIMER_TOKEN, Duration::ZERO),
+        ];
+        let mut events: [wasi::Event; 2] = unsafe { mem::zeroed() };
+        let events_count =
+            unsafe { wasi::poll_oneoff(subs.as_ptr(), events.as_mut_ptr(), subs.len()) }
+                .map_err(|e| Report::msg(format!("Poll_oneoff returned non zero code = {e}!")))?;
+        if !events[..events_count]
+            .iter()
+            .any(|event| event.userdata == Self::SIGINT_TOKEN)
+        {
+            return Ok(false);
+        }
+        let mut read_buff = [0u8; wasi_ext_lib::WASI_EVENTS_MASK_SIZE];
+        self.event_src.read_exact(&mut read_buff)?;
+        let events = u32::from_le_bytes(read_buff) as wasi_ext_lib::WasiEvents;
+        Ok(events & wasi_ext_lib::WASI_EVENT_SIGINT != 0)
+    }
+}
+
+#[cfg(target_os = "wasi")]
+fn clock_subscription(userdata: u64, timeout: Duration) -> wasi::Subscription {
+    wasi::Subscription {
+        userdata,
+        u: wasi::SubscriptionU {
+            tag: wasi::EVENTTYPE_CLOCK.raw(),
+            u: wasi::SubscriptionUU {
+                clock: wasi::SubscriptionClock {
+                    id: wasi::CLOCKID_MONOTONIC,
+                    timeout: timeout.as_nanos() as u64,
+                    precision: 0,
+                    flags: 0,
+                },
+            },
+        },
+    }
+}
+
+/// Waits until `fd` is readable, `false` when `timeout` passes first
+#[cfg(target_os = "wasi")]
+fn wait_for_input(fd: Fd, timeout: Duration) -> Result<bool, Report> {
+    const INPUT_TOKEN: u64 = 1;
+    const TIMER_TOKEN: u64 = 2;
+    let subs = [
+        wasi::Subscription {
+            userdata: INPUT_TOKEN,
+            u: wasi::SubscriptionU {
+                tag: wasi::EVENTTYPE_FD_READ.raw(),
+                u: wasi::SubscriptionUU {
+                    fd_read: wasi::SubscriptionFdReadwrite {
+                        file_descriptor: fd as u32,
+                    },
+                },
+            },
+        },
+        clock_subscription(TIMER_TOKEN, timeout),
+    ];
+    let mut events: [wasi::Event; 2] = unsafe { mem::zeroed() };
+    let events_count = unsafe { wasi::poll_oneoff(subs.as_ptr(), events.as_mut_ptr(), subs.len()) }
+        .map_err(|e| Report::msg(format!("Poll_oneoff returned non zero code = {e}!")))?;
+    Ok(events[..events_count]
+        .iter()
+        .any(|event| event.userdata == INPUT_TOKEN))
+}
+
+/// Waits until `fd` is readable, `false` when `timeout` passes first
+#[cfg(not(target_os = "wasi"))]
+fn wait_for_input(fd: Fd, timeout: Duration) -> Result<bool, Report> {
+    use nix::poll::{poll, PollFd, PollFlags};
+
+    let mut fds = [PollFd::new(fd, PollFlags::POLLIN)];
+    match poll(&mut fds, timeout.as_millis().min(i32::MAX as u128) as i32) {
+        Ok(ready) => Ok(ready > 0),
+        Err(nix::errno::Errno::EINTR) => Ok(false),
+        Err(e) => Err(e.into()),
     }
 }
 
@@ -477,6 +945,15 @@ impl Default for InternalEventSource {
     }
 }
 
+/// Outcome of waiting for a byte of terminal input
+enum ReadEvent {
+    Byte(u8),
+    /// SIGINT reported by the WASI event source
+    #[cfg(target_os = "wasi")]
+    Interrupted,
+    TimedOut,
+}
+
 enum InternalReader {
     #[cfg(target_os = "wasi")]
     StdinWithSigInt(InternalEventSource),
@@ -484,29 +961,146 @@ enum InternalReader {
 }
 
 impl InternalReader {
-    fn read_byte(&mut self) -> Result<Option<u8>, Report> {
+    /// Reads a byte of input, giving up once `timeout` passes
+    fn read_byte(&mut self, timeout: Option<Duration>) -> Result<ReadEvent, Report> {
         match self {
             #[cfg(target_os = "wasi")]
-            InternalReader::StdinWithSigInt(reader) => reader.read_byte(),
+            InternalReader::StdinWithSigInt(reader) => reader.read_byte(timeout),
             InternalReader::OnlyStdin => {
+                if let Some(timeout) = timeout {
+                    if !wait_for_input(STDIN, timeout)? {
+                        return Ok(ReadEvent::TimedOut);
+                    }
+                }
                 let mut buffer: [u8; 1] = [0];
-                io::stdin().read_exact(&mut buffer)?;
+                // unbuffered, bytes held in a buffer would be invisible to `wait_for_input`
+                ManuallyDrop::new(unsafe { File::from_raw_fd(STDIN) }).read_exact(&mut buffer)?;
 
-                Ok(Some(buffer[0]))
+                Ok(ReadEvent::Byte(buffer[0]))
             }
         }
     }
 }
 
+/// Optional behaviors toggled with the `shopt` builtin
+#[derive(Debug, Default)]
+pub struct Shopt {
+    /// Offer corrections for misspelled `cd` targets
+    pub cdspell: bool,
+    /// Offer corrections for misspelled command names
+    pub correct: bool,
+    /// Remove glob patterns that match no files instead of keeping them
+    pub nullglob: bool,
+}
+
+/// Pending `break` or `continue`, counting the enclosing loops it still
+/// applies to, or `return` leaving all loops of a sourced script
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopControl {
+    Break(usize),
+    Continue(usize),
+    Return,
+}
+
+/// Value of a variable where `Shell::set_var` keeps it, see `Shell::save_var`
+#[derive(Debug, Clone)]
+pub enum SavedVar {
+    Array(Array),
+    Exported(String),
+    Shell(Option<String>),
+}
+
+/// Attributes of a variable set with `declare` or `readonly`, they apply to
+/// every later assignment
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Attributes {
+    pub readonly: bool,
+    /// Values are evaluated arithmetically
+    pub integer: bool,
+    pub lowercase: bool,
+    pub uppercase: bool,
+}
+
 pub struct Shell {
     pub pwd: PathBuf,
     pub vars: HashMap<String, String>,
+    /// Array variables, a name is either in `vars` or here
+    pub arrays: HashMap<String, Array>,
+    /// Attributes of shell, environment and array variables
+    pub attributes: HashMap<String, Attributes>,
+    /// Words replacing the first word of simple commands, set by `alias`
+    pub aliases: BTreeMap<String, String>,
+    /// Completion of arguments by command name, set by `complete`
+    pub completions: BTreeMap<String, CompletionSpec>,
+    /// Aliases being expanded, they are not expanded again in their own
+    /// value
+    pub alias_stack: Vec<String>,
     pub args: VecDeque<String>,
     pub last_exit_status: i32,
     pub last_job_pid: Option<u32>,
+    /// Process id of the shell, `$$`. Subshells forked from it keep the value.
+    pub pid: u32,
+    pub jobs: JobList,
+    /// Directories entered in interactive mode, for `z`
+    pub dir_visits: PendingVisits,
+    /// Temporary files holding here-document bodies, removed once the
+    /// command reading them finishes or the shell exits
+    pub heredocs: Vec<PathBuf>,
     pub cli: Cli,
+    pub debugger: Option<Debugger>,
+    pub config: Config,
+    pub theme: Theme,
+    pub dir_env: DirEnv,
+    pub startup_profile: Option<StartupProfile>,
+    pub profile: CommandProfile,
+    pub guard: ResourceGuard,
+    pub shopt: Shopt,
+    pub scheduler: Scheduler,
+    /// Whether file names in messages are printed as OSC 8 links
+    pub hyperlinks: bool,
+    /// Collects output of internals run in-process by a command substitution
+    pub capture: Option<String>,
+    /// Whether commands are traced before running, `set -x`
+    pub xtrace: bool,
+    /// Whether `>` refuses to overwrite existing files, `set -C`
+    pub noclobber: bool,
+    /// Whether a pipeline fails with its rightmost failing stage, `set -o
+    /// pipefail`
+    pub pipefail: bool,
+    /// Whether a failing command exits the shell, `set -e`
+    pub errexit: bool,
+    /// Whether expanding unset parameters is an error, `set -u`
+    pub nounset: bool,
+    /// Number of `if`, `while` and `until` conditions being run, `set -e`
+    /// does not apply to them
+    pub condition_depth: usize,
+    /// Commands set with `trap` by signal number, empty ones ignore the
+    /// signal
+    pub traps: HashMap<i32, String>,
+    /// `OPTIND` of the argument `getopts` is in and the byte offset of the
+    /// next option in it, for grouped options like `-ab`
+    pub getopts_cursor: (usize, usize),
+    /// Number of loops being executed, `break` and `continue` outside of
+    /// them are ignored
+    pub loop_depth: usize,
+    /// Set by `break`, `continue` and `return`, commands are skipped until
+    /// the affected loop or sourced script handles it
+    pub loop_control: Option<LoopControl>,
+    /// Number of sourced scripts being run, `return` outside of them fails
+    pub return_depth: usize,
+    /// `SECONDS` counts up from the value since the instant, both are reset
+    /// by assigning it
+    pub seconds: (Instant, i64),
+    /// Directories searched for commands instead of `PATH`, set by
+    /// `command -p`
+    pub command_path: Option<String>,
+    /// Commands found in `PATH`, listed by `hash`
+    pub command_hash: CommandHash,
 
     history_path: PathBuf,
+    history_state: HistoryState,
+    command_timeout: Option<Duration>,
+    in_hook: bool,
     termios_mode: Option<Termios>,
     reader: InternalReader,
 }
@@ -516,35 +1110,241 @@ impl Shell {
         Shell {
             pwd: PathBuf::from(pwd),
             args,
-            history_path: PathBuf::from(if PathBuf::from(env::var("HOME").unwrap()).exists() {
-                format!(
-                    "{}/.{}_history",
-                    env::var("HOME").unwrap(),
-                    env!("CARGO_PKG_NAME")
-                )
-            } else {
-                format!(
-                    "{}/.{}_history",
-                    env::var("PWD").unwrap(),
-                    env!("CARGO_PKG_NAME")
-                )
-            }),
+            history_path: xdg::history_file(),
             vars: HashMap::new(),
+            arrays: HashMap::new(),
+            attributes: HashMap::new(),
+            aliases: BTreeMap::new(),
+            completions: BTreeMap::new(),
+            alias_stack: Vec::new(),
             last_exit_status: EXIT_SUCCESS,
             last_job_pid: None,
+            pid: shell_pid(),
+            jobs: JobList::default(),
+            dir_visits: PendingVisits::default(),
+            heredocs: Vec::new(),
             termios_mode: None,
             reader: InternalReader::OnlyStdin,
             cli: Cli::new(should_echo),
+            debugger: None,
+            config: Config::default(),
+            theme: Theme::default(),
+            dir_env: DirEnv::default(),
+            startup_profile: None,
+            profile: CommandProfile::default(),
+            guard: ResourceGuard::default(),
+            shopt: Shopt::default(),
+            scheduler: Scheduler::default(),
+            hyperlinks: false,
+            capture: None,
+            xtrace: false,
+            noclobber: false,
+            pipefail: false,
+            errexit: false,
+            nounset: false,
+            condition_depth: 0,
+            traps: HashMap::new(),
+            getopts_cursor: (0, 0),
+            loop_depth: 0,
+            loop_control: None,
+            return_depth: 0,
+            seconds: (Instant::now(), 0),
+            history_state: HistoryState::Unloaded,
+            command_timeout: None,
+            command_path: None,
+            command_hash: CommandHash::default(),
+            in_hook: false,
         }
     }
 
     fn print_prompt(&mut self, input: &str) {
-        print!("{}{}", self.parse_prompt_string(), input);
+        let prompt = self.parse_prompt_string();
+        print!("{}{}", self.terminal_state(), prompt);
+        self.cli.prompt_width = cli::prompt_width(&prompt);
+        self.cli.display(input);
         io::stdout().flush().unwrap();
-        self.cli.cursor_position = input.len();
     }
 
     fn parse_prompt_string(&self) -> String {
+        let template = env::var("PS1")
+            .ok()
+            .or_else(|| self.config.get_str("prompt.ps1").map(String::from))
+            .unwrap_or_else(|| self.theme.default_prompt());
+        self.expand_prompt(&template)
+    }
+
+    /// Value of a shell variable, falling back to the environment
+    fn var(&self, name: &str) -> Option<String> {
+        self.dynamic_var(name)
+            .or_else(|| self.vars.get(name).cloned())
+            .or_else(|| env::var(name).ok())
+    }
+
+    /// Value of a variable computed on every reference, like `RANDOM`
+    pub fn dynamic_var(&self, name: &str) -> Option<String> {
+        match name {
+            "RANDOM" => random::in_range(0, 32767).ok().map(|n| n.to_string()),
+            "SECONDS" => {
+                let (start, base) = self.seconds;
+                Some(
+                    base.wrapping_add(start.elapsed().as_secs() as i64)
+                        .to_string(),
+                )
+            }
+            "EPOCHSECONDS" => SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Value stored when assigning `value` to `name`, transformed by the
+    /// attributes of the variable. Assigning readonly variables fails.
+    pub fn assigned_value(&mut self, name: &str, value: String) -> Result<String, String> {
+        let attributes = self.attributes.get(name).copied().unwrap_or_default();
+        if attributes.readonly {
+            return Err(format!("{name}: {}", tr("readonly variable")));
+        }
+        let value = if attributes.integer {
+            arith::eval_text(self, &value)?.to_string()
+        } else {
+            value
+        };
+        Ok(if attributes.lowercase {
+            value.to_lowercase()
+        } else if attributes.uppercase {
+            value.to_uppercase()
+        } else {
+            value
+        })
+    }
+
+    /// Assigns a variable like `name=value`, exported variables stay
+    /// exported and arrays get their first element set
+    pub fn set_var(&mut self, name: &str, value: String) -> Result<(), String> {
+        let value = self.assigned_value(name, value)?;
+        if name == "SECONDS" {
+            let base = arith::eval_text(self, &value)?;
+            self.seconds = (Instant::now(), base);
+        } else if let Some(array) = self.arrays.get_mut(name) {
+            array.set(Subscript::Index(0), value)?;
+        } else if env::var(name).is_ok() && !self.vars.contains_key(name) {
+            env::set_var(name, &value);
+            #[cfg(target_os = "wasi")]
+            let _ = wasi_ext_lib::set_env(name, Some(&value));
+        } else {
+            self.vars.insert(name.to_string(), value);
+        }
+        Ok(())
+    }
+
+    /// Current value of `name`, to be put back with `restore_var` after
+    /// temporary assignments with `set_var`
+    pub fn save_var(&self, name: &str) -> SavedVar {
+        if let Some(array) = self.arrays.get(name) {
+            return SavedVar::Array(array.clone());
+        }
+        match env::var(name) {
+            Ok(value) if !self.vars.contains_key(name) => SavedVar::Exported(value),
+            _ => SavedVar::Shell(self.vars.get(name).cloned()),
+        }
+    }
+
+    pub fn restore_var(&mut self, name: &str, saved: SavedVar) {
+        match saved {
+            SavedVar::Array(array) => {
+                self.arrays.insert(name.to_string(), array);
+            }
+            SavedVar::Exported(value) => {
+                env::set_var(name, &value);
+                #[cfg(target_os = "wasi")]
+                let _ = wasi_ext_lib::set_env(name, Some(&value));
+            }
+            SavedVar::Shell(Some(value)) => {
+                self.vars.insert(name.to_string(), value);
+            }
+            SavedVar::Shell(None) => {
+                self.vars.remove(name);
+            }
+        }
+    }
+
+    /// Substitutes `$NAME`, `${NAME}` and `$?` in templates such as `PS4`
+    fn expand_vars(&self, template: &str) -> String {
+        let mut expanded = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '$' {
+                expanded.push(c);
+                continue;
+            }
+            let name: String = match chars.peek() {
+                Some('?') => {
+                    chars.next();
+                    expanded.push_str(&self.last_exit_status.to_string());
+                    continue;
+                }
+                Some('{') => {
+                    chars.next();
+                    chars.by_ref().take_while(|c| *c != '}').collect()
+                }
+                _ => {
+                    let mut name = String::new();
+                    while let Some(&c) = chars.peek() {
+                        if !(c.is_ascii_alphanumeric() || c == '_') {
+                            break;
+                        }
+                        name.push(c);
+                        chars.next();
+                    }
+                    name
+                }
+            };
+            if name.is_empty() {
+                expanded.push('$');
+            } else {
+                expanded.push_str(&self.var(&name).unwrap_or_default());
+            }
+        }
+        expanded
+    }
+
+    /// Prints a command about to run with `set -x`, prefixed with expanded
+    /// `PS4`, to the fd named in `WASH_XTRACEFD` or to stderr
+    pub fn trace_command(&self, env: &HashMap<String, String>, args: &[String]) {
+        let mut assignments: Vec<_> = env.iter().collect();
+        assignments.sort();
+        let words: Vec<String> = assignments
+            .into_iter()
+            .map(|(key, value)| format!("{key}={}", shell_quote(value)))
+            .chain(args.iter().map(|arg| shell_quote(arg)))
+            .collect();
+        let prefix = self.var("PS4").unwrap_or_else(|| String::from("+ "));
+        let line = format!("{}{}\n", self.expand_vars(&prefix), words.join(" "));
+
+        match self
+            .var("WASH_XTRACEFD")
+            .and_then(|fd| fd.trim().parse::<Fd>().ok())
+        {
+            Some(fd) if fd != STDERR => {
+                let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+                if let Err(error) = file.write_all(line.as_bytes()) {
+                    eprintln!(
+                        "{}: WASH_XTRACEFD: {}: {}",
+                        env!("CARGO_PKG_NAME"),
+                        fd,
+                        error
+                    );
+                    eprint!("{line}");
+                }
+            }
+            _ => eprint!("{line}"),
+        }
+    }
+
+    /// Replaces `\u`, `\h` and `\w` in prompt and title templates
+    fn expand_prompt(&self, template: &str) -> String {
         fn get_hostname() -> String {
             #[cfg(not(target_os = "wasi"))]
             {
@@ -560,22 +1360,52 @@ impl Shell {
             env::var("HOSTNAME").unwrap_or_else(|_| "hostname".to_string())
         }
 
-        env::var("PS1")
-            .unwrap_or_else(|_| "\x1b[1;34m\\u@\\h \x1b[1;33m\\w$\x1b[0m ".to_string())
+        template
             .replace(
                 "\\u",
                 &env::var("USER").unwrap_or_else(|_| "user".to_string()),
             )
             .replace("\\h", &get_hostname())
-            // FIXME: should only replace if it starts with HOME
-            .replace(
-                "\\w",
-                &self
-                    .pwd
-                    .display()
-                    .to_string()
-                    .replace(&env::var("HOME").unwrap(), "~"),
-            )
+            .replace("\\w", &self.pwd_for_prompt())
+    }
+
+    /// OSC 7 working directory report and OSC 0 window title from the
+    /// `terminal.title` template, empty unless the shell is interactive and
+    /// stdout is a terminal
+    fn terminal_state(&self) -> String {
+        if !self.is_interactive() || !matches!(is_fd_tty(STDOUT), Ok(true)) {
+            return String::new();
+        }
+        let mut state = String::new();
+        if self.config.get_bool("terminal.report_cwd") != Some(false) {
+            state.push_str(&format!("\x1b]7;{}\x07", terminal::file_url(&self.pwd)));
+        }
+        let title = self
+            .config
+            .get_str("terminal.title")
+            .unwrap_or(DEFAULT_TITLE);
+        if !title.is_empty() {
+            let title: String = self
+                .expand_prompt(title)
+                .chars()
+                .filter(|c| !c.is_control())
+                .collect();
+            state.push_str(&format!("\x1b]0;{title}\x07"));
+        }
+        state
+    }
+
+    /// Working directory with `$HOME` prefix abbreviated to `~`
+    fn pwd_for_prompt(&self) -> String {
+        let pwd = self.pwd.display().to_string();
+        match env::var("HOME") {
+            Ok(home) if home != "/" && !home.is_empty() => match self.pwd.strip_prefix(&home) {
+                Ok(rest) if rest.as_os_str().is_empty() => String::from("~"),
+                Ok(rest) => format!("~/{}", rest.display()),
+                Err(_) => pwd,
+            },
+            _ => pwd,
+        }
     }
 
     pub fn run_command(&mut self, command: &str) -> Result<i32, Report> {
@@ -583,55 +1413,358 @@ impl Shell {
     }
 
     pub fn run_script(&mut self, script_name: impl Into<PathBuf>) -> Result<i32, Report> {
-        self.handle_input(&fs::read_to_string(script_name.into()).unwrap())
+        let script_name = script_name.into();
+        self.guarded(|shell| shell.load_and_run_script(script_name))
     }
 
-    fn get_line(&mut self, input: &mut String) -> Result<bool, Report> {
-        let mut vt_parser = Parser::new();
-        self.cli.reset();
+    fn load_and_run_script(&mut self, script_name: PathBuf) -> Result<i32, Report> {
+        let source = script_name.display().to_string();
+        let linked = self.stderr_file_link(&script_name);
+        let fail = |reason: &dyn std::fmt::Display, exit_status: i32| {
+            eprintln!("{}: {}: {}", env!("CARGO_PKG_NAME"), linked, reason);
+            Ok(exit_status)
+        };
 
-        while !self.cli.is_input_ready() {
-            match self.reader.read_byte()? {
-                Some(byte) => vt_parser.advance(&mut self.cli, byte),
-                None => return Ok(false),
+        let file = match File::open(&script_name) {
+            Ok(file) => file,
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                return fail(&tr("No such file or directory"), EXIT_CMD_NOT_FOUND)
             }
+            Err(error) => return fail(&error, EXIT_CMD_NOT_EXECUTABLE),
+        };
+        let metadata = file.metadata()?;
+        if metadata.is_dir() {
+            return fail(&tr("Is a directory"), EXIT_CMD_NOT_EXECUTABLE);
+        }
+        let mut reader = BufReader::new(file);
+        if reader
+            .fill_buf()?
+            .iter()
+            .take(BINARY_CHECK_LEN)
+            .any(|b| *b == 0)
+        {
+            return fail(&tr("cannot execute binary file"), EXIT_CMD_NOT_EXECUTABLE);
+        }
+        if metadata.len() > SCRIPT_STREAM_THRESHOLD {
+            return self.run_script_chunked(reader, &source);
         }
 
-        *input = self.cli.input.iter().collect::<String>().trim().to_string();
-        Ok(true)
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        let (input, latin1) = decode_script(content);
+        if latin1 {
+            warn_latin1(&linked);
+        }
+        let mut interpreter = InputInterpreter::from_source(&input, &source);
+        Ok(interpreter.interpret(self))
     }
 
-    /// Expands input line with history expansion.
-    fn history_expansion(&mut self, input: &str) -> HistoryExpansion {
-        let mut processed = input.to_string();
-        if let Some(last_command) = self.cli.history.last() {
-            processed = processed.replace("!!", &last_command.iter().collect::<String>());
-        }
-        // for eg. "!12", "!-2"
-        lazy_static! {
-            static ref NUMBER_RE: Regex = Regex::new(r"(?:^|[^\[])!(-?\d+)").unwrap();
-        }
-        // for each match
-        for captures in NUMBER_RE.captures_iter(input) {
-            // get matched number
-            let full_match = captures.get(0).unwrap().as_str();
-            let group_match = captures.get(1).unwrap().as_str();
-            let history_number = group_match.parse::<i32>().unwrap();
-            let history_number = if history_number < 0 {
-                (self.cli.history.len() as i32 + history_number) as usize
-            } else {
-                (history_number - 1) as usize
-            };
-            // get that entry from history (if it exists)
-            if let Some(history_cmd) = self.cli.history.get(history_number) {
-                // replace the match with the entry from history
-                processed = processed.replace(full_match, &history_cmd.iter().collect::<String>());
-            } else {
-                return HistoryExpansion::EventNotFound(full_match.into());
+    /// Runs a large script piece by piece, each chunk ends on a command boundary
+    fn run_script_chunked(
+        &mut self,
+        mut reader: impl BufRead,
+        source: &str,
+    ) -> Result<i32, Report> {
+        let mut exit_status = EXIT_SUCCESS;
+        let mut chunk = Vec::new();
+        let mut chunk_first_line = 1;
+        let mut chunk_lines = 0;
+        let mut next_check = SCRIPT_CHUNK_SIZE;
+        let mut warned = false;
+        loop {
+            let eof = reader.read_until(b'\n', &mut chunk)? == 0;
+            if !eof {
+                chunk_lines += 1;
+                if chunk.len() < next_check {
+                    continue;
+                }
+            }
+            if chunk.is_empty() {
+                break;
             }
-        }
 
-        // $ for eg. "!ls"
+            let (input, latin1) = decode_script(chunk.clone());
+            if !eof && !syntax::is_complete(&input) {
+                // inside a long compound command, keep reading
+                next_check = chunk.len() * 2;
+                continue;
+            }
+            if latin1 && !warned {
+                warn_latin1(source);
+                warned = true;
+            }
+            chunk.clear();
+            exit_status = InputInterpreter::from_source(&input, source)
+                .starting_at_line(chunk_first_line)
+                .interpret(self);
+            if eof || exit_status == EXIT_INTERRUPTED {
+                break;
+            }
+            chunk_first_line += chunk_lines;
+            chunk_lines = 0;
+            next_check = SCRIPT_CHUNK_SIZE;
+        }
+        Ok(exit_status)
+    }
+
+    /// Runs script under Debug Adapter Protocol client connected on `fd`
+    pub fn debug_script(&mut self, script_name: impl Into<PathBuf>, fd: Fd) -> Result<i32, Report> {
+        let mut debugger = Debugger::new(fd)?;
+        debugger.wait_for_configuration()?;
+        self.debugger = Some(debugger);
+
+        let result = self.run_script(script_name);
+
+        if let Some(mut debugger) = self.debugger.take() {
+            debugger.finish(*result.as_ref().unwrap_or(&EXIT_FAILURE))?;
+        }
+        result
+    }
+
+    /// Reads a command line, lines ending with a backslash or inside of an
+    /// unfinished construct are followed by more after a `PS2` prompt
+    fn get_line(&mut self, input: &mut String) -> Result<bool, Report> {
+        if !self.read_line()? {
+            return Ok(false);
+        }
+        *input = command_text(&self.cli.input)
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+        Ok(true)
+    }
+
+    /// Expanded `PS2`, or `prompt.ps2` from the config file
+    fn continuation_prompt(&self) -> String {
+        let template = self
+            .var("PS2")
+            .or_else(|| self.config.get_str("prompt.ps2").map(String::from))
+            .unwrap_or_else(|| String::from("> "));
+        self.expand_prompt(&template)
+    }
+
+    /// Reads a command into `self.cli.input`, all of its lines are edited
+    /// together, false when interrupted
+    fn read_line(&mut self) -> Result<bool, Report> {
+        self.load_history();
+        let mut vt_parser = Parser::new();
+        self.cli.reset();
+        self.cli.continuation_prompt = self.continuation_prompt();
+
+        let idle_timeout = self.idle_timeout();
+        let mut last_input = Instant::now();
+        while !self.cli.is_input_ready() {
+            let now = Instant::now();
+            let scheduled = self
+                .scheduler
+                .next_run()
+                .map(|next_run| next_run.saturating_duration_since(now));
+            let idle = idle_timeout.map(|idle| (last_input + idle).saturating_duration_since(now));
+            let timeout = match (scheduled, idle) {
+                (Some(scheduled), Some(idle)) => Some(scheduled.min(idle)),
+                (scheduled, idle) => scheduled.or(idle),
+            };
+            match self.reader.read_byte(timeout)? {
+                ReadEvent::Byte(byte) => {
+                    last_input = Instant::now();
+                    let before = self.cli.input.clone();
+                    vt_parser.advance(&mut self.cli, byte);
+                    if self.cli.take_completion_request() {
+                        self.complete_word();
+                    }
+                    if self.cli.input != before {
+                        self.highlight_input();
+                    }
+                    if self.cli.is_input_ready() {
+                        if command_text(&self.cli.input).is_some() {
+                            self.cli.finish_line();
+                        } else {
+                            self.cli.continue_line();
+                        }
+                    }
+                }
+                #[cfg(target_os = "wasi")]
+                ReadEvent::Interrupted => {
+                    if self.traps.contains_key(&trap::SIGINT) {
+                        trap::raise(trap::SIGINT);
+                        self.run_traps();
+                    }
+                    return Ok(false);
+                }
+                ReadEvent::TimedOut => {
+                    self.run_scheduled_jobs();
+                    if matches!(idle_timeout, Some(idle) if last_input.elapsed() >= idle) {
+                        self.handle_idle_timeout();
+                        last_input = Instant::now();
+                    }
+                }
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Paints the command being edited with the colors of the theme
+    fn highlight_input(&mut self) {
+        if !self.cli.should_echo || self.cli.is_searching() || !self.theme.highlights() {
+            return;
+        }
+        let input: String = self.cli.input.iter().collect();
+        let painted = syntax::highlight(&input, &self.theme);
+        self.cli.repaint(&painted);
+    }
+
+    /// Completes the word before the cursor, or lists the candidates when
+    /// they have nothing more in common
+    fn complete_word(&mut self) {
+        let cursor = self.cli.cursor_position;
+        let line = self.cli.input.clone();
+        let completion = completion::complete(self, &line, cursor);
+        let completed = match completion.candidates.as_slice() {
+            [] => {
+                // bell
+                print!("\x07");
+                io::stdout().flush().unwrap();
+                return;
+            }
+            [only] if only.ends_with('/') => only.clone(),
+            [only] => format!("{only} "),
+            candidates => completion::common_prefix(candidates),
+        };
+        let typed: String = self.cli.input[cursor - completion.word_len..cursor]
+            .iter()
+            .collect();
+        if completed != typed {
+            // undone at once
+            self.cli.begin_undo_group();
+            match completed.strip_prefix(&typed) {
+                Some(rest) => self.cli.insert(rest),
+                None => {
+                    self.cli.delete_before_cursor(completion.word_len);
+                    self.cli.insert(&completed);
+                }
+            }
+            self.cli.end_undo_group();
+            return;
+        }
+        // candidates are listed unless only the bell is wanted
+        if self.config.get_str("completion.style") == Some("bell") {
+            print!("\x07");
+            io::stdout().flush().unwrap();
+            return;
+        }
+        // directories of path candidates are left out
+        let names: Vec<String> = completion
+            .candidates
+            .iter()
+            .map(|candidate| match candidate.strip_suffix('/') {
+                Some(dir) => format!("{}/", dir.rsplit('/').next().unwrap_or(dir)),
+                None => candidate
+                    .rsplit('/')
+                    .next()
+                    .unwrap_or(candidate)
+                    .to_string(),
+            })
+            .collect();
+        println!();
+        println!("{}", names.join("  "));
+        let line: String = self.cli.input.iter().collect();
+        let cursor = self.cli.cursor_position;
+        // the prompt leaves the cursor at the end of the line
+        self.print_prompt(&line);
+        self.cli.move_cursor(cursor);
+        io::stdout().flush().unwrap();
+    }
+
+    /// Time of inactivity at the prompt after which the shell exits, set in
+    /// seconds with `TMOUT`
+    fn idle_timeout(&self) -> Option<Duration> {
+        match self.var("TMOUT")?.trim().parse::<u64>() {
+            Ok(seconds) if seconds > 0 => Some(Duration::from_secs(seconds)),
+            _ => None,
+        }
+    }
+
+    /// Runs the `hooks.idle_timeout` handler if there is one, otherwise exits
+    fn handle_idle_timeout(&mut self) {
+        let interactive = self.termios_mode.is_some();
+        println!();
+        if interactive {
+            _ = self.restore_default_mode();
+        }
+        if self.config.get("hooks.idle_timeout").is_none() {
+            eprintln!("timed out waiting for input: auto-logout");
+            self.exit(EXIT_SUCCESS);
+        }
+        self.run_hooks("idle_timeout");
+        if interactive {
+            _ = self.enable_interpreter_mode();
+        }
+        let line: String = self.cli.input.iter().collect();
+        self.print_prompt(&line);
+    }
+
+    /// Runs `every`/`at` jobs that are due, then redraws the prompt and the
+    /// line being edited
+    fn run_scheduled_jobs(&mut self) {
+        let due = self.scheduler.take_due(Instant::now());
+        if due.is_empty() {
+            return;
+        }
+        let interactive = self.termios_mode.is_some();
+        let exit_status = self.last_exit_status;
+        println!();
+        if interactive {
+            _ = self.restore_default_mode();
+        }
+        for command in due {
+            if let Err(error) = self.run_command(&command) {
+                eprintln!("{}: {}: {}", env!("CARGO_PKG_NAME"), command, error);
+            }
+        }
+        if interactive {
+            _ = self.enable_interpreter_mode();
+        }
+        self.last_exit_status = exit_status;
+        let line: String = self.cli.input.iter().collect();
+        self.print_prompt(&line);
+    }
+
+    /// Expands input line with history expansion.
+    fn history_expansion(&mut self, input: &str) -> HistoryExpansion {
+        // avoid compiling the regexes when there is nothing to expand
+        if !input.contains('!') {
+            return HistoryExpansion::Unchanged;
+        }
+        let mut processed = input.to_string();
+        if let Some(last_command) = self.cli.history.last() {
+            processed = processed.replace("!!", &last_command.iter().collect::<String>());
+        }
+        // for eg. "!12", "!-2"
+        lazy_static! {
+            static ref NUMBER_RE: Regex = Regex::new(r"(?:^|[^\[])!(-?\d+)").unwrap();
+        }
+        // for each match
+        for captures in NUMBER_RE.captures_iter(input) {
+            // get matched number
+            let full_match = captures.get(0).unwrap().as_str();
+            let group_match = captures.get(1).unwrap().as_str();
+            let history_number = group_match.parse::<i32>().unwrap();
+            let history_number = if history_number < 0 {
+                (self.cli.history.len() as i32 + history_number) as usize
+            } else {
+                (history_number - 1) as usize
+            };
+            // get that entry from history (if it exists)
+            if let Some(history_cmd) = self.cli.history.get(history_number) {
+                // replace the match with the entry from history
+                processed = processed.replace(full_match, &history_cmd.iter().collect::<String>());
+            } else {
+                return HistoryExpansion::EventNotFound(full_match.into());
+            }
+        }
+
+        // $ for eg. "!ls"
         lazy_static! {
             static ref STRING_RE: Regex = Regex::new(r"(?:^|[^\[])!(\w+)").unwrap();
         }
@@ -676,37 +1809,80 @@ impl Shell {
             });
         }
 
-        if PathBuf::from(&self.history_path).exists() {
-            self.cli.history = fs::read_to_string(&self.history_path)
-                .unwrap()
-                .lines()
-                .map(|line| line.chars().collect::<Vec<char>>())
-                .collect::<Vec<Vec<char>>>();
+        let legacy_history = xdg::legacy_file("_history");
+        if let Err(error) = xdg::migrate(&legacy_history, &self.history_path).and_then(|_| {
+            fs::create_dir_all(self.history_path.parent().unwrap_or_else(|| Path::new("/")))
+        }) {
+            eprintln!(
+                "{}: cannot use {}: {}",
+                env!("CARGO_PKG_NAME"),
+                self.history_path.display(),
+                error
+            );
+            self.history_path = legacy_history;
+        }
+        // history is not needed before the first prompt, read it in the background
+        #[cfg(not(target_os = "wasi"))]
+        {
+            let history_path = self.history_path.clone();
+            self.history_state = HistoryState::Loading(thread::spawn(move || {
+                fs::read(history_path).unwrap_or_default()
+            }));
         }
 
-        let washrc_path = {
-            if PathBuf::from(env::var("HOME").unwrap()).exists() {
-                format!(
-                    "{}/.{}rc",
-                    env::var("HOME").unwrap(),
-                    env!("CARGO_PKG_NAME")
-                )
-            } else {
-                format!("{}/.{}rc", env::var("PWD").unwrap(), env!("CARGO_PKG_NAME"))
+        let started = Instant::now();
+        let config_path = xdg::config_file();
+        if config_path.exists() {
+            match Config::load(&config_path) {
+                Ok(config) => {
+                    for key in config.unknown_keys() {
+                        eprintln!(
+                            "{}: {}: unknown key `{}'",
+                            env!("CARGO_PKG_NAME"),
+                            config_path.display(),
+                            key
+                        );
+                    }
+                    self.config = config;
+                }
+                Err(error) => eprintln!("{}: {}", env!("CARGO_PKG_NAME"), error),
             }
-        };
-        if PathBuf::from(&washrc_path).exists() {
-            self.run_script(washrc_path).unwrap();
+        }
+        self.apply_config();
+        // washrc can redefine or remove them
+        let aliases: Vec<(String, String)> = self
+            .config
+            .table("aliases")
+            .filter_map(|(name, value)| match value {
+                ConfigValue::String(value) => Some((name.to_string(), value.clone())),
+                _ => None,
+            })
+            .collect();
+        self.aliases.extend(aliases);
+        self.profile_startup_phase("config load", started);
+
+        let started = Instant::now();
+        self.source_rc_files();
+        self.dir_env.enable(&self.pwd.clone());
+        self.profile_startup_phase("rc files", started);
+
+        // otherwise the profile would miss it, as it is loaded after the prompt
+        if self.startup_profile.is_some() {
+            self.load_history();
         }
 
+        let started = Instant::now();
         let motd_path = PathBuf::from("/etc/motd");
         if motd_path.exists() {
-            println!("{}", fs::read_to_string(motd_path).unwrap());
+            println!("{}", fs::read_to_string(motd_path).unwrap_or_default());
         }
+        self.profile_startup_phase("motd", started);
 
         let mut input = String::new();
         // line loop
         loop {
+            self.report_startup_profile("first prompt");
+            self.notify_jobs();
             self.print_prompt(&input);
             if !self.get_line(&mut input)? {
                 self.last_exit_status = EXIT_INTERRUPTED;
@@ -731,43 +1907,707 @@ impl Shell {
                         self.restore_default_mode()?;
                     }
 
-                    if let Err(error) = self.handle_input(&input) {
-                        eprintln!("{error:#?}");
-                    };
+                    // Ctrl-C pressed at the prompt must not stop this line
+                    trap::take(trap::SIGINT);
+                    let started = Instant::now();
+                    self.run_line(&input);
+                    self.notify_completion(&input, started.elapsed());
 
                     if let Ok(true) = is_fd_tty(STDIN) {
                         self.enable_interpreter_mode()?;
                     }
                 }
             }
-            match OpenOptions::new()
-                .create(true)
-                .append(true)
-                .open(&self.history_path)
-            {
-                Ok(mut file) => {
-                    let vectored_input = input.chars().collect::<Vec<char>>();
-                    if Some(&vectored_input) != self.cli.history.last() {
-                        self.cli.history.push(vectored_input);
-                        writeln!(file, "{}", &input).unwrap();
-                    }
-                }
-                Err(error) => {
+            let vectored_input = input.chars().collect::<Vec<char>>();
+            if Some(&vectored_input) != self.cli.history.last() {
+                self.cli.history.push(vectored_input);
+                self.truncate_history();
+                if let Err(error) = self.append_history(&input) {
                     eprintln!(
-                        "Unable to open file for storing {} history: {}",
+                        "Unable to store {} history in {}: {}",
                         env!("CARGO_PKG_NAME"),
+                        self.history_path.display(),
                         error
                     );
                 }
-            };
+            }
             input.clear();
         }
     }
 
+    /// Rings the bell and/or sends an OSC 9 desktop notification when a
+    /// command line ran longer than `notify.after` seconds, off while it is
+    /// not set
+    fn notify_completion(&self, command: &str, elapsed: Duration) {
+        let after = match self.config.get_seconds("notify.after") {
+            Some(after) => after,
+            None => return,
+        };
+        if after <= 0.0 || elapsed.as_secs_f64() < after || !matches!(is_fd_tty(STDOUT), Ok(true)) {
+            return;
+        }
+        let (bell, osc) = match self.config.get_str("notify.style").unwrap_or("both") {
+            "bell" => (true, false),
+            "osc9" => (false, true),
+            _ => (true, true),
+        };
+        let mut notification = String::new();
+        if osc {
+            // control characters would terminate the sequence early
+            let command: String = command
+                .chars()
+                .take(NOTIFY_COMMAND_LEN)
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            notification.push_str(&format!(
+                "\x1b]9;{} finished with exit status {} after {}\x07",
+                command,
+                self.last_exit_status,
+                schedule::format_duration(elapsed)
+            ));
+        }
+        if bell {
+            notification.push('\x07');
+        }
+        print!("{notification}");
+        _ = io::stdout().flush();
+    }
+
+    /// Runs a command line read by the interactive loop, a panic aborts only
+    /// that command line
+    #[cfg(not(target_os = "wasi"))]
+    fn run_line(&mut self, input: &str) {
+        match panic::catch_unwind(AssertUnwindSafe(|| self.handle_input(input))) {
+            Ok(Ok(_)) => {}
+            Ok(Err(error)) => eprintln!("{error:#?}"),
+            Err(payload) => self.recover_from_panic(payload),
+        }
+    }
+
+    /// Runs a command line read by the interactive loop. WASI builds abort on
+    /// panic, so nothing is caught there.
+    #[cfg(target_os = "wasi")]
+    fn run_line(&mut self, input: &str) {
+        if let Err(error) = self.handle_input(input) {
+            eprintln!("{error:#?}");
+        }
+    }
+
+    /// Reports a panic caught in the interactive loop and resets state the
+    /// aborted command may have left behind
+    #[cfg(not(target_os = "wasi"))]
+    fn recover_from_panic(&mut self, payload: Box<dyn std::any::Any + Send>) {
+        let reason = payload
+            .downcast_ref::<&str>()
+            .map(|reason| reason.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown error"));
+        eprintln!(
+            "{}: internal error: {}; command aborted",
+            env!("CARGO_PKG_NAME"),
+            reason
+        );
+        self.guard.reset();
+        self.in_hook = false;
+        self.last_exit_status = EXIT_FAILURE;
+    }
+
+    /// Sources system, user and trusted per-project rc files, in that order
+    fn source_rc_files(&mut self) {
+        let user_rc = xdg::rc_file();
+        for rc in [PathBuf::from(SYSTEM_RC_PATH), user_rc.clone()] {
+            if rc.exists() {
+                self.source_rc(rc);
+            }
+        }
+
+        let project_rc = self.pwd.join(format!(".{}rc", env!("CARGO_PKG_NAME")));
+        if self.config.get_bool("rc.project") == Some(false)
+            || !project_rc.exists()
+            || fs::canonicalize(&project_rc).ok() == fs::canonicalize(&user_rc).ok()
+        {
+            return;
+        }
+        let mut trust_store = TrustStore::load();
+        let trusted = match trust_store.check(&project_rc) {
+            Trust::Allowed => true,
+            Trust::Denied => false,
+            Trust::Unknown => self.ask_trust(&mut trust_store, &project_rc),
+        };
+        if trusted {
+            self.source_rc(project_rc);
+        }
+    }
+
+    fn source_rc(&mut self, path: PathBuf) {
+        if let Err(error) = self.run_script(&path) {
+            eprintln!("{}: {}: {}", env!("CARGO_PKG_NAME"), path.display(), error);
+        }
+    }
+
+    /// Path for messages printed directly to stderr, see `OutputDevice::file_link`
+    pub fn stderr_file_link(&self, path: &Path) -> String {
+        let mut output_device = OutputDevice::new();
+        output_device.set_hyperlinks(self.hyperlinks);
+        output_device.file_link(STDERR, path)
+    }
+
+    /// Asks whether an unknown file may be sourced, remembers "yes" and "never" answers
+    fn ask_trust(&mut self, trust_store: &mut TrustStore, path: &Path) -> bool {
+        if !matches!(is_fd_tty(STDIN), Ok(true)) {
+            eprintln!(
+                "{}: {}: not trusted, skipping",
+                env!("CARGO_PKG_NAME"),
+                path.display()
+            );
+            return false;
+        }
+
+        eprint!(
+            "{}: {} is not trusted. Source it? [y/N/never] ",
+            env!("CARGO_PKG_NAME"),
+            self.stderr_file_link(path)
+        );
+        _ = io::stderr().flush();
+        let mut answer = String::new();
+        _ = self.restore_default_mode();
+        let read = io::stdin().read_line(&mut answer);
+        _ = self.enable_interpreter_mode();
+        if read.is_err() {
+            return false;
+        }
+
+        let result = match answer.trim() {
+            "y" | "yes" => trust_store.allow(path).map(|_| true),
+            "never" => trust_store.deny(path).map(|_| false),
+            _ => return false,
+        };
+        result.unwrap_or_else(|error| {
+            eprintln!(
+                "{}: cannot update trusted files: {}",
+                env!("CARGO_PKG_NAME"),
+                error
+            );
+            answer.trim() != "never"
+        })
+    }
+
+    /// Makes sure history file contents are in `cli.history`, waits for the
+    /// background reader if it is still running
+    pub fn load_history(&mut self) {
+        let started = Instant::now();
+        let content = match mem::replace(&mut self.history_state, HistoryState::Loaded) {
+            HistoryState::Loaded => return,
+            HistoryState::Unloaded => fs::read(&self.history_path).unwrap_or_default(),
+            #[cfg(not(target_os = "wasi"))]
+            HistoryState::Loading(reader) => reader.join().unwrap_or_default(),
+        };
+        let (history, rewrite) = parse_history(&content);
+        self.cli.history = history;
+        let truncated = self.truncate_history();
+        if rewrite || truncated {
+            if let Err(error) = self.rewrite_history() {
+                eprintln!(
+                    "{}: cannot rewrite {}: {}",
+                    env!("CARGO_PKG_NAME"),
+                    self.history_path.display(),
+                    error
+                );
+            }
+        }
+        self.profile_startup_phase("history", started);
+    }
+
+    /// Appends a single entry, synced so it survives the runtime being killed
+    fn append_history(&self, line: &str) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.history_path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(format!("{HISTORY_HEADER}\n").as_bytes())?;
+        }
+        file.write_all(history_line(line).as_bytes())?;
+        file.sync_data()
+    }
+
+    /// Replaces history file with in-memory history
+    fn rewrite_history(&self) -> io::Result<()> {
+        let mut content = format!("{HISTORY_HEADER}\n");
+        content.extend(
+            self.cli
+                .history
+                .iter()
+                .map(|entry| history_line(&entry.iter().collect::<String>())),
+        );
+        xdg::write_atomic(&self.history_path, content.as_bytes())
+    }
+
+    fn profile_startup_phase(&mut self, phase: &str, started: Instant) {
+        if let Some(profile) = &mut self.startup_profile {
+            profile.record(phase, started.elapsed());
+        }
+    }
+
+    /// Prints the `--profile-startup` report to stderr once, with the total
+    /// time until `milestone`
+    pub fn report_startup_profile(&mut self, milestone: &str) {
+        if let Some(profile) = self.startup_profile.take() {
+            eprint!("{}", profile.report(milestone));
+        }
+    }
+
+    /// Records a child started in the background, `$!` expands to its pid
+    pub fn add_job(&mut self, pid: u32, command: String) -> usize {
+        self.last_job_pid = Some(pid);
+        self.jobs.add(pid, command)
+    }
+
+    /// Reports background jobs that finished since the last prompt
+    fn notify_jobs(&mut self) {
+        for (job, exit_status) in self.jobs.reap() {
+            match exit_status {
+                Ok(exit_status) => eprintln!(
+                    "[{}]  {:<10} {}",
+                    job.id,
+                    internals::job_state(exit_status),
+                    job.command
+                ),
+                Err(error) => eprintln!("{}: {}", env!("CARGO_PKG_NAME"), error),
+            }
+        }
+    }
+
+    /// Called by `cd` after the working directory has changed
+    pub fn chpwd(&mut self) {
+        self.dir_env.update(&self.pwd.clone());
+        // like z, home and root are not worth jumping to
+        let home = env::var("HOME").unwrap_or_default();
+        if self.is_interactive()
+            && self.pwd != Path::new(&home)
+            && self.pwd != Path::new("/")
+            && self.dir_visits.push(&self.pwd)
+        {
+            self.save_dir_visits();
+        }
+        print!("{}", self.terminal_state());
+        _ = io::stdout().flush();
+        self.run_hooks("chpwd");
+    }
+
+    /// Writes directories entered so far to the database used by `z`
+    pub fn save_dir_visits(&mut self) {
+        if let Err(error) = self.dir_visits.save() {
+            eprintln!(
+                "{}: cannot update directory history: {}",
+                env!("CARGO_PKG_NAME"),
+                error
+            );
+        }
+    }
+
+    /// Runs commands configured under `hooks.<NAME>`, preserving `$?`
+    pub fn run_hooks(&mut self, name: &str) {
+        if self.in_hook {
+            return;
+        }
+        let commands: Vec<String> = match self.config.get(&format!("hooks.{name}")) {
+            Some(ConfigValue::String(command)) => vec![command.clone()],
+            Some(ConfigValue::Array(items)) => items
+                .iter()
+                .filter_map(|item| match item {
+                    ConfigValue::String(command) => Some(command.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => return,
+        };
+
+        let exit_status = self.last_exit_status;
+        self.in_hook = true;
+        for command in commands {
+            if let Err(error) = self.run_command(&command) {
+                eprintln!("{}: {} hook: {}", env!("CARGO_PKG_NAME"), name, error);
+            }
+        }
+        self.in_hook = false;
+        self.last_exit_status = exit_status;
+    }
+
+    /// Asks a yes/no question on the terminal, answers no when stdin is not a TTY
+    pub fn confirm(&self, question: &str) -> bool {
+        if !matches!(is_fd_tty(STDIN), Ok(true)) {
+            return false;
+        }
+        eprint!("{question} [y/N] ");
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).is_ok() && matches!(answer.trim(), "y" | "yes")
+    }
+
+    /// Offers a builtin or `PATH` command close to a misspelled `command`
+    /// when `shopt -s correct` is on
+    fn correct_command(&self, command: &str) -> Option<String> {
+        if !self.shopt.correct || command.contains('/') {
+            return None;
+        }
+        let mut candidates: Vec<String> =
+            INTERNALS_MAP.keys().map(|name| name.to_string()).collect();
+        for bin_dir in env::var("PATH").unwrap_or_default().split(':') {
+            if let Ok(entries) = fs::read_dir(bin_dir) {
+                candidates.extend(
+                    entries
+                        .filter_map(Result::ok)
+                        .filter_map(|entry| entry.file_name().into_string().ok()),
+                );
+            }
+        }
+        let suggestion = spell::suggest(command, candidates)?;
+        self.confirm(&format!(
+            "{}: did you mean '{}'?",
+            env!("CARGO_PKG_NAME"),
+            suggestion
+        ))
+        .then_some(suggestion)
+    }
+
+    /// Runs the `hooks.command_not_found` handler with the attempted command
+    /// line appended, `None` when no handler is configured
+    fn command_not_found(&mut self, command: &str, args: &[String]) -> Option<i32> {
+        if self.in_hook {
+            return None;
+        }
+        let handler = match self.config.get("hooks.command_not_found") {
+            Some(ConfigValue::String(handler)) => handler.clone(),
+            _ => return None,
+        };
+        let command_line = std::iter::once(command)
+            .chain(args.iter().map(String::as_str))
+            .map(shell_quote)
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        self.in_hook = true;
+        let result = self.run_command(&format!("{handler} {command_line}"));
+        self.in_hook = false;
+        Some(result.unwrap_or_else(|error| {
+            eprintln!(
+                "{}: command_not_found hook: {}",
+                env!("CARGO_PKG_NAME"),
+                error
+            );
+            EXIT_FAILURE
+        }))
+    }
+
+    /// Whether the shell reads commands from a terminal
+    pub fn is_interactive(&self) -> bool {
+        self.termios_mode.is_some()
+    }
+
+    /// Exits the shell process with `exit_status` after running the `EXIT`
+    /// trap
+    pub fn exit(&mut self, exit_status: i32) -> ! {
+        if let Some(command) = self.traps.remove(&trap::EXIT) {
+            self.last_exit_status = exit_status;
+            self.run_trap(&command);
+        }
+        self.report_profile();
+        // forked subshells leave these to the shell that created them
+        if shell_pid() == self.pid {
+            self.save_dir_visits();
+            for path in self.heredocs.drain(..) {
+                let _ = fs::remove_file(path);
+            }
+        }
+        std::process::exit(exit_status);
+    }
+
+    /// Runs traps of signals received since the last check
+    pub fn run_traps(&mut self) {
+        for signal in trap::take_pending() {
+            match self.traps.get(&signal).filter(|c| !c.is_empty()).cloned() {
+                Some(command) => self.run_trap(&command),
+                // left for `interrupted`, which stops the running loop
+                None if signal == trap::SIGINT => trap::raise(signal),
+                None => {}
+            }
+        }
+    }
+
+    /// Runs a trap command, preserving `$?`
+    fn run_trap(&mut self, command: &str) {
+        let exit_status = self.last_exit_status;
+        if let Err(error) = self.run_command(command) {
+            eprintln!("{}: trap: {}", env!("CARGO_PKG_NAME"), error);
+        }
+        self.last_exit_status = exit_status;
+    }
+
+    /// Drops traps that catch signals, used by subshells which start with
+    /// default handlers. Ignored signals stay ignored.
+    pub fn reset_traps(&mut self) {
+        self.traps.retain(|signal, command| {
+            if command.is_empty() {
+                return true;
+            }
+            let _ = trap::set_disposition(*signal, trap::Disposition::Default);
+            false
+        });
+    }
+
+    /// Prints the `--profile` report to stderr, called once before exiting
+    pub fn report_profile(&mut self) {
+        if !self.profile.is_empty() {
+            eprint!("{}", mem::take(&mut self.profile).report());
+        }
+    }
+
+    /// Applies runtime configuration values to the shell state
+    pub fn apply_config(&mut self) {
+        self.truncate_history();
+        self.guard.limits = Limits::from_config(&self.config);
+        self.hyperlinks = self
+            .config
+            .get_bool("terminal.hyperlinks")
+            .unwrap_or_else(terminal::supports_hyperlinks);
+        match Theme::from_config(&self.config) {
+            Ok(theme) => self.theme = theme,
+            Err(error) => eprintln!("{}: {}", env!("CARGO_PKG_NAME"), error),
+        }
+        self.cli.reset_bindings();
+        for (key, value) in self.config.table("keybindings") {
+            let action = match value {
+                ConfigValue::String(action) => cli::Action::parse(action),
+                _ => None,
+            };
+            match (cli::parse_key(key), action) {
+                (Some(key), Some(action)) => self.cli.bind(key, action),
+                (None, _) => eprintln!(
+                    "{}: keybindings.{}: only control keys like ctrl-a can be bound",
+                    env!("CARGO_PKG_NAME"),
+                    key
+                ),
+                (_, None) => eprintln!(
+                    "{}: keybindings.{}: unknown action {}",
+                    env!("CARGO_PKG_NAME"),
+                    key,
+                    value
+                ),
+            }
+        }
+    }
+
+    /// Applies `history.size` limit, returns whether any entries were dropped
+    fn truncate_history(&mut self) -> bool {
+        if let Some(size) = self.config.get_int("history.size") {
+            let size = size.max(0) as usize;
+            if self.cli.history.len() > size {
+                let excess = self.cli.history.len() - size;
+                self.cli.history.drain(..excess);
+                return true;
+            }
+        }
+        false
+    }
+
     fn handle_input(&mut self, input: &str) -> Result<i32, Report> {
-        // TODO: define and use constructor
-        let mut interpreter = InputInterpreter::from_input(input);
-        Ok(interpreter.interpret(self))
+        self.guarded(|shell| {
+            // TODO: define and use constructor
+            let mut interpreter = InputInterpreter::from_input(input);
+            Ok(interpreter.interpret(shell))
+        })
+    }
+
+    /// Runs script or command string under resource limits
+    fn guarded(
+        &mut self,
+        run: impl FnOnce(&mut Shell) -> Result<i32, Report>,
+    ) -> Result<i32, Report> {
+        let result = match self.guard.enter() {
+            Ok(()) => run(self),
+            Err(reason) => {
+                self.guard.trip(&reason);
+                Ok(EXIT_INTERRUPTED)
+            }
+        };
+        self.guard.leave();
+        result
+    }
+
+    /// Timeout of the next foreground command: set by the `timeout` keyword
+    /// or taken from the `WASH_COMMAND_TIMEOUT` shell variable. The
+    /// environment is not consulted, so the setting does not carry over to
+    /// shells started by commands.
+    fn foreground_timeout(&self) -> Option<Duration> {
+        self.command_timeout
+            .or_else(|| {
+                self.vars
+                    .get("WASH_COMMAND_TIMEOUT")
+                    .and_then(|timeout| parse_duration(timeout))
+            })
+            .filter(|timeout| !timeout.is_zero())
+    }
+
+    /// Handles `timeout DURATION COMMAND [ARG]...`, other arguments are passed
+    /// to the `timeout` utility if one is installed
+    fn execute_with_timeout(
+        &mut self,
+        args: &mut Vec<String>,
+        env: &HashMap<String, String>,
+        background: bool,
+        redirects: &[Redirect],
+    ) -> Result<i32, Report> {
+        let timeout = match args.first().map(|arg| parse_duration(arg)) {
+            Some(Some(timeout)) if args.len() > 1 => timeout,
+            parsed => {
+                // options and other usages of GNU timeout are left to it
+                if let Some(path) = self.lookup_command("timeout")? {
+                    let path = path.display().to_string();
+                    return self.execute_command(&path, args, env, background, redirects);
+                }
+                if parsed == Some(None) {
+                    eprintln!("timeout: {}: invalid time interval", args[0]);
+                } else {
+                    eprintln!("timeout: help: timeout <DURATION> <COMMAND> [<ARG>] ...");
+                }
+                return Ok(EXIT_FAILURE);
+            }
+        };
+        let mut command_args = args.split_off(2);
+        let command = args.remove(1);
+        self.command_timeout = Some(timeout);
+        let result = self.execute_command(&command, &mut command_args, env, background, redirects);
+        self.command_timeout = None;
+        result
+    }
+
+    /// Handles `command [-p] COMMAND [ARG]...`, which runs COMMAND without
+    /// looking it up in aliases. Describing commands with `-v` and `-V` is
+    /// left to the internal.
+    fn execute_command_prefix(
+        &mut self,
+        args: &mut [String],
+        env: &HashMap<String, String>,
+        background: bool,
+        redirects: &[Redirect],
+    ) -> Option<Result<i32, Report>> {
+        let matches = internals::COMMAND_SPEC.try_parse(args).ok()?;
+        if matches.flag("print") || matches.flag("describe") || matches.operands.is_empty() {
+            return None;
+        }
+        let saved_path = if matches.flag("default-path") {
+            self.command_path.replace(String::from(DEFAULT_PATH))
+        } else {
+            self.command_path.clone()
+        };
+        let mut command_args = matches.operands;
+        let command = command_args.remove(0);
+        let result = self.execute_command(&command, &mut command_args, env, background, redirects);
+        self.command_path = saved_path;
+        Some(result)
+    }
+
+    /// Handles `builtin NAME [ARG]...`, which runs the internal NAME even
+    /// when an alias of the same name exists
+    fn execute_builtin(
+        &mut self,
+        args: &mut Vec<String>,
+        env: &HashMap<String, String>,
+        background: bool,
+        redirects: &[Redirect],
+    ) -> Result<i32, Report> {
+        if args.is_empty() {
+            return Ok(EXIT_SUCCESS);
+        }
+        let name = args.remove(0);
+        if !INTERNALS_MAP.contains_key(name.as_str()) && !PREFIX_INTERNALS.contains(&name.as_str())
+        {
+            eprintln!("builtin: {name}: {}", tr("not a shell builtin"));
+            return Ok(EXIT_FAILURE);
+        }
+        // internals are looked up first, nothing else can run instead
+        self.execute_command(&name, args, env, background, redirects)
+    }
+
+    /// Files that may run for `command`: the absolute path, the path relative
+    /// to the working directory when it starts with `.`, otherwise the command
+    /// in each directory of `PATH`
+    pub fn command_candidates(&self, command: &str) -> Vec<PathBuf> {
+        if command.starts_with('/') {
+            vec![PathBuf::from(command)]
+        } else if command.starts_with('.') {
+            vec![self.pwd.join(command)]
+        } else {
+            let path = match &self.command_path {
+                Some(path) => path.clone(),
+                None => env::var("PATH").unwrap_or_default(),
+            };
+            path.split(':')
+                .map(|bin_dir| PathBuf::from(bin_dir).join(command))
+                .collect()
+        }
+    }
+
+    /// Path of the file run for `command`, the first existing candidate
+    pub fn find_command(&self, command: &str) -> io::Result<Option<PathBuf>> {
+        for candidate in self.command_candidates(command) {
+            // see https://internals.rust-lang.org/t/the-api-of-path-exists-encourages-broken-code/13817/3
+            if path_exists(candidate.to_str().unwrap())? {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Path of the file run for `command`, commands found in `PATH` are
+    /// remembered in `command_hash`
+    pub fn lookup_command(&mut self, command: &str) -> io::Result<Option<PathBuf>> {
+        if command.contains('/') || command.starts_with('.') || self.command_path.is_some() {
+            return self.find_command(command);
+        }
+        if let Some((hashed, _)) = self.command_hash.entries().get(command) {
+            // removed files are searched for again
+            if path_exists(hashed.to_str().unwrap())? {
+                return Ok(Some(hashed.clone()));
+            }
+        }
+        let found = self.find_command(command)?;
+        let entries = self.command_hash.entries();
+        match &found {
+            Some(full_path) => {
+                let hits = entries.get(command).map_or(0, |(_, hits)| *hits);
+                entries.insert(command.to_string(), (full_path.clone(), hits));
+            }
+            None => {
+                entries.remove(command);
+            }
+        }
+        Ok(found)
+    }
+
+    /// Everything running `name` as a command may do, in the order of lookup
+    pub fn command_kinds(&self, name: &str) -> io::Result<Vec<CommandKind>> {
+        let mut kinds = Vec::new();
+        if let Some(value) = self.aliases.get(name) {
+            kinds.push(CommandKind::Alias(value.clone()));
+        }
+        if syntax::RESERVED_WORDS.contains(&name) {
+            kinds.push(CommandKind::Keyword);
+        }
+        if INTERNALS_MAP.contains_key(name) || PREFIX_INTERNALS.contains(&name) {
+            kinds.push(CommandKind::Internal);
+        }
+        for candidate in self.command_candidates(name) {
+            if path_exists(candidate.to_str().unwrap())? {
+                kinds.push(CommandKind::File(candidate));
+            }
+        }
+        Ok(kinds)
+    }
+
+    /// What running `name` as a command does
+    pub fn command_kind(&self, name: &str) -> io::Result<Option<CommandKind>> {
+        Ok(self.command_kinds(name)?.into_iter().next())
     }
 
     pub fn execute_command(
@@ -778,57 +2618,52 @@ impl Shell {
         background: bool,
         redirects: &[Redirect],
     ) -> Result<i32, Report> {
+        // redirects are processed once, by the command that runs
+        if command == "builtin" {
+            return self.execute_builtin(args, env, background, redirects);
+        }
+        if command == "command" {
+            if let Some(result) = self.execute_command_prefix(args, env, background, redirects) {
+                return result;
+            }
+        }
+
         let mut output_device = OutputDevice::new();
-        if let Err(err) = preprocess_redirects(redirects, &mut output_device) {
+        output_device.set_error_style(self.theme.style(Role::Error));
+        output_device.set_hyperlinks(self.hyperlinks);
+        if let Err(err) = preprocess_redirects(redirects, self.noclobber, &mut output_device) {
             output_device.eprintln(format!("{}: {}", env!("CARGO_PKG_NAME"), err).as_str());
             output_device.flush()?;
             return Ok(EXIT_FAILURE);
         }
 
+        if command == "timeout" {
+            return self.execute_with_timeout(args, env, background, redirects);
+        }
+
         let result: Result<i32, Report> = if let Some(internal) = INTERNALS_MAP.get(command) {
+            debug!(target: "spawn", "{}: builtin", command);
             internal(self, args, &mut output_device)
         } else {
-            let full_path = if command.starts_with('/') {
-                let full_path = PathBuf::from(command);
-                if path_exists(full_path.to_str().unwrap())? {
-                    Ok(full_path)
-                } else {
-                    Err(format!(
-                        "{}: no such file or directory",
-                        full_path.display()
-                    ))
-                }
-            } else if command.starts_with('.') {
-                let path = PathBuf::from(&self.pwd);
-                let full_path = path.join(command);
-                if path_exists(full_path.to_str().unwrap())? {
-                    Ok(full_path)
-                } else {
-                    Err(format!(
-                        "{}: no such file or directory",
-                        full_path.display()
-                    ))
-                }
-            } else {
-                let mut found = false;
-                let mut full_path = PathBuf::new();
-                // get PATH env variable, split it and look for binaries in each directory
-                for bin_dir in env::var("PATH").unwrap_or_default().split(':') {
-                    let bin_dir = PathBuf::from(bin_dir);
-                    full_path = bin_dir.join(command);
-                    // see https://internals.rust-lang.org/t/the-api-of-path-exists-encourages-broken-code/13817/3
-                    if path_exists(full_path.to_str().unwrap())? {
-                        found = true;
-                        break;
+            let job_command = std::iter::once(command)
+                .chain(args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" ");
+            let full_path = match self.lookup_command(command)? {
+                Some(full_path) => {
+                    if let Some((_, hits)) = self.command_hash.entries().get_mut(command) {
+                        *hits += 1;
                     }
-                }
-                if found {
                     Ok(full_path)
-                } else {
-                    Err(format!("{command}: command not found"))
                 }
+                None if command.starts_with('/') || command.starts_with('.') => Err(format!(
+                    "{}: no such file or directory",
+                    self.pwd.join(command).display()
+                )),
+                None => Err(format!("{command}: {}", tr("command not found"))),
             };
 
+            debug!(target: "spawn", ?full_path, "{}: resolved", command);
             match full_path {
                 Ok(path) => {
                     let reader_result = match File::open(&path) {
@@ -843,29 +2678,45 @@ impl Shell {
                         let binary_path = if let Some(path) = line.strip_prefix("#!") {
                             path.trim().to_string()
                         } else {
-                            env::var("SHELL").unwrap()
+                            env::var("SHELL").unwrap_or_else(|_| shell_path())
                         };
+                        debug!(target: "spawn", interpreter = %binary_path, "running as script");
                         args.insert(0, binary_path);
                         args.insert(1, path.into_os_string().into_string().unwrap());
                         let args_: Vec<&str> = args.iter().map(|s| &**s).collect();
 
                         // TODO: we should not unwrap here
-                        let (exit_status, child_pid) =
-                            spawn(args_[0], &args_[1..], env, background, redirects).unwrap();
+                        let (exit_status, child_pid) = spawn_with_timeout(
+                            args_[0],
+                            &args_[1..],
+                            env,
+                            background,
+                            redirects,
+                            self.foreground_timeout(),
+                        )
+                        .unwrap();
 
                         if background {
-                            self.last_job_pid = Some(child_pid as u32);
+                            self.add_job(child_pid as u32, job_command);
                         }
 
                         Ok(exit_status)
                     } else {
                         // most likely WASM binary
+                        debug!(target: "spawn", "running as binary");
                         args.insert(0, path.into_os_string().into_string().unwrap());
                         let args_: Vec<&str> = args.iter().map(|s| &**s).collect();
-                        match spawn(args_[0], &args_[1..], env, background, redirects) {
+                        match spawn_with_timeout(
+                            args_[0],
+                            &args_[1..],
+                            env,
+                            background,
+                            redirects,
+                            self.foreground_timeout(),
+                        ) {
                             // nonempty output message means that binary couldn't be executed
                             Err(e) => {
-                                output_device.eprintln(&format!(
+                                output_device.eprintln_error(&format!(
                                     "{}: could not execute binary (os error {})",
                                     env!("CARGO_PKG_NAME"),
                                     e
@@ -874,7 +2725,7 @@ impl Shell {
                             }
                             Ok((exit_status, child_pid)) => {
                                 if background {
-                                    self.last_job_pid = Some(child_pid as u32);
+                                    self.add_job(child_pid as u32, job_command);
                                 }
                                 Ok(exit_status)
                             }
@@ -882,12 +2733,27 @@ impl Shell {
                     }
                 }
                 Err(reason) => {
-                    output_device.eprintln(&format!("{}: {}", env!("CARGO_PKG_NAME"), &reason));
-                    Ok(EXIT_FAILURE)
+                    if let Some(corrected) = self.correct_command(command) {
+                        return self.execute_command(&corrected, args, env, background, redirects);
+                    }
+                    match self.command_not_found(command, args) {
+                        Some(exit_status) => Ok(exit_status),
+                        None => {
+                            output_device.eprintln_error(&format!(
+                                "{}: {}",
+                                env!("CARGO_PKG_NAME"),
+                                &reason
+                            ));
+                            Ok(EXIT_FAILURE)
+                        }
+                    }
                 }
             }
         };
 
+        if let Some(capture) = &mut self.capture {
+            capture.push_str(&output_device.take_stdout());
+        }
         output_device.flush()?;
 
         self.last_exit_status = if let Ok(exit_status) = result {
@@ -932,6 +2798,7 @@ impl Shell {
         // check echo is set, if set then enable internal echo but disable termios echo
         #[cfg(target_os = "wasi")]
         {
+            restore_mode_on_panic(termios_mode);
             self.termios_mode = Some(termios_mode);
             self.cli.should_echo = (termios_mode.c_lflag & termios::ECHO) != 0;
             termios_mode.c_lflag |= termios::ISIG;
@@ -940,10 +2807,15 @@ impl Shell {
 
         #[cfg(not(target_os = "wasi"))]
         {
+            restore_mode_on_panic(termios_mode.clone());
             self.termios_mode = Some(termios_mode.clone());
             self.cli.should_echo = termios_mode.local_flags.contains(termios::LocalFlags::ECHO);
             termios_mode.local_flags |= termios::LocalFlags::ISIG;
             termios_mode.local_flags &= !(termios::LocalFlags::ICANON | termios::LocalFlags::ECHO);
+            // Ctrl-C stops loops of the interactive shell instead of killing it
+            if !self.traps.contains_key(&trap::SIGINT) {
+                _ = trap::set_disposition(trap::SIGINT, trap::Disposition::Catch);
+            }
         }
 
         Shell::set_termios(STDIN, &termios_mode)?;
@@ -962,6 +2834,30 @@ impl Shell {
         Shell::set_termios(STDIN, mode)
     }
 
+    /// Whether SIGINT arrived since the last check, lets loops of internals
+    /// be stopped with Ctrl-C
+    pub fn interrupted(&mut self) -> bool {
+        match &mut self.reader {
+            #[cfg(target_os = "wasi")]
+            InternalReader::StdinWithSigInt(source) => {
+                let interrupted = source.poll_sigint().unwrap_or(false);
+                // a trapped SIGINT runs its trap instead of stopping commands
+                if interrupted && self.traps.contains_key(&trap::SIGINT) {
+                    trap::raise(trap::SIGINT);
+                    return false;
+                }
+                interrupted
+            }
+            // native builds record SIGINT in interactive mode, a trapped one
+            // is left pending for `run_traps`
+            InternalReader::OnlyStdin => {
+                cfg!(not(target_os = "wasi"))
+                    && !self.traps.contains_key(&trap::SIGINT)
+                    && trap::take(trap::SIGINT)
+            }
+        }
+    }
+
     #[cfg(target_os = "wasi")]
     pub fn register_sigint(&mut self) -> Result<(), Report> {
         let event_source = InternalEventSource::default();
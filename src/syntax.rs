@@ -0,0 +1,908 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::iter::Peekable;
+use std::mem;
+
+pub use conch_parser::ast;
+pub use conch_parser::lexer::Lexer;
+pub use conch_parser::parse::{DefaultParser, ParseError, SourcePos};
+pub use conch_parser::token::Token;
+
+use crate::theme::{Role, Style, Theme};
+
+/// Starts the word of a `<<< word` here-string, which conch-parser does not
+/// support. The parser sees it as a `<&` redirect of a word with this prefix.
+pub const HERE_STRING_MARKER: char = '\u{1}';
+
+/// Marks array syntax, which conch-parser does not support, in the words
+/// `Bashisms` rewrites it to
+pub const ARRAY_MARKER: char = '\u{2}';
+/// Starts the word `Bashisms` rewrites an arithmetic command to
+pub const ARITH_MARKER: char = '\u{3}';
+
+/// Starts the word `Bashisms` rewrites `[[` to
+pub const TEST_MARKER: char = '\u{4}';
+/// Starts the target of a `&>` or `&>>` redirection, which `Bashisms`
+/// rewrites to `>` and `>>`
+pub const COMBINED_MARKER: char = '\u{5}';
+/// Encloses the variable name `Bashisms` moves from `{name}>file` before
+/// the target word
+pub const NAMED_FD_MARKER: char = '\u{6}';
+/// Starts the word `Bashisms` rewrites `${name:offset:length}` to
+pub const SUBSTRING_MARKER: char = '\u{7}';
+
+/// Words with a meaning to the parser or the interpreter when they start a
+/// command, reported by `type` and `command -v`
+pub const RESERVED_WORDS: [&str; 20] = [
+    "!", "[[", "]]", "case", "coproc", "do", "done", "elif", "else", "esac", "fi", "for", "if",
+    "in", "then", "time", "until", "while", "{", "}",
+];
+
+/// Rewrites bash syntax to words conch-parser accepts, keeping the length
+/// of the source:
+/// - `((expr))` starting a command becomes `<MARKER>"expr" `
+/// - `[[` starting a command becomes `<MARKER>[`, operators up to the
+///   closing `]]` are literals
+/// - `name=(a b)` and `name+=(a b)` starting a command become the command
+///   `<MARKER>name a b` (or `<MARKER>+name a b`), `(` and `)` are blanks
+/// - `name[sub]=value` starting a command becomes `name<MARKER>sub]=value`
+/// - `${name[sub]}` becomes `${name-<MARKER>sub}`, the `#` of `${#name[sub]}`
+///   and the `!` of `${!name[sub]}` are moved after the marker
+/// - `&>file` and `&>>file` become `><MARKER>file` and `>><MARKER>file`
+/// - `{name}>file` becomes `><MARKER>name<MARKER>file`, likewise for other
+///   redirection operators
+/// - `<<< word` becomes `<&<MARKER>word`, with a marker for each removed
+///   character
+/// - here-document bodies pass unchanged, except for `${name[sub]}` in
+///   bodies with an unquoted delimiter
+pub struct Bashisms<I: Iterator<Item = Token>> {
+    tokens: Peekable<I>,
+    /// Tokens taken by lookahead, they are processed before the rest
+    pending: VecDeque<Token>,
+    single_quoted: bool,
+    double_quoted: bool,
+    escaped: bool,
+    comment: bool,
+    /// Whether the current word starts a command, where assignments are
+    /// recognized
+    command_start: bool,
+    /// Text of the current word so far
+    word: String,
+    /// Depth of parentheses inside of a compound assignment
+    compound: usize,
+    /// Set after a here-document operator until its delimiter is read,
+    /// whether tabs are stripped from the body
+    heredoc: Option<bool>,
+    delimiter: String,
+    /// Whether the delimiter being read is quoted
+    delimiter_quoted: bool,
+    /// Delimiters of bodies following the current line, whether tabs are
+    /// stripped and whether the delimiter is quoted
+    delimiters: VecDeque<(String, bool, bool)>,
+    /// Current line of a here-document body
+    body: Option<String>,
+    /// Whether tokens are inside of `[[ ... ]]`
+    test: bool,
+}
+
+impl<I: Iterator<Item = Token>> Bashisms<I> {
+    fn new(tokens: I) -> Self {
+        Bashisms {
+            tokens: tokens.peekable(),
+            pending: VecDeque::new(),
+            single_quoted: false,
+            double_quoted: false,
+            escaped: false,
+            comment: false,
+            command_start: true,
+            word: String::new(),
+            compound: 0,
+            heredoc: None,
+            delimiter: String::new(),
+            delimiter_quoted: false,
+            delimiters: VecDeque::new(),
+            body: None,
+            test: false,
+        }
+    }
+
+    fn peek(&mut self) -> Option<&Token> {
+        if self.pending.is_empty() {
+            self.tokens.peek()
+        } else {
+            self.pending.front()
+        }
+    }
+
+    fn take(&mut self) -> Option<Token> {
+        self.pending.pop_front().or_else(|| self.tokens.next())
+    }
+
+    /// Operator of `&>` or `&>>`, the `&` becomes `COMBINED_MARKER` before
+    /// the target word
+    fn combined_redirect(&mut self) -> Token {
+        let operator = self.take().expect("the operator was peeked");
+        let mut taken = Vec::new();
+        while let Some(Token::Whitespace(_)) = self.peek() {
+            taken.extend(self.take());
+        }
+        taken.push(Token::Literal(COMBINED_MARKER.to_string()));
+        self.restore(taken);
+        operator
+    }
+
+    /// Operator of `{name}>file` and the like, `{name}` is moved before the
+    /// target word enclosed in `NAMED_FD_MARKER`s
+    fn named_fd(&mut self, token: Token) -> Token {
+        let mut taken = Vec::new();
+        let name = match self.take() {
+            Some(Token::Name(name)) => name,
+            other => {
+                self.restore(other.into_iter().collect());
+                return token;
+            }
+        };
+        taken.push(Token::Name(name.clone()));
+        match self.take() {
+            Some(Token::CurlyClose) => taken.push(Token::CurlyClose),
+            other => {
+                taken.extend(other);
+                self.restore(taken);
+                return token;
+            }
+        }
+        let operator = match self.peek() {
+            Some(
+                Token::Less
+                | Token::Great
+                | Token::DGreat
+                | Token::LessAnd
+                | Token::GreatAnd
+                | Token::LessGreat
+                | Token::Clobber,
+            ) => self.take().unwrap(),
+            _ => {
+                self.restore(taken);
+                return token;
+            }
+        };
+        let mut taken = Vec::new();
+        while let Some(Token::Whitespace(_)) = self.peek() {
+            taken.extend(self.take());
+        }
+        taken.push(Token::Literal(format!(
+            "{NAMED_FD_MARKER}{name}{NAMED_FD_MARKER}"
+        )));
+        self.restore(taken);
+        operator
+    }
+
+    /// Puts back tokens taken by a lookahead that did not match
+    fn restore(&mut self, taken: Vec<Token>) {
+        for token in taken.into_iter().rev() {
+            self.pending.push_front(token);
+        }
+    }
+
+    /// Tokens up to the `]` closing a subscript, `None` when the word ends
+    /// first. Keys of associative arrays may be quoted.
+    fn subscript(&mut self, taken: &mut Vec<Token>) -> Option<Vec<Token>> {
+        let mut subscript = Vec::new();
+        let (mut single_quoted, mut double_quoted) = (false, false);
+        loop {
+            let token = self.take()?;
+            taken.push(token.clone());
+            match token {
+                Token::SingleQuote if !double_quoted => single_quoted = !single_quoted,
+                Token::DoubleQuote if !single_quoted => double_quoted = !double_quoted,
+                _ if single_quoted || double_quoted => {}
+                Token::SquareClose => return Some(subscript),
+                Token::Whitespace(_)
+                | Token::Newline
+                | Token::Semi
+                | Token::Amp
+                | Token::Pipe
+                | Token::AndIf
+                | Token::OrIf
+                | Token::CurlyClose => return None,
+                _ => {}
+            }
+            subscript.push(token);
+        }
+    }
+
+    /// Handles `name=(`, `name+=(` and `name[sub]=` at the start of a command
+    fn assignment(&mut self, name: String) -> Token {
+        let mut taken = Vec::new();
+        let append = match self.take() {
+            Some(Token::Equals) => {
+                taken.push(Token::Equals);
+                ""
+            }
+            Some(Token::Plus) if self.peek() == Some(&Token::Equals) => {
+                taken.extend([Token::Plus, self.take().unwrap()]);
+                "+"
+            }
+            Some(Token::SquareOpen) => {
+                taken.push(Token::SquareOpen);
+                let subscript = self.subscript(&mut taken);
+                let assigned = match self.peek() {
+                    Some(Token::Equals) => true,
+                    Some(Token::Plus) => {
+                        let plus = self.take().unwrap();
+                        let assigned = self.peek() == Some(&Token::Equals);
+                        self.pending.push_front(plus);
+                        assigned
+                    }
+                    _ => false,
+                };
+                if subscript.is_none() || !assigned {
+                    self.restore(taken);
+                    return Token::Name(name);
+                }
+                // `[` is taken over by the marker
+                self.restore(taken.split_off(1));
+                return Token::Literal(format!("{name}{ARRAY_MARKER}"));
+            }
+            token => {
+                self.restore(token.into_iter().collect());
+                return Token::Name(name);
+            }
+        };
+        if self.peek() != Some(&Token::ParenOpen) {
+            self.restore(taken);
+            return Token::Name(name);
+        }
+        // `(` becomes a blank separating the elements from the name
+        self.take();
+        self.pending
+            .push_front(Token::Whitespace(String::from(" ")));
+        self.compound = 1;
+        Token::Literal(format!("{ARRAY_MARKER}{append}{name}"))
+    }
+
+    /// Rewrites `((expr))` after the first `(`, which is returned. `expr` is
+    /// double quoted so that it is expanded but neither split nor globbed.
+    fn arith_command(&mut self, open: Token) -> Token {
+        let mut taken = vec![self.take().unwrap()];
+        let mut depth = 0;
+        loop {
+            let token = match self.take() {
+                Some(token) => token,
+                None => {
+                    self.restore(taken);
+                    return open;
+                }
+            };
+            taken.push(token.clone());
+            match token {
+                Token::ParenOpen => depth += 1,
+                Token::ParenClose if depth > 0 => depth -= 1,
+                Token::ParenClose if self.peek() == Some(&Token::ParenClose) => break,
+                // `((cmd) )` is a nested subshell
+                Token::ParenClose => {
+                    self.restore(taken);
+                    return open;
+                }
+                _ => {}
+            }
+        }
+        self.take();
+        // both `(` and the first `)` are in `taken`
+        let body = taken[1..taken.len() - 1].to_vec();
+        let mut rewritten = vec![Token::DoubleQuote];
+        rewritten.extend(body);
+        rewritten.extend([Token::DoubleQuote, Token::Whitespace(String::from(" "))]);
+        self.restore(rewritten);
+        Token::Literal(ARITH_MARKER.to_string())
+    }
+
+    /// Rewrites `[[ ` after the first `[`, which is returned
+    fn test_start(&mut self, open: Token) -> Token {
+        let second = self.take().unwrap();
+        if !matches!(self.peek(), Some(Token::Whitespace(_))) {
+            self.pending.push_front(second);
+            return open;
+        }
+        self.test = true;
+        Token::Literal(format!("{TEST_MARKER}["))
+    }
+
+    /// Whether `]` starts the `]]` closing a test
+    fn test_end(&mut self) -> bool {
+        let second = match self.take() {
+            Some(second) => second,
+            None => return false,
+        };
+        let end = second == Token::SquareClose
+            && matches!(
+                self.peek(),
+                None | Some(
+                    Token::Whitespace(_)
+                        | Token::Newline
+                        | Token::Semi
+                        | Token::Amp
+                        | Token::Pipe
+                        | Token::AndIf
+                        | Token::OrIf
+                        | Token::ParenClose
+                )
+            );
+        self.pending.push_front(second);
+        end
+    }
+
+    /// Rewrites `{name[sub]` and `{name:offset` following a `$`, the closing
+    /// `}` is left in place
+    fn subscript_expansion(&mut self) {
+        let mut taken = vec![self.take().unwrap()];
+        let prefix = match self.peek() {
+            Some(Token::Pound) | Some(Token::Bang) => self.take(),
+            _ => None,
+        };
+        taken.extend(prefix.clone());
+        let name = match self.peek() {
+            Some(Token::Name(_)) => self.take().unwrap(),
+            _ => return self.restore(taken),
+        };
+        taken.push(name.clone());
+        if prefix.is_none() && self.peek() == Some(&Token::Colon) {
+            taken.push(self.take().unwrap());
+            // `${name:-word}` and the like are left to the parser
+            if matches!(
+                self.peek(),
+                None | Some(Token::Dash | Token::Equals | Token::Question | Token::Plus)
+            ) {
+                return self.restore(taken);
+            }
+            return self.restore(vec![
+                Token::CurlyOpen,
+                name,
+                Token::Dash,
+                Token::Literal(SUBSTRING_MARKER.to_string()),
+            ]);
+        }
+        if self.peek() != Some(&Token::SquareOpen) {
+            return self.restore(taken);
+        }
+        taken.push(self.take().unwrap());
+        let subscript = match self.subscript(&mut taken) {
+            Some(subscript) if self.peek() == Some(&Token::CurlyClose) => subscript,
+            _ => return self.restore(taken),
+        };
+        let mut rewritten = vec![
+            Token::CurlyOpen,
+            name,
+            Token::Dash,
+            Token::Literal(ARRAY_MARKER.to_string()),
+        ];
+        rewritten.extend(prefix);
+        rewritten.extend(subscript);
+        self.restore(rewritten);
+    }
+
+    /// Rewrites `<<< word` following the taken `<<`
+    fn here_string(&mut self) -> Token {
+        self.take();
+        // `<<<` is one character longer than `<&`
+        let mut len = 1;
+        while let Some(Token::Whitespace(blank)) = self.peek() {
+            len += blank.len();
+            self.take();
+        }
+        self.pending.push_front(Token::Literal(
+            std::iter::repeat_n(HERE_STRING_MARKER, len).collect(),
+        ));
+        Token::LessAnd
+    }
+
+    /// Follows a here-document body to the line with its delimiter
+    fn heredoc_body(&mut self, token: &Token) {
+        let line = match &mut self.body {
+            Some(line) => line,
+            None => return,
+        };
+        if *token != Token::Newline {
+            line.push_str(&token.to_string());
+            return;
+        }
+        let line = mem::take(line);
+        if let Some((delimiter, strip, _)) = self.delimiters.front() {
+            let line = if *strip {
+                line.trim_start_matches('\t')
+            } else {
+                &line
+            };
+            if line == delimiter {
+                self.delimiters.pop_front();
+            }
+        }
+        if self.delimiters.is_empty() {
+            self.body = None;
+        }
+    }
+
+    /// Collects the delimiter of a here-document operator
+    fn heredoc_delimiter(&mut self, token: &Token, strip: bool) {
+        match token {
+            Token::Whitespace(_) if self.delimiter.is_empty() => {}
+            // quoting any part of the delimiter disables expansions in the body
+            Token::SingleQuote | Token::DoubleQuote | Token::Backslash => {
+                self.delimiter_quoted = true
+            }
+            Token::Whitespace(_)
+            | Token::Newline
+            | Token::Semi
+            | Token::Amp
+            | Token::Pipe
+            | Token::AndIf
+            | Token::OrIf
+            | Token::ParenClose => {
+                let delimiter = mem::take(&mut self.delimiter);
+                let quoted = mem::take(&mut self.delimiter_quoted);
+                self.delimiters.push_back((delimiter, strip, quoted));
+                self.heredoc = None;
+            }
+            token => self.delimiter.push_str(&token.to_string()),
+        }
+    }
+
+    /// Follows words to know where commands start
+    fn track(&mut self, token: &Token, quoted: bool) {
+        if quoted {
+            self.word.push_str(&token.to_string());
+            return;
+        }
+        match token {
+            Token::Whitespace(_) => {
+                if !self.word.is_empty() {
+                    let word = mem::take(&mut self.word);
+                    self.command_start = matches!(
+                        word.as_str(),
+                        "if" | "then"
+                            | "do"
+                            | "else"
+                            | "elif"
+                            | "while"
+                            | "until"
+                            | "{"
+                            | "!"
+                            | "time"
+                    ) || (self.command_start && is_assignment(&word));
+                }
+            }
+            Token::Newline
+            | Token::Semi
+            | Token::DSemi
+            | Token::Amp
+            | Token::Pipe
+            | Token::AndIf
+            | Token::OrIf
+            | Token::ParenOpen => {
+                self.word.clear();
+                self.command_start = self.compound == 0;
+            }
+            token => self.word.push_str(&token.to_string()),
+        }
+    }
+}
+
+/// Whether `word` is a `name=value` assignment
+fn is_assignment(word: &str) -> bool {
+    match word.split_once('=') {
+        Some((name, _)) => {
+            let name = name.strip_suffix('+').unwrap_or(name);
+            // `name[sub]=value` rewritten by `assignment`
+            let name = name.split(ARRAY_MARKER).next().unwrap_or(name);
+            !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => false,
+    }
+}
+
+impl<I: Iterator<Item = Token>> Iterator for Bashisms<I> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.take()?;
+        if self.body.is_some() {
+            self.heredoc_body(&token);
+            let expanded = self
+                .delimiters
+                .front()
+                .is_some_and(|(_, _, quoted)| !quoted);
+            if token == Token::Dollar && expanded && self.peek() == Some(&Token::CurlyOpen) {
+                self.subscript_expansion();
+            }
+            return Some(token);
+        }
+        if let Some(strip) = self.heredoc {
+            self.heredoc_delimiter(&token, strip);
+        }
+        if self.comment {
+            self.comment = token != Token::Newline;
+            if !self.comment {
+                self.track(&token, false);
+            }
+            return Some(token);
+        }
+        let escaped = mem::take(&mut self.escaped);
+        let quoted = escaped || self.single_quoted || self.double_quoted;
+        let token = match token {
+            Token::Backslash if !escaped && !self.single_quoted => {
+                self.escaped = true;
+                token
+            }
+            Token::SingleQuote if !escaped && !self.double_quoted => {
+                self.single_quoted = !self.single_quoted;
+                token
+            }
+            Token::DoubleQuote if !escaped && !self.single_quoted => {
+                self.double_quoted = !self.double_quoted;
+                token
+            }
+            Token::SquareClose if !quoted && self.test && self.word.is_empty() => {
+                self.test = !self.test_end();
+                token
+            }
+            // a missing `]]` is reported by the interpreter
+            Token::Newline | Token::Semi if !quoted && self.test => {
+                self.test = false;
+                token
+            }
+            Token::AndIf
+            | Token::OrIf
+            | Token::Less
+            | Token::Great
+            | Token::ParenOpen
+            | Token::ParenClose
+            | Token::Bang
+            | Token::Pipe
+            | Token::Amp
+                if !quoted && self.test =>
+            {
+                Token::Literal(token.to_string())
+            }
+            Token::SquareOpen
+                if !quoted
+                    && self.compound == 0
+                    && self.command_start
+                    && self.word.is_empty()
+                    && self.peek() == Some(&Token::SquareOpen) =>
+            {
+                self.test_start(token)
+            }
+            Token::CurlyOpen if !quoted && self.word.is_empty() => self.named_fd(token),
+            Token::Amp if !quoted && matches!(self.peek(), Some(Token::Great | Token::DGreat)) => {
+                self.combined_redirect()
+            }
+            Token::DLess if !quoted && self.peek() == Some(&Token::Less) => self.here_string(),
+            Token::DLess | Token::DLessDash if !quoted => {
+                self.heredoc = Some(token == Token::DLessDash);
+                token
+            }
+            Token::Pound if !quoted && self.word.is_empty() => {
+                self.comment = true;
+                token
+            }
+            Token::Dollar
+                if !escaped && !self.single_quoted && self.peek() == Some(&Token::CurlyOpen) =>
+            {
+                self.subscript_expansion();
+                token
+            }
+            Token::Name(name) if !quoted && self.word.is_empty() && self.command_start => {
+                self.assignment(name)
+            }
+            Token::ParenOpen
+                if !quoted
+                    && self.compound == 0
+                    && self.command_start
+                    && self.word.is_empty()
+                    && self.peek() == Some(&Token::ParenOpen) =>
+            {
+                self.arith_command(token)
+            }
+            Token::ParenOpen if !quoted && self.compound > 0 => {
+                self.compound += 1;
+                token
+            }
+            Token::ParenClose if !quoted && self.compound > 0 => {
+                self.compound -= 1;
+                if self.compound == 0 {
+                    Token::Whitespace(String::from(" "))
+                } else {
+                    token
+                }
+            }
+            // elements of compound assignments may span lines
+            Token::Newline if self.compound > 0 => Token::Whitespace(String::from("\n")),
+            token => token,
+        };
+        self.track(&token, quoted);
+        if token == Token::Newline && !quoted && !self.delimiters.is_empty() {
+            self.body = Some(String::new());
+        }
+        Some(token)
+    }
+}
+
+/// Creates the parser the interpreter runs on the given characters
+pub fn parser<I: Iterator<Item = char>>(chars: I) -> DefaultParser<Bashisms<Lexer<I>>> {
+    DefaultParser::new(Bashisms::new(Lexer::new(chars)))
+}
+
+/// Splits input into lexer tokens
+pub fn tokenize(input: &str) -> Vec<Token> {
+    Lexer::new(input.chars()).collect()
+}
+
+/// Parses whole input, stops on the first syntax error
+pub fn parse(input: &str) -> Result<Vec<ast::TopLevelCommand<String>>, String> {
+    parser(input.chars())
+        .map(|cmd| cmd.map_err(|e| describe_error(input, &e)))
+        .collect()
+}
+
+/// Words after which another command name follows
+const KEYWORDS: [&str; 11] = [
+    "if", "then", "elif", "else", "do", "while", "until", "time", "!", "{", "exec",
+];
+
+/// Paints `text` line by line, so that prompts drawn between the lines
+/// keep their own style
+fn paint_lines(style: Style, text: &str) -> String {
+    text.split('\n')
+        .map(|line| {
+            if line.is_empty() {
+                String::new()
+            } else {
+                style.paint(line)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Paints command names, arguments and quoted strings of `input` with the
+/// styles of `theme`. Words are found by scanning quotes and control
+/// operators rather than parsing, so that incomplete commands are painted
+/// as they are typed.
+pub fn highlight(input: &str, theme: &Theme) -> String {
+    let is_operator = |c: char| c.is_whitespace() || ";|&()".contains(c);
+    let mut output = String::new();
+    let mut chars = input.chars().peekable();
+    let mut command_position = true;
+    while let Some(c) = chars.next() {
+        if is_operator(c) {
+            if c != ' ' && c != '\t' && c != ')' {
+                command_position = true;
+            }
+            output.push(c);
+            continue;
+        }
+        // unquoted and quoted parts of the word
+        let mut parts: Vec<(bool, String)> = Vec::new();
+        let mut c = c;
+        loop {
+            match c {
+                '\'' | '"' => {
+                    let mut quoted = c.to_string();
+                    let mut escaped = false;
+                    for q in chars.by_ref() {
+                        quoted.push(q);
+                        if escaped {
+                            escaped = false;
+                        } else if q == '\\' && c == '"' {
+                            escaped = true;
+                        } else if q == c {
+                            break;
+                        }
+                    }
+                    parts.push((true, quoted));
+                }
+                _ => {
+                    if !matches!(parts.last(), Some((false, _))) {
+                        parts.push((false, String::new()));
+                    }
+                    let text = &mut parts.last_mut().unwrap().1;
+                    text.push(c);
+                    if c == '\\' {
+                        text.extend(chars.next());
+                    }
+                }
+            }
+            match chars.peek() {
+                Some(next) if !is_operator(*next) => c = chars.next().unwrap(),
+                _ => break,
+            }
+        }
+        let word: String = parts.iter().map(|(_, text)| text.as_str()).collect();
+        let role = if command_position && !is_assignment(&word) {
+            command_position = KEYWORDS.contains(&word.as_str());
+            Role::Command
+        } else {
+            Role::Argument
+        };
+        for (quoted, text) in parts {
+            let role = if quoted { Role::String } else { role };
+            output.push_str(&paint_lines(theme.style(role), &text));
+        }
+    }
+    output
+}
+
+/// Checks that input does not end inside an unfinished construct
+/// (open quote, loop, heredoc, ...)
+pub fn is_complete(input: &str) -> bool {
+    !continues_line(input)
+        && !unterminated_heredoc(input)
+        && !unterminated_array(input)
+        && !parser(input.chars()).any(|cmd| {
+            matches!(
+                cmd,
+                Err(ParseError::IncompleteCmd(..))
+                    | Err(ParseError::Unmatched(..))
+                    | Err(ParseError::UnexpectedEOF)
+            )
+        })
+}
+
+/// Whether input ends with a backslash escaping the final newline, which
+/// the parser takes as a blank rather than as a request for more input.
+/// Backslashes in single quotes are literal.
+pub fn continues_line(input: &str) -> bool {
+    let (mut single_quoted, mut double_quoted, mut escaped) = (false, false, false);
+    let mut continued = false;
+    for token in tokenize(input) {
+        let was_escaped = mem::take(&mut escaped);
+        continued = false;
+        match token {
+            Token::Backslash if !was_escaped && !single_quoted => escaped = true,
+            Token::SingleQuote if !was_escaped && !double_quoted => single_quoted = !single_quoted,
+            Token::DoubleQuote if !was_escaped && !single_quoted => double_quoted = !double_quoted,
+            Token::Newline => continued = was_escaped,
+            _ => {}
+        }
+    }
+    continued
+}
+
+/// Whether input ends inside the parentheses of a compound assignment
+fn unterminated_array(input: &str) -> bool {
+    let mut bashisms = Bashisms::new(tokenize(input).into_iter());
+    bashisms.by_ref().for_each(drop);
+    bashisms.compound > 0
+}
+
+/// Whether a here-document operator in input is not followed by its body
+/// and delimiter line yet. The parser would take the rest of the input as
+/// the body instead.
+fn unterminated_heredoc(input: &str) -> bool {
+    let tokens = tokenize(input);
+    let mut offsets = Vec::with_capacity(tokens.len());
+    let mut offset = 0;
+    for token in &tokens {
+        offsets.push(offset);
+        offset += token.to_string().len();
+    }
+
+    // delimiters of operators on the current line and whether tabs are stripped
+    let mut pending: Vec<(String, bool)> = Vec::new();
+    let mut bodies_end = 0;
+    let (mut single_quoted, mut double_quoted, mut escaped) = (false, false, false);
+    let mut i = 0;
+    while i < tokens.len() {
+        if offsets[i] < bodies_end {
+            i += 1;
+            continue;
+        }
+        let was_escaped = mem::take(&mut escaped);
+        let quoted = was_escaped || single_quoted || double_quoted;
+        match &tokens[i] {
+            Token::Backslash if !was_escaped && !single_quoted => escaped = true,
+            Token::SingleQuote if !was_escaped && !double_quoted => single_quoted = !single_quoted,
+            Token::DoubleQuote if !was_escaped && !single_quoted => double_quoted = !double_quoted,
+            operator @ (Token::DLess | Token::DLessDash)
+                if !quoted && tokens.get(i + 1) != Some(&Token::Less) =>
+            {
+                i += 1;
+                while let Some(Token::Whitespace(_)) = tokens.get(i) {
+                    i += 1;
+                }
+                let mut delimiter = String::new();
+                while let Some(token) = tokens.get(i) {
+                    match token {
+                        Token::Whitespace(_)
+                        | Token::Newline
+                        | Token::Semi
+                        | Token::Amp
+                        | Token::Pipe
+                        | Token::AndIf
+                        | Token::OrIf
+                        | Token::Less
+                        | Token::Great
+                        | Token::ParenClose => break,
+                        // quoting the delimiter only disables expansions in the body
+                        Token::SingleQuote | Token::DoubleQuote | Token::Backslash => {}
+                        token => delimiter.push_str(&token.to_string()),
+                    }
+                    i += 1;
+                }
+                pending.push((delimiter, *operator == Token::DLessDash));
+                continue;
+            }
+            Token::Newline if !quoted && !pending.is_empty() => {
+                // bodies follow the line with the operators, one after another
+                let mut start = offsets[i] + 1;
+                for (delimiter, strip_tabs) in pending.drain(..) {
+                    match delimiter_line_end(input, start, &delimiter, strip_tabs) {
+                        Some(end) => start = end,
+                        None => return true,
+                    }
+                }
+                bodies_end = start;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    !pending.is_empty()
+}
+
+/// Offset just past the first line from `start` on that consists of
+/// `delimiter`
+fn delimiter_line_end(
+    input: &str,
+    start: usize,
+    delimiter: &str,
+    strip_tabs: bool,
+) -> Option<usize> {
+    let mut offset = start;
+    for line in input[start..].split_inclusive('\n') {
+        offset += line.len();
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let line = if strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            line
+        };
+        if line == delimiter {
+            return Some(offset);
+        }
+    }
+    None
+}
+
+/// Formats parse error the way the shell reports it
+pub fn describe_error<T: Debug>(input: &str, error: &ParseError<T>) -> String {
+    match error {
+        /*
+        TODO: Most of these errors will never occur due to
+        unimplemented shell features so error messages are
+        kind of general.
+        */
+        ParseError::BadFd(pos_start, pos_end) => {
+            let idx_start = pos_start.byte;
+            let idx_end = pos_end.byte;
+            format!("{}: ambiguous redirect", &input[idx_start..idx_end])
+        }
+        ParseError::BadIdent(_, _) => "bad idenftifier".to_string(),
+        ParseError::BadSubst(_, _) => "bad substitution".to_string(),
+        ParseError::Unmatched(_, _) => "unmached expression".to_string(),
+        ParseError::IncompleteCmd(_, _, _, _) => "incomplete command".to_string(),
+        ParseError::Unexpected(_, _) => "unexpected token".to_string(),
+        ParseError::UnexpectedEOF => "unexpected end of file".to_string(),
+        ParseError::Custom(t) => {
+            format!("custom AST error: {t:?}")
+        }
+    }
+}
@@ -0,0 +1,114 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::env;
+use std::path::Path;
+
+/// Percent-encodes a path for a `file://` URL, keeping `/` separators
+fn percent_encode_path(path: &str) -> String {
+    let mut encoded = String::with_capacity(path.len());
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+    encoded
+}
+
+/// `file://` URL of an absolute path, with the empty host meaning this machine
+pub fn file_url(path: &Path) -> String {
+    format!("file://{}", percent_encode_path(&path.to_string_lossy()))
+}
+
+/// OSC 8 sequence showing `text` as a link to `url`
+pub fn hyperlink(url: &str, text: &str) -> String {
+    format!("\x1b]8;;{url}\x1b\\{text}\x1b]8;;\x1b\\")
+}
+
+/// Guesses OSC 8 support from the environment. Terminals that do not know the
+/// sequence usually ignore it, but some older ones print it verbatim.
+pub fn supports_hyperlinks() -> bool {
+    // the browser terminal (hterm) understands OSC 8
+    if cfg!(target_os = "wasi") {
+        return true;
+    }
+    let var = |name: &str| env::var(name).unwrap_or_default();
+    if matches!(var("TERM").as_str(), "" | "dumb" | "linux") {
+        return false;
+    }
+    matches!(
+        var("TERM_PROGRAM").as_str(),
+        "iTerm.app" | "WezTerm" | "vscode" | "Hyper" | "ghostty"
+    ) || matches!(var("VTE_VERSION").parse::<u32>(), Ok(version) if version >= 5000)
+        || [
+            "WT_SESSION",
+            "KONSOLE_VERSION",
+            "KITTY_WINDOW_ID",
+            "DOMTERM",
+        ]
+        .iter()
+        .any(|name| env::var_os(name).is_some())
+}
+
+/// Colors a terminal can display, ordered from least to most capable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    None,
+    /// 16 standard colors
+    Basic,
+    /// 256-color palette
+    Ansi256,
+    /// 24-bit RGB colors
+    TrueColor,
+}
+
+impl ColorSupport {
+    pub fn parse(spec: &str) -> Option<ColorSupport> {
+        match spec {
+            "none" | "0" => Some(ColorSupport::None),
+            "basic" | "16" => Some(ColorSupport::Basic),
+            "256" => Some(ColorSupport::Ansi256),
+            "truecolor" | "24bit" => Some(ColorSupport::TrueColor),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorSupport::None => "none",
+            ColorSupport::Basic => "basic",
+            ColorSupport::Ansi256 => "256",
+            ColorSupport::TrueColor => "truecolor",
+        }
+    }
+
+    /// Classifies the terminal from `NO_COLOR`, `COLORTERM` and `TERM`
+    pub fn detect() -> ColorSupport {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        if !var("NO_COLOR").is_empty() {
+            return ColorSupport::None;
+        }
+        let colorterm = var("COLORTERM");
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            return ColorSupport::TrueColor;
+        }
+        let term = var("TERM");
+        match term.as_str() {
+            "dumb" => ColorSupport::None,
+            // the browser terminal (hterm) renders RGB colors and does not
+            // always export TERM to the shell
+            "" if cfg!(target_os = "wasi") => ColorSupport::TrueColor,
+            "" => ColorSupport::None,
+            _ if term.ends_with("-direct") || term.ends_with("truecolor") => {
+                ColorSupport::TrueColor
+            }
+            _ if term.contains("256color") || !colorterm.is_empty() => ColorSupport::Ansi256,
+            _ => ColorSupport::Basic,
+        }
+    }
+}
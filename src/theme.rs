@@ -0,0 +1,343 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::HashMap;
+
+use crate::config::Config;
+use crate::terminal::ColorSupport;
+
+/// Semantic element of shell output that can be styled by a theme
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Role {
+    Command,
+    Argument,
+    String,
+    Error,
+    PromptUser,
+    PromptPath,
+    PromptSymbol,
+}
+
+impl Role {
+    pub const ALL: [Role; 7] = [
+        Role::Command,
+        Role::Argument,
+        Role::String,
+        Role::Error,
+        Role::PromptUser,
+        Role::PromptPath,
+        Role::PromptSymbol,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Command => "command",
+            Role::Argument => "argument",
+            Role::String => "string",
+            Role::Error => "error",
+            Role::PromptUser => "prompt-user",
+            Role::PromptPath => "prompt-path",
+            Role::PromptSymbol => "prompt-symbol",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Role> {
+        Role::ALL.iter().find(|role| role.name() == name).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Color {
+    /// One of 16 standard terminal colors, 8-15 are bright variants
+    Basic(u8),
+    /// 256-color palette index
+    Fixed(u8),
+    Rgb(u8, u8, u8),
+}
+
+const COLOR_NAMES: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// xterm defaults for the 16 standard colors
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// Channel values of the 6x6x6 color cube at palette indices 16-231
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2) as u32;
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+fn cube_index(value: u8) -> u8 {
+    CUBE_LEVELS
+        .iter()
+        .enumerate()
+        .min_by_key(|(_, level)| (**level as i32 - value as i32).abs())
+        .map_or(0, |(i, _)| i as u8)
+}
+
+impl Color {
+    pub fn parse(spec: &str) -> Option<Color> {
+        if let Some(hex) = spec.strip_prefix('#') {
+            // slicing below needs single-byte characters
+            if hex.len() != 6 || !hex.is_ascii() {
+                return None;
+            }
+            let component = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            return Some(Color::Rgb(component(0)?, component(2)?, component(4)?));
+        }
+        if let Ok(index) = spec.parse::<u8>() {
+            return Some(Color::Fixed(index));
+        }
+        let (bright, name) = match spec.strip_prefix("bright-") {
+            Some(name) => (8, name),
+            None => (0, spec),
+        };
+        COLOR_NAMES
+            .iter()
+            .position(|n| *n == name)
+            .map(|i| Color::Basic(i as u8 + bright))
+    }
+
+    fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            Color::Basic(n) | Color::Fixed(n) if n < 16 => BASIC_RGB[n as usize],
+            Color::Basic(_) => BASIC_RGB[15],
+            Color::Fixed(n) if n < 232 => {
+                let n = n - 16;
+                (
+                    CUBE_LEVELS[(n / 36) as usize],
+                    CUBE_LEVELS[(n / 6 % 6) as usize],
+                    CUBE_LEVELS[(n % 6) as usize],
+                )
+            }
+            Color::Fixed(n) => {
+                let gray = 8 + 10 * (n - 232);
+                (gray, gray, gray)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// Nearest color the terminal can display, `None` if it has no colors
+    pub fn downgrade(&self, support: ColorSupport) -> Option<Color> {
+        match (support, *self) {
+            (ColorSupport::None, _) => None,
+            (ColorSupport::TrueColor, color)
+            | (ColorSupport::Ansi256, color @ (Color::Basic(_) | Color::Fixed(_)))
+            | (ColorSupport::Basic, color @ Color::Basic(_)) => Some(color),
+            (ColorSupport::Ansi256, Color::Rgb(r, g, b)) => {
+                let cube =
+                    Color::Fixed(16 + 36 * cube_index(r) + 6 * cube_index(g) + cube_index(b));
+                let average = ((r as u32 + g as u32 + b as u32) / 3) as u8;
+                let gray = Color::Fixed(232 + (average.saturating_sub(3) / 10).min(23));
+                [cube, gray]
+                    .iter()
+                    .copied()
+                    .min_by_key(|color| distance(color.rgb(), (r, g, b)))
+            }
+            (ColorSupport::Basic, color) => (0..16)
+                .min_by_key(|n| distance(BASIC_RGB[*n as usize], color.rgb()))
+                .map(Color::Basic),
+        }
+    }
+
+    fn sgr(&self) -> String {
+        match self {
+            Color::Basic(n) if *n < 8 => format!("{}", 30 + n),
+            Color::Basic(n) => format!("{}", 90 + (n - 8)),
+            Color::Fixed(n) => format!("38;5;{n}"),
+            Color::Rgb(r, g, b) => format!("38;2;{r};{g};{b}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Style {
+    pub fg: Option<Color>,
+    pub bold: bool,
+}
+
+impl Style {
+    /// Parses style specification like `bold red`, `#ff8800` or `bright-blue`
+    pub fn parse(spec: &str) -> Option<Style> {
+        let mut style = Style::default();
+        for word in spec.split_whitespace() {
+            match word {
+                "bold" => style.bold = true,
+                "default" | "none" => {}
+                color => style.fg = Some(Color::parse(color)?),
+            }
+        }
+        Some(style)
+    }
+
+    /// Style as displayed by a terminal with `support` colors
+    pub fn downgrade(&self, support: ColorSupport) -> Style {
+        if support == ColorSupport::None {
+            return Style::default();
+        }
+        Style {
+            fg: self.fg.and_then(|fg| fg.downgrade(support)),
+            bold: self.bold,
+        }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.fg.is_none() && !self.bold
+    }
+
+    /// Returns SGR escape sequence enabling this style
+    pub fn prefix(&self) -> String {
+        let mut codes = Vec::new();
+        if self.bold {
+            codes.push("1".to_string());
+        }
+        if let Some(fg) = self.fg {
+            codes.push(fg.sgr());
+        }
+        format!("\x1b[{}m", codes.join(";"))
+    }
+
+    pub fn paint(&self, text: &str) -> String {
+        if self.is_plain() {
+            text.to_string()
+        } else {
+            format!("{}{}\x1b[0m", self.prefix(), text)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    styles: HashMap<Role, Style>,
+    /// Colors of the terminal, styles are downgraded to fit them
+    pub colors: ColorSupport,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::builtin("default").unwrap()
+    }
+}
+
+impl Theme {
+    pub const BUILTIN: [&'static str; 4] = ["default", "dark", "light", "plain"];
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let specs: &[(Role, &str)] = match name {
+            "default" => &[
+                (Role::Command, "bold green"),
+                (Role::String, "yellow"),
+                (Role::Error, "bold red"),
+                (Role::PromptUser, "bold blue"),
+                (Role::PromptPath, "bold yellow"),
+                (Role::PromptSymbol, "bold yellow"),
+            ],
+            "dark" => &[
+                (Role::Command, "bold #61afef"),
+                (Role::Argument, "#abb2bf"),
+                (Role::String, "#98c379"),
+                (Role::Error, "bold #e06c75"),
+                (Role::PromptUser, "bold #c678dd"),
+                (Role::PromptPath, "bold #56b6c2"),
+                (Role::PromptSymbol, "#e5c07b"),
+            ],
+            "light" => &[
+                (Role::Command, "bold #0550ae"),
+                (Role::Argument, "#24292f"),
+                (Role::String, "#0a3069"),
+                (Role::Error, "bold #cf222e"),
+                (Role::PromptUser, "bold #8250df"),
+                (Role::PromptPath, "bold #116329"),
+                (Role::PromptSymbol, "#953800"),
+            ],
+            "plain" => &[],
+            _ => return None,
+        };
+        Some(Theme {
+            name: name.to_string(),
+            colors: ColorSupport::TrueColor,
+            styles: specs
+                .iter()
+                .map(|(role, spec)| (*role, Style::parse(spec).unwrap()))
+                .collect(),
+        })
+    }
+
+    /// Builds theme from `theme.name` and per-role `theme.colors.<role>` config
+    /// keys, `terminal.colors` overrides detected color support
+    pub fn from_config(config: &Config) -> Result<Theme, String> {
+        let name = config.get_str("theme.name").unwrap_or("default");
+        let mut theme =
+            Theme::builtin(name).ok_or_else(|| format!("theme: {name}: no such theme"))?;
+        theme.colors = match config.get_str("terminal.colors") {
+            Some(spec) => ColorSupport::parse(spec)
+                .ok_or_else(|| format!("terminal.colors: {spec}: invalid color support"))?,
+            None => ColorSupport::detect(),
+        };
+        for (role, spec) in config.table("theme.colors") {
+            let role = Role::from_name(role)
+                .ok_or_else(|| format!("theme: {role}: unknown color role"))?;
+            let spec = match spec {
+                crate::config::ConfigValue::String(spec) => spec.as_str(),
+                _ => return Err(format!("theme: {}: color must be a string", role.name())),
+            };
+            let style =
+                Style::parse(spec).ok_or_else(|| format!("theme: {spec}: invalid color"))?;
+            theme.styles.insert(role, style);
+        }
+        Ok(theme)
+    }
+
+    pub fn style(&self, role: Role) -> Style {
+        self.styles
+            .get(&role)
+            .map(|style| style.downgrade(self.colors))
+            .unwrap_or_default()
+    }
+
+    /// Whether commands typed at the prompt are painted
+    pub fn highlights(&self) -> bool {
+        [Role::Command, Role::Argument, Role::String]
+            .iter()
+            .any(|role| !self.style(*role).is_plain())
+    }
+
+    pub fn paint(&self, role: Role, text: &str) -> String {
+        self.style(role).paint(text)
+    }
+
+    /// Default PS1 used when neither `PS1` nor `prompt.ps1` is set
+    pub fn default_prompt(&self) -> String {
+        format!(
+            "{} {}{} ",
+            self.paint(Role::PromptUser, "\\u@\\h"),
+            self.paint(Role::PromptPath, "\\w"),
+            self.paint(Role::PromptSymbol, "$"),
+        )
+    }
+}
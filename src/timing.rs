@@ -0,0 +1,150 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Measurement of pipelines run with the `time` reserved word
+
+use std::time::{Duration, Instant};
+
+/// Format used when `TIMEFORMAT` is unset
+pub const DEFAULT_FORMAT: &str = "\nreal\t%3lR\nuser\t%3lU\nsys\t%3lS";
+/// Format of `time -p`
+pub const POSIX_FORMAT: &str = "real %2R\nuser %2U\nsys %2S";
+
+/// Elapsed real time and CPU time of the shell and its children
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Times {
+    pub real: Duration,
+    pub user: Duration,
+    pub sys: Duration,
+}
+
+/// Readings taken when a timed pipeline starts
+pub struct Clock {
+    started: Instant,
+    user: Duration,
+    sys: Duration,
+}
+
+impl Clock {
+    pub fn start() -> Self {
+        let (user, sys) = cpu_times();
+        Clock {
+            started: Instant::now(),
+            user,
+            sys,
+        }
+    }
+
+    pub fn stop(&self) -> Times {
+        let (user, sys) = cpu_times();
+        Times {
+            real: self.started.elapsed(),
+            user: user.saturating_sub(self.user),
+            sys: sys.saturating_sub(self.sys),
+        }
+    }
+}
+
+/// User and system time of the shell and its waited for children
+#[cfg(not(target_os = "wasi"))]
+fn cpu_times() -> (Duration, Duration) {
+    use nix::sys::resource::{getrusage, UsageWho};
+    use nix::sys::time::TimeVal;
+
+    let duration = |time: TimeVal| {
+        Duration::from_secs(time.tv_sec().max(0) as u64)
+            + Duration::from_micros(time.tv_usec().max(0) as u64)
+    };
+    let (mut user, mut sys) = (Duration::ZERO, Duration::ZERO);
+    for who in [UsageWho::RUSAGE_SELF, UsageWho::RUSAGE_CHILDREN] {
+        if let Ok(usage) = getrusage(who) {
+            user += duration(usage.user_time());
+            sys += duration(usage.system_time());
+        }
+    }
+    (user, sys)
+}
+
+/// WASI only has a CPU clock of the shell itself, which counts as user time
+#[cfg(target_os = "wasi")]
+fn cpu_times() -> (Duration, Duration) {
+    let user = unsafe { wasi::clock_time_get(wasi::CLOCKID_PROCESS_CPUTIME_ID, 1) }
+        .map_or(Duration::ZERO, Duration::from_nanos);
+    (user, Duration::ZERO)
+}
+
+/// Expands `%[p][l]R`, `%[p][l]U`, `%[p][l]S`, `%P` and `%%` in `format`
+/// like bash does for `TIMEFORMAT`
+pub fn format(format: &str, times: &Times) -> String {
+    let mut out = String::new();
+    let mut chars = format.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+        let precision = match chars.peek() {
+            Some(digit @ '0'..='9') => {
+                let precision = digit.to_digit(10).unwrap_or(3).min(3) as usize;
+                chars.next();
+                Some(precision)
+            }
+            _ => None,
+        };
+        let long = chars.next_if_eq(&'l').is_some();
+        let duration = match chars.next() {
+            Some('%') if precision.is_none() && !long => {
+                out.push('%');
+                continue;
+            }
+            Some('P') if precision.is_none() && !long => {
+                let real = times.real.as_secs_f64();
+                let cpu = (times.user + times.sys).as_secs_f64();
+                let percent = if real > 0.0 { cpu * 100.0 / real } else { 0.0 };
+                out.push_str(&format!("{percent:.2}"));
+                continue;
+            }
+            Some('R') => times.real,
+            Some('U') => times.user,
+            Some('S') => times.sys,
+            // unknown conversions are printed as they are
+            other => {
+                out.push('%');
+                out.extend(precision.map(|precision| precision.to_string()));
+                if long {
+                    out.push('l');
+                }
+                out.extend(other);
+                continue;
+            }
+        };
+        out.push_str(&seconds(duration, precision.unwrap_or(3), long));
+    }
+    out
+}
+
+/// `duration` with `precision` decimal places, as `MmS.FFFs` when `long`
+fn seconds(duration: Duration, precision: usize, long: bool) -> String {
+    let millis = duration.as_millis();
+    let (minutes, secs) = if long {
+        (Some(millis / 60_000), millis % 60_000 / 1000)
+    } else {
+        (None, millis / 1000)
+    };
+    // fractions are truncated, like in bash
+    let fraction = (millis % 1000) / 10u128.pow(3 - precision as u32);
+    let mut text = match minutes {
+        Some(minutes) => format!("{minutes}m{secs}"),
+        None => secs.to_string(),
+    };
+    if precision > 0 {
+        text.push_str(&format!(".{fraction:0precision$}"));
+    }
+    if long {
+        text.push('s');
+    }
+    text
+}
@@ -0,0 +1,112 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+//! Signals handled with `trap`. Native builds install handlers that only
+//! record the signal, its trap runs before the next command. An interactive
+//! native shell records SIGINT even without a trap, so loops can be stopped.
+//! WASI only delivers SIGINT, which is polled from the event source instead.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Pseudo signal whose trap runs when the shell exits
+pub const EXIT: i32 = 0;
+pub const SIGINT: i32 = 2;
+
+/// Signals accepted by `trap`, numbered as on Linux
+pub const SIGNALS: [(&str, i32); 8] = [
+    ("EXIT", EXIT),
+    ("HUP", 1),
+    ("INT", SIGINT),
+    ("QUIT", 3),
+    ("USR1", 10),
+    ("USR2", 12),
+    ("ALRM", 14),
+    ("TERM", 15),
+];
+
+/// Signals received since the last `take_pending`, one bit per signal
+static PENDING: AtomicU64 = AtomicU64::new(0);
+
+/// How the shell reacts to a signal
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Disposition {
+    Catch,
+    Ignore,
+    Default,
+}
+
+/// Number of a signal given by name, with or without `SIG`, or by number
+pub fn parse_signal(spec: &str) -> Option<i32> {
+    let upper = spec.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(known, number)| *known == name || number.to_string() == spec)
+        .map(|(_, number)| *number)
+}
+
+pub fn signal_name(signal: i32) -> &'static str {
+    SIGNALS
+        .iter()
+        .find(|(_, number)| *number == signal)
+        .map_or("?", |(name, _)| name)
+}
+
+/// Marks `signal` as received, safe to call from a signal handler
+pub fn raise(signal: i32) {
+    PENDING.fetch_or(1 << signal, Ordering::SeqCst);
+}
+
+/// Whether `signal` was received, clears it
+pub fn take(signal: i32) -> bool {
+    PENDING.fetch_and(!(1 << signal), Ordering::SeqCst) & (1 << signal) != 0
+}
+
+/// Signals received since the last call, in increasing order
+pub fn take_pending() -> Vec<i32> {
+    let pending = PENDING.swap(0, Ordering::SeqCst);
+    (0..64)
+        .filter(|signal| pending & (1 << signal) != 0)
+        .collect()
+}
+
+#[cfg(not(target_os = "wasi"))]
+extern "C" fn record(signal: nix::libc::c_int) {
+    raise(signal);
+}
+
+/// Changes how the shell process reacts to `signal`
+pub fn set_disposition(signal: i32, disposition: Disposition) -> Result<(), String> {
+    if signal == EXIT {
+        return Ok(());
+    }
+    #[cfg(not(target_os = "wasi"))]
+    {
+        use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+        use std::convert::TryFrom;
+
+        let handler = match disposition {
+            Disposition::Catch => SigHandler::Handler(record),
+            Disposition::Ignore => SigHandler::SigIgn,
+            Disposition::Default => SigHandler::SigDfl,
+        };
+        let signal = Signal::try_from(signal).map_err(|error| error.to_string())?;
+        // restarted system calls keep waits for foreground children going
+        let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+        unsafe { sigaction(signal, &action) }
+            .map(|_| ())
+            .map_err(|error| error.to_string())
+    }
+    #[cfg(target_os = "wasi")]
+    {
+        let _ = disposition;
+        if signal == SIGINT {
+            Ok(())
+        } else {
+            Err(String::from("signal cannot be trapped on WASI"))
+        }
+    }
+}
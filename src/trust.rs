@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::digest::{sha256, to_hex};
+use crate::xdg;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trust {
+    Allowed,
+    Denied,
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Entry {
+    /// Allowed as long as the content hash matches
+    Allow(String),
+    Deny,
+}
+
+/// Allow-list of per-directory files (project rc, `.envrc`) the shell may source.
+///
+/// Entries are keyed by canonical path; an allowed file becomes untrusted again
+/// once its content changes.
+#[derive(Debug)]
+pub struct TrustStore {
+    path: PathBuf,
+    entries: BTreeMap<PathBuf, Entry>,
+}
+
+fn content_hash(file: &Path) -> io::Result<String> {
+    Ok(to_hex(&sha256(&fs::read(file)?)))
+}
+
+impl TrustStore {
+    pub fn load() -> TrustStore {
+        let path = xdg::state_dir().join("trusted");
+        let mut entries = BTreeMap::new();
+        for line in fs::read_to_string(&path).unwrap_or_default().lines() {
+            let mut fields = line.splitn(3, ' ');
+            let entry = match (fields.next(), fields.next()) {
+                (Some("allow"), Some(hash)) => Entry::Allow(hash.to_string()),
+                (Some("deny"), Some(_)) => Entry::Deny,
+                _ => continue,
+            };
+            if let Some(file) = fields.next() {
+                entries.insert(PathBuf::from(file), entry);
+            }
+        }
+        TrustStore { path, entries }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let content: String = self
+            .entries
+            .iter()
+            .map(|(file, entry)| match entry {
+                Entry::Allow(hash) => format!("allow {} {}\n", hash, file.display()),
+                Entry::Deny => format!("deny - {}\n", file.display()),
+            })
+            .collect();
+        xdg::write_atomic(&self.path, content.as_bytes())
+    }
+
+    pub fn check(&self, file: &Path) -> Trust {
+        let file = match fs::canonicalize(file) {
+            Ok(file) => file,
+            Err(_) => return Trust::Unknown,
+        };
+        match self.entries.get(&file) {
+            Some(Entry::Deny) => Trust::Denied,
+            Some(Entry::Allow(hash)) if content_hash(&file).ok().as_ref() == Some(hash) => {
+                Trust::Allowed
+            }
+            _ => Trust::Unknown,
+        }
+    }
+
+    pub fn allow(&mut self, file: &Path) -> io::Result<()> {
+        let file = fs::canonicalize(file)?;
+        let hash = content_hash(&file)?;
+        self.entries.insert(file, Entry::Allow(hash));
+        self.save()
+    }
+
+    pub fn deny(&mut self, file: &Path) -> io::Result<()> {
+        let file = fs::canonicalize(file)?;
+        self.entries.insert(file, Entry::Deny);
+        self.save()
+    }
+}
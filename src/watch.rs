@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+#[cfg(target_os = "linux")]
+use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+#[cfg(target_os = "linux")]
+use std::path::Path;
+
+/// Delay letting editors finish writing before files are compared
+#[cfg(target_os = "linux")]
+const SETTLE_DELAY: Duration = Duration::from_millis(100);
+
+/// How often interrupts are checked while waiting
+const INTERRUPT_POLL: Duration = Duration::from_millis(100);
+
+type Snapshot = BTreeMap<PathBuf, Option<SystemTime>>;
+
+/// Longest leading part of `pattern` without glob metacharacters, new matching
+/// files can only appear below it
+#[cfg(target_os = "linux")]
+fn literal_dir(pattern: &str) -> PathBuf {
+    let mut dir = PathBuf::new();
+    for component in Path::new(pattern).components() {
+        if component
+            .as_os_str()
+            .to_string_lossy()
+            .contains(['*', '?', '['])
+        {
+            break;
+        }
+        dir.push(component);
+    }
+    if dir == Path::new(pattern) {
+        dir.pop();
+    }
+    if dir.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        dir
+    }
+}
+
+/// Watches files matching glob patterns for creation, modification and removal.
+///
+/// Linux builds block on inotify events, other targets (including WASI, where
+/// runtimes do not expose file system events) poll modification times.
+pub struct FileWatcher {
+    patterns: Vec<String>,
+    snapshot: Snapshot,
+    interval: Duration,
+    #[cfg(target_os = "linux")]
+    inotify: Option<Inotify>,
+}
+
+impl FileWatcher {
+    pub fn new(patterns: Vec<String>, interval: Duration) -> FileWatcher {
+        let mut watcher = FileWatcher {
+            patterns,
+            snapshot: Snapshot::new(),
+            interval,
+            #[cfg(target_os = "linux")]
+            inotify: Inotify::init(InitFlags::IN_CLOEXEC | InitFlags::IN_NONBLOCK).ok(),
+        };
+        watcher.rescan();
+        watcher
+    }
+
+    fn scan(&self) -> Snapshot {
+        self.patterns
+            .iter()
+            .filter_map(|pattern| glob::glob(pattern).ok())
+            .flatten()
+            .filter_map(Result::ok)
+            .map(|path| {
+                let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok();
+                (path, modified)
+            })
+            .collect()
+    }
+
+    /// Takes a new snapshot so changes made so far are not reported
+    pub fn rescan(&mut self) {
+        self.snapshot = self.scan();
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &self.inotify {
+            let flags = AddWatchFlags::IN_MODIFY
+                | AddWatchFlags::IN_CLOSE_WRITE
+                | AddWatchFlags::IN_ATTRIB
+                | AddWatchFlags::IN_CREATE
+                | AddWatchFlags::IN_DELETE
+                | AddWatchFlags::IN_MOVED_FROM
+                | AddWatchFlags::IN_MOVED_TO;
+            let dirs: BTreeSet<PathBuf> = self
+                .snapshot
+                .keys()
+                .filter_map(|path| path.parent())
+                .map(|dir| {
+                    if dir.as_os_str().is_empty() {
+                        PathBuf::from(".")
+                    } else {
+                        dir.to_path_buf()
+                    }
+                })
+                .chain(self.patterns.iter().map(|pattern| literal_dir(pattern)))
+                .collect();
+            // watching the same directory again only updates its flags
+            for dir in dirs {
+                _ = inotify.add_watch(&dir, flags);
+            }
+        }
+    }
+
+    /// Waits before the next scan, false when `interrupted` returned true
+    fn sleep(&self, interrupted: &mut dyn FnMut() -> bool) -> bool {
+        #[cfg(target_os = "linux")]
+        if let Some(inotify) = &self.inotify {
+            loop {
+                match inotify.read_events() {
+                    Ok(events) if !events.is_empty() => {
+                        thread::sleep(SETTLE_DELAY);
+                        return true;
+                    }
+                    Ok(_) | Err(nix::errno::Errno::EAGAIN) => {}
+                    Err(_) => break,
+                }
+                if interrupted() {
+                    return false;
+                }
+                thread::sleep(INTERRUPT_POLL);
+            }
+        }
+        let deadline = Instant::now() + self.interval;
+        loop {
+            if interrupted() {
+                return false;
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return true;
+            }
+            thread::sleep(left.min(INTERRUPT_POLL));
+        }
+    }
+
+    /// Blocks until matched files change, returns the changed paths or
+    /// `None` when `interrupted` returned true
+    pub fn wait(&mut self, interrupted: &mut dyn FnMut() -> bool) -> Option<Vec<PathBuf>> {
+        loop {
+            if !self.sleep(interrupted) {
+                return None;
+            }
+            let snapshot = self.scan();
+            let changed: BTreeSet<PathBuf> = snapshot
+                .iter()
+                .filter(|(path, modified)| self.snapshot.get(*path) != Some(modified))
+                .map(|(path, _)| path.clone())
+                .chain(
+                    self.snapshot
+                        .keys()
+                        .filter(|path| !snapshot.contains_key(*path))
+                        .cloned(),
+                )
+                .collect();
+            if !changed.is_empty() {
+                self.rescan();
+                return Some(changed.into_iter().collect());
+            }
+        }
+    }
+}
@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 2022-2024 Antmicro <www.antmicro.com>
+ *
+ * SPDX-License-Identifier: Apache-2.0
+ */
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Home directory, falls back to the working directory when `$HOME` does not exist
+pub fn home_dir() -> PathBuf {
+    let home = PathBuf::from(env::var("HOME").unwrap_or_default());
+    if !home.as_os_str().is_empty() && home.exists() {
+        home
+    } else {
+        PathBuf::from(env::var("PWD").unwrap_or_else(|_| String::from("/")))
+    }
+}
+
+fn base_dir(var: &str, default: &str) -> PathBuf {
+    let base = match env::var(var) {
+        // the specification says relative paths must be ignored
+        Ok(path) if Path::new(&path).is_absolute() => PathBuf::from(path),
+        _ => home_dir().join(default),
+    };
+    base.join(env!("CARGO_PKG_NAME"))
+}
+
+pub fn config_dir() -> PathBuf {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn state_dir() -> PathBuf {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+pub fn data_dir() -> PathBuf {
+    base_dir("XDG_DATA_HOME", ".local/share")
+}
+
+pub fn cache_dir() -> PathBuf {
+    base_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// Dotfile in home directory used before XDG directories were supported
+pub fn legacy_file(suffix: &str) -> PathBuf {
+    home_dir().join(format!(".{}{}", env!("CARGO_PKG_NAME"), suffix))
+}
+
+pub fn history_file() -> PathBuf {
+    state_dir().join("history")
+}
+
+pub fn config_file() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+/// User rc file, `~/.washrc` is still honored when the XDG one is missing
+pub fn rc_file() -> PathBuf {
+    let rc = config_dir().join(format!("{}rc", env!("CARGO_PKG_NAME")));
+    let legacy = legacy_file("rc");
+    if !rc.exists() && legacy.exists() {
+        legacy
+    } else {
+        rc
+    }
+}
+
+/// Moves `legacy` file to `target` unless `target` already exists
+pub fn migrate(legacy: &Path, target: &Path) -> io::Result<()> {
+    if !legacy.exists() || target.exists() {
+        return Ok(());
+    }
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    // rename fails across filesystems, e.g. between WASI mounts
+    if fs::rename(legacy, target).is_err() {
+        fs::copy(legacy, target)?;
+        fs::remove_file(legacy)?;
+    }
+    Ok(())
+}
+
+/// Replaces `path` with `content` through a temporary file and rename so a crash
+/// never leaves a partially written file behind
+pub fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(content)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)
+}